@@ -1,36 +1,25 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId, Throughput};
 use terminator_dancer::{
-    TerminatorRuntime, Transaction, Account, Instruction, InstructionData,
+    TerminatorRuntime, Transaction, Instruction, InstructionData, AccountMeta, Pubkey,
     crypto::{SolanaCrypto, FastCrypto},
     solana_format::{SolanaTransactionParser, SolanaPubkey, SolanaHash},
 };
-use std::collections::HashMap;
 use ed25519_dalek::{SigningKey, Signer};
 use rand::rngs::OsRng;
 
 fn create_test_runtime() -> TerminatorRuntime {
-    TerminatorRuntime::new().expect("Failed to create runtime")
+    tokio::runtime::Runtime::new()
+        .expect("Failed to create tokio runtime")
+        .block_on(TerminatorRuntime::new("nonexistent_config.toml"))
+        .expect("Failed to create runtime")
 }
 
-fn create_test_accounts(count: usize) -> HashMap<[u8; 32], Account> {
-    let mut accounts = HashMap::new();
-    
-    for i in 0..count {
-        let mut key = [0u8; 32];
-        key[0..8].copy_from_slice(&(i as u64).to_le_bytes());
-        
-        let account = Account {
-            lamports: 1_000_000_000, // 1 SOL
-            data: vec![0u8; 128], // Small account data
-            owner: [0u8; 32], // System program
-            executable: false,
-            rent_epoch: 0,
-        };
-        
-        accounts.insert(key, account);
+fn account_meta(key: [u8; 32], is_signer: bool) -> AccountMeta {
+    AccountMeta {
+        pubkey: Pubkey::new(key),
+        is_signer,
+        is_writable: true,
     }
-    
-    accounts
 }
 
 fn create_transfer_transactions(count: usize) -> Vec<Transaction> {
@@ -43,8 +32,8 @@ fn create_transfer_transactions(count: usize) -> Vec<Transaction> {
         to[0..8].copy_from_slice(&((i + 1000) as u64).to_le_bytes());
         
         let instruction = Instruction {
-            program_id: [0u8; 32], // System program
-            accounts: vec![from, to],
+            program_id: Pubkey::system_program(),
+            accounts: vec![account_meta(from, true), account_meta(to, false)],
             data: InstructionData::Transfer { 
                 from, 
                 to, 
@@ -74,8 +63,8 @@ fn create_complex_transactions(count: usize) -> Vec<Transaction> {
         let instructions = vec![
             // Account creation
             Instruction {
-                program_id: [0u8; 32],
-                accounts: vec![payer],
+                program_id: Pubkey::system_program(),
+                accounts: vec![account_meta(payer, true), account_meta([(i + 2000) as u8; 32], true)],
                 data: InstructionData::CreateAccount {
                     from: payer,
                     to: [(i + 2000) as u8; 32],
@@ -86,8 +75,8 @@ fn create_complex_transactions(count: usize) -> Vec<Transaction> {
             },
             // Transfer
             Instruction {
-                program_id: [0u8; 32],
-                accounts: vec![payer, [(i + 3000) as u8; 32]],
+                program_id: Pubkey::system_program(),
+                accounts: vec![account_meta(payer, true), account_meta([(i + 3000) as u8; 32], false)],
                 data: InstructionData::Transfer {
                     from: payer,
                     to: [(i + 3000) as u8; 32],
@@ -112,17 +101,12 @@ fn bench_single_transaction_processing(c: &mut Criterion) {
     let mut group = c.benchmark_group("single_transaction");
     
     let runtime = create_test_runtime();
-    let accounts = create_test_accounts(100);
     let transactions = create_transfer_transactions(1);
     
     group.bench_function("simple_transfer", |b| {
         b.iter(|| {
             let mut runtime_copy = runtime.clone();
-            let mut accounts_copy = accounts.clone();
-            runtime_copy.execute_transaction(
-                black_box(&transactions[0]), 
-                black_box(&mut accounts_copy)
-            )
+            runtime_copy.execute_transaction(black_box(&transactions[0]))
         })
     });
     
@@ -135,19 +119,14 @@ fn bench_batch_transaction_processing(c: &mut Criterion) {
     group.throughput(Throughput::Elements(1000));
     
     let runtime = create_test_runtime();
-    let accounts = create_test_accounts(2000);
     let transactions = create_transfer_transactions(1000);
     
     group.bench_function("1000_transfers", |b| {
         b.iter(|| {
             let mut runtime_copy = runtime.clone();
-            let mut accounts_copy = accounts.clone();
             
             for transaction in &transactions {
-                runtime_copy.execute_transaction(
-                    black_box(transaction), 
-                    black_box(&mut accounts_copy)
-                ).unwrap();
+                runtime_copy.execute_transaction(black_box(transaction)).unwrap();
             }
         })
     });
@@ -160,24 +139,19 @@ fn bench_complex_transactions(c: &mut Criterion) {
     let mut group = c.benchmark_group("complex_transactions");
     
     let runtime = create_test_runtime();
-    let accounts = create_test_accounts(5000);
     let transactions = create_complex_transactions(100);
     
-    for tx_count in [10, 50, 100].iter() {
-        group.throughput(Throughput::Elements(*tx_count));
+    for tx_count in [10usize, 50, 100].iter() {
+        group.throughput(Throughput::Elements(*tx_count as u64));
         group.bench_with_input(
             BenchmarkId::new("multi_instruction", tx_count),
             tx_count,
             |b, &count| {
                 b.iter(|| {
                     let mut runtime_copy = runtime.clone();
-                    let mut accounts_copy = accounts.clone();
                     
                     for transaction in &transactions[0..count] {
-                        runtime_copy.execute_transaction(
-                            black_box(transaction), 
-                            black_box(&mut accounts_copy)
-                        ).unwrap();
+                        runtime_copy.execute_transaction(black_box(transaction)).unwrap();
                     }
                 })
             }
@@ -295,8 +269,8 @@ fn bench_pda_operations(c: &mut Criterion) {
     let mut group = c.benchmark_group("pda_operations");
     
     let program_id = [1u8; 32];
-    let seeds = [b"benchmark", b"pda", b"test"];
-    let seed_refs: Vec<&[u8]> = seeds.iter().map(|s| s.as_slice()).collect();
+    let seeds: [&[u8]; 3] = [b"benchmark", b"pda", b"test"];
+    let seed_refs: Vec<&[u8]> = seeds.to_vec();
     
     group.bench_function("derive_program_address", |b| {
         b.iter(|| {
@@ -315,7 +289,6 @@ fn bench_runtime_throughput(c: &mut Criterion) {
     let mut group = c.benchmark_group("runtime_throughput");
     
     let runtime = create_test_runtime();
-    let accounts = create_test_accounts(10000);
     
     // Test different batch sizes
     for batch_size in [100, 500, 1000, 2000].iter() {
@@ -328,15 +301,11 @@ fn bench_runtime_throughput(c: &mut Criterion) {
             |b, &size| {
                 b.iter(|| {
                     let mut runtime_copy = runtime.clone();
-                    let mut accounts_copy = accounts.clone();
                     
                     let start = std::time::Instant::now();
                     
                     for transaction in &transactions[0..size] {
-                        runtime_copy.execute_transaction(
-                            black_box(transaction),
-                            black_box(&mut accounts_copy)
-                        ).unwrap();
+                        runtime_copy.execute_transaction(black_box(transaction)).unwrap();
                     }
                     
                     let elapsed = start.elapsed();
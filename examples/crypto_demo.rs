@@ -105,8 +105,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
     
     println!("Created Solana-compatible transaction:");
-    println!("From: {}", from.to_string());
-    println!("To: {}", to.to_string());
+    println!("From: {}", from);
+    println!("To: {}", to);
     println!("Amount: 1,000,000 lamports (0.001 SOL)");
     
     // Serialize to Solana wire format
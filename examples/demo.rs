@@ -245,7 +245,7 @@ async fn demo_complex_transaction(runtime: &mut TerminatorRuntime) -> Result<Tra
     runtime.execute_transaction(&transaction)
 }
 
-async fn run_enhanced_conformance_tests(runtime: &mut TerminatorRuntime) -> Result<()> {
+async fn run_enhanced_conformance_tests(_runtime: &mut TerminatorRuntime) -> Result<()> {
     println!("Running conformance tests...");
     
     let mut harness = ConformanceHarness::new();
@@ -0,0 +1,236 @@
+//! Accounts in the JSON format of `solana account <address> --output json`, to set up fixtures
+//! from accounts dumped off a real cluster and to dump runtime accounts back.
+
+use crate::types::*;
+use crate::{Result, TerminatorError};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// Encodings of account data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UiAccountEncoding {
+    Binary,
+    Base58,
+    Base64,
+    JsonParsed,
+    #[serde(rename = "base64+zstd")]
+    Base64Zstd,
+}
+
+/// Account data: a `[data, encoding]` pair, or a bare base58 string in the legacy format
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum UiAccountData {
+    LegacyBinary(String),
+    Binary(String, UiAccountEncoding),
+}
+
+impl UiAccountData {
+    pub fn encode(data: &[u8], encoding: UiAccountEncoding) -> Result<Self> {
+        let encoded = match encoding {
+            UiAccountEncoding::Base58 => bs58::encode(data).into_string(),
+            UiAccountEncoding::Base64 => base64::engine::general_purpose::STANDARD.encode(data),
+            UiAccountEncoding::Base64Zstd => {
+                let compressed = zstd::encode_all(data, 0).map_err(dump_error)?;
+                base64::engine::general_purpose::STANDARD.encode(compressed)
+            }
+            UiAccountEncoding::Binary => return Ok(Self::LegacyBinary(bs58::encode(data).into_string())),
+            UiAccountEncoding::JsonParsed => return Err(dump_error("jsonParsed account data is not supported")),
+        };
+        Ok(Self::Binary(encoded, encoding))
+    }
+
+    pub fn decode(&self) -> Result<Vec<u8>> {
+        match self {
+            Self::LegacyBinary(data) | Self::Binary(data, UiAccountEncoding::Binary | UiAccountEncoding::Base58) => {
+                bs58::decode(data).into_vec().map_err(dump_error)
+            }
+            Self::Binary(data, UiAccountEncoding::Base64) => {
+                base64::engine::general_purpose::STANDARD.decode(data).map_err(dump_error)
+            }
+            Self::Binary(data, UiAccountEncoding::Base64Zstd) => {
+                let compressed = base64::engine::general_purpose::STANDARD.decode(data).map_err(dump_error)?;
+                let mut decoded = Vec::new();
+                zstd::Decoder::new(compressed.as_slice())
+                    .and_then(|mut decoder| decoder.read_to_end(&mut decoded))
+                    .map_err(dump_error)?;
+                Ok(decoded)
+            }
+            Self::Binary(_, UiAccountEncoding::JsonParsed) => {
+                Err(dump_error("jsonParsed account data can't be decoded, dump with base64"))
+            }
+        }
+    }
+}
+
+/// An account as the Solana CLI and RPC present it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UiAccount {
+    pub lamports: u64,
+    pub data: UiAccountData,
+    pub owner: String,
+    pub executable: bool,
+    pub rent_epoch: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub space: Option<u64>,
+}
+
+impl UiAccount {
+    pub fn encode(account: &Account, encoding: UiAccountEncoding) -> Result<Self> {
+        Ok(Self {
+            lamports: account.lamports,
+            data: UiAccountData::encode(&account.data, encoding)?,
+            owner: Pubkey::new(account.owner).to_string(),
+            executable: account.executable,
+            rent_epoch: account.rent_epoch,
+            space: Some(account.data.len() as u64),
+        })
+    }
+
+    pub fn decode(&self) -> Result<Account> {
+        Ok(Account {
+            lamports: self.lamports,
+            data: self.data.decode()?,
+            owner: self.owner.parse::<Pubkey>()?.0,
+            executable: self.executable,
+            rent_epoch: self.rent_epoch,
+        })
+    }
+}
+
+/// Output of `solana account <address> --output json`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CliAccount {
+    pub pubkey: String,
+    pub account: UiAccount,
+}
+
+impl CliAccount {
+    pub fn new(pubkey: &Pubkey, account: &Account) -> Self {
+        Self {
+            pubkey: pubkey.to_string(),
+            account: UiAccount::encode(account, UiAccountEncoding::Base64).expect("base64 always encodes"),
+        }
+    }
+
+    pub fn decode(&self) -> Result<(Pubkey, Account)> {
+        Ok((self.pubkey.parse()?, self.account.decode()?))
+    }
+}
+
+/// Read an account dumped to `path`
+pub fn read_account(path: impl AsRef<Path>) -> Result<(Pubkey, Account)> {
+    let path = path.as_ref();
+    let json = fs::read_to_string(path).map_err(|e| dump_error(format!("{}: {}", path.display(), e)))?;
+    let cli_account: CliAccount =
+        serde_json::from_str(&json).map_err(|e| dump_error(format!("{}: {}", path.display(), e)))?;
+    cli_account.decode()
+}
+
+/// Read every `.json` account dump in `dir`
+pub fn read_accounts_dir(dir: impl AsRef<Path>) -> Result<HashMap<Pubkey, Account>> {
+    let mut paths: Vec<_> = fs::read_dir(dir.as_ref())
+        .map_err(dump_error)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+        .collect();
+    paths.sort();
+    paths.iter().map(read_account).collect()
+}
+
+/// Dump an account to `path` in the CLI's format
+pub fn write_account(path: impl AsRef<Path>, pubkey: &Pubkey, account: &Account) -> Result<()> {
+    let json = serde_json::to_string_pretty(&CliAccount::new(pubkey, account)).map_err(dump_error)?;
+    fs::write(path, json).map_err(dump_error)
+}
+
+/// Dump accounts into `dir` as `<address>.json`, returning how many were written
+pub fn write_accounts_dir<'a>(
+    dir: impl AsRef<Path>,
+    accounts: impl IntoIterator<Item = (&'a Pubkey, &'a Account)>,
+) -> Result<usize> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir).map_err(dump_error)?;
+    let mut written = 0;
+    for (pubkey, account) in accounts {
+        write_account(dir.join(format!("{}.json", pubkey)), pubkey, account)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Add the accounts dumped in `dir` to `state`, returning how many were loaded
+pub fn load_into_bank_state(state: &mut BankState, dir: impl AsRef<Path>) -> Result<usize> {
+    let accounts = read_accounts_dir(dir)?;
+    let loaded = accounts.len();
+    state.accounts.extend(accounts);
+    Ok(loaded)
+}
+
+fn dump_error(e: impl ToString) -> TerminatorError {
+    TerminatorError::SerializationError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Output of `solana account SysvarRent111111111111111111111111111111111 --output json`
+    const RENT_SYSVAR_DUMP: &str = r#"{
+      "pubkey": "SysvarRent111111111111111111111111111111111",
+      "account": {
+        "lamports": 1009200,
+        "data": ["mA0AAAAAAAAAAAAAAAAAQDI=", "base64"],
+        "owner": "Sysvar1111111111111111111111111111111111111",
+        "executable": false,
+        "rentEpoch": 18446744073709551615,
+        "space": 17
+      }
+    }"#;
+
+    #[test]
+    fn test_read_cli_dump() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("rent.json"), RENT_SYSVAR_DUMP).unwrap();
+        fs::write(dir.path().join("notes.txt"), "not an account").unwrap();
+
+        let accounts = read_accounts_dir(dir.path()).unwrap();
+        assert_eq!(accounts.len(), 1);
+        let rent = &accounts[&Pubkey::sysvar_rent()];
+        assert_eq!(rent.lamports, 1_009_200);
+        assert_eq!(rent.rent_epoch, u64::MAX);
+        assert_eq!(rent.data.len(), 17);
+        assert_eq!(&rent.data[..8], &3480u64.to_le_bytes());
+        assert_eq!(Pubkey::new(rent.owner).to_string(), "Sysvar1111111111111111111111111111111111111");
+
+        let mut state = BankState::new();
+        assert_eq!(load_into_bank_state(&mut state, dir.path()).unwrap(), 1);
+        assert_eq!(state.accounts[&Pubkey::sysvar_rent()], *rent);
+    }
+
+    #[test]
+    fn test_export_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let accounts = HashMap::from([
+            (Pubkey::new([1u8; 32]), Account::new(10, vec![1, 2, 3], [4u8; 32])),
+            (Pubkey::new([2u8; 32]), Account::new_executable(20, vec![], Pubkey::bpf_loader().0)),
+        ]);
+        assert_eq!(write_accounts_dir(dir.path(), &accounts).unwrap(), 2);
+        assert_eq!(read_accounts_dir(dir.path()).unwrap(), accounts);
+
+        let data = vec![7u8; 300];
+        for encoding in [UiAccountEncoding::Base58, UiAccountEncoding::Base64Zstd, UiAccountEncoding::Binary] {
+            let encoded = UiAccountData::encode(&data, encoding).unwrap();
+            let json = serde_json::to_string(&encoded).unwrap();
+            let decoded: UiAccountData = serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded.decode().unwrap(), data);
+        }
+        assert!(UiAccountData::Binary("{}".to_string(), UiAccountEncoding::JsonParsed).decode().is_err());
+    }
+}
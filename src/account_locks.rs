@@ -0,0 +1,130 @@
+//! Read/write locks on accounts, held by transactions that execute concurrently.
+
+use crate::transaction_error::TransactionError;
+use crate::types::*;
+use std::collections::{HashMap, HashSet};
+
+/// Most accounts a single transaction may lock
+pub const MAX_TX_ACCOUNT_LOCKS: usize = 64;
+
+/// Accounts a transaction writes and accounts it only reads
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransactionAccountLocks {
+    pub writable: Vec<Pubkey>,
+    pub readonly: Vec<Pubkey>,
+}
+
+impl Transaction {
+    /// Locks the transaction needs: the payer and every account an instruction may write are
+    /// writable, programs and the remaining accounts are read-only
+    pub fn account_locks(&self) -> TransactionAccountLocks {
+        let mut writable = vec![Pubkey::new(self.payer)];
+        for instruction in &self.instructions {
+            let metas = instruction.accounts.iter().filter(|meta| meta.is_writable).map(|meta| meta.pubkey);
+            for pubkey in metas.chain(instruction.data.accounts()) {
+                if !writable.contains(&pubkey) {
+                    writable.push(pubkey);
+                }
+            }
+        }
+        let readonly = self
+            .account_keys()
+            .into_iter()
+            .filter(|pubkey| !writable.contains(pubkey))
+            .collect();
+        TransactionAccountLocks { writable, readonly }
+    }
+}
+
+/// Accounts currently locked: each is either write locked once or read locked any number of times
+#[derive(Debug, Default)]
+pub struct AccountLocks {
+    write_locks: HashSet<Pubkey>,
+    readonly_locks: HashMap<Pubkey, u64>,
+}
+
+impl AccountLocks {
+    /// Lock every account in `locks`, or none of them if any is held in a conflicting way
+    pub fn try_lock(&mut self, locks: &TransactionAccountLocks) -> Result<(), TransactionError> {
+        if locks.writable.len() + locks.readonly.len() > MAX_TX_ACCOUNT_LOCKS {
+            return Err(TransactionError::TooManyAccountLocks);
+        }
+        if self.conflicts(locks) {
+            return Err(TransactionError::AccountInUse);
+        }
+        self.write_locks.extend(locks.writable.iter().copied());
+        for pubkey in &locks.readonly {
+            *self.readonly_locks.entry(*pubkey).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    /// Whether locking `locks` would conflict with a lock already held
+    pub fn conflicts(&self, locks: &TransactionAccountLocks) -> bool {
+        locks
+            .writable
+            .iter()
+            .any(|pubkey| self.write_locks.contains(pubkey) || self.readonly_locks.contains_key(pubkey))
+            || locks.readonly.iter().any(|pubkey| self.write_locks.contains(pubkey))
+    }
+
+    /// Release locks previously taken with `try_lock`
+    pub fn unlock(&mut self, locks: &TransactionAccountLocks) {
+        for pubkey in &locks.writable {
+            self.write_locks.remove(pubkey);
+        }
+        for pubkey in &locks.readonly {
+            if let Some(count) = self.readonly_locks.get_mut(pubkey) {
+                *count -= 1;
+                if *count == 0 {
+                    self.readonly_locks.remove(pubkey);
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.write_locks.is_empty() && self.readonly_locks.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer_locks(from: u8, to: u8) -> TransactionAccountLocks {
+        Transaction::transfer_for_test(from, to, 1, [0u8; 32]).account_locks()
+    }
+
+    #[test]
+    fn test_transaction_account_locks() {
+        let locks = transfer_locks(1, 2);
+        assert_eq!(locks.writable, vec![Pubkey::new([1u8; 32]), Pubkey::new([2u8; 32])]);
+        assert_eq!(locks.readonly, vec![Pubkey::system_program()]);
+    }
+
+    #[test]
+    fn test_lock_conflicts() {
+        let mut account_locks = AccountLocks::default();
+        let first = transfer_locks(1, 2);
+        account_locks.try_lock(&first).unwrap();
+
+        // Both read the system program, but only one may write an account
+        account_locks.try_lock(&transfer_locks(3, 4)).unwrap();
+        assert_eq!(account_locks.try_lock(&transfer_locks(2, 5)), Err(TransactionError::AccountInUse));
+        let writes_program = TransactionAccountLocks { writable: vec![Pubkey::system_program()], readonly: vec![] };
+        assert_eq!(account_locks.try_lock(&writes_program), Err(TransactionError::AccountInUse));
+
+        account_locks.unlock(&first);
+        account_locks.try_lock(&transfer_locks(2, 5)).unwrap();
+        account_locks.unlock(&transfer_locks(2, 5));
+        account_locks.unlock(&transfer_locks(3, 4));
+        assert!(account_locks.is_empty());
+
+        let too_many = TransactionAccountLocks {
+            writable: (0..=MAX_TX_ACCOUNT_LOCKS as u8).map(|i| Pubkey::new([i; 32])).collect(),
+            readonly: vec![],
+        };
+        assert_eq!(account_locks.try_lock(&too_many), Err(TransactionError::TooManyAccountLocks));
+    }
+}
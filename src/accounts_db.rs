@@ -0,0 +1,601 @@
+//! Persistent accounts database: a write cache in front of append-only, memory-mapped storage
+//! files, with an in-memory index of where the latest version of every account lives.
+
+use crate::types::*;
+use crate::{Result, TerminatorError};
+use memmap2::MmapMut;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Default size of a storage file; larger accounts get a file of their own
+pub const DEFAULT_STORAGE_FILE_SIZE: usize = 16 * 1024 * 1024;
+/// How often the background service flushes the write cache
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+/// Extension of storage files, named by their id
+const STORAGE_EXTENSION: &str = "av";
+/// File holding the metadata of the stored root, written after the accounts it describes
+const ROOT_METADATA_FILE: &str = "root.bin";
+
+/// Bytes of a stored account before its data: entry length, pubkey, lamports, rent epoch, owner,
+/// executable flag (padded to 8 bytes) and data length
+const STORED_META_SIZE: usize = 8 + 32 + 8 + 8 + 32 + 8 + 8;
+
+fn stored_size(data_len: usize) -> usize {
+    (STORED_META_SIZE + data_len).next_multiple_of(8)
+}
+
+fn io_error(e: std::io::Error) -> TerminatorError {
+    TerminatorError::AccountsDbError(e.to_string())
+}
+
+/// An append-only storage file, mapped into memory
+#[derive(Debug)]
+struct AppendVec {
+    path: PathBuf,
+    map: MmapMut,
+    /// Bytes appended so far
+    len: usize,
+}
+
+impl AppendVec {
+    fn create(path: PathBuf, capacity: usize) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(io_error)?;
+        file.set_len(capacity as u64).map_err(io_error)?;
+        let map = unsafe { MmapMut::map_mut(&file) }.map_err(io_error)?;
+        Ok(Self { path, map, len: 0 })
+    }
+
+    /// Map an existing file, finding its end by walking its entries
+    fn open(path: PathBuf) -> Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(&path).map_err(io_error)?;
+        let map = unsafe { MmapMut::map_mut(&file) }.map_err(io_error)?;
+        let mut storage = Self { path, map, len: 0 };
+        while let Some((_, _, size)) = storage.get(storage.len) {
+            storage.len += size;
+        }
+        Ok(storage)
+    }
+
+    fn capacity(&self) -> usize {
+        self.map.len()
+    }
+
+    fn remaining(&self) -> usize {
+        self.capacity() - self.len
+    }
+
+    /// Append an account, returning its offset, or `None` if it doesn't fit
+    fn append(&mut self, pubkey: &Pubkey, account: &Account) -> Option<usize> {
+        let size = stored_size(account.data.len());
+        if size > self.remaining() {
+            return None;
+        }
+        let offset = self.len;
+        let entry = &mut self.map[offset..offset + size];
+        entry[8..40].copy_from_slice(&pubkey.0);
+        entry[40..48].copy_from_slice(&account.lamports.to_le_bytes());
+        entry[48..56].copy_from_slice(&account.rent_epoch.to_le_bytes());
+        entry[56..88].copy_from_slice(&account.owner);
+        entry[88] = account.executable as u8;
+        entry[96..104].copy_from_slice(&(account.data.len() as u64).to_le_bytes());
+        entry[STORED_META_SIZE..STORED_META_SIZE + account.data.len()].copy_from_slice(&account.data);
+        // The length goes in last: a zero length marks the end of the file
+        entry[0..8].copy_from_slice(&(size as u64).to_le_bytes());
+        self.len += size;
+        Some(offset)
+    }
+
+    /// Account stored at `offset` and the size of its entry
+    fn get(&self, offset: usize) -> Option<(Pubkey, Account, usize)> {
+        let read_u64 = |at: usize| -> Option<u64> {
+            let bytes = self.map.get(at..at + 8)?;
+            Some(u64::from_le_bytes(bytes.try_into().ok()?))
+        };
+        let size = read_u64(offset)? as usize;
+        if size < STORED_META_SIZE || offset + size > self.capacity() {
+            return None;
+        }
+        let entry = &self.map[offset..offset + size];
+        let data_len = read_u64(offset + 96)? as usize;
+        if stored_size(data_len) != size {
+            return None;
+        }
+        let pubkey = Pubkey::new(entry[8..40].try_into().ok()?);
+        let account = Account {
+            lamports: read_u64(offset + 40)?,
+            rent_epoch: read_u64(offset + 48)?,
+            owner: entry[56..88].try_into().ok()?,
+            executable: entry[88] != 0,
+            data: entry[STORED_META_SIZE..STORED_META_SIZE + data_len].to_vec(),
+        };
+        Some((pubkey, account, size))
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.map.flush().map_err(io_error)
+    }
+}
+
+/// Where the stored version of an account lives
+#[derive(Debug, Clone, Copy)]
+struct StoredLocation {
+    storage_id: u32,
+    offset: usize,
+    size: usize,
+}
+
+/// Sizes governing when the write cache is flushed and storages are compacted
+#[derive(Debug, Clone)]
+pub struct AccountsDbConfig {
+    /// Bytes of accounts held in the write cache before they're flushed to storage
+    pub cache_size_bytes: usize,
+    /// Bytes of superseded accounts in storage before the storages are compacted
+    pub gc_threshold_bytes: usize,
+    pub storage_file_size: usize,
+}
+
+impl From<&PerformanceSettings> for AccountsDbConfig {
+    fn from(settings: &PerformanceSettings) -> Self {
+        Self {
+            cache_size_bytes: settings.cache_size_mb as usize * 1024 * 1024,
+            gc_threshold_bytes: settings.gc_threshold_mb as usize * 1024 * 1024,
+            storage_file_size: DEFAULT_STORAGE_FILE_SIZE,
+        }
+    }
+}
+
+impl Default for AccountsDbConfig {
+    fn default() -> Self {
+        Self::from(&RuntimeConfig::default().performance)
+    }
+}
+
+/// Sizes of the accounts database
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountsDbStats {
+    pub accounts: usize,
+    pub storages: usize,
+    pub write_cache_bytes: usize,
+    pub alive_bytes: usize,
+    pub dead_bytes: usize,
+}
+
+#[derive(Debug, Default)]
+struct AccountsDbInner {
+    /// Accounts stored since the last flush, deletions as zero-lamport accounts
+    write_cache: HashMap<Pubkey, Account>,
+    write_cache_bytes: usize,
+    /// Metadata of the stored root, written once the accounts are flushed
+    pending_root_metadata: Option<Vec<u8>>,
+    storages: BTreeMap<u32, AppendVec>,
+    index: HashMap<Pubkey, StoredLocation>,
+    next_storage_id: u32,
+    alive_bytes: usize,
+    dead_bytes: usize,
+}
+
+/// Account state persisted in a directory.
+///
+/// Stores land in a write cache that is flushed to the storage files once it outgrows
+/// `cache_size_bytes`, or by an [`AccountsBackgroundService`]. Every flush appends, so updated and
+/// deleted accounts leave dead bytes behind, which are reclaimed by rewriting the live accounts
+/// once they pass `gc_threshold_bytes`.
+#[derive(Debug)]
+pub struct AccountsDb {
+    dir: PathBuf,
+    config: AccountsDbConfig,
+    inner: RwLock<AccountsDbInner>,
+}
+
+impl AccountsDb {
+    /// Open the database in `dir`, creating it if needed and indexing any accounts already stored
+    pub fn open(dir: impl AsRef<Path>, config: AccountsDbConfig) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(io_error)?;
+
+        let mut storage_ids: Vec<u32> = fs::read_dir(&dir)
+            .map_err(io_error)?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != STORAGE_EXTENSION {
+                    return None;
+                }
+                path.file_stem()?.to_str()?.parse().ok()
+            })
+            .collect();
+        storage_ids.sort_unstable();
+
+        // Later entries supersede earlier ones, zero-lamport entries delete the account
+        let mut inner = AccountsDbInner::default();
+        for storage_id in storage_ids {
+            let storage = AppendVec::open(Self::storage_path(&dir, storage_id))?;
+            let mut offset = 0;
+            while let Some((pubkey, account, size)) = storage.get(offset) {
+                let location = StoredLocation { storage_id, offset, size };
+                let previous = if account.lamports == 0 {
+                    inner.dead_bytes += size;
+                    inner.index.remove(&pubkey)
+                } else {
+                    inner.alive_bytes += size;
+                    inner.index.insert(pubkey, location)
+                };
+                if let Some(previous) = previous {
+                    inner.alive_bytes -= previous.size;
+                    inner.dead_bytes += previous.size;
+                }
+                offset += size;
+            }
+            inner.storages.insert(storage_id, storage);
+            inner.next_storage_id = storage_id + 1;
+        }
+        info!(
+            "Opened accounts db at {} with {} accounts in {} storages",
+            dir.display(),
+            inner.index.len(),
+            inner.storages.len()
+        );
+
+        Ok(Self {
+            dir,
+            config,
+            inner: RwLock::new(inner),
+        })
+    }
+
+    fn storage_path(dir: &Path, storage_id: u32) -> PathBuf {
+        dir.join(format!("{}.{}", storage_id, STORAGE_EXTENSION))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn config(&self) -> &AccountsDbConfig {
+        &self.config
+    }
+
+    /// Latest version of an account, `None` if it doesn't exist
+    pub fn load(&self, pubkey: &Pubkey) -> Option<Account> {
+        let inner = self.inner.read().unwrap();
+        if let Some(account) = inner.write_cache.get(pubkey) {
+            return Some(account.clone()).filter(|account| account.lamports > 0);
+        }
+        let location = inner.index.get(pubkey)?;
+        let (_, account, _) = inner.storages.get(&location.storage_id)?.get(location.offset)?;
+        Some(account)
+    }
+
+    /// Store accounts, deleting those without lamports. They are flushed to storage once the write
+    /// cache is full.
+    pub fn store(&self, accounts: impl IntoIterator<Item = (Pubkey, Account)>) -> Result<()> {
+        let mut inner = self.inner.write().unwrap();
+        for (pubkey, account) in accounts {
+            inner.write_cache_bytes += stored_size(account.data.len());
+            if let Some(previous) = inner.write_cache.insert(pubkey, account) {
+                inner.write_cache_bytes -= stored_size(previous.data.len());
+            }
+        }
+        if inner.write_cache_bytes > self.config.cache_size_bytes {
+            self.flush_inner(&mut inner)?;
+        }
+        Ok(())
+    }
+
+    /// Record metadata describing the stored state, persisted by the flush that persists the
+    /// accounts stored before it
+    pub fn set_root_metadata(&self, metadata: Vec<u8>) {
+        self.inner.write().unwrap().pending_root_metadata = Some(metadata);
+    }
+
+    /// Metadata of the last flushed root, if any was stored
+    pub fn root_metadata(&self) -> Option<Vec<u8>> {
+        fs::read(self.dir.join(ROOT_METADATA_FILE)).ok()
+    }
+
+    /// Write the cached accounts to storage and sync the storage files to disk
+    pub fn flush(&self) -> Result<()> {
+        let mut inner = self.inner.write().unwrap();
+        self.flush_inner(&mut inner)
+    }
+
+    fn flush_inner(&self, inner: &mut AccountsDbInner) -> Result<()> {
+        if inner.write_cache.is_empty() && inner.pending_root_metadata.is_none() {
+            return Ok(());
+        }
+        let mut accounts: Vec<_> = inner.write_cache.drain().collect();
+        accounts.sort_unstable_by_key(|(pubkey, _)| pubkey.0);
+        debug!("Flushing {} accounts to storage", accounts.len());
+        for (pubkey, account) in &accounts {
+            // Deleting an account that was never stored leaves nothing to supersede
+            if account.lamports == 0 && !inner.index.contains_key(pubkey) {
+                continue;
+            }
+            let location = self.append(inner, pubkey, account)?;
+            let previous = if account.lamports == 0 {
+                inner.dead_bytes += location.size;
+                inner.index.remove(pubkey)
+            } else {
+                inner.alive_bytes += location.size;
+                inner.index.insert(*pubkey, location)
+            };
+            if let Some(previous) = previous {
+                inner.alive_bytes -= previous.size;
+                inner.dead_bytes += previous.size;
+            }
+        }
+        inner.write_cache_bytes = 0;
+
+        for storage in inner.storages.values() {
+            storage.flush()?;
+        }
+        if let Some(metadata) = inner.pending_root_metadata.take() {
+            let path = self.dir.join(ROOT_METADATA_FILE);
+            let tmp = path.with_extension("tmp");
+            fs::write(&tmp, metadata).map_err(io_error)?;
+            fs::rename(&tmp, &path).map_err(io_error)?;
+        }
+
+        if inner.dead_bytes > self.config.gc_threshold_bytes {
+            self.compact_inner(inner)?;
+        }
+        Ok(())
+    }
+
+    /// Append to the newest storage, starting a new one when it's full
+    fn append(&self, inner: &mut AccountsDbInner, pubkey: &Pubkey, account: &Account) -> Result<StoredLocation> {
+        if let Some((&storage_id, storage)) = inner.storages.iter_mut().next_back() {
+            if let Some(offset) = storage.append(pubkey, account) {
+                return Ok(StoredLocation { storage_id, offset, size: stored_size(account.data.len()) });
+            }
+        }
+        let storage_id = inner.next_storage_id;
+        inner.next_storage_id += 1;
+        let capacity = self.config.storage_file_size.max(stored_size(account.data.len()));
+        let mut storage = AppendVec::create(Self::storage_path(&self.dir, storage_id), capacity)?;
+        let offset = storage.append(pubkey, account).expect("storage was sized for the account");
+        inner.storages.insert(storage_id, storage);
+        Ok(StoredLocation { storage_id, offset, size: stored_size(account.data.len()) })
+    }
+
+    /// Rewrite every live account into fresh storages and remove the old ones, reclaiming the
+    /// space of superseded and deleted accounts
+    pub fn compact(&self) -> Result<()> {
+        let mut inner = self.inner.write().unwrap();
+        self.flush_inner(&mut inner)?;
+        self.compact_inner(&mut inner)
+    }
+
+    fn compact_inner(&self, inner: &mut AccountsDbInner) -> Result<()> {
+        let old_storages = std::mem::take(&mut inner.storages);
+        let mut live: Vec<_> = inner.index.iter().map(|(pubkey, location)| (*pubkey, *location)).collect();
+        live.sort_unstable_by_key(|(_, location)| (location.storage_id, location.offset));
+
+        let mut index = HashMap::with_capacity(live.len());
+        inner.alive_bytes = 0;
+        for (pubkey, location) in live {
+            let (_, account, _) = old_storages[&location.storage_id]
+                .get(location.offset)
+                .ok_or_else(|| TerminatorError::AccountsDbError(format!("corrupt entry for {}", pubkey)))?;
+            let location = self.append(inner, &pubkey, &account)?;
+            inner.alive_bytes += location.size;
+            index.insert(pubkey, location);
+        }
+        for storage in inner.storages.values() {
+            storage.flush()?;
+        }
+        inner.index = index;
+
+        let reclaimed = inner.dead_bytes;
+        inner.dead_bytes = 0;
+        for storage in old_storages.into_values() {
+            let path = storage.path.clone();
+            drop(storage);
+            fs::remove_file(&path).map_err(io_error)?;
+        }
+        info!("Compacted accounts db, reclaiming {} bytes", reclaimed);
+        Ok(())
+    }
+
+    /// Whether the write cache holds anything to flush or storages are due for compaction
+    pub fn needs_maintenance(&self) -> bool {
+        let inner = self.inner.read().unwrap();
+        !inner.write_cache.is_empty()
+            || inner.pending_root_metadata.is_some()
+            || inner.dead_bytes > self.config.gc_threshold_bytes
+    }
+
+    /// Every account that exists
+    pub fn accounts(&self) -> HashMap<Pubkey, Account> {
+        let inner = self.inner.read().unwrap();
+        let mut accounts: HashMap<_, _> = inner
+            .index
+            .values()
+            .filter_map(|location| inner.storages.get(&location.storage_id)?.get(location.offset))
+            .map(|(pubkey, account, _)| (pubkey, account))
+            .collect();
+        for (pubkey, account) in &inner.write_cache {
+            if account.lamports > 0 {
+                accounts.insert(*pubkey, account.clone());
+            } else {
+                accounts.remove(pubkey);
+            }
+        }
+        accounts
+    }
+
+    pub fn stats(&self) -> AccountsDbStats {
+        let inner = self.inner.read().unwrap();
+        AccountsDbStats {
+            accounts: inner.index.len(),
+            storages: inner.storages.len(),
+            write_cache_bytes: inner.write_cache_bytes,
+            alive_bytes: inner.alive_bytes,
+            dead_bytes: inner.dead_bytes,
+        }
+    }
+}
+
+/// Nothing cached may be lost when the database goes away
+impl Drop for AccountsDb {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            warn!("Failed to flush accounts db at {}: {}", self.dir.display(), e);
+        }
+    }
+}
+
+/// Thread that periodically flushes the write cache and compacts the storages of an accounts db
+#[derive(Debug)]
+pub struct AccountsBackgroundService {
+    exit: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl AccountsBackgroundService {
+    pub fn start(accounts_db: Arc<AccountsDb>, interval: Duration) -> Self {
+        let exit = Arc::new(AtomicBool::new(false));
+        let handle = thread::Builder::new()
+            .name("accountsBackground".to_string())
+            .spawn({
+                let exit = exit.clone();
+                move || {
+                    while !exit.load(Ordering::Relaxed) {
+                        if accounts_db.needs_maintenance() {
+                            if let Err(e) = accounts_db.flush() {
+                                warn!("Background flush failed: {}", e);
+                            }
+                        }
+                        thread::park_timeout(interval);
+                    }
+                }
+            })
+            .expect("failed to spawn accounts background thread");
+        Self {
+            exit,
+            handle: Some(handle),
+        }
+    }
+
+    /// Stop the thread and wait for it to finish
+    pub fn join(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        self.exit.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for AccountsBackgroundService {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Storage files are small enough for tests to roll over to new ones
+    fn open_for_tests(dir: &Path, cache_size_bytes: usize, gc_threshold_bytes: usize) -> AccountsDb {
+        let config = AccountsDbConfig { cache_size_bytes, gc_threshold_bytes, storage_file_size: 4096 };
+        AccountsDb::open(dir, config).unwrap()
+    }
+
+    fn account(lamports: u64, data_len: usize) -> Account {
+        Account::new(lamports, vec![lamports as u8; data_len], [3u8; 32])
+    }
+
+    #[test]
+    fn test_store_load_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b, c) = (Pubkey::new([1u8; 32]), Pubkey::new([2u8; 32]), Pubkey::new([3u8; 32]));
+        {
+            let db = open_for_tests(dir.path(), 1 << 20, 1 << 20);
+            db.store([(a, account(10, 100)), (b, account(20, 3000)), (c, account(30, 0))]).unwrap();
+            // Cached until flushed
+            assert_eq!(db.stats().storages, 0);
+            assert_eq!(db.load(&b), Some(account(20, 3000)));
+            db.flush().unwrap();
+            assert_eq!(db.stats().storages, 1);
+
+            db.store([(a, account(11, 5000)), (c, account(0, 0))]).unwrap();
+            db.set_root_metadata(vec![7, 7]);
+            assert!(db.load(&c).is_none());
+        }
+
+        // Dropping flushed the cache, reopening rebuilds the index
+        let db = open_for_tests(dir.path(), 1 << 20, 1 << 20);
+        assert_eq!(db.load(&a), Some(account(11, 5000)));
+        assert_eq!(db.load(&b), Some(account(20, 3000)));
+        assert!(db.load(&c).is_none());
+        assert_eq!(db.accounts().len(), 2);
+        assert_eq!(db.root_metadata(), Some(vec![7, 7]));
+        // The account too large for the open storage got one of its own
+        assert_eq!(db.stats().storages, 3);
+        let stats = db.stats();
+        assert_eq!(stats.accounts, 2);
+        assert_eq!(stats.alive_bytes, stored_size(5000) + stored_size(3000));
+        assert!(stats.dead_bytes > 0);
+    }
+
+    #[test]
+    fn test_cache_size_and_compaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = open_for_tests(dir.path(), 1000, 4000);
+        let pubkeys: Vec<_> = (0..8u8).map(|i| Pubkey::new([i; 32])).collect();
+
+        // Overflowing the cache flushes it
+        db.store(pubkeys.iter().map(|pubkey| (*pubkey, account(1, 200)))).unwrap();
+        assert_eq!(db.stats().write_cache_bytes, 0);
+        assert_eq!(db.stats().accounts, 8);
+
+        // Rewriting the accounts leaves dead bytes until the threshold triggers a compaction
+        for round in 2..=4 {
+            db.store(pubkeys.iter().map(|pubkey| (*pubkey, account(round, 200)))).unwrap();
+        }
+        let stats = db.stats();
+        assert!(stats.dead_bytes <= 4000);
+        assert_eq!(stats.alive_bytes, 8 * stored_size(200));
+        db.compact().unwrap();
+        assert_eq!(db.stats().dead_bytes, 0);
+        assert_eq!(db.stats().storages, 1);
+        for pubkey in &pubkeys {
+            assert_eq!(db.load(pubkey), Some(account(4, 200)));
+        }
+        let files = fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(files, 1);
+    }
+
+    #[test]
+    fn test_background_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(open_for_tests(dir.path(), 1 << 20, 1 << 20));
+        let service = AccountsBackgroundService::start(db.clone(), Duration::from_millis(5));
+        db.store([(Pubkey::new([1u8; 32]), account(5, 10))]).unwrap();
+        for _ in 0..200 {
+            if !db.needs_maintenance() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        service.join();
+        assert_eq!(db.stats().storages, 1);
+        assert_eq!(db.stats().write_cache_bytes, 0);
+    }
+}
@@ -0,0 +1,182 @@
+//! State commitments over accounts: the lattice hash of every account, maintained incrementally
+//! as accounts are written, and the hash of the accounts written in a slot.
+
+use crate::types::*;
+use sha2::{Digest, Sha256};
+
+/// Number of 16-bit elements in a lattice hash
+pub const LT_HASH_NUM_ELEMENTS: usize = 1024;
+
+/// Homomorphic hash of a set of accounts: the element-wise wrapping sum of every account's hash.
+///
+/// Accounts can be mixed in and out in any order, so the hash of the whole account set can be
+/// updated on every write instead of recomputed.
+#[derive(Clone, PartialEq, Eq)]
+pub struct LtHash(pub [u16; LT_HASH_NUM_ELEMENTS]);
+
+impl LtHash {
+    /// Hash of the empty set
+    pub const fn identity() -> Self {
+        Self([0; LT_HASH_NUM_ELEMENTS])
+    }
+
+    /// Lattice hash of a single account. Accounts without lamports don't exist and hash to the
+    /// identity.
+    pub fn of_account(pubkey: &Pubkey, account: &Account) -> Self {
+        if account.lamports == 0 {
+            return Self::identity();
+        }
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&account.lamports.to_le_bytes());
+        hasher.update(&account.data);
+        hasher.update(&[account.executable as u8]);
+        hasher.update(&account.owner);
+        hasher.update(&pubkey.0);
+
+        let mut bytes = [0u8; LT_HASH_NUM_ELEMENTS * 2];
+        hasher.finalize_xof().fill(&mut bytes);
+        let mut elements = [0u16; LT_HASH_NUM_ELEMENTS];
+        for (element, chunk) in elements.iter_mut().zip(bytes.chunks_exact(2)) {
+            *element = u16::from_le_bytes([chunk[0], chunk[1]]);
+        }
+        Self(elements)
+    }
+
+    pub fn mix_in(&mut self, other: &LtHash) {
+        for (element, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *element = element.wrapping_add(*other);
+        }
+    }
+
+    pub fn mix_out(&mut self, other: &LtHash) {
+        for (element, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *element = element.wrapping_sub(*other);
+        }
+    }
+
+    /// 32-byte digest of the lattice hash
+    pub fn checksum(&self) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        for element in &self.0 {
+            hasher.update(&element.to_le_bytes());
+        }
+        hasher.finalize().into()
+    }
+}
+
+impl Default for LtHash {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl std::fmt::Debug for LtHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LtHash({})", bs58::encode(self.checksum()).into_string())
+    }
+}
+
+/// Lattice hash of a whole account set
+pub fn calculate_accounts_lt_hash<'a>(accounts: impl IntoIterator<Item = (&'a Pubkey, &'a Account)>) -> LtHash {
+    let mut lt_hash = LtHash::identity();
+    for (pubkey, account) in accounts {
+        lt_hash.mix_in(&LtHash::of_account(pubkey, account));
+    }
+    lt_hash
+}
+
+/// blake3 hash of an account's state and address
+pub fn hash_account(pubkey: &Pubkey, account: &Account) -> [u8; 32] {
+    if account.lamports == 0 {
+        return [0u8; 32];
+    }
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&account.lamports.to_le_bytes());
+    hasher.update(&account.rent_epoch.to_le_bytes());
+    hasher.update(&account.data);
+    hasher.update(&[account.executable as u8]);
+    hasher.update(&account.owner);
+    hasher.update(&pubkey.0);
+    hasher.finalize().into()
+}
+
+/// Hash of the accounts written in a slot: the sha256 of their account hashes, ordered by pubkey
+pub fn accounts_delta_hash<'a>(accounts: impl IntoIterator<Item = (&'a Pubkey, &'a Account)>) -> [u8; 32] {
+    let mut hashes: Vec<_> = accounts
+        .into_iter()
+        .map(|(pubkey, account)| (pubkey.0, hash_account(pubkey, account)))
+        .collect();
+    hashes.sort_unstable_by_key(|(pubkey, _)| *pubkey);
+    let mut hasher = Sha256::new();
+    for (_, hash) in hashes {
+        hasher.update(hash);
+    }
+    hasher.finalize().into()
+}
+
+/// Bank hash of a frozen slot, chaining its parent's bank hash
+pub fn bank_hash(
+    parent_bank_hash: &[u8; 32],
+    accounts_delta_hash: &[u8; 32],
+    signature_count: u64,
+    blockhash: &[u8; 32],
+    accounts_lt_hash: &LtHash,
+) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(parent_bank_hash);
+    hasher.update(accounts_delta_hash);
+    hasher.update(signature_count.to_le_bytes());
+    hasher.update(blockhash);
+    hasher.update(accounts_lt_hash.checksum());
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lt_hash_is_incremental() {
+        let accounts: Vec<_> = (1..=3u8)
+            .map(|i| (Pubkey::new([i; 32]), Account::new(i as u64 * 100, vec![i; i as usize], [0u8; 32])))
+            .collect();
+        let full = calculate_accounts_lt_hash(accounts.iter().map(|(pubkey, account)| (pubkey, account)));
+        let reversed = calculate_accounts_lt_hash(accounts.iter().rev().map(|(pubkey, account)| (pubkey, account)));
+        assert_eq!(full, reversed);
+
+        // Replacing an account is mixing out its old state and mixing in the new one
+        let (pubkey, old) = &accounts[1];
+        let new = Account::new(1, vec![], [9u8; 32]);
+        let mut incremental = full.clone();
+        incremental.mix_out(&LtHash::of_account(pubkey, old));
+        incremental.mix_in(&LtHash::of_account(pubkey, &new));
+        let expected = calculate_accounts_lt_hash([(&accounts[0].0, &accounts[0].1), (pubkey, &new), (&accounts[2].0, &accounts[2].1)]);
+        assert_eq!(incremental, expected);
+        assert_ne!(incremental.checksum(), full.checksum());
+
+        // Every field is committed to
+        let base = Account::new(5, vec![1], [2u8; 32]);
+        let variants = [
+            Account::new(6, vec![1], [2u8; 32]),
+            Account::new(5, vec![2], [2u8; 32]),
+            Account::new(5, vec![1], [3u8; 32]),
+            Account::new_executable(5, vec![1], [2u8; 32]),
+        ];
+        for variant in &variants {
+            assert_ne!(LtHash::of_account(pubkey, variant), LtHash::of_account(pubkey, &base));
+        }
+        assert_ne!(LtHash::of_account(&Pubkey::new([9u8; 32]), &base), LtHash::of_account(pubkey, &base));
+        assert_eq!(LtHash::of_account(pubkey, &Account::new(0, vec![1], [2u8; 32])), LtHash::identity());
+    }
+
+    #[test]
+    fn test_accounts_delta_hash_is_ordered() {
+        let a = (Pubkey::new([1u8; 32]), Account::new(1, vec![], [0u8; 32]));
+        let b = (Pubkey::new([2u8; 32]), Account::new(2, vec![], [0u8; 32]));
+        assert_eq!(
+            accounts_delta_hash([(&a.0, &a.1), (&b.0, &b.1)]),
+            accounts_delta_hash([(&b.0, &b.1), (&a.0, &a.1)])
+        );
+        assert_ne!(accounts_delta_hash([(&a.0, &a.1)]), accounts_delta_hash([(&b.0, &b.1)]));
+    }
+}
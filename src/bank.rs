@@ -1,30 +1,421 @@
-use crate::{Result, TerminatorError};
+//! Banks: the account state of one slot, layered over the frozen bank of its parent slot.
+
+use crate::accounts_db::AccountsDb;
+use crate::accounts_hash::{self, LtHash};
+use crate::blockhash_queue::BlockhashQueue;
 use crate::types::*;
+use crate::{Result, TerminatorError};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
 
+/// State of a slot. Accounts written in the slot live in the bank itself, everything else is read
+/// through to the parent, so a child bank costs only what it changes.
+///
+/// Once frozen a bank is immutable and can be shared as the parent of any number of forks.
+///
+/// A bank without a parent may be backed by an [`AccountsDb`] holding the rooted state, which
+/// squashing a bank into a root writes to.
+#[derive(Debug, Clone)]
 pub struct Bank {
-    accounts: HashMap<Pubkey, u64>,
+    parent: Option<Arc<Bank>>,
+    accounts_db: Option<Arc<AccountsDb>>,
+    /// Accounts written in this slot; a zero-lamport account hides the parent's
+    state: BankState,
+    blockhash_queue: BlockhashQueue,
+    /// Lattice hash of every account as of this bank, updated on every write
+    accounts_lt_hash: LtHash,
+    /// Signatures of the transactions processed in this slot
+    signature_count: u64,
+    frozen: bool,
 }
 
 impl Bank {
-    pub fn new() -> Self {
-        Bank {
+    /// A bank without a parent holding all of `state`'s accounts
+    pub fn new(state: BankState) -> Self {
+        let mut blockhash_queue = BlockhashQueue::default();
+        blockhash_queue.register_hash(state.blockhash, state.fee_calculator.lamports_per_signature);
+        Self {
+            parent: None,
+            accounts_db: None,
+            accounts_lt_hash: accounts_hash::calculate_accounts_lt_hash(&state.accounts),
+            state,
+            blockhash_queue,
+            signature_count: 0,
+            frozen: false,
+        }
+    }
+
+    /// A bank without a parent reading through to the accounts stored in `accounts_db`
+    pub fn new_with_accounts_db(state: BankState, accounts_db: Arc<AccountsDb>) -> Self {
+        let mut bank = Self::new(state);
+        bank.accounts_db = Some(accounts_db);
+        bank.accounts_lt_hash = bank.calculate_accounts_lt_hash();
+        bank
+    }
+
+    /// The frozen root last squashed into `accounts_db`, if there is one
+    pub fn from_accounts_db(accounts_db: Arc<AccountsDb>) -> Result<Option<Self>> {
+        let Some(metadata) = accounts_db.root_metadata() else {
+            return Ok(None);
+        };
+        let fields: RootFields = bincode::deserialize(&metadata)
+            .map_err(|e| TerminatorError::SerializationError(e.to_string()))?;
+        let accounts_lt_hash = LtHash(
+            fields
+                .accounts_lt_hash
+                .try_into()
+                .map_err(|_| TerminatorError::SerializationError("invalid accounts lattice hash".to_string()))?,
+        );
+        Ok(Some(Self {
+            parent: None,
+            accounts_db: Some(accounts_db),
+            state: BankState {
+                accounts: HashMap::new(),
+                slot: fields.slot,
+                blockhash: fields.blockhash,
+                bank_hash: fields.bank_hash,
+                fee_calculator: fields.fee_calculator,
+            },
+            blockhash_queue: fields.blockhash_queue,
+            accounts_lt_hash,
+            signature_count: 0,
+            frozen: true,
+        }))
+    }
+
+    /// A frozen bank without a parent, e.g. a root restored from a snapshot. `state` already
+    /// carries the slot's blockhash and bank hash.
+    pub fn new_frozen(state: BankState, blockhash_queue: BlockhashQueue) -> Self {
+        Self {
+            parent: None,
+            accounts_db: None,
+            accounts_lt_hash: accounts_hash::calculate_accounts_lt_hash(&state.accounts),
+            state,
+            blockhash_queue,
+            signature_count: 0,
+            frozen: true,
+        }
+    }
+
+    /// An empty bank for `slot` on top of the frozen `parent`
+    pub fn new_from_parent(parent: Arc<Bank>, slot: u64) -> Self {
+        assert!(parent.is_frozen(), "parent bank {} is not frozen", parent.slot());
+        assert!(slot > parent.slot(), "slot {} does not follow parent {}", slot, parent.slot());
+        let state = BankState {
             accounts: HashMap::new(),
+            slot,
+            blockhash: parent.state.blockhash,
+            bank_hash: parent.state.bank_hash,
+            fee_calculator: parent.state.fee_calculator.clone(),
+        };
+        Self {
+            accounts_db: None,
+            blockhash_queue: parent.blockhash_queue.clone(),
+            accounts_lt_hash: parent.accounts_lt_hash.clone(),
+            parent: Some(parent),
+            state,
+            signature_count: 0,
+            frozen: false,
         }
     }
 
-    pub fn process_transaction(&mut self, instruction: &Instruction) -> Result<()> {
-        match instruction.program_id {
-            _ => {
-                let account = instruction.accounts.get(0).ok_or(TerminatorError::AccountNotFound("missing account".to_string()))?;
-                let balance = self.accounts.entry(account.pubkey).or_insert(0);
-                *balance += 10; // Simplified logic for demo purposes
+    pub fn slot(&self) -> u64 {
+        self.state.slot
+    }
+
+    pub fn parent(&self) -> Option<&Arc<Bank>> {
+        self.parent.as_ref()
+    }
+
+    pub fn parent_slot(&self) -> Option<u64> {
+        self.parent.as_ref().map(|parent| parent.slot())
+    }
+
+    /// Blockhash of the slot once frozen, the parent's until then
+    pub fn blockhash(&self) -> [u8; 32] {
+        self.state.blockhash
+    }
+
+    /// Bank hash of the slot once frozen, the parent's until then
+    pub fn bank_hash(&self) -> [u8; 32] {
+        self.state.bank_hash
+    }
+
+    /// Database holding the rooted state this bank reads through to
+    pub fn accounts_db(&self) -> Option<&Arc<AccountsDb>> {
+        self.chain().last().and_then(|root| root.accounts_db.as_ref())
+    }
+
+    pub fn fee_calculator(&self) -> &FeeCalculator {
+        &self.state.fee_calculator
+    }
+
+    pub fn blockhash_queue(&self) -> &BlockhashQueue {
+        &self.blockhash_queue
+    }
+
+    pub fn accounts_lt_hash(&self) -> &LtHash {
+        &self.accounts_lt_hash
+    }
+
+    /// Lattice hash recomputed from every account, to check the incrementally updated one
+    pub fn calculate_accounts_lt_hash(&self) -> LtHash {
+        accounts_hash::calculate_accounts_lt_hash(&self.accounts())
+    }
+
+    pub fn signature_count(&self) -> u64 {
+        self.signature_count
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Whether `slot` is this bank's slot or one of its ancestors'.
+    ///
+    /// A bank without a parent is a root, and every slot before a root is its ancestor: other
+    /// forks are discarded when a bank is rooted.
+    pub fn is_ancestor_or_self(&self, slot: u64) -> bool {
+        let mut bank = self;
+        loop {
+            if bank.slot() == slot {
+                return true;
+            }
+            match &bank.parent {
+                Some(parent) if slot <= bank.slot() => bank = parent,
+                Some(_) => return false,
+                None => return slot < bank.slot(),
             }
         }
-        Ok(())
     }
 
-    pub fn get_balance(&self, pubkey: &Pubkey) -> u64 {
-        *self.accounts.get(pubkey).unwrap_or(&0)
+    /// Current state of an account, as written in this slot or inherited from an ancestor
+    pub fn get_account(&self, pubkey: &Pubkey) -> Option<Account> {
+        let mut bank = self;
+        loop {
+            if let Some(account) = bank.state.accounts.get(pubkey) {
+                return Some(account.clone()).filter(|account| account.lamports > 0);
+            }
+            match &bank.parent {
+                Some(parent) => bank = parent,
+                None => return bank.accounts_db.as_ref()?.load(pubkey),
+            }
+        }
+    }
+
+    /// Write an account in this slot, deleting it if it has no lamports
+    pub fn store_account(&mut self, pubkey: Pubkey, account: Account) {
+        assert!(!self.frozen, "bank {} is frozen", self.slot());
+        if let Some(old) = self.get_account(&pubkey) {
+            self.accounts_lt_hash.mix_out(&LtHash::of_account(&pubkey, &old));
+        }
+        self.accounts_lt_hash.mix_in(&LtHash::of_account(&pubkey, &account));
+        if account.lamports == 0 && self.parent.is_none() && self.accounts_db.is_none() {
+            self.state.accounts.remove(&pubkey);
+        } else {
+            self.state.accounts.insert(pubkey, account);
+        }
+    }
+
+    /// Delete an account in this slot. Where an ancestor or the accounts db may still hold it, an
+    /// empty zero-lamport account is left in its place.
+    pub fn remove_account(&mut self, pubkey: &Pubkey) {
+        self.store_account(*pubkey, Account::new(0, vec![], Pubkey::system_program().0));
+    }
+
+    /// Accounts written in this slot, including deletions as zero-lamport accounts
+    pub fn accounts_delta(&self) -> impl Iterator<Item = (&Pubkey, &Account)> {
+        self.state.accounts.iter()
+    }
+
+    /// This bank followed by its ancestors
+    fn chain(&self) -> Vec<&Bank> {
+        let mut chain = vec![self];
+        while let Some(parent) = chain.last().and_then(|bank| bank.parent.as_deref()) {
+            chain.push(parent);
+        }
+        chain
+    }
+
+    /// Every account that exists as of this bank
+    pub fn accounts(&self) -> HashMap<Pubkey, Account> {
+        let chain = self.chain();
+        let mut accounts = self.accounts_db().map(|db| db.accounts()).unwrap_or_default();
+        for bank in chain.into_iter().rev() {
+            for (pubkey, account) in &bank.state.accounts {
+                if account.lamports > 0 {
+                    accounts.insert(*pubkey, account.clone());
+                } else {
+                    accounts.remove(pubkey);
+                }
+            }
+        }
+        accounts
+    }
+
+    pub fn add_signature_count(&mut self, count: u64) {
+        self.signature_count += count;
+    }
+
+    /// Freeze the bank with `blockhash`, the last PoH hash of its block, and return its bank hash.
+    ///
+    /// The bank hash chains the parent bank hash with a hash of the accounts written in the slot,
+    /// the number of signatures processed, the blockhash and the lattice hash of all accounts.
+    pub fn freeze(&mut self, blockhash: [u8; 32]) -> [u8; 32] {
+        if self.frozen {
+            return self.state.bank_hash;
+        }
+
+        let accounts_delta_hash = accounts_hash::accounts_delta_hash(self.accounts_delta());
+        self.state.bank_hash = accounts_hash::bank_hash(
+            &self.state.bank_hash,
+            &accounts_delta_hash,
+            self.signature_count,
+            &blockhash,
+            &self.accounts_lt_hash,
+        );
+
+        self.state.blockhash = blockhash;
+        self.blockhash_queue
+            .register_hash(blockhash, self.state.fee_calculator.lamports_per_signature);
+        self.frozen = true;
+        self.state.bank_hash
+    }
+
+    /// Accept transactions referencing `blockhash` without finishing a slot
+    #[cfg(test)]
+    pub(crate) fn register_recent_blockhash_for_test(&mut self, blockhash: [u8; 32]) {
+        self.blockhash_queue
+            .register_hash(blockhash, self.state.fee_calculator.lamports_per_signature);
+    }
+
+    /// The same bank with its ancestors' accounts folded in, so they can be dropped.
+    ///
+    /// With an accounts db the accounts written since the last root are stored in it, along with
+    /// what's needed to reopen the bank.
+    pub fn squash(&self) -> Result<Self> {
+        let accounts_db = self.accounts_db().cloned();
+        let accounts = match &accounts_db {
+            Some(accounts_db) => {
+                for bank in self.chain().into_iter().rev() {
+                    accounts_db.store(bank.state.accounts.iter().map(|(pubkey, account)| (*pubkey, account.clone())))?;
+                }
+                if self.frozen {
+                    let metadata = bincode::serialize(&self.root_fields())
+                        .map_err(|e| TerminatorError::SerializationError(e.to_string()))?;
+                    accounts_db.set_root_metadata(metadata);
+                }
+                HashMap::new()
+            }
+            None => self.accounts(),
+        };
+        Ok(Self {
+            parent: None,
+            accounts_db,
+            state: BankState {
+                accounts,
+                ..self.state.clone()
+            },
+            blockhash_queue: self.blockhash_queue.clone(),
+            accounts_lt_hash: self.accounts_lt_hash.clone(),
+            signature_count: self.signature_count,
+            frozen: self.frozen,
+        })
+    }
+
+    fn root_fields(&self) -> RootFields {
+        RootFields {
+            slot: self.state.slot,
+            blockhash: self.state.blockhash,
+            bank_hash: self.state.bank_hash,
+            fee_calculator: self.state.fee_calculator.clone(),
+            blockhash_queue: self.blockhash_queue.clone(),
+            accounts_lt_hash: self.accounts_lt_hash.0.to_vec(),
+        }
+    }
+}
+
+/// What it takes to reopen a root from its accounts db
+#[derive(Serialize, Deserialize)]
+struct RootFields {
+    slot: u64,
+    blockhash: [u8; 32],
+    bank_hash: [u8; 32],
+    fee_calculator: FeeCalculator,
+    blockhash_queue: BlockhashQueue,
+    /// `LT_HASH_NUM_ELEMENTS` elements
+    accounts_lt_hash: Vec<u16>,
+}
+
+impl Default for Bank {
+    fn default() -> Self {
+        Self::new(BankState::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_child_overlays_parent() {
+        let (alice, bob) = (Pubkey::new([1u8; 32]), Pubkey::new([2u8; 32]));
+        let mut genesis = Bank::default();
+        genesis.store_account(alice, Account::new(100, vec![], [0u8; 32]));
+        genesis.store_account(bob, Account::new(50, vec![], [0u8; 32]));
+        genesis.freeze([7u8; 32]);
+        let genesis = Arc::new(genesis);
+
+        let mut child = Bank::new_from_parent(genesis.clone(), 2);
+        assert_eq!(child.blockhash(), [7u8; 32]);
+        assert_eq!(child.get_account(&alice).unwrap().lamports, 100);
+        child.store_account(alice, Account::new(60, vec![], [0u8; 32]));
+        child.store_account(bob, Account::new(0, vec![], [0u8; 32]));
+
+        // The parent is untouched, only the written accounts are copied
+        assert_eq!(child.get_account(&alice).unwrap().lamports, 60);
+        assert!(child.get_account(&bob).is_none());
+        assert_eq!(genesis.get_account(&bob).unwrap().lamports, 50);
+        assert_eq!(child.accounts_delta().count(), 2);
+        assert_eq!(child.accounts().len(), 1);
+        assert_eq!(child.accounts_lt_hash(), &child.calculate_accounts_lt_hash());
+        assert_ne!(child.accounts_lt_hash(), genesis.accounts_lt_hash());
+
+        assert!(child.is_ancestor_or_self(0));
+        assert!(child.is_ancestor_or_self(2));
+        assert!(!child.is_ancestor_or_self(1));
+        assert!(!child.is_ancestor_or_self(3));
+
+        child.freeze([8u8; 32]);
+        let squashed = child.squash().unwrap();
+        assert!(squashed.parent().is_none());
+        assert_eq!(squashed.bank_hash(), child.bank_hash());
+        assert_eq!(squashed.accounts_delta().count(), 1);
+        assert!(squashed.blockhash_queue().is_hash_valid(&[7u8; 32]));
+    }
+
+    #[test]
+    fn test_bank_hash_depends_on_parent_and_delta() {
+        let mut parent = Bank::default();
+        parent.freeze([1u8; 32]);
+        let parent = Arc::new(parent);
+
+        let freeze = |lamports: u64| {
+            let mut bank = Bank::new_from_parent(parent.clone(), 1);
+            bank.store_account(Pubkey::new([3u8; 32]), Account::new(lamports, vec![], [0u8; 32]));
+            bank.freeze([2u8; 32])
+        };
+        assert_eq!(freeze(10), freeze(10));
+        assert_ne!(freeze(10), freeze(11));
+        assert_ne!(freeze(10), parent.bank_hash());
+    }
+
+    #[test]
+    #[should_panic(expected = "frozen")]
+    fn test_frozen_bank_is_immutable() {
+        let mut bank = Bank::default();
+        bank.freeze([0u8; 32]);
+        bank.store_account(Pubkey::new([1u8; 32]), Account::new(1, vec![], [0u8; 32]));
     }
 }
@@ -0,0 +1,210 @@
+//! Frozen banks of every live fork, pruned to the descendants of the root as banks are rooted.
+
+use crate::bank::Bank;
+use crate::{Result, TerminatorError};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Frozen banks by slot. Forks share their common ancestors, and rooting a bank discards every
+/// fork that doesn't descend from it.
+#[derive(Debug, Clone, Default)]
+pub struct BankForks {
+    banks: HashMap<u64, Arc<Bank>>,
+    root: u64,
+}
+
+impl BankForks {
+    /// Forks rooted at the frozen `root_bank`
+    pub fn new(root_bank: Bank) -> Self {
+        assert!(root_bank.is_frozen(), "root bank {} is not frozen", root_bank.slot());
+        let root = root_bank.slot();
+        Self {
+            banks: HashMap::from([(root, Arc::new(root_bank))]),
+            root,
+        }
+    }
+
+    pub fn root(&self) -> u64 {
+        self.root
+    }
+
+    pub fn root_bank(&self) -> Option<&Arc<Bank>> {
+        self.banks.get(&self.root)
+    }
+
+    pub fn get(&self, slot: u64) -> Option<&Arc<Bank>> {
+        self.banks.get(&slot)
+    }
+
+    /// Slots of every bank, in ascending order
+    pub fn slots(&self) -> Vec<u64> {
+        let mut slots: Vec<_> = self.banks.keys().copied().collect();
+        slots.sort_unstable();
+        slots
+    }
+
+    pub fn highest_slot(&self) -> u64 {
+        self.banks.keys().copied().max().unwrap_or(self.root)
+    }
+
+    /// Banks no other bank is built on: the tips of the forks
+    pub fn leaves(&self) -> Vec<&Arc<Bank>> {
+        let mut leaves: Vec<_> = self
+            .banks
+            .values()
+            .filter(|bank| !self.banks.values().any(|child| child.parent_slot() == Some(bank.slot())))
+            .collect();
+        leaves.sort_by_key(|bank| bank.slot());
+        leaves
+    }
+
+    /// Slots of the banks descending from `slot`, including `slot` itself
+    pub fn descendants(&self, slot: u64) -> Vec<u64> {
+        let mut descendants: Vec<_> = self
+            .banks
+            .values()
+            .filter(|bank| bank.slot() >= slot && bank.is_ancestor_or_self(slot))
+            .map(|bank| bank.slot())
+            .collect();
+        descendants.sort_unstable();
+        descendants
+    }
+
+    /// Add a frozen bank whose parent is already in the forks
+    pub fn insert(&mut self, bank: Bank) -> Result<Arc<Bank>> {
+        if !bank.is_frozen() {
+            return Err(TerminatorError::BankError(format!("bank {} is not frozen", bank.slot())));
+        }
+        if self.banks.contains_key(&bank.slot()) {
+            return Err(TerminatorError::BankError(format!("bank {} already exists", bank.slot())));
+        }
+        match bank.parent_slot() {
+            Some(parent_slot) if self.banks.contains_key(&parent_slot) => {}
+            None if self.banks.is_empty() => self.root = bank.slot(),
+            _ => {
+                return Err(TerminatorError::BankError(format!(
+                    "parent of bank {} is not in the forks",
+                    bank.slot()
+                )))
+            }
+        }
+        let bank = Arc::new(bank);
+        self.banks.insert(bank.slot(), bank.clone());
+        Ok(bank)
+    }
+
+    /// Start a bank for `slot` on top of the bank at `parent_slot`
+    pub fn new_bank_from_parent(&self, parent_slot: u64, slot: u64) -> Result<Bank> {
+        let parent = self
+            .banks
+            .get(&parent_slot)
+            .ok_or_else(|| TerminatorError::BankError(format!("bank {} not found", parent_slot)))?;
+        if slot <= parent_slot || self.banks.contains_key(&slot) {
+            return Err(TerminatorError::BankError(format!(
+                "slot {} cannot be built on {}",
+                slot, parent_slot
+            )));
+        }
+        Ok(Bank::new_from_parent(parent.clone(), slot))
+    }
+
+    /// Root the bank at `slot`, squashing its ancestors into it so they can be freed, and discard
+    /// every other bank that doesn't descend from it. Returns the slots of the discarded forks,
+    /// which don't include the root's ancestors.
+    pub fn set_root(&mut self, slot: u64) -> Result<Vec<u64>> {
+        let root_bank = self
+            .banks
+            .get(&slot)
+            .cloned()
+            .ok_or_else(|| TerminatorError::BankError(format!("bank {} not found", slot)))?;
+        let squashed = Arc::new(root_bank.squash()?);
+
+        let descendants = self.descendants(slot);
+        let removed: Vec<_> = self
+            .banks
+            .keys()
+            .copied()
+            .filter(|slot| !descendants.contains(slot))
+            .collect();
+        let mut discarded = Vec::new();
+        for removed_slot in removed {
+            self.banks.remove(&removed_slot);
+            if !root_bank.is_ancestor_or_self(removed_slot) {
+                discarded.push(removed_slot);
+            }
+        }
+        discarded.sort_unstable();
+        self.banks.insert(slot, squashed);
+        self.root = slot;
+        Ok(discarded)
+    }
+
+    /// Discard the bank at `slot` and every bank built on it. Returns the discarded slots.
+    pub fn remove(&mut self, slot: u64) -> Result<Vec<u64>> {
+        if slot == self.root {
+            return Err(TerminatorError::BankError(format!("cannot discard root bank {}", slot)));
+        }
+        let discarded = self.descendants(slot);
+        for slot in &discarded {
+            self.banks.remove(slot);
+        }
+        Ok(discarded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::*;
+
+    fn freeze(mut bank: Bank, lamports: u64) -> Bank {
+        bank.store_account(Pubkey::new([1u8; 32]), Account::new(lamports, vec![], [0u8; 32]));
+        bank.freeze([bank.slot() as u8; 32]);
+        bank
+    }
+
+    /// 0 - 1 - 2 - 4
+    ///      \
+    ///       3 - 5
+    fn forks() -> BankForks {
+        let mut bank_forks = BankForks::new(freeze(Bank::default(), 100));
+        for (parent, slot) in [(0, 1), (1, 2), (1, 3), (2, 4), (3, 5)] {
+            let bank = bank_forks.new_bank_from_parent(parent, slot).unwrap();
+            bank_forks.insert(freeze(bank, slot * 10)).unwrap();
+        }
+        bank_forks
+    }
+
+    #[test]
+    fn test_forks_see_their_own_state() {
+        let bank_forks = forks();
+        let lamports = |slot| bank_forks.get(slot).unwrap().get_account(&Pubkey::new([1u8; 32])).unwrap().lamports;
+        assert_eq!(lamports(4), 40);
+        assert_eq!(lamports(5), 50);
+        assert_eq!(lamports(1), 10);
+        assert_eq!(bank_forks.leaves().iter().map(|bank| bank.slot()).collect::<Vec<_>>(), vec![4, 5]);
+        assert_eq!(bank_forks.descendants(3), vec![3, 5]);
+        assert_eq!(bank_forks.highest_slot(), 5);
+
+        assert!(bank_forks.new_bank_from_parent(2, 2).is_err());
+        assert!(bank_forks.new_bank_from_parent(6, 7).is_err());
+        let unfrozen = bank_forks.new_bank_from_parent(4, 6).unwrap();
+        assert!(forks().insert(unfrozen).is_err());
+    }
+
+    #[test]
+    fn test_set_root_discards_other_forks() {
+        let mut bank_forks = forks();
+        assert_eq!(bank_forks.set_root(3).unwrap(), vec![2, 4]);
+        assert_eq!(bank_forks.root(), 3);
+        assert_eq!(bank_forks.slots(), vec![3, 5]);
+        assert!(bank_forks.root_bank().unwrap().parent().is_none());
+        let child = bank_forks.get(5).unwrap();
+        assert_eq!(child.get_account(&Pubkey::new([1u8; 32])).unwrap().lamports, 50);
+        assert!(child.is_ancestor_or_self(1));
+
+        assert!(bank_forks.remove(3).is_err());
+        assert_eq!(bank_forks.remove(5).unwrap(), vec![5]);
+        assert_eq!(bank_forks.slots(), vec![3]);
+    }
+}
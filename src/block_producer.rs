@@ -0,0 +1,265 @@
+//! Local block production: a slot clock that executes queued transactions in batches, records
+//! them in the PoH stream and freezes a bank every slot, like a single-node test validator.
+
+use crate::poh::{Entry, PohRecorder};
+use crate::runtime::{SharedRuntime, TerminatorRuntime};
+use crate::types::*;
+use std::future::Future;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+
+/// Transactions waiting for the next slot before senders are turned away
+pub const MAX_QUEUED_TRANSACTIONS: usize = 10_000;
+
+/// Handle for queueing transactions into the next slot
+pub type TransactionSender = mpsc::Sender<Transaction>;
+
+/// A produced slot
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub slot: u64,
+    /// Blockhash of the parent slot, which transactions in this block referenced at the latest
+    pub previous_blockhash: [u8; 32],
+    /// Last PoH hash of the block
+    pub blockhash: [u8; 32],
+    pub bank_hash: [u8; 32],
+    pub entries: Vec<Entry>,
+}
+
+impl Block {
+    /// Transactions recorded in the block, in PoH order
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.entries.iter().flat_map(|entry| &entry.transactions)
+    }
+}
+
+/// Produces a block per slot from the transactions queued through its senders
+pub struct BlockProducer {
+    runtime: SharedRuntime,
+    poh: PohRecorder,
+    settings: BlockProductionSettings,
+    sender: TransactionSender,
+    receiver: mpsc::Receiver<Transaction>,
+}
+
+impl BlockProducer {
+    pub fn new(runtime: TerminatorRuntime, settings: BlockProductionSettings) -> Self {
+        Self::with_shared_runtime(Arc::new(RwLock::new(runtime)), settings)
+    }
+
+    /// Produce blocks on a runtime other services read from and submit to as well
+    pub fn with_shared_runtime(runtime: SharedRuntime, settings: BlockProductionSettings) -> Self {
+        let poh = PohRecorder::new(runtime.read().unwrap().latest_blockhash(), settings.hashes_per_tick);
+        let (sender, receiver) = mpsc::channel(MAX_QUEUED_TRANSACTIONS);
+        Self {
+            runtime,
+            poh,
+            settings,
+            sender,
+            receiver,
+        }
+    }
+
+    /// A sender for queueing transactions, executed when the next slot is produced
+    pub fn sender(&self) -> TransactionSender {
+        self.sender.clone()
+    }
+
+    pub fn shared_runtime(&self) -> SharedRuntime {
+        self.runtime.clone()
+    }
+
+    pub fn runtime(&self) -> RwLockReadGuard<'_, TerminatorRuntime> {
+        self.runtime.read().unwrap()
+    }
+
+    pub fn runtime_mut(&self) -> RwLockWriteGuard<'_, TerminatorRuntime> {
+        self.runtime.write().unwrap()
+    }
+
+    /// Produce the current slot: execute everything queued, tick to the end of the slot, freeze
+    /// the bank and advance to the next slot
+    pub fn produce_slot(&mut self) -> Block {
+        // The runtime is locked while executing and freezing, not while ticking
+        let shared_runtime = self.runtime.clone();
+        let mut runtime = shared_runtime.write().unwrap();
+        let slot = runtime.slot();
+        let previous_blockhash = runtime.latest_blockhash();
+
+        let mut queued = Vec::new();
+        while let Ok(txn) = self.receiver.try_recv() {
+            if runtime.is_blockhash_valid(&txn.recent_blockhash) {
+                queued.push(txn);
+            } else {
+                warn!("Dropping transaction with unknown or expired blockhash");
+            }
+        }
+
+        for batch in queued.chunks(self.settings.max_batch_size.max(1)) {
+            // Transactions rejected before execution leave no trace in the block
+            let processed: Vec<Transaction> = batch
+                .iter()
+                .zip(runtime.execute_batch(batch))
+                .filter_map(|(txn, result)| match result {
+                    Ok(_) => Some(txn.clone()),
+                    Err(e) => {
+                        debug!("Transaction rejected in slot {}: {}", slot, e);
+                        None
+                    }
+                })
+                .collect();
+            if !processed.is_empty() {
+                self.poh.record(processed);
+            }
+        }
+
+        drop(runtime);
+
+        while self.slot_ticks() < self.settings.ticks_per_slot {
+            self.poh.tick();
+        }
+        let entries = self.poh.take_entries();
+        let blockhash = self.poh.hash();
+        let mut runtime = shared_runtime.write().unwrap();
+        let bank_hash = runtime.freeze_slot(blockhash);
+        // A single producer has no competing forks, so every slot is rooted right away
+        if let Err(e) = runtime.set_root(slot) {
+            warn!("Failed to root slot {}: {}", slot, e);
+        }
+        runtime.advance_slot();
+
+        Block {
+            slot,
+            previous_blockhash,
+            blockhash,
+            bank_hash,
+            entries,
+        }
+    }
+
+    /// Ticks produced so far in the current slot
+    fn slot_ticks(&self) -> u64 {
+        self.poh.entries().iter().filter(|entry| entry.is_tick()).count() as u64
+    }
+
+    /// Produce a slot every `slot_duration_ms` until `shutdown` completes
+    pub async fn run(&mut self, shutdown: impl Future<Output = ()>) {
+        let mut slot_clock = tokio::time::interval(Duration::from_millis(self.settings.slot_duration_ms.max(1)));
+        slot_clock.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                _ = slot_clock.tick() => {
+                    let block = self.produce_slot();
+                    info!(
+                        "Produced slot {} with {} transactions, bank hash {}",
+                        block.slot,
+                        block.transactions().count(),
+                        bs58::encode(block.bank_hash).into_string()
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poh::{verify_entries, verify_tick_hash_count};
+    use crate::transaction_error::TransactionError;
+    use crate::TerminatorError;
+
+    fn settings() -> BlockProductionSettings {
+        BlockProductionSettings {
+            slot_duration_ms: 10,
+            ticks_per_slot: 4,
+            hashes_per_tick: 8,
+            max_batch_size: 2,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_produce_slots() {
+        let runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        let mut producer = BlockProducer::new(runtime, settings());
+        let genesis_hash = producer.runtime().latest_blockhash();
+
+        let empty = producer.produce_slot();
+        assert_eq!(empty.slot, 0);
+        assert_eq!(empty.entries.len(), 4);
+        assert!(verify_entries(&empty.entries, &genesis_hash));
+        assert_eq!(producer.runtime().slot(), 1);
+        assert_eq!(producer.runtime().latest_blockhash(), empty.blockhash);
+        assert!(producer.runtime().is_blockhash_valid(&genesis_hash));
+
+        let sender = producer.sender();
+        for from in 1..=3 {
+            sender.try_send(Transaction::transfer_for_test(from, 9, 10, empty.blockhash)).unwrap();
+        }
+        sender.try_send(Transaction::transfer_for_test(4, 9, 10, [7u8; 32])).unwrap();
+        let block = producer.produce_slot();
+
+        // Three transactions in two batches, the one with an unknown blockhash dropped
+        assert_eq!(block.slot, 1);
+        assert_eq!(block.previous_blockhash, empty.blockhash);
+        assert_eq!(block.transactions().count(), 3);
+        assert_eq!(block.entries.iter().filter(|entry| !entry.is_tick()).count(), 2);
+        assert_eq!(block.entries.iter().filter(|entry| entry.is_tick()).count(), 4);
+        assert!(verify_entries(&block.entries, &empty.blockhash));
+        assert!(verify_tick_hash_count(&block.entries, 8));
+        assert_eq!(producer.runtime().get_account(&Pubkey::new([9u8; 32])).unwrap().lamports, 30);
+        assert!(producer.runtime().get_account(&Pubkey::new([4u8; 32])).is_none());
+        assert_ne!(block.bank_hash, empty.bank_hash);
+        assert_eq!(producer.runtime().bank_hash(), block.bank_hash);
+    }
+
+    #[tokio::test]
+    async fn test_bank_hash_is_deterministic() {
+        let mut bank_hashes = Vec::new();
+        for _ in 0..2 {
+            let runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+            let mut producer = BlockProducer::new(runtime, settings());
+            let blockhash = producer.runtime().latest_blockhash();
+            producer.sender().try_send(Transaction::transfer_for_test(1, 2, 10, blockhash)).unwrap();
+            let block = producer.produce_slot();
+            assert_eq!(block.transactions().count(), 1);
+            bank_hashes.push(block.bank_hash);
+        }
+        assert_eq!(bank_hashes[0], bank_hashes[1]);
+    }
+
+    #[tokio::test]
+    async fn test_replay_rejected_across_slots() {
+        let runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        let mut producer = BlockProducer::new(runtime, settings());
+        let txn = Transaction::transfer_for_test(1, 2, 10, producer.runtime().latest_blockhash());
+        producer.sender().try_send(txn.clone()).unwrap();
+        assert_eq!(producer.produce_slot().transactions().count(), 1);
+
+        // Rooting every produced slot must not forget what was already processed
+        for _ in 0..3 {
+            producer.sender().try_send(txn.clone()).unwrap();
+            assert_eq!(producer.produce_slot().transactions().count(), 0);
+            assert!(matches!(
+                producer.runtime_mut().execute_transaction(&txn),
+                Err(TerminatorError::Transaction(TransactionError::AlreadyProcessed))
+            ));
+        }
+        assert!(producer.runtime().get_transaction(&txn.signatures[0]).is_some());
+        assert_eq!(producer.runtime().get_account(&Pubkey::new([2u8; 32])).unwrap().lamports, 10);
+    }
+
+    #[tokio::test]
+    async fn test_run_until_shutdown() {
+        let runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        let mut producer = BlockProducer::new(runtime, settings());
+        producer.run(tokio::time::sleep(Duration::from_millis(35))).await;
+        assert!(producer.runtime().slot() >= 2);
+    }
+}
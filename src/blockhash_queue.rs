@@ -0,0 +1,114 @@
+//! Recent blockhashes that transactions may reference, with the fee rate in effect for each.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Number of recent blockhashes kept, matching Solana's `MAX_RECENT_BLOCKHASHES`
+pub const MAX_RECENT_BLOCKHASHES: usize = 300;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockhashInfo {
+    /// Position of the hash in the order it was registered
+    pub hash_index: u64,
+    pub lamports_per_signature: u64,
+}
+
+/// Blockhashes registered at the end of each slot, oldest evicted first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockhashQueue {
+    hashes: HashMap<[u8; 32], BlockhashInfo>,
+    last_hash: Option<[u8; 32]>,
+    last_hash_index: u64,
+    max_age: usize,
+}
+
+impl BlockhashQueue {
+    pub fn new(max_age: usize) -> Self {
+        Self {
+            hashes: HashMap::new(),
+            last_hash: None,
+            last_hash_index: 0,
+            max_age,
+        }
+    }
+
+    /// Most recently registered blockhash
+    pub fn last_hash(&self) -> Option<[u8; 32]> {
+        self.last_hash
+    }
+
+    /// Register the blockhash of a finished slot, evicting hashes older than `max_age`
+    pub fn register_hash(&mut self, hash: [u8; 32], lamports_per_signature: u64) {
+        self.last_hash_index += 1;
+        let max_age = self.max_age as u64;
+        let last_hash_index = self.last_hash_index;
+        self.hashes
+            .retain(|_, info| last_hash_index - info.hash_index <= max_age);
+        self.hashes.insert(
+            hash,
+            BlockhashInfo {
+                hash_index: self.last_hash_index,
+                lamports_per_signature,
+            },
+        );
+        self.last_hash = Some(hash);
+    }
+
+    /// Number of blockhashes registered after `hash`, if it's still in the queue
+    pub fn get_hash_age(&self, hash: &[u8; 32]) -> Option<u64> {
+        self.hashes
+            .get(hash)
+            .map(|info| self.last_hash_index - info.hash_index)
+    }
+
+    /// Whether transactions referencing `hash` are still accepted
+    pub fn is_hash_valid(&self, hash: &[u8; 32]) -> bool {
+        self.hashes.contains_key(hash)
+    }
+
+    pub fn get_lamports_per_signature(&self, hash: &[u8; 32]) -> Option<u64> {
+        self.hashes.get(hash).map(|info| info.lamports_per_signature)
+    }
+
+    /// Slots a blockhash stays valid after it was registered
+    pub fn max_age(&self) -> usize {
+        self.max_age
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+}
+
+impl Default for BlockhashQueue {
+    fn default() -> Self {
+        Self::new(MAX_RECENT_BLOCKHASHES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blockhashes_expire() {
+        let mut queue = BlockhashQueue::new(2);
+        assert_eq!(queue.last_hash(), None);
+        for i in 0..3u8 {
+            queue.register_hash([i; 32], 5000);
+        }
+        assert_eq!(queue.last_hash(), Some([2u8; 32]));
+        assert_eq!(queue.get_hash_age(&[0u8; 32]), Some(2));
+        assert_eq!(queue.get_lamports_per_signature(&[1u8; 32]), Some(5000));
+
+        queue.register_hash([3u8; 32], 10_000);
+        assert!(!queue.is_hash_valid(&[0u8; 32]));
+        assert!(queue.is_hash_valid(&[1u8; 32]));
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.get_lamports_per_signature(&[3u8; 32]), Some(10_000));
+    }
+}
@@ -0,0 +1,343 @@
+use crate::invoke_context::{InstructionProcessor, InvokeContext};
+use crate::sbpf::{EbpfError, EbpfVm, Executable};
+use crate::syscalls::create_syscall_registry;
+use crate::sysvar::SysvarCache;
+use crate::transaction_context::TransactionContext;
+use crate::transaction_error::InstructionError;
+use crate::types::*;
+
+/// Bytes a program may grow an account's data by during one instruction
+pub const MAX_PERMITTED_DATA_INCREASE: usize = 10 * 1024;
+/// Alignment of account data in the aligned input format
+const BPF_ALIGN_OF_U128: usize = 8;
+/// Marker preceding an account that has not been serialized earlier in the input
+const NON_DUP_MARKER: u8 = u8::MAX;
+
+type InstructionResult = std::result::Result<(), InstructionError>;
+
+/// Whether `owner` is one of the loaders that execute sBPF programs
+pub fn is_bpf_loader(owner: &Pubkey) -> bool {
+    *owner == Pubkey::bpf_loader()
+        || *owner == Pubkey::bpf_loader_deprecated()
+        || *owner == Pubkey::bpf_loader_upgradeable()
+}
+
+/// Where an instruction account ended up in the serialized input
+#[derive(Debug, Clone, Copy)]
+pub struct SerializedAccount {
+    pub pubkey: Pubkey,
+    pub is_writable: bool,
+    /// `None` if the account repeats an earlier one
+    pub offset: Option<usize>,
+    pub original_data_len: usize,
+}
+
+/// Bytes handed to a program as its instruction data
+pub fn instruction_data(data: &InstructionData) -> Vec<u8> {
+    match data {
+        InstructionData::Generic { data } => data.clone(),
+        other => bincode::serialize(other).unwrap_or_default(),
+    }
+}
+
+/// Serialize the instruction's accounts and data into the program input region
+pub fn serialize_parameters(
+    loader_id: &Pubkey,
+    program_id: &Pubkey,
+    instruction: &Instruction,
+    transaction_context: &TransactionContext,
+) -> std::result::Result<(Vec<u8>, Vec<SerializedAccount>), InstructionError> {
+    let aligned = *loader_id != Pubkey::bpf_loader_deprecated();
+    let mut input = Vec::new();
+    let mut serialized = Vec::with_capacity(instruction.accounts.len());
+
+    input.extend_from_slice(&(instruction.accounts.len() as u64).to_le_bytes());
+    for (index, meta) in instruction.accounts.iter().enumerate() {
+        let account = transaction_context
+            .get(&meta.pubkey)
+            .ok_or(InstructionError::MissingAccount)?;
+
+        let duplicate = instruction.accounts[..index]
+            .iter()
+            .position(|earlier| earlier.pubkey == meta.pubkey);
+        if let Some(position) = duplicate {
+            input.push(position as u8);
+            if aligned {
+                input.extend_from_slice(&[0u8; 7]);
+            }
+            serialized.push(SerializedAccount {
+                pubkey: meta.pubkey,
+                is_writable: meta.is_writable,
+                offset: None,
+                original_data_len: account.data.len(),
+            });
+            continue;
+        }
+
+        input.push(NON_DUP_MARKER);
+        let offset = input.len();
+        input.push(meta.is_signer as u8);
+        input.push(meta.is_writable as u8);
+        if aligned {
+            input.push(account.executable as u8);
+            input.extend_from_slice(&[0u8; 4]);
+            input.extend_from_slice(&meta.pubkey.0);
+            input.extend_from_slice(&account.owner);
+            input.extend_from_slice(&account.lamports.to_le_bytes());
+            input.extend_from_slice(&(account.data.len() as u64).to_le_bytes());
+            input.extend_from_slice(&account.data);
+            input.resize(input.len() + MAX_PERMITTED_DATA_INCREASE + alignment_padding(account.data.len()), 0);
+            input.extend_from_slice(&account.rent_epoch.to_le_bytes());
+        } else {
+            input.extend_from_slice(&meta.pubkey.0);
+            input.extend_from_slice(&account.lamports.to_le_bytes());
+            input.extend_from_slice(&(account.data.len() as u64).to_le_bytes());
+            input.extend_from_slice(&account.data);
+            input.extend_from_slice(&account.owner);
+            input.push(account.executable as u8);
+            input.extend_from_slice(&account.rent_epoch.to_le_bytes());
+        }
+
+        serialized.push(SerializedAccount {
+            pubkey: meta.pubkey,
+            is_writable: meta.is_writable,
+            offset: Some(offset),
+            original_data_len: account.data.len(),
+        });
+    }
+
+    let data = instruction_data(&instruction.data);
+    input.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    input.extend_from_slice(&data);
+    input.extend_from_slice(&program_id.0);
+
+    Ok((input, serialized))
+}
+
+/// Copy the program's view of its accounts back into the transaction.
+///
+/// Read-only accounts are copied too, so post-instruction verification rejects changes to them.
+pub fn deserialize_parameters(
+    loader_id: &Pubkey,
+    input: &[u8],
+    serialized: &[SerializedAccount],
+    transaction_context: &mut TransactionContext,
+) -> InstructionResult {
+    let aligned = *loader_id != Pubkey::bpf_loader_deprecated();
+
+    for account in serialized {
+        let Some(offset) = account.offset else {
+            continue;
+        };
+
+        let read_u64 = |at: usize| -> std::result::Result<u64, InstructionError> {
+            input
+                .get(at..at + 8)
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+                .ok_or(InstructionError::InvalidArgument)
+        };
+
+        let target = transaction_context
+            .get_mut(&account.pubkey)
+            .ok_or(InstructionError::MissingAccount)?;
+
+        if aligned {
+            // signer, writable, executable, padding, pubkey
+            let owner_start = offset + 3 + 4 + 32;
+            let lamports_start = owner_start + 32;
+            let data_start = lamports_start + 16;
+
+            let post_len = read_u64(lamports_start + 8)? as usize;
+            if post_len > account.original_data_len + MAX_PERMITTED_DATA_INCREASE {
+                return Err(InstructionError::InvalidRealloc);
+            }
+            let owner = input
+                .get(owner_start..owner_start + 32)
+                .ok_or(InstructionError::InvalidArgument)?;
+            let data = input
+                .get(data_start..data_start + post_len)
+                .ok_or(InstructionError::InvalidArgument)?;
+
+            target.lamports = read_u64(lamports_start)?;
+            target.owner.copy_from_slice(owner);
+            target.data = data.to_vec();
+        } else {
+            // signer, writable, pubkey
+            let lamports_start = offset + 2 + 32;
+            let data_start = lamports_start + 16;
+            let data = input
+                .get(data_start..data_start + account.original_data_len)
+                .ok_or(InstructionError::InvalidArgument)?;
+
+            target.lamports = read_u64(lamports_start)?;
+            target.data.copy_from_slice(data);
+        }
+    }
+
+    Ok(())
+}
+
+fn alignment_padding(data_len: usize) -> usize {
+    (BPF_ALIGN_OF_U128 - data_len % BPF_ALIGN_OF_U128) % BPF_ALIGN_OF_U128
+}
+
+/// Run a loaded sBPF program against the instruction's accounts.
+///
+/// `executable` is `None` if the program account holds no loadable program.
+pub fn execute_program(
+    instruction: &Instruction,
+    executable: Option<&Executable>,
+    transaction_context: &mut TransactionContext,
+    execution_context: &mut ExecutionContext,
+    sysvars: &SysvarCache,
+    processor: &dyn InstructionProcessor,
+) -> InstructionResult {
+    let program_id = instruction.program_id;
+    let program_account = transaction_context
+        .get(&program_id)
+        .ok_or(InstructionError::MissingAccount)?;
+    if !program_account.executable {
+        return Err(InstructionError::AccountNotExecutable);
+    }
+    let loader_id = Pubkey::new(program_account.owner);
+
+    execution_context.log(format!(
+        "Program {} invoke [{}]",
+        program_id,
+        transaction_context.instruction_stack_height()
+    ));
+    let Some(executable) = executable else {
+        execution_context.log("Program is not deployed".to_string());
+        return Err(InstructionError::UnsupportedProgramId);
+    };
+    let syscalls = create_syscall_registry();
+
+    let (input, serialized) = serialize_parameters(&loader_id, &program_id, instruction, transaction_context)?;
+    let budget_before = execution_context.compute_units_remaining;
+
+    let mut invoke_context = InvokeContext::new(transaction_context, execution_context, sysvars, processor, instruction);
+    invoke_context.serialized_accounts = serialized.clone();
+    let (result, output) = {
+        let mut vm = EbpfVm::new(executable, &syscalls, &mut invoke_context, input);
+        let result = vm.execute();
+        (result, vm.memory.into_input())
+    };
+
+    let consumed = budget_before - invoke_context.execution_context.compute_units_remaining;
+    invoke_context.log(format!(
+        "Program {} consumed {} of {} compute units",
+        program_id, consumed, budget_before
+    ));
+
+    let result = match result {
+        Ok(0) => deserialize_parameters(&loader_id, &output, &serialized, invoke_context.transaction_context),
+        Ok(code) => Err(InstructionError::from(code)),
+        Err(EbpfError::ExceededMaxInstructions(_)) => Err(InstructionError::ComputationalBudgetExceeded),
+        Err(EbpfError::InstructionError(error)) => Err(error),
+        Err(error) => {
+            invoke_context.log(format!("Program {} failed: {}", program_id, error));
+            return Err(InstructionError::ProgramFailedToComplete);
+        }
+    };
+
+    match &result {
+        Ok(()) => invoke_context.log(format!("Program {} success", program_id)),
+        Err(error) => invoke_context.log(format!("Program {} failed: {}", program_id, error)),
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(pubkey: Pubkey, is_writable: bool) -> AccountMeta {
+        AccountMeta { pubkey, is_signer: false, is_writable }
+    }
+
+    #[test]
+    fn test_aligned_serialization_round_trip() {
+        let program_id = Pubkey::new([1u8; 32]);
+        let first = Pubkey::new([2u8; 32]);
+        let second = Pubkey::new([3u8; 32]);
+        let mut transaction_context = TransactionContext::new(
+            vec![first, second, program_id],
+            vec![
+                Account::new(100, vec![1, 2, 3], program_id.0),
+                Account::new(200, vec![], program_id.0),
+                Account::new_executable(1, vec![], Pubkey::bpf_loader().0),
+            ],
+        );
+        let instruction = Instruction {
+            program_id,
+            accounts: vec![meta(first, true), meta(second, false), meta(first, true)],
+            data: InstructionData::Generic { data: vec![9, 9] },
+        };
+
+        let (mut input, serialized) =
+            serialize_parameters(&Pubkey::bpf_loader(), &program_id, &instruction, &transaction_context).unwrap();
+
+        assert_eq!(u64::from_le_bytes(input[0..8].try_into().unwrap()), 3);
+        // The first account's data always starts at a fixed offset
+        assert_eq!(input[96..99], [1, 2, 3]);
+        assert_eq!(serialized[2].offset, None);
+        assert_eq!(input[input.len() - 32..], program_id.0);
+        assert_eq!(input[input.len() - 34..input.len() - 32], [9, 9]);
+
+        // Grow the first account's data and change both accounts' lamports
+        let first_offset = serialized[0].offset.unwrap();
+        input[first_offset + 71..first_offset + 79].copy_from_slice(&50u64.to_le_bytes());
+        input[first_offset + 79..first_offset + 87].copy_from_slice(&4u64.to_le_bytes());
+        input[99] = 4;
+        let second_offset = serialized[1].offset.unwrap();
+        input[second_offset + 71..second_offset + 79].copy_from_slice(&0u64.to_le_bytes());
+
+        deserialize_parameters(&Pubkey::bpf_loader(), &input, &serialized, &mut transaction_context).unwrap();
+        let first_account = transaction_context.get(&first).unwrap();
+        assert_eq!(first_account.lamports, 50);
+        assert_eq!(first_account.data, vec![1, 2, 3, 4]);
+        // Readonly accounts are written back too, for verification to reject
+        assert_eq!(transaction_context.get(&second).unwrap().lamports, 0);
+
+        input[first_offset + 79..first_offset + 87]
+            .copy_from_slice(&((3 + MAX_PERMITTED_DATA_INCREASE + 1) as u64).to_le_bytes());
+        assert_eq!(
+            deserialize_parameters(&Pubkey::bpf_loader(), &input, &serialized, &mut transaction_context),
+            Err(InstructionError::InvalidRealloc)
+        );
+    }
+
+    #[test]
+    fn test_unaligned_serialization_layout() {
+        let program_id = Pubkey::new([1u8; 32]);
+        let account = Pubkey::new([2u8; 32]);
+        let transaction_context = TransactionContext::new(
+            vec![account, program_id],
+            vec![
+                Account::new(100, vec![7, 8], program_id.0),
+                Account::new_executable(1, vec![], Pubkey::bpf_loader_deprecated().0),
+            ],
+        );
+        let instruction = Instruction {
+            program_id,
+            accounts: vec![meta(account, true), meta(account, true)],
+            data: InstructionData::Generic { data: vec![] },
+        };
+
+        let (input, serialized) = serialize_parameters(
+            &Pubkey::bpf_loader_deprecated(),
+            &program_id,
+            &instruction,
+            &transaction_context,
+        )
+        .unwrap();
+
+        // count, marker, signer, writable, pubkey, lamports, data_len
+        assert_eq!(input[8 + 3 + 32 + 16..8 + 3 + 32 + 18], [7, 8]);
+        // The duplicate is a single index byte
+        let duplicate_at = 8 + 3 + 32 + 16 + 2 + 32 + 1 + 8;
+        assert_eq!(input[duplicate_at], 0);
+        assert_eq!(input.len(), duplicate_at + 1 + 8 + 32);
+        assert_eq!(serialized.len(), 2);
+    }
+}
@@ -0,0 +1,439 @@
+//! Native programs implemented in Rust, dispatched by program id instead of running in the VM.
+
+use crate::invoke_context::InvokeContext;
+use crate::transaction_context::TransactionContext;
+use crate::transaction_error::InstructionError;
+use crate::types::*;
+use crate::upgradeable_loader;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// System program error code for creating an account that already holds lamports, data or an owner
+const SYSTEM_ERROR_ACCOUNT_ALREADY_IN_USE: u32 = 0;
+/// System program error code for a debit that would leave an account negative
+const SYSTEM_ERROR_RESULT_WITH_NEGATIVE_LAMPORTS: u32 = 1;
+
+/// Compute units charged per invocation, matching Solana's builtin costs
+pub const SYSTEM_PROGRAM_COMPUTE_UNITS: u64 = 150;
+pub const TOKEN_PROGRAM_COMPUTE_UNITS: u64 = 150;
+pub const UPGRADEABLE_LOADER_COMPUTE_UNITS: u64 = 2_370;
+
+type InstructionResult = std::result::Result<(), InstructionError>;
+
+/// A program whose instructions are processed by native code
+pub trait BuiltinProgram: Send + Sync {
+    fn process_instruction(&self, invoke_context: &mut InvokeContext) -> InstructionResult;
+}
+
+impl<F> BuiltinProgram for F
+where
+    F: Fn(&mut InvokeContext) -> InstructionResult + Send + Sync,
+{
+    fn process_instruction(&self, invoke_context: &mut InvokeContext) -> InstructionResult {
+        self(invoke_context)
+    }
+}
+
+/// A registered builtin and what it costs to invoke
+#[derive(Clone)]
+pub struct BuiltinEntry {
+    pub name: String,
+    pub compute_units: u64,
+    pub program: Arc<dyn BuiltinProgram>,
+}
+
+impl fmt::Debug for BuiltinEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BuiltinEntry")
+            .field("name", &self.name)
+            .field("compute_units", &self.compute_units)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Builtin programs by program id
+#[derive(Debug, Clone)]
+pub struct BuiltinRegistry {
+    programs: HashMap<Pubkey, BuiltinEntry>,
+}
+
+impl BuiltinRegistry {
+    /// A registry without any programs
+    pub fn empty() -> Self {
+        Self { programs: HashMap::new() }
+    }
+
+    /// Register `program` under `program_id`, returning the builtin it replaces
+    pub fn register(
+        &mut self,
+        program_id: Pubkey,
+        name: &str,
+        compute_units: u64,
+        program: impl BuiltinProgram + 'static,
+    ) -> Option<BuiltinEntry> {
+        let entry = BuiltinEntry {
+            name: name.to_string(),
+            compute_units,
+            program: Arc::new(program),
+        };
+        self.programs.insert(program_id, entry)
+    }
+
+    pub fn get(&self, program_id: &Pubkey) -> Option<&BuiltinEntry> {
+        self.programs.get(program_id)
+    }
+
+    pub fn contains(&self, program_id: &Pubkey) -> bool {
+        self.programs.contains_key(program_id)
+    }
+
+    pub fn program_ids(&self) -> impl Iterator<Item = &Pubkey> {
+        self.programs.keys()
+    }
+}
+
+/// The system program, token program and upgradeable loader
+impl Default for BuiltinRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(Pubkey::system_program(), "system_program", SYSTEM_PROGRAM_COMPUTE_UNITS, SystemProgram);
+        registry.register(Pubkey::token_program(), "spl_token", TOKEN_PROGRAM_COMPUTE_UNITS, TokenProgram);
+        registry.register(
+            Pubkey::bpf_loader_upgradeable(),
+            "bpf_loader_upgradeable",
+            UPGRADEABLE_LOADER_COMPUTE_UNITS,
+            upgradeable_loader::process_instruction,
+        );
+        registry
+    }
+}
+
+/// Transfers, account creation and assignment
+pub struct SystemProgram;
+
+impl BuiltinProgram for SystemProgram {
+    fn process_instruction(&self, invoke_context: &mut InvokeContext) -> InstructionResult {
+        let instruction = invoke_context.instruction();
+        handle_system_instruction(instruction, invoke_context.transaction_context, invoke_context.execution_context)
+    }
+}
+
+/// Accepts token instructions without modelling token state
+pub struct TokenProgram;
+
+impl BuiltinProgram for TokenProgram {
+    fn process_instruction(&self, invoke_context: &mut InvokeContext) -> InstructionResult {
+        invoke_context.log("Processing token program instruction".to_string());
+        // Simplified token instruction handling
+        Ok(())
+    }
+}
+
+fn handle_system_instruction(instruction: &Instruction, accounts: &mut TransactionContext, context: &mut ExecutionContext) -> InstructionResult {
+    context.log("Processing system program instruction".to_string());
+    
+    // Handle based on InstructionData
+    match &instruction.data {
+        InstructionData::Transfer { from, to, lamports } => {
+            handle_transfer_instruction(*from, *to, *lamports, accounts, context)
+        }
+        InstructionData::CreateAccount { from, to, lamports, space, owner } => {
+            handle_create_account_instruction(*from, *to, *lamports, *space, *owner, accounts, context)
+        }
+        InstructionData::Assign { account, owner } => {
+            handle_assign_instruction(*account, *owner, accounts, context)
+        }
+        InstructionData::Generic { data } => {
+            // Legacy handling for generic data
+            if data.is_empty() {
+                return Ok(());
+            }
+            
+            match data[0] {
+                0 => handle_create_account(instruction, accounts, context),
+                1 => handle_assign(instruction, context),
+                2 => handle_transfer(instruction, accounts, context),
+                _ => {
+                    context.log(format!("Unknown system instruction: {}", data[0]));
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+fn handle_transfer_instruction(from: [u8; 32], to: [u8; 32], lamports: u64, accounts: &mut TransactionContext, context: &mut ExecutionContext) -> InstructionResult {
+    let from_key = Pubkey::new(from);
+    let to_key = Pubkey::new(to);
+    
+    context.log(format!("Transferring {} lamports from {:?} to {:?}", lamports, from_key, to_key));
+    
+    require_signer(accounts, &from_key, context)?;
+    debit(accounts, &from_key, lamports)?;
+    
+    credit(accounts, &to_key, lamports)?;
+    
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_create_account_instruction(from: [u8; 32], to: [u8; 32], lamports: u64, space: u64, owner: [u8; 32], accounts: &mut TransactionContext, context: &mut ExecutionContext) -> InstructionResult {
+    let to_key = Pubkey::new(to);
+    
+    context.log(format!("Creating account {:?} with {} lamports and {} bytes", to_key, lamports, space));
+    
+    // Create new account, funded by the from account
+    require_signer(accounts, &Pubkey::new(from), context)?;
+    let to_account = accounts.get(&to_key)
+        .ok_or(InstructionError::MissingAccount)?;
+    if to_account.lamports > 0 || !to_account.data.is_empty() || to_account.owner != Pubkey::system_program().0 {
+        context.log(format!("Create account: account {:?} already in use", to_key));
+        return Err(InstructionError::Custom(SYSTEM_ERROR_ACCOUNT_ALREADY_IN_USE));
+    }
+    if space > upgradeable_loader::MAX_PERMITTED_DATA_LENGTH as u64 {
+        context.log(format!("Create account: requested {} bytes, the maximum is {}", space, upgradeable_loader::MAX_PERMITTED_DATA_LENGTH));
+        return Err(InstructionError::InvalidRealloc);
+    }
+    debit(accounts, &Pubkey::new(from), lamports)?;
+    credit(accounts, &to_key, lamports)?;
+    let to_account = accounts.get_mut(&to_key)
+        .ok_or(InstructionError::MissingAccount)?;
+    to_account.data = vec![0u8; space as usize];
+    to_account.owner = owner;
+    
+    Ok(())
+}
+
+fn handle_assign_instruction(account: [u8; 32], owner: [u8; 32], accounts: &mut TransactionContext, context: &mut ExecutionContext) -> InstructionResult {
+    let account_key = Pubkey::new(account);
+    
+    context.log(format!("Assigning account {:?} to owner {:?}", account_key, owner));
+    
+    require_signer(accounts, &account_key, context)?;
+    if let Some(acc) = accounts.get_mut(&account_key) {
+        acc.owner = owner;
+    }
+    
+    Ok(())
+}
+
+fn handle_create_account(instruction: &Instruction, accounts: &mut TransactionContext, context: &mut ExecutionContext) -> InstructionResult {
+    if instruction.accounts.len() < 2 {
+        return Err(InstructionError::NotEnoughAccountKeys);
+    }
+    
+    let from = &instruction.accounts[0];
+    let to = &instruction.accounts[1];
+    
+    context.log(format!("Creating account: {:?}", to.pubkey));
+    
+    // Create new account with minimal lamports
+    require_signer(accounts, &from.pubkey, context)?;
+    debit(accounts, &from.pubkey, 1_000_000)?;
+    credit(accounts, &to.pubkey, 1_000_000)?;
+    
+    Ok(())
+}
+
+fn handle_assign(_instruction: &Instruction, context: &mut ExecutionContext) -> InstructionResult {
+    context.log("Handling assign instruction".to_string());
+    // Simplified assign implementation
+    Ok(())
+}
+
+fn handle_transfer(instruction: &Instruction, accounts: &mut TransactionContext, context: &mut ExecutionContext) -> InstructionResult {
+    if instruction.accounts.len() < 2 {
+        return Err(InstructionError::NotEnoughAccountKeys);
+    }
+    
+    let from_key = instruction.accounts[0].pubkey;
+    let to_key = instruction.accounts[1].pubkey;
+    
+    // Parse lamports from instruction data (simplified)
+    let lamports = if let InstructionData::Generic { data } = &instruction.data {
+        if data.len() >= 9 {
+            u64::from_le_bytes([
+                data[1], data[2], data[3], data[4],
+                data[5], data[6], data[7], data[8],
+            ])
+        } else {
+            1000000 // Default transfer amount
+        }
+    } else {
+        1000000
+    };
+    
+    context.log(format!("Transferring {} lamports from {:?} to {:?}", lamports, from_key, to_key));
+    
+    require_signer(accounts, &from_key, context)?;
+    debit(accounts, &from_key, lamports)?;
+    
+    credit(accounts, &to_key, lamports)?;
+    
+    Ok(())
+}
+
+
+/// Only an account's own signature lets the system program spend or reassign it
+fn require_signer(accounts: &TransactionContext, pubkey: &Pubkey, context: &mut ExecutionContext) -> InstructionResult {
+    if !accounts.is_signer(pubkey) {
+        context.log(format!("Account {:?} must sign the instruction", pubkey));
+        return Err(InstructionError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+
+fn debit(accounts: &mut TransactionContext, pubkey: &Pubkey, lamports: u64) -> InstructionResult {
+    let account = accounts.get_mut(pubkey)
+        .ok_or(InstructionError::MissingAccount)?;
+    if account.lamports < lamports {
+        return Err(InstructionError::Custom(SYSTEM_ERROR_RESULT_WITH_NEGATIVE_LAMPORTS));
+    }
+    account.lamports -= lamports;
+    Ok(())
+}
+
+fn credit(accounts: &mut TransactionContext, pubkey: &Pubkey, lamports: u64) -> InstructionResult {
+    let account = accounts.get_mut(pubkey)
+        .ok_or(InstructionError::MissingAccount)?;
+    account.lamports = account.lamports
+        .checked_add(lamports)
+        .ok_or(InstructionError::ArithmeticOverflow)?;
+    Ok(())
+}
+
+/// For demo purposes, fund the source of a top-level transfer or account creation that doesn't
+/// exist yet with sufficient balance. This happens before any instruction runs so the system
+/// program's instructions still balance.
+pub(crate) fn fund_demo_accounts(txn: &Transaction, accounts: &mut TransactionContext, context: &mut ExecutionContext) {
+    for instruction in txn.instructions.iter().filter(|ix| ix.program_id == Pubkey::system_program()) {
+        let (from, lamports) = match &instruction.data {
+            InstructionData::Transfer { from, lamports, .. }
+            | InstructionData::CreateAccount { from, lamports, .. } => (Pubkey::new(*from), *lamports),
+            _ => continue,
+        };
+        if let Some(from_account) = accounts.get_mut(&from) {
+            if from_account.lamports == 0 && from_account.data.is_empty() {
+                context.log("Creating from account with initial balance for demo".to_string());
+                from_account.lamports = std::cmp::max(lamports.saturating_mul(2), 10_000_000); // Ensure sufficient balance
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sysvar::{EpochSchedule, Rent, SysvarCache};
+
+    #[test]
+    fn test_default_registry() {
+        let registry = BuiltinRegistry::default();
+        assert_eq!(registry.get(&Pubkey::system_program()).unwrap().compute_units, SYSTEM_PROGRAM_COMPUTE_UNITS);
+        assert!(registry.contains(&Pubkey::token_program()));
+        assert!(registry.contains(&Pubkey::bpf_loader_upgradeable()));
+        assert!(!registry.contains(&Pubkey::bpf_loader()));
+        assert_eq!(registry.program_ids().count(), 3);
+
+        let mut registry = registry;
+        let mock = |_: &mut InvokeContext| Ok(());
+        let replaced = registry.register(Pubkey::system_program(), "mock", 7, mock).unwrap();
+        assert_eq!(replaced.name, "system_program");
+        assert_eq!(registry.get(&Pubkey::system_program()).unwrap().compute_units, 7);
+    }
+
+    #[test]
+    fn test_system_program_transfer() {
+        let from = Pubkey::new([1u8; 32]);
+        let to = Pubkey::new([2u8; 32]);
+        let unsigned = TransactionContext::new(
+            vec![from, to],
+            vec![Account::new(500, vec![], [0u8; 32]), Account::new(0, vec![], [0u8; 32])],
+        );
+        let mut transaction_context = unsigned.clone().with_signers(vec![from]);
+        let mut execution_context = ExecutionContext::new(200_000);
+        let sysvars = SysvarCache::new(0, Rent::default(), EpochSchedule::default());
+        let processor = |_: &Instruction, _: &mut TransactionContext, _: &mut ExecutionContext| Ok(());
+        let mut transfer = |transaction_context: &mut TransactionContext, lamports: u64| {
+            let instruction = Instruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![],
+                data: InstructionData::Transfer { from: from.0, to: to.0, lamports },
+            };
+            transaction_context.push_instruction(&instruction).unwrap();
+            let mut invoke_context =
+                InvokeContext::new(transaction_context, &mut execution_context, &sysvars, &processor, &instruction);
+            let result = SystemProgram.process_instruction(&mut invoke_context);
+            transaction_context.pop_instruction();
+            result
+        };
+
+        assert_eq!(transfer(&mut transaction_context, 200), Ok(()));
+        assert_eq!(transaction_context.get(&from).unwrap().lamports, 300);
+        assert_eq!(transaction_context.get(&to).unwrap().lamports, 200);
+        assert_eq!(
+            transfer(&mut transaction_context, 301),
+            Err(InstructionError::Custom(SYSTEM_ERROR_RESULT_WITH_NEGATIVE_LAMPORTS))
+        );
+        // Without the sender's signature nothing moves
+        assert_eq!(transfer(&mut unsigned.clone(), 200), Err(InstructionError::MissingRequiredSignature));
+
+        // A credit past u64::MAX fails rather than wrapping
+        let mut full = transaction_context.clone();
+        full.get_mut(&to).unwrap().lamports = u64::MAX;
+        assert_eq!(transfer(&mut full, 1), Err(InstructionError::ArithmeticOverflow));
+    }
+
+    #[test]
+    fn test_system_program_create_account() {
+        let from = Pubkey::new([1u8; 32]);
+        let new = Pubkey::new([2u8; 32]);
+        let existing = Pubkey::new([3u8; 32]);
+        let owner = Pubkey::new([4u8; 32]);
+        let mut transaction_context = TransactionContext::new(
+            vec![from, new, existing],
+            vec![
+                Account::new(10_000, vec![], Pubkey::system_program().0),
+                Account::new(0, vec![], Pubkey::system_program().0),
+                Account::new(1, vec![], Pubkey::system_program().0),
+            ],
+        )
+        .with_signers(vec![from]);
+        let mut execution_context = ExecutionContext::new(200_000);
+        let sysvars = SysvarCache::new(0, Rent::default(), EpochSchedule::default());
+        let processor = |_: &Instruction, _: &mut TransactionContext, _: &mut ExecutionContext| Ok(());
+        let mut create = |transaction_context: &mut TransactionContext, to: Pubkey, space: u64| {
+            let instruction = Instruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![],
+                data: InstructionData::CreateAccount { from: from.0, to: to.0, lamports: 1_000, space, owner: owner.0 },
+            };
+            transaction_context.push_instruction(&instruction).unwrap();
+            let mut invoke_context =
+                InvokeContext::new(transaction_context, &mut execution_context, &sysvars, &processor, &instruction);
+            let result = SystemProgram.process_instruction(&mut invoke_context);
+            transaction_context.pop_instruction();
+            result
+        };
+
+        assert_eq!(
+            create(&mut transaction_context, new, upgradeable_loader::MAX_PERMITTED_DATA_LENGTH as u64 + 1),
+            Err(InstructionError::InvalidRealloc)
+        );
+        assert_eq!(
+            create(&mut transaction_context, existing, 8),
+            Err(InstructionError::Custom(SYSTEM_ERROR_ACCOUNT_ALREADY_IN_USE))
+        );
+        assert_eq!(transaction_context.get(&from).unwrap().lamports, 10_000);
+
+        assert_eq!(create(&mut transaction_context, new, 8), Ok(()));
+        let created = transaction_context.get(&new).unwrap();
+        assert_eq!((created.lamports, created.data.len(), created.owner), (1_000, 8, owner.0));
+        // Creating it a second time would wipe its data
+        assert_eq!(
+            create(&mut transaction_context, new, 8),
+            Err(InstructionError::Custom(SYSTEM_ERROR_ACCOUNT_ALREADY_IN_USE))
+        );
+    }
+}
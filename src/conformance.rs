@@ -1,5 +1,4 @@
 use crate::{Result};
-use crate::types::*;
 
 pub struct ConformanceHarness {
     pub passed: usize,
@@ -34,3 +33,9 @@ impl ConformanceHarness {
         println!("Conformance test results: {} passed, {} failed", self.passed, self.failed);
     }
 }
+
+impl Default for ConformanceHarness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,778 @@
+//! Cross-program invocation through `sol_invoke_signed_c` and `sol_invoke_signed_rust`.
+//!
+//! The caller's account infos are translated out of its memory, changes it made to
+//! writable accounts are flushed to the transaction before the callee runs, and the
+//! callee's changes are copied back into the caller's memory afterwards.
+
+use crate::bpf_loader::MAX_PERMITTED_DATA_INCREASE;
+use crate::invoke_context::InvokeContext;
+use crate::sbpf::{EbpfError, MemoryMapping};
+use crate::syscalls::{
+    consume, costs, create_program_address, syscall_error, translate_array, translate_slices, write_bytes,
+    SyscallResult,
+};
+use crate::transaction_error::InstructionError;
+use crate::types::*;
+
+/// Most accounts an invoked instruction may reference
+pub const MAX_CPI_INSTRUCTION_ACCOUNTS: u64 = u8::MAX as u64;
+/// Largest instruction data a program may pass to an invoked instruction
+pub const MAX_CPI_INSTRUCTION_DATA_LEN: u64 = 10 * 1024;
+/// Most account infos a program may pass along with an invoked instruction
+pub const MAX_CPI_ACCOUNT_INFOS: u64 = 128;
+/// Most program derived addresses a program may sign for in one invocation
+pub const MAX_SIGNERS: u64 = 16;
+
+/// Size of `SolAccountInfo` in the C ABI
+const C_ACCOUNT_INFO_SIZE: u64 = 56;
+/// Size of `AccountInfo` in the Rust ABI
+const RUST_ACCOUNT_INFO_SIZE: u64 = 48;
+/// Offset of the value inside an `Rc<RefCell<T>>` allocation: strong and weak counts, then the borrow flag
+const RC_REFCELL_VALUE_OFFSET: u64 = 24;
+
+/// An account info in the caller's memory, resolved to the addresses of its fields
+#[derive(Debug, Clone, Copy)]
+struct CallerAccount {
+    key: Pubkey,
+    lamports_addr: u64,
+    owner_addr: u64,
+    data_addr: u64,
+    data_len: u64,
+    /// Where the account info stores the data length
+    data_len_addr: u64,
+}
+
+/// ABI-specific layout of the syscall's instruction and account info arguments
+struct Abi {
+    translate_instruction: fn(&MemoryMapping, u64) -> Result<Instruction, EbpfError>,
+    translate_account_info: fn(&MemoryMapping, u64) -> Result<CallerAccount, EbpfError>,
+    account_info_size: u64,
+}
+
+pub fn syscall_invoke_signed_c(
+    invoke_context: &mut InvokeContext,
+    args: [u64; 5],
+    memory: &mut MemoryMapping,
+) -> SyscallResult {
+    let abi = Abi {
+        translate_instruction: translate_instruction_c,
+        translate_account_info: translate_account_info_c,
+        account_info_size: C_ACCOUNT_INFO_SIZE,
+    };
+    cpi_common(invoke_context, args, memory, &abi)
+}
+
+pub fn syscall_invoke_signed_rust(
+    invoke_context: &mut InvokeContext,
+    args: [u64; 5],
+    memory: &mut MemoryMapping,
+) -> SyscallResult {
+    let abi = Abi {
+        translate_instruction: translate_instruction_rust,
+        translate_account_info: translate_account_info_rust,
+        account_info_size: RUST_ACCOUNT_INFO_SIZE,
+    };
+    cpi_common(invoke_context, args, memory, &abi)
+}
+
+/// `args` are (instruction, account infos, account info count, signer seeds, signer count)
+fn cpi_common(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping, abi: &Abi) -> SyscallResult {
+    consume(invoke_context, costs::INVOKE_UNITS)?;
+
+    let instruction = (abi.translate_instruction)(memory, args[0])?;
+    if let InstructionData::Generic { data } = &instruction.data {
+        consume(invoke_context, data.len() as u64 / costs::CPI_BYTES_PER_UNIT)?;
+    }
+    let signers = translate_signers(memory, &invoke_context.program_id(), args[3], args[4])?;
+
+    if args[2] > MAX_CPI_ACCOUNT_INFOS {
+        return Err(syscall_error(format!(
+            "too many account infos ({} > {})",
+            args[2], MAX_CPI_ACCOUNT_INFOS
+        )));
+    }
+    let account_infos = (0..args[2])
+        .map(|index| (abi.translate_account_info)(memory, field_addr(args[1], index * abi.account_info_size)?))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Every account the callee may write must be backed by one of the caller's account infos
+    let mut writable_accounts: Vec<CallerAccount> = Vec::new();
+    for meta in instruction.accounts.iter().filter(|meta| meta.is_writable) {
+        if writable_accounts.iter().any(|account| account.key == meta.pubkey) {
+            continue;
+        }
+        let Some(caller_account) = account_infos.iter().find(|info| info.key == meta.pubkey) else {
+            invoke_context.log(format!("Instruction references an unknown account {}", meta.pubkey));
+            return Err(InstructionError::MissingAccount.into());
+        };
+        writable_accounts.push(*caller_account);
+    }
+
+    for caller_account in &writable_accounts {
+        update_callee_account(invoke_context, memory, caller_account)?;
+    }
+    invoke_context.invoke(&instruction, &signers)?;
+    for caller_account in &writable_accounts {
+        update_caller_account(invoke_context, memory, caller_account)?;
+    }
+    Ok(0)
+}
+
+/// Address `offset` bytes past `addr`, which the program controls and may place anywhere
+fn field_addr(addr: u64, offset: u64) -> Result<u64, EbpfError> {
+    addr.checked_add(offset).ok_or_else(|| InstructionError::InvalidArgument.into())
+}
+
+fn read_u64(memory: &MemoryMapping, addr: u64) -> Result<u64, EbpfError> {
+    translate_array(memory, addr).map(u64::from_le_bytes)
+}
+
+fn read_pubkey(memory: &MemoryMapping, addr: u64) -> Result<Pubkey, EbpfError> {
+    translate_array(memory, addr).map(Pubkey::new)
+}
+
+fn read_bool(memory: &MemoryMapping, addr: u64) -> Result<bool, EbpfError> {
+    translate_array::<1>(memory, addr).map(|byte| byte[0] != 0)
+}
+
+fn check_instruction_size(accounts_len: u64, data_len: u64) -> Result<(), EbpfError> {
+    if accounts_len > MAX_CPI_INSTRUCTION_ACCOUNTS {
+        return Err(syscall_error(format!(
+            "too many instruction accounts ({} > {})",
+            accounts_len, MAX_CPI_INSTRUCTION_ACCOUNTS
+        )));
+    }
+    if data_len > MAX_CPI_INSTRUCTION_DATA_LEN {
+        return Err(syscall_error(format!(
+            "instruction data too large ({} > {})",
+            data_len, MAX_CPI_INSTRUCTION_DATA_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// `SolInstruction`: program id pointer, account metas, account count, data, data length
+fn translate_instruction_c(memory: &MemoryMapping, addr: u64) -> Result<Instruction, EbpfError> {
+    let program_id = read_pubkey(memory, read_u64(memory, addr)?)?;
+    let accounts_addr = read_u64(memory, field_addr(addr, 8)?)?;
+    let accounts_len = read_u64(memory, field_addr(addr, 16)?)?;
+    let data_addr = read_u64(memory, field_addr(addr, 24)?)?;
+    let data_len = read_u64(memory, field_addr(addr, 32)?)?;
+    check_instruction_size(accounts_len, data_len)?;
+
+    // `SolAccountMeta`: pubkey pointer, is_writable, is_signer
+    let accounts = (0..accounts_len)
+        .map(|index| {
+            let meta_addr = field_addr(accounts_addr, index * 16)?;
+            Ok(AccountMeta {
+                pubkey: read_pubkey(memory, read_u64(memory, meta_addr)?)?,
+                is_writable: read_bool(memory, field_addr(meta_addr, 8)?)?,
+                is_signer: read_bool(memory, field_addr(meta_addr, 9)?)?,
+            })
+        })
+        .collect::<Result<Vec<_>, EbpfError>>()?;
+    let data = memory.translate(data_addr, data_len)?.to_vec();
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data: InstructionData::Generic { data },
+    })
+}
+
+/// `StableInstruction`: account metas and data as (pointer, capacity, length), then the program id
+fn translate_instruction_rust(memory: &MemoryMapping, addr: u64) -> Result<Instruction, EbpfError> {
+    let accounts_addr = read_u64(memory, addr)?;
+    let accounts_len = read_u64(memory, field_addr(addr, 16)?)?;
+    let data_addr = read_u64(memory, field_addr(addr, 24)?)?;
+    let data_len = read_u64(memory, field_addr(addr, 40)?)?;
+    let program_id = read_pubkey(memory, field_addr(addr, 48)?)?;
+    check_instruction_size(accounts_len, data_len)?;
+
+    // `AccountMeta`: pubkey, is_signer, is_writable
+    let accounts = (0..accounts_len)
+        .map(|index| {
+            let meta_addr = field_addr(accounts_addr, index * 34)?;
+            Ok(AccountMeta {
+                pubkey: read_pubkey(memory, meta_addr)?,
+                is_signer: read_bool(memory, field_addr(meta_addr, 32)?)?,
+                is_writable: read_bool(memory, field_addr(meta_addr, 33)?)?,
+            })
+        })
+        .collect::<Result<Vec<_>, EbpfError>>()?;
+    let data = memory.translate(data_addr, data_len)?.to_vec();
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data: InstructionData::Generic { data },
+    })
+}
+
+/// `SolAccountInfo`: key, lamports and owner pointers around the data length and pointer
+fn translate_account_info_c(memory: &MemoryMapping, addr: u64) -> Result<CallerAccount, EbpfError> {
+    Ok(CallerAccount {
+        key: read_pubkey(memory, read_u64(memory, addr)?)?,
+        lamports_addr: read_u64(memory, field_addr(addr, 8)?)?,
+        data_len: read_u64(memory, field_addr(addr, 16)?)?,
+        data_len_addr: field_addr(addr, 16)?,
+        data_addr: read_u64(memory, field_addr(addr, 24)?)?,
+        owner_addr: read_u64(memory, field_addr(addr, 32)?)?,
+    })
+}
+
+/// `AccountInfo`: lamports and data sit behind `Rc<RefCell<..>>` pointers
+fn translate_account_info_rust(memory: &MemoryMapping, addr: u64) -> Result<CallerAccount, EbpfError> {
+    let lamports_cell = field_addr(read_u64(memory, field_addr(addr, 8)?)?, RC_REFCELL_VALUE_OFFSET)?;
+    let data_cell = field_addr(read_u64(memory, field_addr(addr, 16)?)?, RC_REFCELL_VALUE_OFFSET)?;
+    Ok(CallerAccount {
+        key: read_pubkey(memory, read_u64(memory, addr)?)?,
+        lamports_addr: read_u64(memory, lamports_cell)?,
+        data_addr: read_u64(memory, data_cell)?,
+        data_len: read_u64(memory, field_addr(data_cell, 8)?)?,
+        data_len_addr: field_addr(data_cell, 8)?,
+        owner_addr: read_u64(memory, field_addr(addr, 24)?)?,
+    })
+}
+
+/// Derive the program addresses the caller signs for from its signer seeds
+fn translate_signers(memory: &MemoryMapping, program_id: &Pubkey, addr: u64, len: u64) -> Result<Vec<Pubkey>, EbpfError> {
+    if len > MAX_SIGNERS {
+        return Err(syscall_error(format!("too many signers ({} > {})", len, MAX_SIGNERS)));
+    }
+    (0..len)
+        .map(|index| {
+            let seeds_addr = read_u64(memory, field_addr(addr, index * 16)?)?;
+            let seeds_len = read_u64(memory, field_addr(addr, index * 16 + 8)?)?;
+            if seeds_len > crate::crypto::MAX_SEEDS as u64 {
+                return Err(InstructionError::MaxSeedLengthExceeded.into());
+            }
+            let seeds = translate_slices(memory, seeds_addr, seeds_len)?;
+            let seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+            create_program_address(&seeds, &program_id.0)?
+                .map(Pubkey::new)
+                .ok_or_else(|| syscall_error("could not create program address with signer seeds"))
+        })
+        .collect()
+}
+
+/// Flush changes the caller made to an account in its memory to the transaction
+fn update_callee_account(
+    invoke_context: &mut InvokeContext,
+    memory: &MemoryMapping,
+    caller_account: &CallerAccount,
+) -> Result<(), EbpfError> {
+    let lamports = read_u64(memory, caller_account.lamports_addr)?;
+    let owner = translate_array(memory, caller_account.owner_addr)?;
+    let data = memory.translate(caller_account.data_addr, caller_account.data_len)?;
+
+    let account = invoke_context
+        .transaction_context
+        .get_mut(&caller_account.key)
+        .ok_or(InstructionError::MissingAccount)?;
+    account.lamports = lamports;
+    account.owner = owner;
+    if account.data != data {
+        account.data = data.to_vec();
+    }
+    Ok(())
+}
+
+/// Copy the callee's changes to an account back into the caller's memory
+fn update_caller_account(
+    invoke_context: &mut InvokeContext,
+    memory: &mut MemoryMapping,
+    caller_account: &CallerAccount,
+) -> Result<(), EbpfError> {
+    let program_id = invoke_context.program_id();
+    let aligned = invoke_context
+        .transaction_context
+        .get(&program_id)
+        .is_none_or(|program| program.owner != Pubkey::bpf_loader_deprecated().0);
+    let account = invoke_context
+        .transaction_context
+        .get(&caller_account.key)
+        .ok_or(InstructionError::MissingAccount)?;
+
+    write_bytes(memory, caller_account.lamports_addr, &account.lamports.to_le_bytes())?;
+    write_bytes(memory, caller_account.owner_addr, &account.owner)?;
+
+    let post_len = account.data.len() as u64;
+    let pre_len = caller_account.data_len;
+    if post_len != pre_len {
+        // The caller's buffer only has room for the original data plus the permitted growth
+        let original_len = invoke_context
+            .serialized_accounts
+            .iter()
+            .find(|serialized| serialized.pubkey == caller_account.key && serialized.offset.is_some())
+            .map_or(pre_len, |serialized| serialized.original_data_len as u64);
+        let max_len = if aligned {
+            original_len.saturating_add(MAX_PERMITTED_DATA_INCREASE as u64)
+        } else {
+            original_len
+        };
+        if post_len > max_len {
+            return Err(InstructionError::InvalidRealloc.into());
+        }
+        write_bytes(memory, caller_account.data_len_addr, &post_len.to_le_bytes())?;
+        // The serialized input keeps its own copy of the length right before the data
+        let serialized_len_addr = caller_account.data_addr.checked_sub(8).ok_or(EbpfError::AccessViolation {
+            pc: 0,
+            kind: "store",
+            vm_addr: caller_account.data_addr,
+            len: 8,
+        })?;
+        write_bytes(memory, serialized_len_addr, &post_len.to_le_bytes())?;
+    }
+
+    let buffer = memory.translate_mut(caller_account.data_addr, post_len.max(pre_len))?;
+    buffer[..account.data.len()].copy_from_slice(&account.data);
+    buffer[account.data.len()..].fill(0);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::{BuiltinProgram, SystemProgram};
+    use crate::invoke_context::InstructionProcessor;
+    use crate::sbpf::MM_INPUT_START;
+    use crate::sysvar::{EpochSchedule, Rent, SysvarCache};
+    use crate::transaction_context::TransactionContext;
+    use crate::AddressDerivation;
+
+    /// Builds a caller's memory: every `push` returns the virtual address of what it appended
+    #[derive(Default)]
+    struct Memory(Vec<u8>);
+
+    impl Memory {
+        fn push(&mut self, bytes: &[u8]) -> u64 {
+            let addr = MM_INPUT_START + self.0.len() as u64;
+            self.0.extend_from_slice(bytes);
+            addr
+        }
+
+        fn push_u64s(&mut self, values: &[u64]) -> u64 {
+            let bytes: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+            self.push(&bytes)
+        }
+    }
+
+    /// A caller account laid out like the aligned input: length, data, then room to grow
+    struct AccountInfo {
+        key: Pubkey,
+        lamports: u64,
+        owner: Pubkey,
+        data: Vec<u8>,
+        is_signer: bool,
+        is_writable: bool,
+    }
+
+    /// Addresses of the fields the syscall writes back to
+    struct AccountAddrs {
+        lamports: u64,
+        data: u64,
+        info: u64,
+    }
+
+    fn push_c_account_infos(memory: &mut Memory, accounts: &[AccountInfo]) -> Vec<AccountAddrs> {
+        let fields: Vec<(u64, u64, u64, u64)> = accounts
+            .iter()
+            .map(|account| {
+                let key = memory.push(&account.key.0);
+                let owner = memory.push(&account.owner.0);
+                let lamports = memory.push_u64s(&[account.lamports]);
+                memory.push_u64s(&[account.data.len() as u64]);
+                let data = memory.push(&account.data);
+                memory.push(&vec![0u8; MAX_PERMITTED_DATA_INCREASE]);
+                (key, owner, lamports, data)
+            })
+            .collect();
+
+        accounts
+            .iter()
+            .zip(fields)
+            .map(|(account, (key, owner, lamports, data))| {
+                let info = memory.push_u64s(&[key, lamports, account.data.len() as u64, data, owner, 0]);
+                memory.push(&[account.is_signer as u8, account.is_writable as u8, 0, 0, 0, 0, 0, 0]);
+                AccountAddrs { lamports, data, info }
+            })
+            .collect()
+    }
+
+    fn push_c_instruction(memory: &mut Memory, program_id: &Pubkey, metas: &[AccountMeta], data: &[u8]) -> u64 {
+        let program_id = memory.push(&program_id.0);
+        let keys: Vec<u64> = metas.iter().map(|meta| memory.push(&meta.pubkey.0)).collect();
+        let mut meta_bytes = Vec::new();
+        for (meta, key) in metas.iter().zip(keys) {
+            meta_bytes.extend_from_slice(&key.to_le_bytes());
+            meta_bytes.extend_from_slice(&[meta.is_writable as u8, meta.is_signer as u8, 0, 0, 0, 0, 0, 0]);
+        }
+        let accounts = memory.push(&meta_bytes);
+        let data_addr = memory.push(data);
+        memory.push_u64s(&[program_id, accounts, metas.len() as u64, data_addr, data.len() as u64])
+    }
+
+    fn push_signer_seeds(memory: &mut Memory, signers: &[&[&[u8]]]) -> u64 {
+        let seed_lists: Vec<u64> = signers
+            .iter()
+            .map(|seeds| {
+                let descriptors: Vec<u64> = seeds
+                    .iter()
+                    .flat_map(|seed| [memory.push(seed), seed.len() as u64])
+                    .collect();
+                memory.push_u64s(&descriptors)
+            })
+            .collect();
+        let descriptors: Vec<u64> = seed_lists
+            .iter()
+            .zip(signers)
+            .flat_map(|(addr, seeds)| [*addr, seeds.len() as u64])
+            .collect();
+        memory.push_u64s(&descriptors)
+    }
+
+    fn meta(pubkey: Pubkey, is_signer: bool, is_writable: bool) -> AccountMeta {
+        AccountMeta { pubkey, is_signer, is_writable }
+    }
+
+    /// Run `sol_invoke_signed_c` as `caller`, executing invoked instructions with `processor`
+    fn invoke(
+        caller: &Instruction,
+        transaction_context: &mut TransactionContext,
+        processor: &dyn InstructionProcessor,
+        memory: Memory,
+        args: [u64; 5],
+    ) -> (SyscallResult, Vec<u8>, Vec<String>) {
+        let mut execution_context = ExecutionContext::new(200_000);
+        let sysvars = SysvarCache::new(0, Rent::default(), EpochSchedule::default());
+        let mut memory = MemoryMapping::new(&[], 0, memory.0);
+        let result = {
+            let mut invoke_context =
+                InvokeContext::new(transaction_context, &mut execution_context, &sysvars, processor, caller);
+            syscall_invoke_signed_c(&mut invoke_context, args, &mut memory)
+        };
+        (result, memory.into_input(), execution_context.log_messages)
+    }
+
+    fn read_u64_at(input: &[u8], addr: u64) -> u64 {
+        let at = (addr - MM_INPUT_START) as usize;
+        u64::from_le_bytes(input[at..at + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_invoke_syncs_accounts_both_ways() {
+        let caller_id = Pubkey::new([1u8; 32]);
+        let callee_id = Pubkey::new([2u8; 32]);
+        let source = Pubkey::new([3u8; 32]);
+        let destination = Pubkey::new([4u8; 32]);
+        let mut transaction_context = TransactionContext::new(
+            vec![caller_id, callee_id, source, destination],
+            vec![
+                Account::new_executable(1, vec![], Pubkey::bpf_loader().0),
+                Account::new_executable(1, vec![], Pubkey::bpf_loader().0),
+                Account::new(100, vec![], caller_id.0),
+                Account::new(0, vec![1, 2], callee_id.0),
+            ],
+        );
+        let caller = Instruction {
+            program_id: caller_id,
+            accounts: vec![meta(source, true, true), meta(destination, false, true), meta(callee_id, false, false)],
+            data: InstructionData::Generic { data: vec![] },
+        };
+
+        // The caller moved 10 lamports out of `source` before invoking
+        let mut memory = Memory::default();
+        let accounts = [
+            AccountInfo { key: source, lamports: 90, owner: caller_id, data: vec![], is_signer: true, is_writable: true },
+            AccountInfo { key: destination, lamports: 10, owner: callee_id, data: vec![1, 2], is_signer: false, is_writable: true },
+        ];
+        let addrs = push_c_account_infos(&mut memory, &accounts);
+        let instruction = push_c_instruction(
+            &mut memory,
+            &callee_id,
+            &[meta(source, true, true), meta(destination, false, true)],
+            &[7],
+        );
+
+        let processor = |instruction: &Instruction, transaction_context: &mut TransactionContext, _: &mut ExecutionContext| {
+            assert_eq!(transaction_context.get(&instruction.accounts[0].pubkey).unwrap().lamports, 90);
+            assert_eq!(transaction_context.instruction_stack_height(), 2);
+            let destination = transaction_context.get_mut(&instruction.accounts[1].pubkey).unwrap();
+            destination.lamports -= 5;
+            destination.data = vec![9, 9, 9, 9];
+            transaction_context.get_mut(&instruction.accounts[0].pubkey).unwrap().lamports += 5;
+            Ok(())
+        };
+        transaction_context.push_instruction(&caller).unwrap();
+        let (result, input, _) = invoke(
+            &caller,
+            &mut transaction_context,
+            &processor,
+            memory,
+            [instruction, addrs[0].info, 2, 0, 0],
+        );
+
+        assert_eq!(result, Ok(0));
+        assert_eq!(read_u64_at(&input, addrs[0].lamports), 95);
+        assert_eq!(read_u64_at(&input, addrs[1].lamports), 5);
+        assert_eq!(read_u64_at(&input, addrs[1].info + 16), 4);
+        assert_eq!(read_u64_at(&input, addrs[1].data - 8), 4);
+        let data_at = (addrs[1].data - MM_INPUT_START) as usize;
+        assert_eq!(input[data_at..data_at + 4], [9, 9, 9, 9]);
+
+        let inner = transaction_context.inner_instructions();
+        assert_eq!(inner[0].instructions[0].instruction.program_id_index, 1);
+        assert_eq!(inner[0].instructions[0].instruction.accounts, vec![2, 3]);
+        assert_eq!(inner[0].instructions[0].stack_height, Some(2));
+    }
+
+    #[test]
+    fn test_invoke_privilege_escalation() {
+        let caller_id = Pubkey::new([1u8; 32]);
+        let account = Pubkey::new([3u8; 32]);
+        let mut transaction_context = TransactionContext::new(
+            vec![caller_id, account, Pubkey::system_program()],
+            vec![
+                Account::new_executable(1, vec![], Pubkey::bpf_loader().0),
+                Account::new(100, vec![], [0u8; 32]),
+                Account::new(0, vec![], [0u8; 32]),
+            ],
+        );
+        let caller = Instruction {
+            program_id: caller_id,
+            accounts: vec![meta(account, false, false), meta(Pubkey::system_program(), false, false)],
+            data: InstructionData::Generic { data: vec![] },
+        };
+        let processor = |_: &Instruction, _: &mut TransactionContext, _: &mut ExecutionContext| Ok(());
+        transaction_context.push_instruction(&caller).unwrap();
+
+        for (is_signer, is_writable) in [(false, true), (true, false)] {
+            let mut memory = Memory::default();
+            let addrs = push_c_account_infos(
+                &mut memory,
+                &[AccountInfo { key: account, lamports: 100, owner: Pubkey::system_program(), data: vec![], is_signer, is_writable }],
+            );
+            let instruction = push_c_instruction(
+                &mut memory,
+                &Pubkey::system_program(),
+                &[meta(account, is_signer, is_writable)],
+                &[],
+            );
+            let (result, _, logs) = invoke(
+                &caller,
+                &mut transaction_context.clone(),
+                &processor,
+                memory,
+                [instruction, addrs[0].info, 1, 0, 0],
+            );
+            assert_eq!(result, Err(EbpfError::InstructionError(InstructionError::PrivilegeEscalation)));
+            assert!(logs[0].contains("privilege escalated"));
+        }
+
+        // Programs can only be invoked if the caller was given their account
+        let mut memory = Memory::default();
+        let instruction = push_c_instruction(&mut memory, &Pubkey::token_program(), &[], &[]);
+        let (result, _, _) = invoke(&caller, &mut transaction_context, &processor, memory, [instruction, 0, 0, 0, 0]);
+        assert_eq!(result, Err(EbpfError::InstructionError(InstructionError::MissingAccount)));
+    }
+
+    #[test]
+    fn test_invoke_signed_with_program_address() {
+        let caller_id = Pubkey::new([1u8; 32]);
+        let (vault, bump) = AddressDerivation::derive_program_address(&[b"vault"], &caller_id.0).unwrap();
+        let vault = Pubkey::new(vault);
+        let mut transaction_context = TransactionContext::new(
+            vec![caller_id, vault, Pubkey::system_program()],
+            vec![
+                Account::new_executable(1, vec![], Pubkey::bpf_loader().0),
+                Account::new(100, vec![], [0u8; 32]),
+                Account::new_executable(1, vec![], [0u8; 32]),
+            ],
+        );
+        let caller = Instruction {
+            program_id: caller_id,
+            accounts: vec![meta(vault, false, true), meta(Pubkey::system_program(), false, false)],
+            data: InstructionData::Generic { data: vec![] },
+        };
+        let processor = |_: &Instruction, _: &mut TransactionContext, _: &mut ExecutionContext| Ok(());
+        transaction_context.push_instruction(&caller).unwrap();
+
+        let run = |seeds: &[&[u8]], transaction_context: &mut TransactionContext| {
+            let mut memory = Memory::default();
+            let addrs = push_c_account_infos(
+                &mut memory,
+                &[AccountInfo { key: vault, lamports: 100, owner: Pubkey::system_program(), data: vec![], is_signer: false, is_writable: true }],
+            );
+            let instruction =
+                push_c_instruction(&mut memory, &Pubkey::system_program(), &[meta(vault, true, true)], &[]);
+            let signers = push_signer_seeds(&mut memory, &[seeds]);
+            invoke(&caller, transaction_context, &processor, memory, [instruction, addrs[0].info, 1, signers, 1]).0
+        };
+
+        assert_eq!(run(&[b"vault", &[bump]], &mut transaction_context.clone()), Ok(0));
+        // Seeds for a different address don't make the program a signer for the vault
+        let (_, other_bump) = AddressDerivation::derive_program_address(&[b"other"], &caller_id.0).unwrap();
+        assert_eq!(
+            run(&[b"other", &[other_bump]], &mut transaction_context),
+            Err(EbpfError::InstructionError(InstructionError::PrivilegeEscalation))
+        );
+    }
+
+    #[test]
+    fn test_invoke_system_transfer_requires_signer() {
+        let caller_id = Pubkey::new([1u8; 32]);
+        let source = Pubkey::new([3u8; 32]);
+        let destination = Pubkey::new([4u8; 32]);
+        let transaction_context = TransactionContext::new(
+            vec![caller_id, source, destination, Pubkey::system_program()],
+            vec![
+                Account::new_executable(1, vec![], Pubkey::bpf_loader().0),
+                Account::new(100, vec![], Pubkey::system_program().0),
+                Account::new(0, vec![], Pubkey::system_program().0),
+                Account::new_executable(1, vec![], [0u8; 32]),
+            ],
+        );
+        // Invoked instructions run on the real system program
+        let processor = |instruction: &Instruction, accounts: &mut TransactionContext, context: &mut ExecutionContext| {
+            let sysvars = SysvarCache::new(0, Rent::default(), EpochSchedule::default());
+            let nested = |_: &Instruction, _: &mut TransactionContext, _: &mut ExecutionContext| Ok(());
+            SystemProgram.process_instruction(&mut InvokeContext::new(accounts, context, &sysvars, &nested, instruction))
+        };
+
+        // The caller is handed `source` writable, and only passes on a signature for it in the
+        // second run
+        for source_is_signer in [false, true] {
+            let caller = Instruction {
+                program_id: caller_id,
+                accounts: vec![
+                    meta(source, source_is_signer, true),
+                    meta(destination, false, true),
+                    meta(Pubkey::system_program(), false, false),
+                ],
+                data: InstructionData::Generic { data: vec![] },
+            };
+            let mut transaction_context = transaction_context.clone();
+            transaction_context.push_instruction(&caller).unwrap();
+
+            let mut memory = Memory::default();
+            let addrs = push_c_account_infos(
+                &mut memory,
+                &[
+                    AccountInfo { key: source, lamports: 100, owner: Pubkey::system_program(), data: vec![], is_signer: source_is_signer, is_writable: true },
+                    AccountInfo { key: destination, lamports: 0, owner: Pubkey::system_program(), data: vec![], is_signer: false, is_writable: true },
+                ],
+            );
+            let data = [vec![2], 60u64.to_le_bytes().to_vec()].concat();
+            let instruction = push_c_instruction(
+                &mut memory,
+                &Pubkey::system_program(),
+                &[meta(source, source_is_signer, true), meta(destination, false, true)],
+                &data,
+            );
+            let (result, input, _) =
+                invoke(&caller, &mut transaction_context, &processor, memory, [instruction, addrs[0].info, 2, 0, 0]);
+
+            if source_is_signer {
+                assert_eq!(result, Ok(0));
+                assert_eq!(read_u64_at(&input, addrs[1].lamports), 60);
+            } else {
+                assert_eq!(result, Err(EbpfError::InstructionError(InstructionError::MissingRequiredSignature)));
+                assert_eq!(transaction_context.get(&source).unwrap().lamports, 100);
+            }
+
+            // A typed transfer names its sender without a meta, so the caller's signature is required
+            let typed = Instruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![],
+                data: InstructionData::Transfer { from: source.0, to: destination.0, lamports: 10 },
+            };
+            let mut execution_context = ExecutionContext::new(200_000);
+            let sysvars = SysvarCache::new(0, Rent::default(), EpochSchedule::default());
+            let mut invoke_context =
+                InvokeContext::new(&mut transaction_context, &mut execution_context, &sysvars, &processor, &caller);
+            let expected = if source_is_signer { Ok(()) } else { Err(InstructionError::PrivilegeEscalation) };
+            assert_eq!(invoke_context.invoke(&typed, &[]), expected);
+        }
+    }
+
+    #[test]
+    fn test_invoke_depth_and_reentrancy() {
+        let caller_id = Pubkey::new([1u8; 32]);
+        let callee_id = Pubkey::new([2u8; 32]);
+        let mut transaction_context = TransactionContext::new(
+            vec![caller_id, callee_id],
+            vec![
+                Account::new_executable(1, vec![], Pubkey::bpf_loader().0),
+                Account::new_executable(1, vec![], Pubkey::bpf_loader().0),
+            ],
+        );
+        let caller = Instruction {
+            program_id: caller_id,
+            accounts: vec![meta(caller_id, false, false), meta(callee_id, false, false)],
+            data: InstructionData::Generic { data: vec![] },
+        };
+        let processor = |_: &Instruction, _: &mut TransactionContext, _: &mut ExecutionContext| Ok(());
+
+        // The caller is already running beneath `callee_id`, so it can't invoke it again
+        let callee = Instruction { program_id: callee_id, accounts: vec![], data: InstructionData::Generic { data: vec![] } };
+        transaction_context.push_instruction(&callee).unwrap();
+        transaction_context.push_instruction(&caller).unwrap();
+        let mut memory = Memory::default();
+        let instruction = push_c_instruction(&mut memory, &callee_id, &[], &[]);
+        let (result, _, _) = invoke(&caller, &mut transaction_context, &processor, memory, [instruction, 0, 0, 0, 0]);
+        assert_eq!(result, Err(EbpfError::InstructionError(InstructionError::ReentrancyNotAllowed)));
+
+        // Direct recursion is allowed, but only up to the maximum depth
+        for _ in 0..3 {
+            transaction_context.push_instruction(&caller).unwrap();
+        }
+        let mut memory = Memory::default();
+        let instruction = push_c_instruction(&mut memory, &caller_id, &[], &[]);
+        let (result, _, _) = invoke(&caller, &mut transaction_context, &processor, memory, [instruction, 0, 0, 0, 0]);
+        assert_eq!(result, Err(EbpfError::InstructionError(InstructionError::CallDepth)));
+    }
+
+    #[test]
+    fn test_rust_abi_translation() {
+        let key = Pubkey::new([5u8; 32]);
+        let program_id = Pubkey::new([6u8; 32]);
+        let mut memory = Memory::default();
+
+        let key_addr = memory.push(&key.0);
+        let owner_addr = memory.push(&program_id.0);
+        let lamports_addr = memory.push_u64s(&[77]);
+        let data_addr = memory.push(&[1, 2, 3]);
+        // `Rc<RefCell<&mut u64>>` and `Rc<RefCell<&mut [u8]>>`: strong, weak, borrow flag, value
+        let lamports_rc = memory.push_u64s(&[1, 1, 0, lamports_addr]);
+        let data_rc = memory.push_u64s(&[1, 1, 0, data_addr, 3]);
+        let info = memory.push_u64s(&[key_addr, lamports_rc, data_rc, owner_addr, 0, 0x0101]);
+        // The `Rc` pointers are the program's to choose
+        let overflowing = memory.push_u64s(&[key_addr, u64::MAX - 8, data_rc, owner_addr, 0, 0x0101]);
+
+        let mut meta = key.0.to_vec();
+        meta.extend_from_slice(&[0, 1]);
+        let metas = memory.push(&meta);
+        let data = memory.push(&[4, 5]);
+        let instruction = memory.push_u64s(&[metas, 1, 1, data, 2, 2]);
+        memory.push(&program_id.0);
+
+        let memory = MemoryMapping::new(&[], 0, memory.0);
+        let caller_account = translate_account_info_rust(&memory, info).unwrap();
+        assert_eq!(caller_account.key, key);
+        assert_eq!(caller_account.lamports_addr, lamports_addr);
+        assert_eq!((caller_account.data_addr, caller_account.data_len), (data_addr, 3));
+        assert_eq!(caller_account.data_len_addr, data_rc + 32);
+        assert_eq!(caller_account.owner_addr, owner_addr);
+        assert_eq!(
+            translate_account_info_rust(&memory, overflowing).err(),
+            Some(EbpfError::InstructionError(InstructionError::InvalidArgument))
+        );
+
+        let instruction = translate_instruction_rust(&memory, instruction).unwrap();
+        assert_eq!(instruction.program_id, program_id);
+        assert_eq!(instruction.accounts[0].pubkey, key);
+        assert!(!instruction.accounts[0].is_signer && instruction.accounts[0].is_writable);
+        assert!(matches!(instruction.data, InstructionData::Generic { data } if data == vec![4, 5]));
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{Result, TerminatorError};
 use ed25519_dalek::{Signature, Verifier, VerifyingKey};
 use sha2::{Sha256, Digest};
+use sha3::Keccak256;
 use blake3::Hasher as Blake3Hasher;
 
 /// Real cryptographic operations using industry-standard libraries
@@ -34,6 +35,32 @@ impl SolanaCrypto {
         hasher.finalize().into()
     }
 
+    /// Compute Keccak-256 hash as used by `sol_keccak256`
+    pub fn keccak256_hash(data: &[u8]) -> [u8; 32] {
+        let mut hasher = Keccak256::new();
+        hasher.update(data);
+        hasher.finalize().into()
+    }
+
+    /// Recover the uncompressed secp256k1 public key (without the 0x04 prefix) that signed `hash`
+    pub fn secp256k1_recover(hash: &[u8; 32], recovery_id: u8, signature: &[u8; 64]) -> Result<[u8; 64]> {
+        let message = libsecp256k1::Message::parse(hash);
+        let recovery_id = libsecp256k1::RecoveryId::parse(recovery_id)
+            .map_err(|_| TerminatorError::InvalidSignature)?;
+        let signature = libsecp256k1::Signature::parse_standard(signature)
+            .map_err(|_| TerminatorError::InvalidSignature)?;
+        // Solana rejects malleable high-S signatures
+        if signature.s.is_high() {
+            return Err(TerminatorError::InvalidSignature);
+        }
+
+        let public_key = libsecp256k1::recover(&message, &signature, &recovery_id)
+            .map_err(|_| TerminatorError::InvalidSignature)?;
+        let mut recovered = [0u8; 64];
+        recovered.copy_from_slice(&public_key.serialize()[1..]);
+        Ok(recovered)
+    }
+
     /// Compute Blake3 hash
     pub fn blake3_hash(data: &[u8]) -> [u8; 32] {
         let mut hasher = Blake3Hasher::new();
@@ -110,6 +137,11 @@ impl FastCrypto {
     }
 }
 
+/// Maximum number of seeds in a program address
+pub const MAX_SEEDS: usize = 16;
+/// Maximum length of a single seed
+pub const MAX_SEED_LEN: usize = 32;
+
 /// Real Solana account address derivation
 pub struct AddressDerivation;
 
@@ -119,37 +151,51 @@ impl AddressDerivation {
         seeds: &[&[u8]],
         program_id: &[u8; 32],
     ) -> Result<([u8; 32], u8)> {
-        // Solana PDA derivation algorithm
-        const PDA_MARKER: &[u8] = b"ProgramDerivedAddress";
-        
         for bump in (0..=255u8).rev() {
-            let mut hasher = Sha256::new();
-            
-            // Hash all seeds
-            for seed in seeds {
-                hasher.update(seed);
-            }
-            
-            // Add bump seed
-            hasher.update(&[bump]);
-            
-            // Add program ID
-            hasher.update(program_id);
-            
-            // Add PDA marker
-            hasher.update(PDA_MARKER);
-            
-            let hash: [u8; 32] = hasher.finalize().into();
-            
-            // Check if this is a valid PDA (not on the Ed25519 curve)
-            if let Err(_) = VerifyingKey::from_bytes(&hash) {
-                return Ok((hash, bump));
+            let bump_seed = [bump];
+            let mut seeds_with_bump = seeds.to_vec();
+            seeds_with_bump.push(&bump_seed);
+
+            match Self::create_program_address(&seeds_with_bump, program_id) {
+                Ok(address) => return Ok((address, bump)),
+                Err(TerminatorError::ProgramError(_)) => continue,
+                Err(e) => return Err(e),
             }
         }
         
         Err(TerminatorError::ProgramError("Unable to find valid PDA".to_string()))
     }
 
+    /// Create a program address from seeds that already include the bump seed
+    pub fn create_program_address(
+        seeds: &[&[u8]],
+        program_id: &[u8; 32],
+    ) -> Result<[u8; 32]> {
+        // Solana PDA derivation algorithm
+        const PDA_MARKER: &[u8] = b"ProgramDerivedAddress";
+
+        if seeds.len() > MAX_SEEDS {
+            return Err(TerminatorError::InvalidSeeds(format!("at most {} seeds are allowed", MAX_SEEDS)));
+        }
+        if seeds.iter().any(|seed| seed.len() > MAX_SEED_LEN) {
+            return Err(TerminatorError::InvalidSeeds(format!("seeds are limited to {} bytes", MAX_SEED_LEN)));
+        }
+
+        let mut hasher = Sha256::new();
+        for seed in seeds {
+            hasher.update(seed);
+        }
+        hasher.update(program_id);
+        hasher.update(PDA_MARKER);
+        let hash: [u8; 32] = hasher.finalize().into();
+
+        // A valid PDA must not be on the Ed25519 curve
+        if VerifyingKey::from_bytes(&hash).is_ok() {
+            return Err(TerminatorError::ProgramError("Address is on the ed25519 curve".to_string()));
+        }
+        Ok(hash)
+    }
+
     /// Find a Program Derived Address with a specific bump seed
     pub fn find_program_address(
         seeds: &[&[u8]],
@@ -227,6 +273,43 @@ mod tests {
         assert_eq!(bump1, bump2, "Bump seed should be deterministic");
     }
 
+    #[test]
+    fn test_create_program_address_matches_find() {
+        let program_id = [1u8; 32];
+        let seeds: [&[u8]; 2] = [b"test", b"seed"];
+        let (address, bump) = AddressDerivation::derive_program_address(&seeds, &program_id).unwrap();
+
+        let bump_seed = [bump];
+        let with_bump: [&[u8]; 3] = [b"test", b"seed", &bump_seed];
+        assert_eq!(AddressDerivation::create_program_address(&with_bump, &program_id).unwrap(), address);
+
+        let long_seed = [0u8; MAX_SEED_LEN + 1];
+        assert!(matches!(
+            AddressDerivation::create_program_address(&[&long_seed], &program_id),
+            Err(TerminatorError::InvalidSeeds(_))
+        ));
+    }
+
+    #[test]
+    fn test_keccak256_and_secp256k1_recover() {
+        assert_eq!(
+            SolanaCrypto::keccak256_hash(b""),
+            [
+                0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7, 0x03, 0xc0,
+                0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70,
+            ]
+        );
+
+        let secret_key = libsecp256k1::SecretKey::parse(&[7u8; 32]).unwrap();
+        let hash = SolanaCrypto::keccak256_hash(b"hello");
+        let (signature, recovery_id) = libsecp256k1::sign(&libsecp256k1::Message::parse(&hash), &secret_key);
+        let expected = libsecp256k1::PublicKey::from_secret_key(&secret_key).serialize();
+
+        let recovered = SolanaCrypto::secp256k1_recover(&hash, recovery_id.serialize(), &signature.serialize()).unwrap();
+        assert_eq!(recovered[..], expected[1..]);
+        assert!(SolanaCrypto::secp256k1_recover(&hash, 4, &signature.serialize()).is_err());
+    }
+
     #[test]
     fn test_batch_verification() {
         let mut csprng = OsRng;
@@ -0,0 +1,705 @@
+//! Loading and verification of sBPF programs compiled to ELF shared objects.
+
+use crate::sbpf::{ebpf, hash_symbol_name, Executable, Insn, SyscallRegistry, INSN_SIZE, MM_PROGRAM_START};
+use crate::{Result, TerminatorError};
+
+const ELFMAG: &[u8; 4] = b"\x7fELF";
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const EV_CURRENT: u8 = 1;
+const ET_DYN: u16 = 3;
+const EM_BPF: u16 = 247;
+const EM_SBPF: u16 = 263;
+
+const EHDR_SIZE: usize = 64;
+const SHDR_SIZE: usize = 64;
+const SYM_SIZE: usize = 24;
+const REL_SIZE: usize = 16;
+
+const SHT_NOBITS: u32 = 8;
+const SHT_REL: u32 = 9;
+const STT_FUNC: u8 = 2;
+
+const R_BPF_64_64: u32 = 1;
+const R_BPF_64_RELATIVE: u32 = 8;
+const R_BPF_64_32: u32 = 10;
+
+/// Offset of the immediate within an instruction slot
+const BYTE_OFFSET_IMMEDIATE: usize = 4;
+
+#[derive(Debug, Clone)]
+struct SectionHeader {
+    name: String,
+    sh_type: u32,
+    sh_addr: u64,
+    sh_offset: u64,
+    sh_size: u64,
+    sh_link: u32,
+}
+
+impl SectionHeader {
+    /// Bytes of the section within the file; `SHT_NOBITS` sections have none
+    fn file_range(&self) -> Result<std::ops::Range<usize>> {
+        if self.sh_type == SHT_NOBITS {
+            return Err(invalid_elf(format!("section {} has no file data", self.name)));
+        }
+        let end = self
+            .sh_offset
+            .checked_add(self.sh_size)
+            .and_then(|end| usize::try_from(end).ok())
+            .ok_or_else(|| invalid_elf(format!("section {} is out of bounds", self.name)))?;
+        Ok(self.sh_offset as usize..end)
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.name == ".text"
+            || self.name.starts_with(".rodata")
+            || self.name.starts_with(".data.rel.ro")
+            || self.name == ".eh_frame"
+    }
+
+    fn is_writable_data(&self) -> bool {
+        (self.name.starts_with(".data") && !self.name.starts_with(".data.rel.ro")) || self.name.starts_with(".bss")
+    }
+}
+
+struct Symbol {
+    name: String,
+    st_info: u8,
+    st_value: u64,
+}
+
+fn invalid_elf(message: impl Into<String>) -> TerminatorError {
+    TerminatorError::InvalidElf(message.into())
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16> {
+    offset
+        .checked_add(2)
+        .and_then(|end| bytes.get(offset..end))
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| invalid_elf(format!("read past end of file at {:#x}", offset)))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    offset
+        .checked_add(4)
+        .and_then(|end| bytes.get(offset..end))
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid_elf(format!("read past end of file at {:#x}", offset)))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64> {
+    offset
+        .checked_add(8)
+        .and_then(|end| bytes.get(offset..end))
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid_elf(format!("read past end of file at {:#x}", offset)))
+}
+
+fn write_u32(bytes: &mut [u8], offset: usize, value: u32) -> Result<()> {
+    offset
+        .checked_add(4)
+        .and_then(|end| bytes.get_mut(offset..end))
+        .ok_or_else(|| invalid_elf(format!("relocation at {:#x} is out of bounds", offset)))?
+        .copy_from_slice(&value.to_le_bytes());
+    Ok(())
+}
+
+fn write_u64(bytes: &mut [u8], offset: usize, value: u64) -> Result<()> {
+    offset
+        .checked_add(8)
+        .and_then(|end| bytes.get_mut(offset..end))
+        .ok_or_else(|| invalid_elf(format!("relocation at {:#x} is out of bounds", offset)))?
+        .copy_from_slice(&value.to_le_bytes());
+    Ok(())
+}
+
+fn read_c_str(bytes: &[u8], offset: usize) -> Result<String> {
+    let tail = bytes
+        .get(offset..)
+        .ok_or_else(|| invalid_elf(format!("string at {:#x} is out of bounds", offset)))?;
+    let end = tail
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| invalid_elf("unterminated string"))?;
+    Ok(String::from_utf8_lossy(&tail[..end]).into_owned())
+}
+
+fn parse_section_headers(elf: &[u8]) -> Result<Vec<SectionHeader>> {
+    let shoff = read_u64(elf, 0x28)? as usize;
+    let shentsize = read_u16(elf, 0x3a)? as usize;
+    let shnum = read_u16(elf, 0x3c)? as usize;
+    let shstrndx = read_u16(elf, 0x3e)? as usize;
+
+    if shentsize != SHDR_SIZE {
+        return Err(invalid_elf(format!("unexpected section header size {}", shentsize)));
+    }
+    let table_end = shnum
+        .checked_mul(SHDR_SIZE)
+        .and_then(|len| len.checked_add(shoff))
+        .filter(|end| *end <= elf.len())
+        .ok_or_else(|| invalid_elf("section header table is out of bounds"))?;
+    if shstrndx >= shnum {
+        return Err(invalid_elf("section name table index is out of bounds"));
+    }
+
+    let raw: Vec<_> = (shoff..table_end)
+        .step_by(SHDR_SIZE)
+        .map(|at| -> Result<_> {
+            Ok((
+                read_u32(elf, at)?,
+                SectionHeader {
+                    name: String::new(),
+                    sh_type: read_u32(elf, at + 4)?,
+                    sh_addr: read_u64(elf, at + 16)?,
+                    sh_offset: read_u64(elf, at + 24)?,
+                    sh_size: read_u64(elf, at + 32)?,
+                    sh_link: read_u32(elf, at + 40)?,
+                },
+            ))
+        })
+        .collect::<Result<_>>()?;
+
+    for (_, header) in &raw {
+        let in_bounds = header.file_range().is_ok_and(|range| range.end <= elf.len());
+        if header.sh_type != SHT_NOBITS && !in_bounds {
+            return Err(invalid_elf("section data is out of bounds"));
+        }
+    }
+
+    let names_offset = raw[shstrndx].1.file_range()?.start;
+    raw.into_iter()
+        .map(|(name_offset, mut header)| {
+            let offset = names_offset
+                .checked_add(name_offset as usize)
+                .ok_or_else(|| invalid_elf("section name is out of bounds"))?;
+            header.name = read_c_str(elf, offset)?;
+            Ok(header)
+        })
+        .collect()
+}
+
+fn parse_symbol(elf: &[u8], symtab: &SectionHeader, strtab: &SectionHeader, index: usize) -> Result<Symbol> {
+    let symbols = symtab.file_range()?;
+    let at = index
+        .checked_mul(SYM_SIZE)
+        .filter(|offset| offset + SYM_SIZE <= symbols.len())
+        .map(|offset| symbols.start + offset)
+        .ok_or_else(|| invalid_elf(format!("symbol index {} is out of bounds", index)))?;
+    let name_offset = strtab
+        .file_range()?
+        .start
+        .checked_add(read_u32(elf, at)? as usize)
+        .ok_or_else(|| invalid_elf(format!("name of symbol {} is out of bounds", index)))?;
+    Ok(Symbol {
+        name: read_c_str(elf, name_offset)?,
+        st_info: *elf.get(at + 4).ok_or_else(|| invalid_elf(format!("symbol {} is out of bounds", index)))?,
+        st_value: read_u64(elf, at + 8)?,
+    })
+}
+
+/// Parse, relocate and verify an sBPF ELF, resolving syscalls against `syscalls`
+pub fn load_program<C>(elf: &[u8], syscalls: &SyscallRegistry<C>) -> Result<Executable> {
+    if elf.len() < EHDR_SIZE || &elf[0..4] != ELFMAG {
+        return Err(invalid_elf("missing ELF magic"));
+    }
+    if elf[4] != ELFCLASS64 || elf[5] != ELFDATA2LSB || elf[6] != EV_CURRENT {
+        return Err(invalid_elf("expected a little-endian ELF64 file"));
+    }
+    if read_u16(elf, 0x10)? != ET_DYN {
+        return Err(invalid_elf("expected a shared object (ET_DYN)"));
+    }
+    let machine = read_u16(elf, 0x12)?;
+    if machine != EM_BPF && machine != EM_SBPF {
+        return Err(invalid_elf(format!("unsupported machine {}", machine)));
+    }
+    let entry = read_u64(elf, 0x18)?;
+
+    let sections = parse_section_headers(elf)?;
+    let text = sections
+        .iter()
+        .find(|section| section.name == ".text")
+        .cloned()
+        .ok_or_else(|| invalid_elf("missing .text section"))?;
+    if text.sh_size == 0 || !(text.sh_size as usize).is_multiple_of(INSN_SIZE) {
+        return Err(invalid_elf(".text size is not a multiple of the instruction size"));
+    }
+    for section in &sections {
+        if section.is_writable_data() && section.sh_size > 0 {
+            return Err(invalid_elf(format!("writable section {} is not supported", section.name)));
+        }
+        if section.is_read_only() && section.sh_addr != section.sh_offset {
+            return Err(invalid_elf(format!("section {} is not mapped at its file offset", section.name)));
+        }
+    }
+
+    let entry_pc = entry
+        .checked_sub(text.sh_addr)
+        .filter(|offset| *offset < text.sh_size && offset % INSN_SIZE as u64 == 0)
+        .map(|offset| (offset / INSN_SIZE as u64) as usize)
+        .ok_or_else(|| invalid_elf(format!("entrypoint {:#x} is outside .text", entry)))?;
+
+    let mut bytes = elf.to_vec();
+    let mut functions = vec![entry_pc];
+    fixup_relative_calls(&mut bytes, &text, &mut functions)?;
+    apply_relocations(&mut bytes, &sections, &text, syscalls, &mut functions)?;
+
+    // The program region holds only the read-only sections, at their file offsets, all of which
+    // were checked to be within the file
+    let read_only = sections
+        .iter()
+        .filter(|section| section.is_read_only())
+        .map(|section| section.file_range())
+        .collect::<Result<Vec<_>>>()?;
+    let region_len = read_only.iter().map(|range| range.end).max().unwrap_or(0);
+    let mut program = vec![0u8; region_len];
+    for range in read_only {
+        program[range.clone()].copy_from_slice(&bytes[range]);
+    }
+
+    let mut executable = Executable::new(program, text.sh_offset as usize, text.sh_size as usize, entry_pc);
+    for pc in functions {
+        executable.register_function(pc);
+    }
+    verify(&executable)?;
+    Ok(executable)
+}
+
+/// Rewrite pc-relative `call` immediates into function hashes
+fn fixup_relative_calls(bytes: &mut [u8], text: &SectionHeader, functions: &mut Vec<usize>) -> Result<()> {
+    let text_range = text.file_range()?;
+    let insn_count = text_range.len() / INSN_SIZE;
+
+    for pc in 0..insn_count {
+        let insn = Insn::decode(&bytes[text_range.clone()], pc).expect("pc is within .text");
+        if insn.opc != ebpf::CALL_IMM || insn.imm == -1 {
+            continue;
+        }
+        let target_pc = (pc as i64 + 1 + insn.imm) as usize;
+        if target_pc >= insn_count {
+            return Err(invalid_elf(format!("call at instruction {} targets {} outside .text", pc, target_pc)));
+        }
+        functions.push(target_pc);
+        let hash = hash_symbol_name(&(target_pc as u64).to_le_bytes());
+        write_u32(bytes, text_range.start + pc * INSN_SIZE + BYTE_OFFSET_IMMEDIATE, hash)?;
+    }
+    Ok(())
+}
+
+fn apply_relocations<C>(
+    bytes: &mut [u8],
+    sections: &[SectionHeader],
+    text: &SectionHeader,
+    syscalls: &SyscallRegistry<C>,
+    functions: &mut Vec<usize>,
+) -> Result<()> {
+    let text_range = text.file_range()?;
+
+    for rel_section in sections.iter().filter(|section| section.sh_type == SHT_REL) {
+        let symtab = sections
+            .get(rel_section.sh_link as usize)
+            .ok_or_else(|| invalid_elf("relocation section has no symbol table"))?;
+        let strtab = sections
+            .get(symtab.sh_link as usize)
+            .ok_or_else(|| invalid_elf("symbol table has no string table"))?;
+
+        for at in rel_section.file_range()?.step_by(REL_SIZE) {
+            let r_offset = read_u64(bytes, at)? as usize;
+            let r_info = read_u64(bytes, at + 8)?;
+            let r_type = r_info as u32;
+            let r_sym = (r_info >> 32) as usize;
+            let imm_offset = r_offset
+                .checked_add(BYTE_OFFSET_IMMEDIATE)
+                .ok_or_else(|| invalid_elf(format!("relocation at {:#x} is out of bounds", r_offset)))?;
+
+            match r_type {
+                R_BPF_64_64 => {
+                    let symbol = parse_symbol(bytes, symtab, strtab, r_sym)?;
+                    let addend = read_u32(bytes, imm_offset)? as u64;
+                    let mut address = symbol.st_value.wrapping_add(addend);
+                    if address < MM_PROGRAM_START {
+                        address += MM_PROGRAM_START;
+                    }
+                    write_u32(bytes, imm_offset, address as u32)?;
+                    write_u32(bytes, imm_offset + INSN_SIZE, (address >> 32) as u32)?;
+                }
+                R_BPF_64_RELATIVE if text_range.contains(&r_offset) => {
+                    let low = read_u32(bytes, imm_offset)? as u64;
+                    let high = read_u32(bytes, imm_offset + INSN_SIZE)? as u64;
+                    let mut address = (high << 32) | low;
+                    if address == 0 {
+                        return Err(invalid_elf(format!("relocation at {:#x} references address zero", r_offset)));
+                    }
+                    if address < MM_PROGRAM_START {
+                        address += MM_PROGRAM_START;
+                    }
+                    write_u32(bytes, imm_offset, address as u32)?;
+                    write_u32(bytes, imm_offset + INSN_SIZE, (address >> 32) as u32)?;
+                }
+                R_BPF_64_RELATIVE => {
+                    let address = read_u32(bytes, imm_offset)? as u64;
+                    write_u64(bytes, r_offset, address.saturating_add(MM_PROGRAM_START))?;
+                }
+                R_BPF_64_32 => {
+                    let symbol = parse_symbol(bytes, symtab, strtab, r_sym)?;
+                    let pc = r_offset.saturating_sub(text_range.start) / INSN_SIZE;
+                    let hash = if symbol.st_info & 0x0f == STT_FUNC && symbol.st_value != 0 {
+                        let target_pc = symbol
+                            .st_value
+                            .checked_sub(text.sh_addr)
+                            .map(|offset| (offset / INSN_SIZE as u64) as usize)
+                            .filter(|target_pc| *target_pc < text_range.len() / INSN_SIZE)
+                            .ok_or_else(|| invalid_elf(format!("function {} is outside .text", symbol.name)))?;
+                        functions.push(target_pc);
+                        hash_symbol_name(&(target_pc as u64).to_le_bytes())
+                    } else {
+                        let hash = hash_symbol_name(symbol.name.as_bytes());
+                        if syscalls.lookup(hash).is_none() {
+                            return Err(TerminatorError::UnresolvedSymbol { symbol: symbol.name, pc });
+                        }
+                        hash
+                    };
+                    write_u32(bytes, imm_offset, hash)?;
+                }
+                other => return Err(invalid_elf(format!("unsupported relocation type {}", other))),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn verification_failed(pc: usize, reason: impl Into<String>) -> TerminatorError {
+    TerminatorError::VerificationFailed { pc, reason: reason.into() }
+}
+
+/// Reject bytecode the interpreter should never be asked to run
+pub fn verify(executable: &Executable) -> Result<()> {
+    use ebpf::*;
+
+    let text = executable.text();
+    if text.is_empty() {
+        return Err(verification_failed(0, "program is empty"));
+    }
+    if !text.len().is_multiple_of(INSN_SIZE) {
+        return Err(verification_failed(0, "program length is not a multiple of 8"));
+    }
+
+    let insn_count = executable.instruction_count();
+    let is_lddw = |pc: usize| Insn::decode(text, pc).is_some_and(|insn| insn.opc == LD_DW_IMM);
+
+    let mut pc = 0;
+    while pc < insn_count {
+        let insn = Insn::decode(text, pc).expect("pc is within .text");
+        if insn.src > 10 {
+            return Err(verification_failed(pc, format!("invalid source register r{}", insn.src)));
+        }
+
+        let class = insn.opc & BPF_CLS_MASK;
+        let op = insn.opc & 0xf0;
+        let writes_dst = matches!(class, BPF_ALU | BPF_ALU64 | BPF_LD | BPF_LDX);
+        if insn.dst > 10 || (writes_dst && insn.dst == 10) {
+            return Err(verification_failed(pc, format!("invalid destination register r{}", insn.dst)));
+        }
+
+        match class {
+            BPF_ALU | BPF_ALU64 => {
+                let shift_limit = if class == BPF_ALU { 32 } else { 64 };
+                let uses_imm = insn.opc & BPF_X == 0;
+                match op {
+                    BPF_END if class == BPF_ALU => {
+                        if !matches!(insn.imm, 16 | 32 | 64) {
+                            return Err(verification_failed(pc, format!("invalid byte swap width {}", insn.imm)));
+                        }
+                    }
+                    BPF_NEG if uses_imm => {}
+                    BPF_DIV | BPF_MOD if uses_imm && insn.imm == 0 => {
+                        return Err(verification_failed(pc, "division by constant zero"));
+                    }
+                    BPF_LSH | BPF_RSH | BPF_ARSH if uses_imm && !(0..shift_limit).contains(&insn.imm) => {
+                        return Err(verification_failed(pc, format!("shift by {} overflows", insn.imm)));
+                    }
+                    BPF_ADD | BPF_SUB | BPF_MUL | BPF_DIV | BPF_OR | BPF_AND | BPF_LSH | BPF_RSH
+                    | BPF_MOD | BPF_XOR | BPF_MOV | BPF_ARSH => {}
+                    _ => return Err(verification_failed(pc, format!("unknown opcode {:#04x}", insn.opc))),
+                }
+            }
+            BPF_LD => {
+                if insn.opc != LD_DW_IMM {
+                    return Err(verification_failed(pc, format!("unknown opcode {:#04x}", insn.opc)));
+                }
+                match Insn::decode(text, pc + 1) {
+                    Some(next) if next.opc == 0 => pc += 1,
+                    _ => return Err(verification_failed(pc, "incomplete lddw")),
+                }
+            }
+            BPF_LDX | BPF_ST | BPF_STX => {
+                let valid = matches!(
+                    insn.opc,
+                    LD_B_REG | LD_H_REG | LD_W_REG | LD_DW_REG
+                        | ST_B_IMM | ST_H_IMM | ST_W_IMM | ST_DW_IMM
+                        | ST_B_REG | ST_H_REG | ST_W_REG | ST_DW_REG
+                );
+                if !valid {
+                    return Err(verification_failed(pc, format!("unknown opcode {:#04x}", insn.opc)));
+                }
+            }
+            BPF_JMP => match insn.opc {
+                CALL_IMM | EXIT => {}
+                CALL_REG => {
+                    if !(0..=10).contains(&insn.imm) {
+                        return Err(verification_failed(pc, format!("invalid callx register r{}", insn.imm)));
+                    }
+                }
+                _ => {
+                    let conditional = matches!(
+                        op,
+                        BPF_JEQ | BPF_JGT | BPF_JGE | BPF_JSET | BPF_JNE | BPF_JSGT | BPF_JSGE
+                            | BPF_JLT | BPF_JLE | BPF_JSLT | BPF_JSLE
+                    );
+                    if insn.opc != JA && !conditional {
+                        return Err(verification_failed(pc, format!("unknown opcode {:#04x}", insn.opc)));
+                    }
+                    let target = pc as i64 + 1 + insn.off as i64;
+                    if target < 0 || target as usize >= insn_count {
+                        return Err(verification_failed(pc, format!("jump to {} is out of bounds", target)));
+                    }
+                    if target > 0 && is_lddw(target as usize - 1) {
+                        return Err(verification_failed(pc, "jump into the middle of lddw"));
+                    }
+                }
+            },
+            _ => return Err(verification_failed(pc, format!("unknown opcode {:#04x}", insn.opc))),
+        }
+        pc += 1;
+    }
+    Ok(())
+}
+
+/// Minimal ELF writer for building test programs
+#[cfg(test)]
+pub(crate) mod test_elf {
+    use super::*;
+
+    /// Build a shared object whose `.text` is `text`, followed by `rodata`.
+    ///
+    /// `syscalls` lists `(pc, name)` pairs for `call` instructions resolved by relocation;
+    /// `rodata_refs` lists `lddw` instructions whose immediate is an offset into `rodata`.
+    pub fn build(text: &[u8], rodata: &[u8], syscalls: &[(usize, &str)], rodata_refs: &[usize]) -> Vec<u8> {
+        let shstrtab = b"\0.text\0.rodata\0.dynsym\0.dynstr\0.rel.dyn\0.shstrtab\0";
+        let name_offset = |name: &str| {
+            let needle = format!("\0{}\0", name);
+            shstrtab.windows(needle.len()).position(|w| w == needle.as_bytes()).unwrap() as u32 + 1
+        };
+
+        let mut dynstr = vec![0u8];
+        let mut dynsym = vec![0u8; SYM_SIZE];
+        for (_, name) in syscalls {
+            let mut symbol = [0u8; SYM_SIZE];
+            symbol[0..4].copy_from_slice(&(dynstr.len() as u32).to_le_bytes());
+            dynsym.extend_from_slice(&symbol);
+            dynstr.extend_from_slice(name.as_bytes());
+            dynstr.push(0);
+        }
+
+        let text_offset = EHDR_SIZE;
+        let rodata_offset = text_offset + text.len();
+        let mut text = text.to_vec();
+        let mut rel_dyn = Vec::new();
+        for (index, (pc, _)) in syscalls.iter().enumerate() {
+            text[pc * INSN_SIZE + BYTE_OFFSET_IMMEDIATE..][..4].copy_from_slice(&(-1i32).to_le_bytes());
+            rel_dyn.extend_from_slice(&((text_offset + pc * INSN_SIZE) as u64).to_le_bytes());
+            rel_dyn.extend_from_slice(&((((index + 1) as u64) << 32) | R_BPF_64_32 as u64).to_le_bytes());
+        }
+        for pc in rodata_refs {
+            let imm = &mut text[pc * INSN_SIZE + BYTE_OFFSET_IMMEDIATE..][..4];
+            let offset = u32::from_le_bytes(imm.try_into().unwrap()) + rodata_offset as u32;
+            imm.copy_from_slice(&offset.to_le_bytes());
+            rel_dyn.extend_from_slice(&((text_offset + pc * INSN_SIZE) as u64).to_le_bytes());
+            rel_dyn.extend_from_slice(&(R_BPF_64_RELATIVE as u64).to_le_bytes());
+        }
+
+        let mut elf = vec![0u8; EHDR_SIZE];
+        elf.extend_from_slice(&text);
+        elf.extend_from_slice(rodata);
+        let place = |elf: &mut Vec<u8>, data: &[u8]| {
+            let offset = elf.len();
+            elf.extend_from_slice(data);
+            offset
+        };
+        let dynsym_offset = place(&mut elf, &dynsym);
+        let dynstr_offset = place(&mut elf, &dynstr);
+        let rel_offset = place(&mut elf, &rel_dyn);
+        let shstrtab_offset = place(&mut elf, shstrtab);
+        let shoff = elf.len();
+
+        // (name, type, offset, size, link)
+        let sections = [
+            ("", 0, 0, 0, 0),
+            (".text", 1, text_offset, text.len(), 0),
+            (".rodata", 1, rodata_offset, rodata.len(), 0),
+            (".dynsym", 11, dynsym_offset, dynsym.len(), 4),
+            (".dynstr", 3, dynstr_offset, dynstr.len(), 0),
+            (".rel.dyn", SHT_REL, rel_offset, rel_dyn.len(), 3),
+            (".shstrtab", 3, shstrtab_offset, shstrtab.len(), 0),
+        ];
+        for (name, sh_type, offset, size, link) in sections {
+            let mut header = [0u8; SHDR_SIZE];
+            if !name.is_empty() {
+                header[0..4].copy_from_slice(&name_offset(name).to_le_bytes());
+            }
+            header[4..8].copy_from_slice(&sh_type.to_le_bytes());
+            header[16..24].copy_from_slice(&(offset as u64).to_le_bytes());
+            header[24..32].copy_from_slice(&(offset as u64).to_le_bytes());
+            header[32..40].copy_from_slice(&(size as u64).to_le_bytes());
+            header[40..44].copy_from_slice(&(link as u32).to_le_bytes());
+            elf.extend_from_slice(&header);
+        }
+
+        elf[0..4].copy_from_slice(ELFMAG);
+        elf[4] = ELFCLASS64;
+        elf[5] = ELFDATA2LSB;
+        elf[6] = EV_CURRENT;
+        elf[0x10..0x12].copy_from_slice(&ET_DYN.to_le_bytes());
+        elf[0x12..0x14].copy_from_slice(&EM_BPF.to_le_bytes());
+        elf[0x14..0x18].copy_from_slice(&1u32.to_le_bytes());
+        elf[0x18..0x20].copy_from_slice(&(text_offset as u64).to_le_bytes());
+        elf[0x28..0x30].copy_from_slice(&(shoff as u64).to_le_bytes());
+        elf[0x34..0x36].copy_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+        elf[0x3a..0x3c].copy_from_slice(&(SHDR_SIZE as u16).to_le_bytes());
+        elf[0x3c..0x3e].copy_from_slice(&(sections.len() as u16).to_le_bytes());
+        elf[0x3e..0x40].copy_from_slice(&6u16.to_le_bytes());
+        elf
+    }
+
+    /// Assemble instructions into `.text` bytes
+    pub fn assemble(insns: &[Insn]) -> Vec<u8> {
+        insns.iter().flat_map(|insn| insn.to_bytes()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_elf::{assemble, build};
+    use super::*;
+    use crate::sbpf::{ContextObject, EbpfError, EbpfVm, MemoryMapping};
+    use ebpf::*;
+
+    struct TestContext;
+
+    impl ContextObject for TestContext {
+        fn consume(&mut self, _: u64) -> bool {
+            true
+        }
+
+        fn remaining(&self) -> u64 {
+            u64::MAX
+        }
+    }
+
+    fn syscalls() -> SyscallRegistry<TestContext> {
+        fn add_one(_: &mut TestContext, args: [u64; 5], _: &mut MemoryMapping) -> std::result::Result<u64, EbpfError> {
+            Ok(args[0] + 1)
+        }
+        let mut syscalls = SyscallRegistry::new();
+        syscalls.register("add_one", add_one);
+        syscalls
+    }
+
+    #[test]
+    fn test_load_relocate_and_run() {
+        // Load a u64 from rodata, pass it through a syscall and a local function
+        let mut text = Insn::lddw(1, 8).to_vec();
+        text.extend(assemble(&[
+            Insn::new(LD_DW_REG, 1, 1, 0, 0),
+            Insn::new(CALL_IMM, 0, 0, 0, -1),
+            Insn::new(MOV64_REG, 1, 0, 0, 0),
+            Insn::new(CALL_IMM, 0, 0, 0, 1),
+            Insn::new(EXIT, 0, 0, 0, 0),
+            // function: r0 = r1 * 2
+            Insn::new(MOV64_REG, 0, 1, 0, 0),
+            Insn::new(BPF_ALU64 | BPF_K | BPF_MUL, 0, 0, 0, 2),
+            Insn::new(EXIT, 0, 0, 0, 0),
+        ]));
+        let mut rodata = vec![0xffu8; 8];
+        rodata.extend_from_slice(&20u64.to_le_bytes());
+        let elf = build(&text, &rodata, &[(3, "add_one")], &[0]);
+
+        let syscalls = syscalls();
+        let executable = load_program(&elf, &syscalls).unwrap();
+        let result = EbpfVm::new(&executable, &syscalls, &mut TestContext, vec![]).execute();
+        assert_eq!(result, Ok(42));
+    }
+
+    #[test]
+    fn test_rejects_malformed_elf() {
+        let syscalls = syscalls();
+        let text = assemble(&[Insn::new(EXIT, 0, 0, 0, 0)]);
+
+        assert!(matches!(load_program(b"not an elf", &syscalls), Err(TerminatorError::InvalidElf(_))));
+
+        let mut wrong_machine = build(&text, &[], &[], &[]);
+        wrong_machine[0x12] = 62;
+        assert!(matches!(load_program(&wrong_machine, &syscalls), Err(TerminatorError::InvalidElf(_))));
+
+        let mut truncated = build(&text, &[], &[], &[]);
+        truncated.truncate(truncated.len() - 10);
+        assert!(matches!(load_program(&truncated, &syscalls), Err(TerminatorError::InvalidElf(_))));
+
+        // Read-only sections must have file data within the file, whatever their type
+        let section_header = |elf: &[u8], index: usize| read_u64(elf, 0x28).unwrap() as usize + index * SHDR_SIZE;
+        let mut nobits_rodata = build(&text, &[1, 2, 3, 4], &[], &[]);
+        let rodata = section_header(&nobits_rodata, 2);
+        nobits_rodata[rodata + 4..rodata + 8].copy_from_slice(&SHT_NOBITS.to_le_bytes());
+        nobits_rodata[rodata + 32..rodata + 40].copy_from_slice(&0x100000u64.to_le_bytes());
+        assert!(matches!(load_program(&nobits_rodata, &syscalls), Err(TerminatorError::InvalidElf(_))));
+
+        let mut nobits_text = build(&text, &[], &[], &[]);
+        let text_header = section_header(&nobits_text, 1);
+        nobits_text[text_header + 4..text_header + 8].copy_from_slice(&SHT_NOBITS.to_le_bytes());
+        assert!(matches!(load_program(&nobits_text, &syscalls), Err(TerminatorError::InvalidElf(_))));
+
+        let mut overflowing = build(&text, &[1, 2, 3, 4], &[], &[]);
+        let rodata = section_header(&overflowing, 2);
+        overflowing[rodata + 32..rodata + 40].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(load_program(&overflowing, &syscalls), Err(TerminatorError::InvalidElf(_))));
+
+        let calls_unknown = assemble(&[Insn::new(CALL_IMM, 0, 0, 0, -1), Insn::new(EXIT, 0, 0, 0, 0)]);
+        match load_program(&build(&calls_unknown, &[], &[(0, "sol_missing")], &[]), &syscalls) {
+            Err(TerminatorError::UnresolvedSymbol { symbol, pc }) => {
+                assert_eq!(symbol, "sol_missing");
+                assert_eq!(pc, 0);
+            }
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_verifier() {
+        let verify_text = |insns: &[Insn]| verify(&Executable::from_text_bytes(&assemble(insns)));
+
+        assert!(verify_text(&[Insn::new(MOV64_IMM, 0, 0, 0, 0), Insn::new(EXIT, 0, 0, 0, 0)]).is_ok());
+
+        let failure = |result: Result<()>| match result {
+            Err(TerminatorError::VerificationFailed { pc, reason }) => (pc, reason),
+            other => panic!("expected a verification failure, got {:?}", other),
+        };
+        assert_eq!(failure(verify_text(&[Insn::new(0xff, 0, 0, 0, 0)])).1, "unknown opcode 0xff");
+        assert_eq!(
+            failure(verify_text(&[Insn::new(MOV64_IMM, 0, 0, 0, 1), Insn::new(DIV64_IMM, 0, 0, 0, 0)])),
+            (1, "division by constant zero".to_string())
+        );
+        assert_eq!(failure(verify_text(&[Insn::new(JA, 0, 0, 5, 0)])).1, "jump to 6 is out of bounds");
+        assert_eq!(failure(verify_text(&[Insn::new(MOV64_IMM, 10, 0, 0, 0)])).1, "invalid destination register r10");
+        assert_eq!(failure(verify_text(&[Insn::new(BPF_ALU | BPF_K | BPF_LSH, 0, 0, 0, 32)])).1, "shift by 32 overflows");
+        assert_eq!(failure(verify_text(&[Insn::new(LD_DW_IMM, 0, 0, 0, 0)])).1, "incomplete lddw");
+
+        let mut into_lddw = assemble(&[Insn::new(JA, 0, 0, 1, 0)]);
+        into_lddw.extend(Insn::lddw(0, 1));
+        into_lddw.extend(assemble(&[Insn::new(EXIT, 0, 0, 0, 0)]));
+        assert_eq!(
+            failure(verify(&Executable::from_text_bytes(&into_lddw))).1,
+            "jump into the middle of lddw"
+        );
+    }
+}
@@ -1,21 +1,239 @@
+//! Batch execution: transactions that don't contend for accounts run in parallel.
+
+use crate::account_locks::{AccountLocks, TransactionAccountLocks};
+use crate::runtime::TerminatorRuntime;
+use crate::transaction_error::TransactionError;
 use crate::types::*;
-use crate::{Result};
+use crate::Result;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use tracing::debug;
+
+/// Transactions that execute together, holding their account locks
+#[derive(Default)]
+struct Wave {
+    account_locks: AccountLocks,
+    transactions: Vec<usize>,
+}
 
+/// Schedules batches of transactions into waves of non-conflicting transactions and executes
+/// each wave in parallel
 pub struct TransactionExecutor {
-    pub compute_budget: u64,
+    /// Most transactions executing at once
+    pub max_concurrent_transactions: usize,
 }
 
 impl TransactionExecutor {
-    pub fn new(compute_budget: u64) -> Self {
-        Self { compute_budget }
+    pub fn new(max_concurrent_transactions: usize) -> Self {
+        Self {
+            max_concurrent_transactions: max_concurrent_transactions.max(1),
+        }
+    }
+
+    /// Execute `transactions` against `runtime` with the same outcome as executing them in order.
+    ///
+    /// A transaction that conflicts with an earlier one is always scheduled in a later wave, so
+    /// every account sees its writes in the original order. Statuses, history and events are
+    /// recorded once every wave has run, in the original order as well.
+    pub fn execute_batch(
+        &self,
+        runtime: &mut TerminatorRuntime,
+        transactions: &[Transaction],
+    ) -> Vec<Result<TransactionResult>> {
+        let mut results: Vec<Option<Result<TransactionResult>>> = transactions.iter().map(|_| None).collect();
+        let mut written_accounts: Vec<Option<Vec<Pubkey>>> = vec![None; transactions.len()];
+        let mut message_hashes = vec![[0u8; 32]; transactions.len()];
+        let mut account_locks: Vec<TransactionAccountLocks> = vec![TransactionAccountLocks::default(); transactions.len()];
+        let mut waves: Vec<Wave> = Vec::new();
+        // Last wave each account was written or read in
+        let mut last_write: HashMap<Pubkey, usize> = HashMap::new();
+        let mut last_read: HashMap<Pubkey, usize> = HashMap::new();
+        let mut seen = HashSet::new();
+
+        for (index, txn) in transactions.iter().enumerate() {
+            let message_hash = match runtime.check_transaction(txn) {
+                Ok(message_hash) if seen.insert(message_hash) => message_hash,
+                Ok(_) => {
+                    results[index] = Some(Err(TransactionError::AlreadyProcessed.into()));
+                    continue;
+                }
+                Err(e) => {
+                    results[index] = Some(Err(e));
+                    continue;
+                }
+            };
+            let locks = txn.account_locks();
+
+            let after_writes = locks.readonly.iter().filter_map(|pubkey| last_write.get(pubkey));
+            let after_accesses = locks
+                .writable
+                .iter()
+                .flat_map(|pubkey| last_write.get(pubkey).into_iter().chain(last_read.get(pubkey)));
+            let mut wave = after_writes.chain(after_accesses).map(|wave| wave + 1).max().unwrap_or(0);
+            loop {
+                if wave == waves.len() {
+                    waves.push(Wave::default());
+                }
+                if waves[wave].transactions.len() < self.max_concurrent_transactions {
+                    match waves[wave].account_locks.try_lock(&locks) {
+                        Ok(()) => break,
+                        Err(TransactionError::AccountInUse) => {}
+                        Err(e) => {
+                            results[index] = Some(Err(e.into()));
+                            break;
+                        }
+                    }
+                }
+                wave += 1;
+            }
+            if results[index].is_some() {
+                continue;
+            }
+
+            waves[wave].transactions.push(index);
+            for pubkey in &locks.writable {
+                last_write.insert(*pubkey, wave);
+            }
+            for pubkey in &locks.readonly {
+                let last = last_read.entry(*pubkey).or_default();
+                *last = (*last).max(wave);
+            }
+            message_hashes[index] = message_hash;
+            account_locks[index] = locks;
+        }
+
+        for (number, mut wave) in waves.into_iter().enumerate() {
+            debug!("Executing wave {} of {} transactions", number, wave.transactions.len());
+            let shared: &TerminatorRuntime = runtime;
+            let processed: Vec<_> = wave
+                .transactions
+                .par_iter()
+                .map(|&index| shared.process_transaction(&transactions[index]))
+                .collect();
+
+            // Later waves read the accounts this one wrote
+            for (&index, processed) in wave.transactions.iter().zip(processed) {
+                results[index] = Some(processed.map(|(result, transaction_context)| {
+                    written_accounts[index] = Some(runtime.commit_accounts(transaction_context));
+                    result
+                }));
+                wave.account_locks.unlock(&account_locks[index]);
+            }
+        }
+
+        for (index, written_accounts) in written_accounts.into_iter().enumerate() {
+            if let (Some(written_accounts), Some(Ok(result))) = (written_accounts, &results[index]) {
+                runtime.record_transaction(&transactions[index], message_hashes[index], result, written_accounts);
+            }
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.expect("every transaction is either rejected or scheduled"))
+            .collect()
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::RuntimeEvent;
+    use crate::TerminatorError;
+
+    /// A Generic system transfer, so its write locks come from the account metas it declares
+    fn transfer(from: u8, to: u8, lamports: u64, blockhash: u8) -> Transaction {
+        let (from, to) = (Pubkey::new([from; 32]), Pubkey::new([to; 32]));
+        Transaction {
+            instructions: vec![Instruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![
+                    AccountMeta { pubkey: from, is_signer: true, is_writable: true },
+                    AccountMeta { pubkey: to, is_signer: false, is_writable: true },
+                ],
+                data: InstructionData::Generic { data: [vec![2], lamports.to_le_bytes().to_vec()].concat() },
+            }],
+            signatures: vec![[blockhash; 64]],
+            payer: from.0,
+            recent_blockhash: [blockhash; 32],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_matches_sequential_execution() {
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        // Every account also covers the fees of the transactions it pays for
+        for (owner, fees) in [(1u8, 2), (2, 1), (3, 0), (4, 2)] {
+            let lamports = 1_000 + fees * 5_000;
+            runtime.store_account(Pubkey::new([owner; 32]), Account::new(lamports, vec![], Pubkey::system_program().0));
+        }
+        for blockhash in 1..=5u8 {
+            runtime.register_recent_blockhash_for_test([blockhash; 32]);
+        }
+
+        // A chain through 1 -> 2 -> 3 only succeeds in order, 4 -> 5 is independent,
+        // and the last transfer fails once 1 has been drained
+        let transactions = vec![
+            transfer(1, 2, 600, 1),
+            transfer(4, 5, 100, 2),
+            transfer(2, 3, 1_500, 3),
+            transfer(4, 5, 100, 4),
+            transfer(1, 6, 500, 5),
+            transfer(1, 2, 600, 1),
+        ];
+        let mut sequential = runtime.clone();
+        let expected: Vec<_> = transactions.iter().map(|txn| sequential.execute_transaction(txn)).collect();
+
+        let results = TransactionExecutor::new(2).execute_batch(&mut runtime, &transactions);
+        assert_eq!(results.len(), expected.len());
+        for (result, expected) in results.iter().zip(&expected) {
+            match (result, expected) {
+                (Ok(result), Ok(expected)) => {
+                    assert_eq!(result.success, expected.success);
+                    assert_eq!(result.error, expected.error);
+                    assert_eq!(result.post_balances, expected.post_balances);
+                }
+                (Err(error), Err(expected)) => assert_eq!(error.to_string(), expected.to_string()),
+                other => panic!("results differ: {:?}", other),
+            }
+        }
+        assert!(results[2].as_ref().unwrap().success);
+        assert!(!results[4].as_ref().unwrap().success);
+        assert!(matches!(
+            results[5],
+            Err(TerminatorError::Transaction(TransactionError::AlreadyProcessed))
+        ));
+
+        for owner in 1..=6u8 {
+            let pubkey = Pubkey::new([owner; 32]);
+            assert_eq!(
+                runtime.get_account(&pubkey).map(|account| account.lamports),
+                sequential.get_account(&pubkey).map(|account| account.lamports),
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commits_recorded_in_input_order() {
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        for (owner, lamports) in [(1u8, 5_600), (2, 5_000), (4, 5_100)] {
+            runtime.store_account(Pubkey::new([owner; 32]), Account::new(lamports, vec![], Pubkey::system_program().0));
+        }
+        for blockhash in 1..=3u8 {
+            runtime.register_recent_blockhash_for_test([blockhash; 32]);
+        }
+        let mut events = runtime.subscribe_events();
+
+        // The second transfer waits a wave for the first, while the third runs alongside it
+        let transactions = vec![transfer(1, 2, 600, 1), transfer(2, 3, 100, 2), transfer(4, 5, 100, 3)];
+        let results = TransactionExecutor::new(2).execute_batch(&mut runtime, &transactions);
+        assert!(results.iter().all(|result| result.as_ref().unwrap().success));
 
-    pub fn execute(&self, _transaction: &Transaction) -> Result<TransactionResult> {
-        Ok(TransactionResult {
-            success: true,
-            compute_units_consumed: 100,
-            logs: vec!["Transaction executed successfully".to_string()],
-            error: None,
-        })
+        let mut signatures = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let RuntimeEvent::TransactionCommitted(committed) = event {
+                signatures.push(committed.signature.unwrap());
+            }
+        }
+        assert_eq!(signatures, vec![[1u8; 64], [2u8; 64], [3u8; 64]]);
     }
 }
@@ -1,8 +1,8 @@
 use crate::{Result, TerminatorError};
-use std::ptr;
 
 // Example Firedancer C library bindings
 // In real implementation, these would come from proper C bindings
+#[allow(dead_code)]
 extern "C" {
     // Ed25519 signature verification from Firedancer
     fn fd_ed25519_verify(
@@ -35,7 +35,7 @@ impl FiredancerCrypto {
     /// Verify an Ed25519 signature using Firedancer's optimized implementation
     pub fn verify_ed25519_signature(
         signature: &[u8; 64],
-        message: &[u8],
+        _message: &[u8],
         public_key: &[u8; 32],
     ) -> Result<bool> {
         if signature.len() != 64 {
@@ -52,7 +52,7 @@ impl FiredancerCrypto {
         //         message.as_ptr(),
         //         message.len(),
         //         public_key.as_ptr(),
-        //         ptr::null_mut(), // SHA context
+        //         std::ptr::null_mut(), // SHA context
         //     );
         //     Ok(result == 0)
         // }
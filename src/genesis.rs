@@ -0,0 +1,413 @@
+//! Genesis: the accounts, programs and cluster parameters a runtime starts from.
+//!
+//! Read from Solana's bincode `genesis.bin`, or from a readable TOML or JSON file that lists
+//! accounts with base64 data and `.so` programs to preload.
+
+use crate::sysvar::{EpochSchedule, Rent};
+use crate::types::*;
+use crate::{Result, TerminatorError};
+use base64::Engine;
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Seed of the faucet keypair funded by the default genesis
+const FAUCET_SEED: &[u8] = b"terminator-dancer faucet";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClusterType {
+    Testnet,
+    MainnetBeta,
+    Devnet,
+    #[default]
+    Development,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PohConfig {
+    pub target_tick_duration: Duration,
+    pub target_tick_count: Option<u64>,
+    pub hashes_per_tick: Option<u64>,
+}
+
+impl Default for PohConfig {
+    fn default() -> Self {
+        Self {
+            // 64 ticks per 400ms slot
+            target_tick_duration: Duration::from_micros(6_250),
+            target_tick_count: None,
+            hashes_per_tick: Some(12_500),
+        }
+    }
+}
+
+/// Bounds the fee per signature moves between with the cluster's load
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeRateGovernor {
+    pub target_lamports_per_signature: u64,
+    pub target_signatures_per_slot: u64,
+    pub min_lamports_per_signature: u64,
+    pub max_lamports_per_signature: u64,
+    pub burn_percent: u8,
+}
+
+impl FeeRateGovernor {
+    /// Fee per signature of the genesis slot, before any load was observed
+    pub fn initial_lamports_per_signature(&self) -> u64 {
+        if self.target_signatures_per_slot > 0 {
+            self.min_lamports_per_signature
+        } else {
+            self.target_lamports_per_signature
+        }
+    }
+}
+
+impl Default for FeeRateGovernor {
+    fn default() -> Self {
+        Self {
+            target_lamports_per_signature: 10_000,
+            target_signatures_per_slot: 20_000,
+            min_lamports_per_signature: 5_000,
+            max_lamports_per_signature: 100_000,
+            burn_percent: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Inflation {
+    pub initial: f64,
+    pub terminal: f64,
+    pub taper: f64,
+    pub foundation: f64,
+    pub foundation_term: f64,
+    pub unused: f64,
+}
+
+impl Default for Inflation {
+    fn default() -> Self {
+        Self {
+            initial: 0.08,
+            terminal: 0.015,
+            taper: 0.15,
+            foundation: 0.05,
+            foundation_term: 7.0,
+            unused: 0.0,
+        }
+    }
+}
+
+/// Solana's `GenesisConfig`, laid out as it is serialized in `genesis.bin`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenesisConfig {
+    pub creation_time: i64,
+    pub accounts: BTreeMap<Pubkey, Account>,
+    /// Builtin programs by name and program id
+    pub native_instruction_processors: Vec<(String, Pubkey)>,
+    pub rewards_pools: BTreeMap<Pubkey, Account>,
+    pub ticks_per_slot: u64,
+    pub unused: u64,
+    pub poh_config: PohConfig,
+    pub backwards_compat_with_v0_23: u64,
+    pub fee_rate_governor: FeeRateGovernor,
+    pub rent: Rent,
+    pub inflation: Inflation,
+    pub epoch_schedule: EpochSchedule,
+    pub cluster_type: ClusterType,
+}
+
+impl Default for GenesisConfig {
+    fn default() -> Self {
+        Self {
+            creation_time: 0,
+            accounts: BTreeMap::new(),
+            native_instruction_processors: Vec::new(),
+            rewards_pools: BTreeMap::new(),
+            ticks_per_slot: 64,
+            unused: u64::MAX,
+            poh_config: PohConfig::default(),
+            backwards_compat_with_v0_23: 0,
+            fee_rate_governor: FeeRateGovernor::default(),
+            rent: Rent::default(),
+            inflation: Inflation::default(),
+            epoch_schedule: EpochSchedule::default(),
+            cluster_type: ClusterType::Development,
+        }
+    }
+}
+
+impl GenesisConfig {
+    /// Default genesis with `lamports` in the faucet account
+    pub fn with_faucet(lamports: u64) -> Self {
+        let mut genesis = Self::default();
+        if lamports > 0 {
+            genesis.accounts.insert(faucet_pubkey(), Account::new(lamports, vec![], Pubkey::system_program().0));
+        }
+        genesis
+    }
+
+    /// Load a genesis file: `genesis.bin` in Solana's format, `.json` or otherwise TOML
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| genesis_error(format!("{}: {}", path.display(), e)))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("bin") => bincode::deserialize(&bytes).map_err(genesis_error),
+            Some("json") => serde_json::from_slice::<GenesisFile>(&bytes)
+                .map_err(genesis_error)?
+                .into_genesis_config(base_dir),
+            _ => {
+                let text = String::from_utf8(bytes).map_err(genesis_error)?;
+                toml::from_str::<GenesisFile>(&text)
+                    .map_err(genesis_error)?
+                    .into_genesis_config(base_dir)
+            }
+        }
+    }
+
+    /// Write in Solana's `genesis.bin` format
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let bytes = bincode::serialize(self).map_err(genesis_error)?;
+        fs::write(path, bytes).map_err(genesis_error)
+    }
+
+    /// sha256 of the serialized config, the first blockhash of the cluster
+    pub fn hash(&self) -> [u8; 32] {
+        let bytes = bincode::serialize(self).expect("genesis config serializes");
+        Sha256::digest(bytes).into()
+    }
+
+    /// State of the genesis slot: every account, the rewards pools and an executable account per
+    /// builtin, with the genesis hash as its blockhash
+    pub fn bank_state(&self) -> BankState {
+        let mut state = BankState::new();
+        state.blockhash = self.hash();
+        state.fee_calculator = FeeCalculator {
+            lamports_per_signature: self.fee_rate_governor.initial_lamports_per_signature(),
+        };
+        for (name, program_id) in &self.native_instruction_processors {
+            state.accounts.insert(
+                *program_id,
+                Account::new_executable(1, name.as_bytes().to_vec(), Pubkey::native_loader().0),
+            );
+        }
+        for (pubkey, account) in self.accounts.iter().chain(&self.rewards_pools) {
+            state.accounts.insert(*pubkey, account.clone());
+        }
+        state
+    }
+}
+
+/// Keypair of the faucet the default genesis funds
+pub fn faucet_keypair() -> SigningKey {
+    SigningKey::from_bytes(&Sha256::digest(FAUCET_SEED).into())
+}
+
+pub fn faucet_pubkey() -> Pubkey {
+    Pubkey::new(faucet_keypair().verifying_key().to_bytes())
+}
+
+/// Readable genesis file. Addresses are base58 and account data base64; program paths are
+/// relative to the file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenesisFile {
+    pub creation_time: i64,
+    pub cluster_type: ClusterType,
+    pub ticks_per_slot: Option<u64>,
+    pub hashes_per_tick: Option<u64>,
+    pub rent: Option<Rent>,
+    pub epoch_schedule: Option<GenesisEpochSchedule>,
+    pub fee_rate_governor: Option<FeeRateGovernor>,
+    pub inflation: Option<Inflation>,
+    pub accounts: Vec<GenesisAccount>,
+    pub builtins: Vec<GenesisBuiltin>,
+    pub programs: Vec<GenesisProgram>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenesisEpochSchedule {
+    pub slots_per_epoch: u64,
+    #[serde(default)]
+    pub warmup: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenesisAccount {
+    pub pubkey: String,
+    pub lamports: u64,
+    /// base64
+    #[serde(default)]
+    pub data: String,
+    /// The system program if unset
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub executable: bool,
+    #[serde(default)]
+    pub rent_epoch: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenesisBuiltin {
+    pub name: String,
+    pub program_id: String,
+}
+
+/// A program deployed through the BPF loader at genesis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenesisProgram {
+    pub program_id: String,
+    /// Path of the `.so` file
+    pub path: String,
+}
+
+impl GenesisFile {
+    /// Resolve addresses, decode account data and read programs relative to `base_dir`
+    pub fn into_genesis_config(self, base_dir: &Path) -> Result<GenesisConfig> {
+        let mut genesis = GenesisConfig {
+            creation_time: self.creation_time,
+            cluster_type: self.cluster_type,
+            ..GenesisConfig::default()
+        };
+        if let Some(ticks_per_slot) = self.ticks_per_slot {
+            genesis.ticks_per_slot = ticks_per_slot;
+        }
+        if let Some(hashes_per_tick) = self.hashes_per_tick {
+            genesis.poh_config.hashes_per_tick = Some(hashes_per_tick);
+        }
+        if let Some(rent) = self.rent {
+            genesis.rent = rent;
+        }
+        if let Some(epoch_schedule) = self.epoch_schedule {
+            genesis.epoch_schedule = EpochSchedule::new(epoch_schedule.slots_per_epoch, epoch_schedule.warmup);
+        }
+        if let Some(fee_rate_governor) = self.fee_rate_governor {
+            genesis.fee_rate_governor = fee_rate_governor;
+        }
+        if let Some(inflation) = self.inflation {
+            genesis.inflation = inflation;
+        }
+
+        for account in self.accounts {
+            let data = base64::engine::general_purpose::STANDARD
+                .decode(&account.data)
+                .map_err(|e| genesis_error(format!("data of {}: {}", account.pubkey, e)))?;
+            let owner = match &account.owner {
+                Some(owner) => owner.parse::<Pubkey>()?,
+                None => Pubkey::system_program(),
+            };
+            genesis.accounts.insert(
+                account.pubkey.parse()?,
+                Account {
+                    lamports: account.lamports,
+                    data,
+                    owner: owner.0,
+                    executable: account.executable,
+                    rent_epoch: account.rent_epoch,
+                },
+            );
+        }
+        for builtin in self.builtins {
+            genesis.native_instruction_processors.push((builtin.name, builtin.program_id.parse()?));
+        }
+        for program in self.programs {
+            let path = base_dir.join(&program.path);
+            let elf = fs::read(&path).map_err(|e| genesis_error(format!("{}: {}", path.display(), e)))?;
+            let lamports = genesis.rent.minimum_balance(elf.len()).max(1);
+            genesis.accounts.insert(
+                program.program_id.parse()?,
+                Account::new_executable(lamports, elf, Pubkey::bpf_loader().0),
+            );
+        }
+        Ok(genesis)
+    }
+}
+
+fn genesis_error(e: impl ToString) -> TerminatorError {
+    TerminatorError::GenesisError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_genesis_bin_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut genesis = GenesisConfig::with_faucet(1_000);
+        genesis.native_instruction_processors.push(("vote_program".to_string(), Pubkey::new([7u8; 32])));
+        let path = dir.path().join("genesis.bin");
+        genesis.write(&path).unwrap();
+
+        let loaded = GenesisConfig::load(&path).unwrap();
+        assert_eq!(loaded.hash(), genesis.hash());
+        assert_eq!(loaded.accounts[&faucet_pubkey()].lamports, 1_000);
+
+        let state = loaded.bank_state();
+        assert_eq!(state.blockhash, genesis.hash());
+        assert_eq!(state.fee_calculator.lamports_per_signature, 5_000);
+        let builtin = &state.accounts[&Pubkey::new([7u8; 32])];
+        assert!(builtin.executable);
+        assert_eq!(builtin.owner, Pubkey::native_loader().0);
+        assert_ne!(GenesisConfig::with_faucet(1).hash(), genesis.hash());
+    }
+
+    #[test]
+    fn test_readable_genesis() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("noop.so"), b"\x7fELF program").unwrap();
+        let account = bs58::encode([1u8; 32]).into_string();
+        let owner = bs58::encode([2u8; 32]).into_string();
+        let program = bs58::encode([3u8; 32]).into_string();
+        let toml = format!(
+            r#"
+            ticks_per_slot = 8
+            hashes_per_tick = 100
+
+            [rent]
+            lamports_per_byte_year = 1
+            exemption_threshold = 1.0
+            burn_percent = 0
+
+            [epoch_schedule]
+            slots_per_epoch = 32
+
+            [[accounts]]
+            pubkey = "{account}"
+            lamports = 500
+            data = "AQID"
+            owner = "{owner}"
+
+            [[programs]]
+            program_id = "{program}"
+            path = "noop.so"
+            "#
+        );
+        let toml_path = dir.path().join("genesis.toml");
+        fs::write(&toml_path, toml).unwrap();
+        let genesis = GenesisConfig::load(&toml_path).unwrap();
+        assert_eq!(genesis.ticks_per_slot, 8);
+        assert_eq!(genesis.poh_config.hashes_per_tick, Some(100));
+        assert_eq!(genesis.epoch_schedule, EpochSchedule::new(32, false));
+        let stored = &genesis.accounts[&Pubkey::new([1u8; 32])];
+        assert_eq!((stored.lamports, stored.data.as_slice(), stored.owner), (500, &[1u8, 2, 3][..], [2u8; 32]));
+        let deployed = &genesis.accounts[&Pubkey::new([3u8; 32])];
+        assert!(deployed.executable);
+        assert_eq!(deployed.owner, Pubkey::bpf_loader().0);
+        assert_eq!(deployed.data, b"\x7fELF program");
+
+        let json_path = dir.path().join("genesis.json");
+        fs::write(&json_path, format!(r#"{{"ticks_per_slot": 8, "accounts": [{{"pubkey": "{account}", "lamports": 9}}]}}"#)).unwrap();
+        let genesis = GenesisConfig::load(&json_path).unwrap();
+        assert_eq!(genesis.accounts[&Pubkey::new([1u8; 32])].owner, Pubkey::system_program().0);
+
+        fs::write(&json_path, r#"{"accounts": [{"pubkey": "not base58!", "lamports": 9}]}"#).unwrap();
+        assert!(GenesisConfig::load(&json_path).is_err());
+    }
+}
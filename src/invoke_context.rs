@@ -0,0 +1,208 @@
+use crate::bpf_loader::{instruction_data, SerializedAccount};
+use crate::sbpf::ContextObject;
+use crate::solana_format::CompiledInstruction;
+use crate::sysvar::SysvarCache;
+use crate::transaction_context::TransactionContext;
+use crate::transaction_error::InstructionError;
+use crate::transaction_status::InnerInstruction;
+use crate::types::*;
+
+type InstructionResult = std::result::Result<(), InstructionError>;
+
+/// Executes instructions issued by programs through cross-program invocation
+pub trait InstructionProcessor {
+    fn process_instruction(
+        &self,
+        instruction: &Instruction,
+        transaction_context: &mut TransactionContext,
+        execution_context: &mut ExecutionContext,
+    ) -> InstructionResult;
+
+    /// Whether `program_id` is handled natively and so needs no executable account
+    fn is_builtin(&self, _program_id: &Pubkey) -> bool {
+        false
+    }
+}
+
+impl<F> InstructionProcessor for F
+where
+    F: Fn(&Instruction, &mut TransactionContext, &mut ExecutionContext) -> InstructionResult,
+{
+    fn process_instruction(
+        &self,
+        instruction: &Instruction,
+        transaction_context: &mut TransactionContext,
+        execution_context: &mut ExecutionContext,
+    ) -> InstructionResult {
+        self(instruction, transaction_context, execution_context)
+    }
+}
+
+/// State available to a program while it executes: the transaction's accounts,
+/// the compute meter and the log collector
+pub struct InvokeContext<'a> {
+    pub transaction_context: &'a mut TransactionContext,
+    pub execution_context: &'a mut ExecutionContext,
+    /// Where the program's accounts live in its input region, used to sync them across invocations
+    pub serialized_accounts: Vec<SerializedAccount>,
+    sysvars: &'a SysvarCache,
+    processor: &'a dyn InstructionProcessor,
+    instruction: &'a Instruction,
+}
+
+impl<'a> InvokeContext<'a> {
+    pub fn new(
+        transaction_context: &'a mut TransactionContext,
+        execution_context: &'a mut ExecutionContext,
+        sysvars: &'a SysvarCache,
+        processor: &'a dyn InstructionProcessor,
+        instruction: &'a Instruction,
+    ) -> Self {
+        Self {
+            transaction_context,
+            execution_context,
+            serialized_accounts: Vec::new(),
+            sysvars,
+            processor,
+            instruction,
+        }
+    }
+
+    /// Program currently executing
+    pub fn program_id(&self) -> Pubkey {
+        self.instruction.program_id
+    }
+
+    /// Instruction currently executing
+    pub fn instruction(&self) -> &'a Instruction {
+        self.instruction
+    }
+
+    /// Sysvars for the slot the transaction executes in
+    pub fn sysvars(&self) -> &'a SysvarCache {
+        self.sysvars
+    }
+
+    pub fn log(&mut self, message: String) {
+        self.execution_context.log(message);
+    }
+
+    /// Signer and writable privileges the current instruction grants for `pubkey`
+    fn privileges(&self, pubkey: &Pubkey) -> Option<(bool, bool)> {
+        let mut metas = self.instruction.accounts.iter().filter(|meta| meta.pubkey == *pubkey).peekable();
+        let is_named = self.instruction.data.accounts().contains(pubkey);
+        if metas.peek().is_none() && !is_named {
+            return None;
+        }
+        let is_writable = is_named || metas.any(|meta| meta.is_writable);
+        Some((self.transaction_context.is_signer(pubkey), is_writable))
+    }
+
+    /// Check that `instruction` only uses privileges the current instruction holds.
+    ///
+    /// `signers` are program derived addresses the current program signs for.
+    fn check_privileges(&mut self, instruction: &Instruction, signers: &[Pubkey]) -> InstructionResult {
+        for meta in &instruction.accounts {
+            let Some((is_signer, is_writable)) = self.privileges(&meta.pubkey) else {
+                self.log(format!("Instruction references an unknown account {}", meta.pubkey));
+                return Err(InstructionError::MissingAccount);
+            };
+            if meta.is_writable && !is_writable {
+                self.log(format!("{}'s writable privilege escalated", meta.pubkey));
+                return Err(InstructionError::PrivilegeEscalation);
+            }
+            if meta.is_signer && !is_signer && !signers.contains(&meta.pubkey) {
+                self.log(format!("{}'s signer privilege escalated", meta.pubkey));
+                return Err(InstructionError::PrivilegeEscalation);
+            }
+        }
+        // Typed system instructions write every account they name
+        for pubkey in instruction.data.accounts() {
+            match self.privileges(&pubkey) {
+                None => {
+                    self.log(format!("Instruction references an unknown account {}", pubkey));
+                    return Err(InstructionError::MissingAccount);
+                }
+                Some((_, false)) => {
+                    self.log(format!("{}'s writable privilege escalated", pubkey));
+                    return Err(InstructionError::PrivilegeEscalation);
+                }
+                Some(_) => {}
+            }
+        }
+        // and need the signatures of the accounts they debit or reassign
+        for pubkey in instruction.data.signer_accounts() {
+            if !self.transaction_context.is_signer(&pubkey) && !signers.contains(&pubkey) {
+                self.log(format!("{}'s signer privilege escalated", pubkey));
+                return Err(InstructionError::PrivilegeEscalation);
+            }
+        }
+
+        let program_id = instruction.program_id;
+        if self.privileges(&program_id).is_none() {
+            self.log(format!("Unknown program {}", program_id));
+            return Err(InstructionError::MissingAccount);
+        }
+        let is_native = self.processor.is_builtin(&program_id);
+        let executable = self
+            .transaction_context
+            .get(&program_id)
+            .is_some_and(|account| account.executable);
+        if !is_native && !executable {
+            self.log(format!("Account {} is not executable", program_id));
+            return Err(InstructionError::AccountNotExecutable);
+        }
+        Ok(())
+    }
+
+    /// Execute `instruction` on behalf of the current program, recording it as an inner instruction
+    pub fn invoke(&mut self, instruction: &Instruction, signers: &[Pubkey]) -> InstructionResult {
+        self.check_privileges(instruction, signers)?;
+        // The caller's own changes must hold up before the callee sees them
+        self.transaction_context.verify_instruction()?;
+        self.transaction_context.push_instruction(instruction)?;
+
+        let compiled = self.compile_instruction(instruction);
+        let stack_height = self.transaction_context.instruction_stack_height() as u32;
+        let index = self.transaction_context.top_level_instruction_index() as u8;
+        self.transaction_context.record_inner_instruction(
+            index,
+            InnerInstruction {
+                instruction: compiled,
+                stack_height: Some(stack_height),
+            },
+        );
+
+        let result = self
+            .processor
+            .process_instruction(instruction, self.transaction_context, self.execution_context)
+            .and_then(|()| self.transaction_context.verify_instruction());
+        self.transaction_context.pop_instruction();
+        result
+    }
+
+    /// Express `instruction` in terms of the transaction's account indexes
+    fn compile_instruction(&self, instruction: &Instruction) -> CompiledInstruction {
+        let index_of = |pubkey: &Pubkey| self.transaction_context.find_index(pubkey).unwrap_or_default() as u8;
+        CompiledInstruction {
+            program_id_index: index_of(&instruction.program_id),
+            accounts: instruction.accounts.iter().map(|meta| index_of(&meta.pubkey)).collect(),
+            data: instruction_data(&instruction.data),
+        }
+    }
+}
+
+impl ContextObject for InvokeContext<'_> {
+    fn consume(&mut self, units: u64) -> bool {
+        if self.execution_context.consume_compute_units(units) {
+            return true;
+        }
+        // An exhausted meter stays exhausted for the rest of the transaction
+        self.execution_context.compute_units_remaining = 0;
+        false
+    }
+
+    fn remaining(&self) -> u64 {
+        self.execution_context.compute_units_remaining
+    }
+}
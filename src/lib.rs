@@ -1,5 +1,12 @@
 pub mod runtime;
 pub mod bank;
+pub mod bank_forks;
+pub mod accounts_hash;
+pub mod accounts_db;
+pub mod snapshot;
+pub mod solana_snapshot;
+pub mod genesis;
+pub mod account_dump;
 pub mod executor;
 pub mod conformance;
 pub mod fuzzing;
@@ -8,16 +15,61 @@ pub mod utils;
 pub mod firedancer_integration;
 pub mod crypto;
 pub mod solana_format;
+pub mod status_cache;
+pub mod transaction_context;
+pub mod account_locks;
+pub mod pre_account;
+pub mod transaction_error;
+pub mod transaction_status;
+pub mod sbpf;
+pub mod bpf_loader;
+pub mod invoke_context;
+pub mod elf_loader;
+pub mod syscalls;
+pub mod cpi;
+pub mod builtins;
+pub mod upgradeable_loader;
+pub mod program_cache;
+pub mod poh;
+pub mod blockhash_queue;
+pub mod block_producer;
+pub mod rpc;
+pub mod pubsub;
+pub mod tpu;
+pub mod sysvar;
 
-pub use runtime::TerminatorRuntime;
+pub use runtime::{SharedRuntime, TerminatorRuntime};
 pub use bank::Bank;
+pub use bank_forks::BankForks;
+pub use accounts_db::{AccountsDb, AccountsDbConfig};
+pub use snapshot::Snapshot;
+pub use solana_snapshot::{SnapshotFilter, SolanaSnapshot};
+pub use genesis::GenesisConfig;
+pub use account_dump::{CliAccount, UiAccount};
 pub use executor::TransactionExecutor;
 pub use conformance::ConformanceHarness;
 pub use fuzzing::RuntimeFuzzer;
 pub use firedancer_integration::{FiredancerCrypto, FiredancerValidator, FiredancerConformanceTest};
-pub use types::{Transaction, Account, Instruction, InstructionData, Pubkey, AccountMeta, TransactionResult};
+pub use types::{Transaction, Account, Instruction, InstructionData, Pubkey, AccountMeta, TransactionResult, SimulationConfig, SimulationResult};
 pub use crypto::{SolanaCrypto, FastCrypto, AddressDerivation};
 pub use solana_format::{SolanaTransaction, SolanaTransactionParser, SolanaPubkey, SolanaHash};
+pub use status_cache::{StatusCache, TransactionStatus};
+pub use transaction_context::TransactionContext;
+pub use account_locks::{AccountLocks, TransactionAccountLocks};
+pub use transaction_error::{TransactionError, InstructionError};
+pub use transaction_status::{TransactionTokenBalance, UiTokenAmount, InnerInstructions, TransactionReturnData, LoadedAddresses};
+pub use sbpf::{EbpfVm, EbpfError, Executable};
+pub use invoke_context::{InvokeContext, InstructionProcessor};
+pub use builtins::{BuiltinProgram, BuiltinRegistry};
+pub use poh::{Entry, PohRecorder};
+pub use blockhash_queue::BlockhashQueue;
+pub use block_producer::{Block, BlockProducer};
+pub use rpc::JsonRpcService;
+pub use pubsub::PubSubService;
+pub use tpu::{TpuService, TpuStats};
+pub use upgradeable_loader::{UpgradeableLoaderInstruction, UpgradeableLoaderState};
+pub use program_cache::{ProgramCache, ProgramCacheStats};
+pub use sysvar::{Clock, Rent, EpochSchedule, SysvarCache};
 
 #[derive(Debug, thiserror::Error)]
 pub enum TerminatorError {
@@ -41,6 +93,36 @@ pub enum TerminatorError {
     
     #[error("Conformance test failed: {0}")]
     ConformanceTestFailed(String),
+
+    #[error(transparent)]
+    Transaction(#[from] TransactionError),
+
+    #[error("Invalid seeds: {0}")]
+    InvalidSeeds(String),
+
+    #[error("Invalid ELF: {0}")]
+    InvalidElf(String),
+
+    #[error("Unresolved symbol {symbol} at instruction {pc}")]
+    UnresolvedSymbol { symbol: String, pc: usize },
+
+    #[error("Program verification failed at instruction {pc}: {reason}")]
+    VerificationFailed { pc: usize, reason: String },
+
+    #[error("Bank error: {0}")]
+    BankError(String),
+
+    #[error("Accounts db error: {0}")]
+    AccountsDbError(String),
+
+    #[error("Snapshot error: {0}")]
+    SnapshotError(String),
+
+    #[error("Genesis error: {0}")]
+    GenesisError(String),
+
+    #[error("RPC error: {0}")]
+    RpcError(String),
 }
 
 pub type Result<T> = std::result::Result<T, TerminatorError>;
@@ -63,6 +145,7 @@ mod tests {
         
         let program_id = Pubkey::new_unique();
         let account = Pubkey::new_unique();
+        runtime.store_account(account, Account::new(1_000_000, vec![], Pubkey::system_program().0));
         
         let instruction = Instruction {
             program_id,
@@ -78,7 +161,7 @@ mod tests {
             instructions: vec![instruction],
             signatures: vec![[0u8; 64]],
             payer: account.0,
-            recent_blockhash: [1u8; 32],
+            recent_blockhash: runtime.latest_blockhash(),
         };
 
         let result = runtime.execute_transaction(&transaction);
@@ -86,6 +169,1023 @@ mod tests {
         assert!(result.unwrap().success);
     }
 
+    #[tokio::test]
+    async fn test_duplicate_transaction_rejected() {
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        
+        let from = Pubkey::new([7u8; 32]);
+        let to = Pubkey::new([8u8; 32]);
+        let transaction = Transaction {
+            instructions: vec![Instruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![
+                    AccountMeta { pubkey: from, is_signer: true, is_writable: true },
+                    AccountMeta { pubkey: to, is_signer: false, is_writable: true },
+                ],
+                data: InstructionData::Transfer { from: from.0, to: to.0, lamports: 1000 },
+            }],
+            signatures: vec![[9u8; 64]],
+            payer: from.0,
+            recent_blockhash: runtime.latest_blockhash(),
+        };
+
+        let result = runtime.execute_transaction(&transaction).unwrap();
+        assert!(result.success);
+        assert_eq!(result.fee, 5000);
+        assert_eq!(transaction.account_keys(), vec![from, to, Pubkey::system_program()]);
+        assert_eq!(result.pre_balances[..2], [10_000_000, 0]);
+        assert_eq!(result.post_balances[..2], [10_000_000 - 1000 - 5000, 1000]);
+        assert!(matches!(
+            runtime.execute_transaction(&transaction),
+            Err(TerminatorError::Transaction(TransactionError::AlreadyProcessed))
+        ));
+        assert!(runtime.get_signature_status(&[9u8; 64]).unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_expired_blockhash_rejected() {
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        let to = Pubkey::new([8u8; 32]);
+        let transaction = Transaction {
+            instructions: vec![Instruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![],
+                data: InstructionData::Transfer { from: [7u8; 32], to: to.0, lamports: 10 },
+            }],
+            signatures: vec![[9u8; 64]],
+            payer: [7u8; 32],
+            recent_blockhash: runtime.latest_blockhash(),
+        };
+        assert!(runtime.execute_transaction(&transaction).unwrap().success);
+
+        // Once the blockhash expires, so does its status cache entry, and the transaction can't
+        // be replayed
+        for slot in 0..400u32 {
+            let mut blockhash = [0u8; 32];
+            blockhash[..4].copy_from_slice(&slot.to_le_bytes());
+            runtime.freeze_slot(blockhash);
+            runtime.advance_slot();
+        }
+        assert!(!runtime.is_blockhash_valid(&transaction.recent_blockhash));
+        assert!(matches!(
+            runtime.execute_transaction(&transaction),
+            Err(TerminatorError::Transaction(TransactionError::BlockhashNotFound))
+        ));
+        assert!(matches!(
+            runtime.execute_transaction(&Transaction { recent_blockhash: [1u8; 32], ..transaction }),
+            Err(TerminatorError::Transaction(TransactionError::BlockhashNotFound))
+        ));
+        assert_eq!(runtime.get_account(&to).unwrap().lamports, 10);
+    }
+
+    #[tokio::test]
+    async fn test_drained_account_is_removed() {
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        let from = Pubkey::new([28u8; 32]);
+        runtime.store_account(from, Account::new(10_000, vec![1, 2, 3], Pubkey::system_program().0));
+        runtime.advance_slot();
+
+        // The transfer and the fee take every lamport
+        let transaction = Transaction::transfer_for_test(28, 29, 5_000, runtime.latest_blockhash());
+        assert!(runtime.execute_transaction(&transaction).unwrap().success);
+
+        assert!(runtime.get_account(&from).is_none());
+        let bank = runtime.bank();
+        assert!(!bank.accounts().contains_key(&from));
+        // The parent still holds the account, so this slot only shadows it
+        let (_, tombstone) = bank.accounts_delta().find(|(pubkey, _)| **pubkey == from).unwrap();
+        assert_eq!(*tombstone, Account::new(0, vec![], Pubkey::system_program().0));
+        assert_eq!(bank.calculate_accounts_lt_hash(), *bank.accounts_lt_hash());
+        assert!(runtime.snapshot().accounts.iter().all(|(pubkey, _)| *pubkey != from));
+    }
+
+    #[tokio::test]
+    async fn test_fee_is_charged() {
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        let from = Pubkey::new([24u8; 32]);
+        let to = Pubkey::new([25u8; 32]);
+        runtime.store_account(from, Account::new(1_000_000, vec![], Pubkey::system_program().0));
+        let transaction = Transaction {
+            instructions: vec![Instruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![
+                    AccountMeta { pubkey: from, is_signer: true, is_writable: true },
+                    AccountMeta { pubkey: to, is_signer: false, is_writable: true },
+                ],
+                data: InstructionData::Transfer { from: from.0, to: to.0, lamports: 1000 },
+            }],
+            signatures: vec![[24u8; 64], [25u8; 64]],
+            payer: from.0,
+            recent_blockhash: runtime.latest_blockhash(),
+        };
+
+        let result = runtime.execute_transaction(&transaction).unwrap();
+        assert!(result.success);
+        assert_eq!(result.fee, 2 * 5000);
+        assert_eq!(result.pre_balances[0] - result.post_balances[0], result.fee + 1000);
+        assert_eq!(result.post_balances[1] - result.pre_balances[1], 1000);
+        assert_eq!(runtime.get_account(&from).unwrap().lamports, result.post_balances[0]);
+
+        // A payer that can't cover the fee is rejected without a recorded status
+        let unfunded = Pubkey::new([26u8; 32]);
+        let transaction = Transaction {
+            instructions: vec![Instruction {
+                program_id: Pubkey::new([27u8; 32]),
+                accounts: vec![],
+                data: InstructionData::Generic { data: vec![] },
+            }],
+            signatures: vec![[26u8; 64]],
+            payer: unfunded.0,
+            recent_blockhash: runtime.latest_blockhash(),
+        };
+        assert!(matches!(
+            runtime.execute_transaction(&transaction),
+            Err(TerminatorError::Transaction(TransactionError::InsufficientFundsForFee))
+        ));
+        assert!(runtime.get_signature_status(&[26u8; 64]).is_none());
+        assert!(runtime.get_account(&unfunded).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failed_transaction_is_rolled_back() {
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        
+        let from = Pubkey::new([10u8; 32]);
+        let to = Pubkey::new([11u8; 32]);
+        let unfunded = Pubkey::new([12u8; 32]);
+        let transaction = Transaction {
+            instructions: vec![
+                Instruction {
+                    program_id: Pubkey::system_program(),
+                    accounts: vec![
+                        AccountMeta { pubkey: from, is_signer: true, is_writable: true },
+                        AccountMeta { pubkey: to, is_signer: false, is_writable: true },
+                    ],
+                    data: InstructionData::Transfer { from: from.0, to: to.0, lamports: 1000 },
+                },
+                Instruction {
+                    program_id: Pubkey::system_program(),
+                    accounts: vec![
+                        AccountMeta { pubkey: unfunded, is_signer: true, is_writable: true },
+                        AccountMeta { pubkey: to, is_signer: false, is_writable: true },
+                    ],
+                    data: InstructionData::Generic { data: vec![2, 232, 3, 0, 0, 0, 0, 0, 0] },
+                },
+            ],
+            signatures: vec![[13u8; 64]],
+            payer: from.0,
+            recent_blockhash: runtime.latest_blockhash(),
+        };
+
+        let result = runtime.execute_transaction(&transaction).unwrap();
+        assert!(!result.success);
+        assert_eq!(
+            result.error,
+            Some(TransactionError::InstructionError(1, InstructionError::Custom(1)))
+        );
+        // Only the fee is charged
+        assert_eq!(runtime.get_account(&from).unwrap().lamports, 10_000_000 - 5000);
+        assert!(runtime.get_account(&to).is_none());
+        assert_eq!(result.post_balances[0], result.pre_balances[0] - result.fee);
+        assert_eq!(result.pre_balances[1..], result.post_balances[1..]);
+        assert!(!runtime.get_signature_status(&[13u8; 64]).unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_replay_on_forks() {
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        let from = Pubkey::new([14u8; 32]);
+        let to = Pubkey::new([15u8; 32]);
+        let blockhash = runtime.latest_blockhash();
+        let transfer = |lamports: u64, signature: u8| Transaction {
+            instructions: vec![Instruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![],
+                data: InstructionData::Transfer { from: from.0, to: to.0, lamports },
+            }],
+            signatures: vec![[signature; 64]],
+            payer: from.0,
+            recent_blockhash: blockhash,
+        };
+
+        // Slot 0 funds the accounts, slots 1 and 2 fork from it
+        assert!(runtime.execute_transaction(&transfer(100, 1)).unwrap().success);
+        runtime.advance_slot();
+        assert!(runtime.execute_transaction(&transfer(50, 2)).unwrap().success);
+        let fork_a_hash = runtime.freeze_slot([1u8; 32]);
+        runtime.new_bank_from_parent(0, 2).unwrap();
+        assert_eq!(runtime.get_account(&to).unwrap().lamports, 100);
+
+        // The transaction of the other fork isn't a duplicate here
+        assert!(runtime.execute_transaction(&transfer(50, 2)).unwrap().success);
+        assert!(runtime.execute_transaction(&transfer(7, 3)).unwrap().success);
+        let fork_b_hash = runtime.freeze_slot([2u8; 32]);
+        assert_ne!(fork_a_hash, fork_b_hash);
+        assert!(matches!(
+            runtime.execute_transaction(&transfer(1, 4)),
+            Err(TerminatorError::BankError(_))
+        ));
+        assert_eq!(runtime.bank_forks().get(1).unwrap().get_account(&to).unwrap().lamports, 150);
+        assert_eq!(runtime.bank_forks().get(2).unwrap().get_account(&to).unwrap().lamports, 157);
+        assert_eq!(runtime.bank().accounts_lt_hash(), &runtime.bank().calculate_accounts_lt_hash());
+        assert_ne!(runtime.accounts_hash(), runtime.bank_forks().get(1).unwrap().accounts_lt_hash().checksum());
+
+        // Rooting fork B discards fork A, while slot 0 is squashed into the root
+        assert!(runtime.set_root(1).is_err());
+        assert_eq!(runtime.set_root(2).unwrap(), vec![1]);
+        assert_eq!(runtime.advance_slot(), 3);
+        assert_eq!(runtime.get_account(&to).unwrap().lamports, 157);
+        assert!(matches!(
+            runtime.execute_transaction(&transfer(7, 3)),
+            Err(TerminatorError::Transaction(TransactionError::AlreadyProcessed))
+        ));
+        assert!(runtime.remove_fork(2).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_accounts_persist_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = RuntimeConfig::default();
+        config.bank.accounts_path = Some(dir.path().join("accounts").to_string_lossy().into_owned());
+        let config_path = dir.path().join("config.toml");
+        std::fs::write(&config_path, toml::to_string(&config).unwrap()).unwrap();
+        let config_path = config_path.to_str().unwrap();
+
+        let to = Pubkey::new([17u8; 32]);
+        let (accounts_hash, bank_hash) = {
+            let mut runtime = TerminatorRuntime::new(config_path).await.unwrap();
+            let transaction = Transaction {
+                instructions: vec![Instruction {
+                    program_id: Pubkey::system_program(),
+                    accounts: vec![],
+                    data: InstructionData::Transfer { from: [16u8; 32], to: to.0, lamports: 250 },
+                }],
+                signatures: vec![[16u8; 64]],
+                payer: [16u8; 32],
+                recent_blockhash: runtime.latest_blockhash(),
+            };
+            assert!(runtime.execute_transaction(&transaction).unwrap().success);
+            let bank_hash = runtime.freeze_slot([5u8; 32]);
+            runtime.set_root(0).unwrap();
+            assert_eq!(runtime.advance_slot(), 1);
+            (runtime.accounts_hash(), bank_hash)
+        };
+
+        // The reopened runtime continues from the rooted slot
+        let runtime = TerminatorRuntime::new(config_path).await.unwrap();
+        assert_eq!(runtime.slot(), 1);
+        assert_eq!(runtime.get_account(&to).unwrap().lamports, 250);
+        assert_eq!(runtime.accounts_hash(), accounts_hash);
+        assert_eq!(runtime.bank_forks().root_bank().unwrap().bank_hash(), bank_hash);
+        assert!(runtime.is_blockhash_valid(&[5u8; 32]));
+    }
+
+    #[tokio::test]
+    async fn test_genesis_bootstrap() {
+        let runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        assert!(runtime.get_account(&Pubkey::system_program()).is_none());
+        assert_eq!(
+            runtime.get_account(&genesis::faucet_pubkey()).unwrap().lamports,
+            runtime.config().bank.initial_lamports
+        );
+        assert_eq!(runtime.latest_blockhash(), runtime.genesis_hash());
+
+        let dir = tempfile::tempdir().unwrap();
+        let funded = Pubkey::new([21u8; 32]);
+        let mut genesis = GenesisConfig { ticks_per_slot: 8, ..GenesisConfig::default() };
+        genesis.accounts.insert(funded, Account::new(1_000_000, vec![], Pubkey::system_program().0));
+        genesis.native_instruction_processors.push(("spl_token".to_string(), Pubkey::token_program()));
+        let genesis_path = dir.path().join("genesis.bin");
+        genesis.write(&genesis_path).unwrap();
+
+        let mut config = RuntimeConfig::default();
+        config.bank.genesis_path = Some(genesis_path.to_string_lossy().into_owned());
+        let config_path = dir.path().join("config.toml");
+        std::fs::write(&config_path, toml::to_string(&config).unwrap()).unwrap();
+
+        let mut runtime = TerminatorRuntime::new(config_path.to_str().unwrap()).await.unwrap();
+        assert_eq!(runtime.genesis_hash(), genesis.hash());
+        assert!(runtime.is_blockhash_valid(&genesis.hash()));
+        assert!(runtime.get_account(&genesis::faucet_pubkey()).is_none());
+        assert!(runtime.get_account(&Pubkey::token_program()).unwrap().executable);
+        assert_eq!(runtime.config().block_production.ticks_per_slot, 8);
+
+        let transaction = Transaction {
+            instructions: vec![Instruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![],
+                data: InstructionData::Transfer { from: funded.0, to: [22u8; 32], lamports: 400 },
+            }],
+            signatures: vec![[21u8; 64]],
+            payer: funded.0,
+            recent_blockhash: genesis.hash(),
+        };
+        assert!(runtime.execute_transaction(&transaction).unwrap().success);
+        assert_eq!(runtime.get_account(&funded).unwrap().lamports, 1_000_000 - 400 - 5000);
+    }
+
+    #[tokio::test]
+    async fn test_account_dump_fixtures() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        let mint = Pubkey::new([23u8; 32]);
+        runtime.store_account(mint, Account::new(1_461_600, vec![1u8; 82], Pubkey::token_program().0));
+        let exported = runtime.export_account_dumps(dir.path(), &[mint, Pubkey::new([24u8; 32])]).unwrap();
+        assert_eq!(exported, 1);
+
+        let mut fixture = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        assert_eq!(fixture.load_account_dumps(dir.path()).unwrap(), 1);
+        assert_eq!(fixture.get_account(&mint), runtime.get_account(&mint));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        let to = Pubkey::new([19u8; 32]);
+        let blockhash = runtime.latest_blockhash();
+        let transfer = |lamports: u64, signature: u8| Transaction {
+            instructions: vec![Instruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![],
+                data: InstructionData::Transfer { from: [18u8; 32], to: to.0, lamports },
+            }],
+            signatures: vec![[signature; 64]],
+            payer: [18u8; 32],
+            recent_blockhash: blockhash,
+        };
+
+        assert!(runtime.execute_transaction(&transfer(100, 1)).unwrap().success);
+        runtime.freeze_slot([3u8; 32]);
+        let full = runtime.snapshot();
+        let full_path = dir.path().join(full.archive_file_name());
+        full.write_archive(&full_path).unwrap();
+
+        runtime.advance_slot();
+        assert!(runtime.execute_transaction(&transfer(50, 2)).unwrap().success);
+        let incremental = runtime.incremental_snapshot(&full).unwrap();
+        assert_eq!(incremental.slot, 1);
+        assert_eq!(incremental.accounts.len(), 2);
+        let incremental_path = dir.path().join(incremental.archive_file_name());
+        incremental.write_archive(&incremental_path).unwrap();
+
+        // A fresh runtime picks up from either snapshot
+        let mut restored = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        restored.restore_snapshot(&Snapshot::read_archive(&full_path).unwrap()).unwrap();
+        assert_eq!(restored.slot(), 1);
+        assert_eq!(restored.get_account(&to).unwrap().lamports, 100);
+        assert!(restored.is_blockhash_valid(&[3u8; 32]));
+
+        let latest = snapshot::load_snapshot_archives(&full_path, Some(&incremental_path)).unwrap();
+        restored.restore_snapshot(&latest).unwrap();
+        assert_eq!(restored.slot(), 2);
+        assert_eq!(restored.get_account(&to).unwrap().lamports, 150);
+        assert_eq!(restored.accounts_hash(), runtime.accounts_hash());
+        assert_eq!(restored.bank_hash(), runtime.bank_hash());
+        assert!(restored.restore_snapshot(&incremental).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_simulation_does_not_commit() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        
+        let signing_key = SigningKey::from_bytes(&[14u8; 32]);
+        let from = Pubkey::new(signing_key.verifying_key().to_bytes());
+        let to = Pubkey::new([15u8; 32]);
+        let mut transaction = Transaction {
+            instructions: vec![Instruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![
+                    AccountMeta { pubkey: from, is_signer: true, is_writable: true },
+                    AccountMeta { pubkey: to, is_signer: false, is_writable: true },
+                ],
+                data: InstructionData::Transfer { from: from.0, to: to.0, lamports: 1000 },
+            }],
+            signatures: vec![[0u8; 64]],
+            payer: from.0,
+            recent_blockhash: runtime.latest_blockhash(),
+        };
+
+        let config = SimulationConfig {
+            sig_verify: true,
+            accounts: Some(vec![to, Pubkey::new([16u8; 32])]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            runtime.simulate_transaction(&transaction, &config),
+            Err(TerminatorError::Transaction(TransactionError::SignatureFailure))
+        ));
+
+        transaction.signatures = vec![signing_key.sign(&transaction.message_data()).to_bytes()];
+        let unknown_blockhash = Transaction { recent_blockhash: [17u8; 32], ..transaction.clone() };
+        assert!(matches!(
+            runtime.simulate_transaction(&unknown_blockhash, &SimulationConfig::default()),
+            Err(TerminatorError::Transaction(TransactionError::BlockhashNotFound))
+        ));
+        let replaced = SimulationConfig { replace_recent_blockhash: true, ..Default::default() };
+        assert!(runtime.simulate_transaction(&unknown_blockhash, &replaced).unwrap().result.success);
+
+        let simulation = runtime.simulate_transaction(&transaction, &config).unwrap();
+        assert!(simulation.result.success);
+        let accounts = simulation.accounts.unwrap();
+        assert_eq!(accounts[0].as_ref().unwrap().lamports, 1000);
+        assert!(accounts[1].is_none());
+        assert!(runtime.get_account(&to).is_none());
+
+        // Simulation doesn't record the transaction, so it can still be executed
+        assert!(runtime.execute_transaction(&transaction).unwrap().success);
+        assert_eq!(runtime.get_account(&to).unwrap().lamports, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_bpf_program_execution() {
+        use crate::elf_loader::test_elf::{assemble, build};
+        use crate::sbpf::{ebpf, Insn};
+
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+
+        // Writes 42 into the first byte of the first account's data, then returns success
+        let program_id = Pubkey::new([20u8; 32]);
+        let text = assemble(&[
+            Insn::new(ebpf::ST_B_IMM, 1, 0, 96, 42),
+            Insn::new(ebpf::MOV64_IMM, 0, 0, 0, 0),
+            Insn::new(ebpf::EXIT, 0, 0, 0, 0),
+        ]);
+        runtime.deploy_program(program_id, &build(&text, &[], &[], &[])).unwrap();
+        assert!(runtime.get_account(&program_id).unwrap().executable);
+
+        let data_account = Pubkey::new([21u8; 32]);
+        runtime.store_account(data_account, Account::new(1_000_000, vec![0u8; 8], program_id.0));
+
+        let transaction = |recent_blockhash: [u8; 32], signature: [u8; 64]| Transaction {
+            instructions: vec![Instruction {
+                program_id,
+                accounts: vec![AccountMeta { pubkey: data_account, is_signer: false, is_writable: true }],
+                data: InstructionData::Generic { data: vec![] },
+            }],
+            signatures: vec![signature],
+            payer: data_account.0,
+            recent_blockhash,
+        };
+
+        runtime.register_recent_blockhash_for_test([1u8; 32]);
+        runtime.register_recent_blockhash_for_test([2u8; 32]);
+        let result = runtime.execute_transaction(&transaction([1u8; 32], [22u8; 64])).unwrap();
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(runtime.get_account(&data_account).unwrap().data[0], 42);
+        assert_eq!(result.compute_units_consumed, 1000 + 3);
+        assert!(result.logs.contains(&format!("Program {} success", program_id)));
+
+        // A non-zero r0 is a custom program error and the write is rolled back
+        runtime.store_account(data_account, Account::new(1_000_000, vec![0u8; 8], program_id.0));
+        let return_seven = assemble(&[Insn::new(ebpf::MOV64_IMM, 0, 0, 0, 7), Insn::new(ebpf::EXIT, 0, 0, 0, 0)]);
+        runtime.deploy_program(program_id, &build(&return_seven, &[], &[], &[])).unwrap();
+        let result = runtime.execute_transaction(&transaction([2u8; 32], [23u8; 64])).unwrap();
+        assert_eq!(result.error, Some(TransactionError::InstructionError(0, InstructionError::Custom(7))));
+        assert_eq!(runtime.get_account(&data_account).unwrap().data[0], 0);
+
+        // Programs that fail verification can't be deployed
+        let divide_by_zero = assemble(&[Insn::new(ebpf::DIV64_IMM, 0, 0, 0, 0), Insn::new(ebpf::EXIT, 0, 0, 0, 0)]);
+        assert!(matches!(
+            runtime.deploy_program(program_id, &build(&divide_by_zero, &[], &[], &[])),
+            Err(TerminatorError::VerificationFailed { pc: 0, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cross_program_invocation() {
+        use crate::elf_loader::test_elf::{assemble, build};
+        use crate::sbpf::{ebpf, Insn};
+
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+
+        // Invokes a system transfer of 1000 lamports from the first account to the second.
+        // With empty data, the first account's fields start at input offset 16 and the second's at 10352.
+        let store_pointer = |stack_offset: i16, input_offset: i32| {
+            [
+                Insn::new(ebpf::MOV64_REG, 2, 6, 0, 0),
+                Insn::new(ebpf::ADD64_IMM, 2, 0, 0, input_offset),
+                Insn::new(ebpf::ST_DW_REG, 10, 2, stack_offset, 0),
+            ]
+        };
+        let store_stack_pointer = |stack_offset: i16, target: i32| {
+            [
+                Insn::new(ebpf::MOV64_REG, 2, 10, 0, 0),
+                Insn::new(ebpf::ADD64_IMM, 2, 0, 0, target),
+                Insn::new(ebpf::ST_DW_REG, 10, 2, stack_offset, 0),
+            ]
+        };
+        let mut insns = vec![
+            Insn::new(ebpf::MOV64_REG, 6, 1, 0, 0),
+            // Instruction data: legacy transfer tag and amount
+            Insn::new(ebpf::ST_B_IMM, 10, 0, -16, 2),
+            Insn::new(ebpf::ST_DW_IMM, 10, 0, -15, 1000),
+            // Account metas at -80: (from, writable, signer), (to, writable)
+            Insn::new(ebpf::ST_B_IMM, 10, 0, -72, 1),
+            Insn::new(ebpf::ST_B_IMM, 10, 0, -71, 1),
+            Insn::new(ebpf::ST_B_IMM, 10, 0, -56, 1),
+            // Instruction at -120: system program id (zeroed stack at -48), metas, data
+            Insn::new(ebpf::ST_DW_IMM, 10, 0, -104, 2),
+            Insn::new(ebpf::ST_DW_IMM, 10, 0, -88, 9),
+            // Account info flags
+            Insn::new(ebpf::ST_B_IMM, 10, 0, -184, 1),
+            Insn::new(ebpf::ST_B_IMM, 10, 0, -183, 1),
+            Insn::new(ebpf::ST_B_IMM, 10, 0, -127, 1),
+        ];
+        insns.extend(store_pointer(-80, 16));
+        insns.extend(store_pointer(-64, 10352));
+        insns.extend(store_stack_pointer(-120, -48));
+        insns.extend(store_stack_pointer(-112, -80));
+        insns.extend(store_stack_pointer(-96, -16));
+        // Account infos at -232 and -176: key, lamports, data length, data, owner
+        for (info, account) in [(-232i16, 16i32), (-176, 10352)] {
+            insns.extend(store_pointer(info, account));
+            insns.extend(store_pointer(info + 8, account + 64));
+            insns.extend(store_pointer(info + 24, account + 80));
+            insns.extend(store_pointer(info + 32, account + 32));
+        }
+        insns.extend([
+            Insn::new(ebpf::MOV64_REG, 1, 10, 0, 0),
+            Insn::new(ebpf::ADD64_IMM, 1, 0, 0, -120),
+            Insn::new(ebpf::MOV64_REG, 2, 10, 0, 0),
+            Insn::new(ebpf::ADD64_IMM, 2, 0, 0, -232),
+            Insn::new(ebpf::MOV64_IMM, 3, 0, 0, 2),
+            Insn::new(ebpf::MOV64_IMM, 4, 0, 0, 0),
+            Insn::new(ebpf::MOV64_IMM, 5, 0, 0, 0),
+            Insn::new(ebpf::CALL_IMM, 0, 0, 0, -1),
+            Insn::new(ebpf::EXIT, 0, 0, 0, 0),
+        ]);
+        let call_pc = insns.len() - 2;
+        let program_id = Pubkey::new([30u8; 32]);
+        let elf = build(&assemble(&insns), &[], &[(call_pc, "sol_invoke_signed_c")], &[]);
+        runtime.deploy_program(program_id, &elf).unwrap();
+
+        let from = Pubkey::new([31u8; 32]);
+        let to = Pubkey::new([32u8; 32]);
+        runtime.store_account(from, Account::new(1_000_000, vec![], Pubkey::system_program().0));
+        let transaction = |from_is_signer: bool, recent_blockhash: [u8; 32]| Transaction {
+            instructions: vec![Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta { pubkey: from, is_signer: from_is_signer, is_writable: true },
+                    AccountMeta { pubkey: to, is_signer: false, is_writable: true },
+                    AccountMeta { pubkey: Pubkey::system_program(), is_signer: false, is_writable: false },
+                ],
+                data: InstructionData::Generic { data: vec![] },
+            }],
+            signatures: vec![[33u8; 64]],
+            payer: from.0,
+            recent_blockhash,
+        };
+
+        runtime.register_recent_blockhash_for_test([1u8; 32]);
+        runtime.register_recent_blockhash_for_test([2u8; 32]);
+        let result = runtime.execute_transaction(&transaction(true, [1u8; 32])).unwrap();
+        assert!(result.success, "{:?} {:?}", result.error, result.logs);
+        assert_eq!(runtime.get_account(&from).unwrap().lamports, 1_000_000 - 1000 - 5000);
+        assert_eq!(runtime.get_account(&to).unwrap().lamports, 1000);
+        let inner = &result.inner_instructions[0].instructions[0];
+        assert_eq!(inner.stack_height, Some(2));
+        assert_eq!(inner.instruction.data, vec![2, 232, 3, 0, 0, 0, 0, 0, 0]);
+
+        // The program can't sign for an account the transaction didn't sign for
+        let result = runtime.execute_transaction(&transaction(false, [2u8; 32])).unwrap();
+        assert_eq!(
+            result.error,
+            Some(TransactionError::InstructionError(0, InstructionError::PrivilegeEscalation))
+        );
+        assert_eq!(runtime.get_account(&from).unwrap().lamports, 1_000_000 - 1000 - 2 * 5000);
+    }
+
+    #[tokio::test]
+    async fn test_registered_builtin() {
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        let program_id = Pubkey::new([40u8; 32]);
+        let counter = Pubkey::new([41u8; 32]);
+        runtime.store_account(counter, Account::new(1_000_000, vec![0u8; 8], program_id.0));
+
+        // Increments a counter held in the first account, invoking the system program to pay for it
+        runtime.register_builtin(program_id, "counter", 500, |invoke_context: &mut InvokeContext| {
+            let instruction = invoke_context.instruction();
+            let counter = instruction.accounts[0].pubkey;
+            let account = invoke_context
+                .transaction_context
+                .get_mut(&counter)
+                .ok_or(InstructionError::MissingAccount)?;
+            let value = u64::from_le_bytes(account.data[..8].try_into().unwrap()) + 1;
+            account.data[..8].copy_from_slice(&value.to_le_bytes());
+            invoke_context.log(format!("Counter is {}", value));
+
+            let payer = instruction.accounts[1].pubkey;
+            invoke_context.invoke(
+                &Instruction {
+                    program_id: Pubkey::system_program(),
+                    accounts: vec![],
+                    data: InstructionData::Transfer { from: payer.0, to: counter.0, lamports: 10 },
+                },
+                &[],
+            )
+        });
+
+        let payer = Pubkey::new([42u8; 32]);
+        runtime.store_account(payer, Account::new(1_000_000, vec![], Pubkey::system_program().0));
+        let transaction = Transaction {
+            instructions: vec![Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta { pubkey: counter, is_signer: false, is_writable: true },
+                    AccountMeta { pubkey: payer, is_signer: true, is_writable: true },
+                    AccountMeta { pubkey: Pubkey::system_program(), is_signer: false, is_writable: false },
+                ],
+                data: InstructionData::Generic { data: vec![] },
+            }],
+            signatures: vec![[43u8; 64]],
+            payer: payer.0,
+            recent_blockhash: runtime.latest_blockhash(),
+        };
+
+        let result = runtime.execute_transaction(&transaction).unwrap();
+        assert!(result.success, "{:?} {:?}", result.error, result.logs);
+        assert!(result.logs.iter().any(|log| log == "Counter is 1"));
+        assert_eq!(result.compute_units_consumed, 1000 + 500 + builtins::SYSTEM_PROGRAM_COMPUTE_UNITS);
+        let counter_account = runtime.get_account(&counter).unwrap();
+        assert_eq!(counter_account.data, 1u64.to_le_bytes());
+        assert_eq!(counter_account.lamports, 1_000_010);
+        assert_eq!(result.inner_instructions[0].instructions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_instruction_account_verification() {
+        use crate::elf_loader::test_elf::{assemble, build};
+        use crate::sbpf::{ebpf, Insn};
+
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        let program_id = Pubkey::new([50u8; 32]);
+        let owned = Pubkey::new([51u8; 32]);
+        let external = Pubkey::new([52u8; 32]);
+        runtime.store_account(owned, Account::new(1_000_000, vec![0u8; 8], program_id.0));
+        runtime.store_account(external, Account::new(1_000_000, vec![0u8; 8], Pubkey::system_program().0));
+
+        // Writes 42 into the first byte of the first account's data
+        let elf = build(
+            &assemble(&[
+                Insn::new(ebpf::ST_B_IMM, 1, 0, 96, 42),
+                Insn::new(ebpf::MOV64_IMM, 0, 0, 0, 0),
+                Insn::new(ebpf::EXIT, 0, 0, 0, 0),
+            ]),
+            &[],
+            &[],
+            &[],
+        );
+        runtime.deploy_program(program_id, &elf).unwrap();
+
+        let mut blockhash = 0u8;
+        let mut execute = |runtime: &mut TerminatorRuntime, instruction: Instruction| {
+            blockhash += 1;
+            runtime.register_recent_blockhash_for_test([blockhash; 32]);
+            let transaction = Transaction {
+                instructions: vec![instruction],
+                signatures: vec![[blockhash; 64]],
+                payer: owned.0,
+                recent_blockhash: [blockhash; 32],
+            };
+            runtime.execute_transaction(&transaction).unwrap()
+        };
+        let write = |account: Pubkey, is_writable: bool| Instruction {
+            program_id,
+            accounts: vec![AccountMeta { pubkey: account, is_signer: false, is_writable }],
+            data: InstructionData::Generic { data: vec![] },
+        };
+
+        let result = execute(&mut runtime, write(external, true));
+        assert_eq!(
+            result.error,
+            Some(TransactionError::InstructionError(0, InstructionError::ExternalAccountDataModified))
+        );
+        let result = execute(&mut runtime, write(owned, false));
+        assert_eq!(
+            result.error,
+            Some(TransactionError::InstructionError(0, InstructionError::ReadonlyDataModified))
+        );
+        assert_eq!(runtime.get_account(&owned).unwrap().data[0], 0);
+        let result = execute(&mut runtime, write(owned, true));
+        assert!(result.success, "{:?} {:?}", result.error, result.logs);
+        assert_eq!(runtime.get_account(&owned).unwrap().data[0], 42);
+
+        // Lamports can't be created out of nothing
+        let minter = Pubkey::new([53u8; 32]);
+        runtime.register_builtin(minter, "minter", 100, |invoke_context: &mut InvokeContext| {
+            let account = invoke_context.instruction().accounts[0].pubkey;
+            invoke_context.transaction_context.get_mut(&account).unwrap().lamports += 1;
+            Ok(())
+        });
+        let result = execute(
+            &mut runtime,
+            Instruction {
+                program_id: minter,
+                accounts: vec![AccountMeta { pubkey: owned, is_signer: false, is_writable: true }],
+                data: InstructionData::Generic { data: vec![] },
+            },
+        );
+        assert_eq!(
+            result.error,
+            Some(TransactionError::InstructionError(0, InstructionError::UnbalancedInstruction))
+        );
+
+        // Nor can accounts the instruction doesn't list be changed, here the payer
+        let sneaky = Pubkey::new([54u8; 32]);
+        runtime.register_builtin(sneaky, "sneaky", 100, move |invoke_context: &mut InvokeContext| {
+            invoke_context.transaction_context.get_mut(&owned).unwrap().lamports += 1;
+            Ok(())
+        });
+        let result = execute(
+            &mut runtime,
+            Instruction {
+                program_id: sneaky,
+                accounts: vec![],
+                data: InstructionData::Generic { data: vec![] },
+            },
+        );
+        assert_eq!(
+            result.error,
+            Some(TransactionError::InstructionError(0, InstructionError::ReadonlyLamportChange))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upgradeable_program_deployment() {
+        use crate::elf_loader::test_elf::{assemble, build};
+        use crate::sbpf::{ebpf, Insn};
+        use crate::upgradeable_loader::{BUFFER_METADATA_SIZE, PROGRAM_SIZE};
+
+        let mut runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        let rent = Rent::default();
+        let loader = Pubkey::bpf_loader_upgradeable();
+
+        // Writes 42 into the first byte of the first account's data
+        let elf = build(
+            &assemble(&[
+                Insn::new(ebpf::ST_B_IMM, 1, 0, 96, 42),
+                Insn::new(ebpf::MOV64_IMM, 0, 0, 0, 0),
+                Insn::new(ebpf::EXIT, 0, 0, 0, 0),
+            ]),
+            &[],
+            &[],
+            &[],
+        );
+
+        let payer = Pubkey::new([40u8; 32]);
+        let buffer = Pubkey::new([41u8; 32]);
+        let program_id = Pubkey::new([42u8; 32]);
+        let programdata = Pubkey::programdata_address(&program_id);
+        runtime.store_account(payer, Account::new(10_000_000_000, vec![], Pubkey::system_program().0));
+
+        let meta = |pubkey: Pubkey, is_signer: bool, is_writable: bool| AccountMeta { pubkey, is_signer, is_writable };
+        let loader_instruction = |instruction: UpgradeableLoaderInstruction, accounts: Vec<AccountMeta>| Instruction {
+            program_id: loader,
+            accounts,
+            data: InstructionData::Generic { data: bincode::serialize(&instruction).unwrap() },
+        };
+        let create_account = |to: Pubkey, space: usize| Instruction {
+            program_id: Pubkey::system_program(),
+            accounts: vec![meta(payer, true, true), meta(to, true, true)],
+            data: InstructionData::CreateAccount {
+                from: payer.0,
+                to: to.0,
+                lamports: rent.minimum_balance(space),
+                space: space as u64,
+                owner: loader.0,
+            },
+        };
+        let mut blockhash = 0u8;
+        let mut execute = |runtime: &mut TerminatorRuntime, instructions: Vec<Instruction>| {
+            blockhash += 1;
+            runtime.register_recent_blockhash_for_test([blockhash; 32]);
+            let transaction = Transaction {
+                instructions,
+                signatures: vec![[blockhash; 64]],
+                payer: payer.0,
+                recent_blockhash: [blockhash; 32],
+            };
+            let result = runtime.execute_transaction(&transaction).unwrap();
+            assert!(result.success, "{:?} {:?}", result.error, result.logs);
+            result
+        };
+
+        execute(
+            &mut runtime,
+            vec![
+                create_account(buffer, BUFFER_METADATA_SIZE + elf.len()),
+                loader_instruction(
+                    UpgradeableLoaderInstruction::InitializeBuffer,
+                    vec![meta(buffer, false, true), meta(payer, false, false)],
+                ),
+            ],
+        );
+        for (index, chunk) in elf.chunks(512).enumerate() {
+            let write = UpgradeableLoaderInstruction::Write { offset: (index * 512) as u32, bytes: chunk.to_vec() };
+            execute(&mut runtime, vec![loader_instruction(write, vec![meta(buffer, false, true), meta(payer, true, false)])]);
+        }
+        let result = execute(
+            &mut runtime,
+            vec![
+                create_account(program_id, PROGRAM_SIZE),
+                loader_instruction(
+                    UpgradeableLoaderInstruction::DeployWithMaxDataLen { max_data_len: elf.len() * 2 },
+                    vec![
+                        meta(payer, true, true),
+                        meta(programdata, false, true),
+                        meta(program_id, false, true),
+                        meta(buffer, false, true),
+                        meta(Pubkey::sysvar_rent(), false, false),
+                        meta(Pubkey::sysvar_clock(), false, false),
+                        meta(Pubkey::system_program(), false, false),
+                        meta(payer, true, false),
+                    ],
+                ),
+            ],
+        );
+        assert!(result.logs.contains(&format!("Deployed program {}", program_id)));
+        assert!(runtime.get_account(&program_id).unwrap().executable);
+        assert!(runtime.get_account(&buffer).is_none());
+
+        let data_account = Pubkey::new([43u8; 32]);
+        runtime.store_account(data_account, Account::new(1_000_000, vec![0u8; 8], program_id.0));
+        let invoke_program = Instruction {
+            program_id,
+            accounts: vec![meta(data_account, false, true)],
+            data: InstructionData::Generic { data: vec![] },
+        };
+
+        // Deployed programs can't be invoked until the next slot
+        runtime.register_recent_blockhash_for_test([99u8; 32]);
+        let result = runtime
+            .execute_transaction(&Transaction {
+                instructions: vec![invoke_program.clone()],
+                signatures: vec![[99u8; 64]],
+                payer: payer.0,
+                recent_blockhash: [99u8; 32],
+            })
+            .unwrap();
+        assert_eq!(
+            result.error,
+            Some(TransactionError::InstructionError(0, InstructionError::UnsupportedProgramId))
+        );
+        assert!(result.logs.contains(&"Program is not deployed".to_string()));
+
+        runtime.advance_slot();
+        execute(&mut runtime, vec![invoke_program.clone()]);
+        assert_eq!(runtime.get_account(&data_account).unwrap().data[0], 42);
+
+        // The verified executable is reused on later invocations
+        let misses = runtime.program_cache_stats().misses;
+        execute(&mut runtime, vec![invoke_program]);
+        let stats = runtime.program_cache_stats();
+        assert_eq!(stats.misses, misses);
+        assert_eq!(stats.hits, 1);
+    }
+
+    /// POST a JSON-RPC request over a plain HTTP/1.1 connection
+    async fn rpc_call(address: std::net::SocketAddr, method: &str, params: serde_json::Value) -> serde_json::Value {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let body = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }).to_string();
+        let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        let request = format!(
+            "POST / HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            address,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_json_rpc_server() {
+        let runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        let networking = runtime.config().networking.clone();
+        let settings = BlockProductionSettings {
+            slot_duration_ms: 10,
+            ticks_per_slot: 4,
+            hashes_per_tick: 8,
+            max_batch_size: 64,
+        };
+        let mut producer = BlockProducer::new(runtime, settings);
+        let service = JsonRpcService::new(producer.shared_runtime(), &networking)
+            .with_transaction_sender(producer.sender());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (shutdown, shutdown_receiver) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(service.serve(listener, async {
+            let _ = shutdown_receiver.await;
+        }));
+        let producer = tokio::spawn(async move {
+            producer.run(tokio::time::sleep(std::time::Duration::from_secs(5))).await;
+        });
+
+        assert_eq!(rpc_call(address, "getHealth", serde_json::json!([])).await["result"], "ok");
+
+        // The airdrop is queued for the next slot and reported once that slot is rooted
+        let recipient = Pubkey::new([31u8; 32]);
+        let airdrop = rpc_call(address, "requestAirdrop", serde_json::json!([recipient.to_string(), 5_000])).await;
+        let signature = airdrop["result"].as_str().unwrap().to_string();
+        let mut confirmation_status = serde_json::Value::Null;
+        for _ in 0..200 {
+            let statuses = rpc_call(address, "getSignatureStatuses", serde_json::json!([[signature]])).await;
+            confirmation_status = statuses["result"]["value"][0]["confirmationStatus"].clone();
+            if confirmation_status == "finalized" {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(confirmation_status, "finalized");
+
+        let balance = rpc_call(address, "getBalance", serde_json::json!([recipient.to_string()])).await;
+        assert_eq!(balance["result"]["value"], 5_000);
+        let transaction = rpc_call(address, "getTransaction", serde_json::json!([signature])).await;
+        assert_eq!(transaction["result"]["meta"]["err"], serde_json::Value::Null);
+        assert!(rpc_call(address, "getSlot", serde_json::json!([])).await["result"].as_u64().unwrap() >= 1);
+
+        shutdown.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        producer.abort();
+    }
+
+    #[tokio::test]
+    async fn test_pubsub_server() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        let settings = BlockProductionSettings {
+            slot_duration_ms: 10,
+            ticks_per_slot: 4,
+            hashes_per_tick: 8,
+            max_batch_size: 64,
+        };
+        let mut producer = BlockProducer::new(runtime, settings);
+        let sender = producer.sender();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (shutdown, shutdown_receiver) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(PubSubService::new(producer.shared_runtime()).serve(listener, async {
+            let _ = shutdown_receiver.await;
+        }));
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}", address)).await.unwrap();
+        let request = |id: u64, method: &str, params: serde_json::Value| {
+            let request = serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+            Message::text(request.to_string())
+        };
+        let signature = [41u8; 64];
+        let subscribe = request(1, "signatureSubscribe", serde_json::json!([bs58::encode(signature).into_string()]));
+        socket.send(subscribe).await.unwrap();
+        let response: serde_json::Value =
+            serde_json::from_str(socket.next().await.unwrap().unwrap().to_text().unwrap()).unwrap();
+        assert_eq!(response["id"], 1);
+        let subscription = response["result"].clone();
+
+        let blockhash = producer.runtime().latest_blockhash();
+        let transfer = Transaction {
+            instructions: vec![Instruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![],
+                data: InstructionData::Transfer { from: [41u8; 32], to: [42u8; 32], lamports: 10 },
+            }],
+            signatures: vec![signature],
+            payer: [41u8; 32],
+            recent_blockhash: blockhash,
+        };
+        sender.try_send(transfer).unwrap();
+        let producer = tokio::spawn(async move {
+            producer.run(tokio::time::sleep(std::time::Duration::from_secs(5))).await;
+        });
+
+        // Notified once the slot of the transfer is rooted
+        let notification = tokio::time::timeout(std::time::Duration::from_secs(5), socket.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let notification: serde_json::Value = serde_json::from_str(notification.to_text().unwrap()).unwrap();
+        assert_eq!(notification["method"], "signatureNotification");
+        assert_eq!(notification["params"]["subscription"], subscription);
+        assert_eq!(notification["params"]["result"]["value"]["err"], serde_json::Value::Null);
+
+        socket.close(None).await.unwrap();
+        shutdown.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        producer.abort();
+    }
+
     #[test]
     fn test_conformance_harness() {
         let mut harness = ConformanceHarness::new();
@@ -1,12 +1,25 @@
 use anyhow::Result;
 use clap::Parser;
-use terminator_dancer::TerminatorRuntime;
+use std::sync::{Arc, RwLock};
+use terminator_dancer::{BlockProducer, JsonRpcService, PubSubService, TerminatorRuntime, TpuService};
 
 #[derive(Parser, Debug)]
 #[clap(name = "Terminator-Dancer", version = "0.1.0", about = "A lightweight Solana runtime")]
 struct Args {
     #[clap(short, long, default_value = "config.toml")]
     config: String,
+
+    /// Produce a block every slot until interrupted, like a local test validator
+    #[clap(long)]
+    produce_blocks: bool,
+
+    /// Serve the JSON-RPC and PubSub APIs on the configured addresses until interrupted
+    #[clap(long)]
+    rpc: bool,
+
+    /// Ingest raw transaction packets over UDP on the configured `tpu_bind_address`
+    #[clap(long, requires = "produce_blocks")]
+    tpu: bool,
 }
 
 #[tokio::main]
@@ -14,5 +27,43 @@ async fn main() -> Result<()> {
     let args = Args::parse();
     let runtime = TerminatorRuntime::new(&args.config).await?;
     runtime.start().await?;
+
+    let networking = runtime.config().networking.clone();
+    let max_transaction_size = runtime.config().runtime.max_transaction_size;
+    let settings = runtime.config().block_production.clone();
+    let runtime = Arc::new(RwLock::new(runtime));
+
+    let mut servers = Vec::new();
+    let mut rpc_service = None;
+    if args.rpc {
+        let listener = tokio::net::TcpListener::bind(&networking.rpc_bind_address).await?;
+        rpc_service = Some((JsonRpcService::new(runtime.clone(), &networking), listener));
+        let listener = tokio::net::TcpListener::bind(&networking.pubsub_bind_address).await?;
+        servers.push(tokio::spawn(PubSubService::new(runtime.clone()).serve(listener, shutdown())));
+    }
+
+    if args.produce_blocks {
+        let mut producer = BlockProducer::with_shared_runtime(runtime, settings);
+        if let Some((service, listener)) = rpc_service {
+            let service = service.with_transaction_sender(producer.sender());
+            servers.push(tokio::spawn(service.serve(listener, shutdown())));
+        }
+        if args.tpu {
+            let socket = tokio::net::UdpSocket::bind(&networking.tpu_bind_address).await?;
+            let tpu = TpuService::new(socket, producer.sender(), max_transaction_size);
+            tokio::spawn(async move { tpu.run(shutdown()).await });
+        }
+        producer.run(shutdown()).await;
+    } else if let Some((service, listener)) = rpc_service {
+        servers.push(tokio::spawn(service.serve(listener, shutdown())));
+    }
+
+    for server in servers {
+        server.await??;
+    }
     Ok(())
 }
+
+async fn shutdown() {
+    let _ = tokio::signal::ctrl_c().await;
+}
@@ -0,0 +1,250 @@
+//! Proof of History: a sha256 hash chain that ticks at a fixed rate and records transaction
+//! batches by mixing their hash into the chain.
+
+use crate::types::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Hashes per tick on mainnet
+pub const DEFAULT_HASHES_PER_TICK: u64 = 12_500;
+/// Ticks per slot on mainnet
+pub const DEFAULT_TICKS_PER_SLOT: u64 = 64;
+
+fn hash(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+fn hash_with_mixin(hash: &[u8; 32], mixin: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(hash);
+    hasher.update(mixin);
+    hasher.finalize().into()
+}
+
+/// Hash mixed into the chain for a batch of transactions: the sha256 of their signatures
+pub fn hash_transactions(transactions: &[Transaction]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for signature in transactions.iter().flat_map(|txn| &txn.signatures) {
+        hasher.update(signature);
+    }
+    hasher.finalize().into()
+}
+
+/// Hash reached from `start_hash` after `num_hashes` hashes, the last of which mixes in
+/// `transactions` if there are any
+pub fn next_hash(start_hash: &[u8; 32], num_hashes: u64, transactions: &[Transaction]) -> [u8; 32] {
+    if num_hashes == 0 && transactions.is_empty() {
+        return *start_hash;
+    }
+
+    let mut next = *start_hash;
+    for _ in 1..num_hashes {
+        next = hash(&next);
+    }
+    if transactions.is_empty() {
+        hash(&next)
+    } else {
+        hash_with_mixin(&next, &hash_transactions(transactions))
+    }
+}
+
+/// A step of the hash chain: either a tick or a batch of transactions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Hashes since the previous entry
+    pub num_hashes: u64,
+    pub hash: [u8; 32],
+    pub transactions: Vec<Transaction>,
+}
+
+impl Entry {
+    pub fn new(prev_hash: &[u8; 32], num_hashes: u64, transactions: Vec<Transaction>) -> Self {
+        let hash = next_hash(prev_hash, num_hashes, &transactions);
+        Self { num_hashes, hash, transactions }
+    }
+
+    pub fn is_tick(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    /// Whether the entry follows from `start_hash`
+    pub fn verify(&self, start_hash: &[u8; 32]) -> bool {
+        next_hash(start_hash, self.num_hashes, &self.transactions) == self.hash
+    }
+}
+
+/// Check that every entry follows from the one before it, the first from `start_hash`.
+///
+/// Entries are independent once their start hashes are known, so they're verified in parallel.
+pub fn verify_entries(entries: &[Entry], start_hash: &[u8; 32]) -> bool {
+    let start_hashes = std::iter::once(start_hash).chain(entries.iter().map(|entry| &entry.hash));
+    let pairs: Vec<_> = entries.iter().zip(start_hashes).collect();
+    pairs.par_iter().all(|(entry, start_hash)| entry.verify(start_hash))
+}
+
+/// Check that each tick, together with the transaction entries before it, spans exactly
+/// `hashes_per_tick` hashes
+pub fn verify_tick_hash_count(entries: &[Entry], hashes_per_tick: u64) -> bool {
+    let mut tick_hashes = 0u64;
+    for entry in entries {
+        tick_hashes = tick_hashes.saturating_add(entry.num_hashes);
+        if entry.is_tick() {
+            if tick_hashes != hashes_per_tick {
+                return false;
+            }
+            tick_hashes = 0;
+        }
+    }
+    // A trailing partial tick is still being produced
+    tick_hashes < hashes_per_tick
+}
+
+/// Extends the hash chain, producing tick entries at a fixed hash count and an entry for every
+/// recorded transaction batch
+#[derive(Debug, Clone)]
+pub struct PohRecorder {
+    hash: [u8; 32],
+    /// Hashes since the last entry
+    num_hashes: u64,
+    hashes_per_tick: u64,
+    /// Hashes left before the next tick, including the tick's own hash
+    remaining_hashes: u64,
+    tick_height: u64,
+    entries: Vec<Entry>,
+}
+
+impl PohRecorder {
+    pub fn new(start_hash: [u8; 32], hashes_per_tick: u64) -> Self {
+        let hashes_per_tick = hashes_per_tick.max(2);
+        Self {
+            hash: start_hash,
+            num_hashes: 0,
+            hashes_per_tick,
+            remaining_hashes: hashes_per_tick,
+            tick_height: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Latest hash of the chain
+    pub fn hash(&self) -> [u8; 32] {
+        self.hash
+    }
+
+    pub fn tick_height(&self) -> u64 {
+        self.tick_height
+    }
+
+    pub fn hashes_per_tick(&self) -> u64 {
+        self.hashes_per_tick
+    }
+
+    /// Advance the chain by up to `max_num_hashes`, stopping short of the next tick's hash
+    pub fn hash_chain(&mut self, max_num_hashes: u64) {
+        let num_hashes = max_num_hashes.min(self.remaining_hashes - 1);
+        for _ in 0..num_hashes {
+            self.hash = hash(&self.hash);
+        }
+        self.num_hashes += num_hashes;
+        self.remaining_hashes -= num_hashes;
+    }
+
+    /// Mix `transactions` into the chain, ticking first if the current tick has no hash to spare
+    pub fn record(&mut self, transactions: Vec<Transaction>) -> &Entry {
+        if self.remaining_hashes == 1 {
+            self.tick();
+        }
+        self.hash = hash_with_mixin(&self.hash, &hash_transactions(&transactions));
+        self.remaining_hashes -= 1;
+        self.push_entry(transactions)
+    }
+
+    /// Finish the current tick, spending the rest of its hashes
+    pub fn tick(&mut self) -> &Entry {
+        self.hash_chain(self.remaining_hashes);
+        self.hash = hash(&self.hash);
+        self.remaining_hashes = self.hashes_per_tick;
+        self.tick_height += 1;
+        self.push_entry(Vec::new())
+    }
+
+    fn push_entry(&mut self, transactions: Vec<Transaction>) -> &Entry {
+        self.entries.push(Entry {
+            num_hashes: self.num_hashes + 1,
+            hash: self.hash,
+            transactions,
+        });
+        self.num_hashes = 0;
+        self.entries.last().expect("entry was just pushed")
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Hand over the entries produced so far
+    pub fn take_entries(&mut self) -> Vec<Entry> {
+        std::mem::take(&mut self.entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(signature: u8) -> Transaction {
+        Transaction {
+            instructions: vec![],
+            signatures: vec![[signature; 64]],
+            payer: [0u8; 32],
+            recent_blockhash: [0u8; 32],
+        }
+    }
+
+    #[test]
+    fn test_next_hash() {
+        let start = [1u8; 32];
+        assert_eq!(next_hash(&start, 0, &[]), start);
+        assert_eq!(next_hash(&start, 2, &[]), hash(&hash(&start)));
+
+        let transactions = vec![transaction(7)];
+        let mixed = hash_with_mixin(&hash(&start), &hash_transactions(&transactions));
+        assert_eq!(next_hash(&start, 2, &transactions), mixed);
+        assert!(Entry::new(&start, 2, transactions.clone()).verify(&start));
+        assert!(!Entry::new(&start, 2, transactions).verify(&[2u8; 32]));
+    }
+
+    #[test]
+    fn test_recorded_entries_verify() {
+        let start = [3u8; 32];
+        let mut recorder = PohRecorder::new(start, 4);
+        recorder.hash_chain(10);
+        recorder.record(vec![transaction(1)]);
+        // The tick spends what's left of its hashes
+        let tick = recorder.tick();
+        assert!(tick.is_tick());
+        assert_eq!(tick.num_hashes, 3);
+        for signature in 2..=4 {
+            recorder.record(vec![transaction(signature)]);
+        }
+        // The third tick has no hash left for a batch, so recording ticks first
+        recorder.record(vec![transaction(5), transaction(6)]);
+        assert_eq!(recorder.tick_height(), 3);
+        recorder.tick();
+
+        let entries = recorder.take_entries();
+        assert_eq!(entries.iter().filter(|entry| entry.is_tick()).count(), 4);
+        assert!(verify_entries(&entries, &start));
+        assert!(verify_tick_hash_count(&entries, 4));
+        assert_eq!(entries.last().unwrap().hash, recorder.hash());
+
+        let mut tampered = entries.clone();
+        tampered[4].transactions[0].signatures[0] = [9u8; 64];
+        assert!(!verify_entries(&tampered, &start));
+        let mut reordered = entries;
+        reordered.swap(3, 4);
+        assert!(!verify_entries(&reordered, &start));
+        assert!(!verify_tick_hash_count(&reordered, 5));
+    }
+}
@@ -0,0 +1,199 @@
+//! Post-instruction verification: the rules on how a program may change the accounts it was given.
+
+use crate::bpf_loader::MAX_PERMITTED_DATA_INCREASE;
+use crate::transaction_error::InstructionError;
+use crate::types::*;
+use crate::upgradeable_loader::MAX_PERMITTED_DATA_LENGTH;
+
+type InstructionResult = std::result::Result<(), InstructionError>;
+
+/// A transaction account as it was before the program ran
+#[derive(Debug, Clone)]
+pub struct PreAccount {
+    index: usize,
+    is_writable: bool,
+    account: Account,
+}
+
+impl PreAccount {
+    pub fn new(index: usize, is_writable: bool, account: &Account) -> Self {
+        Self {
+            index,
+            is_writable,
+            account: account.clone(),
+        }
+    }
+
+    /// Position of the account in the transaction
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.is_writable
+    }
+
+    pub fn lamports(&self) -> u64 {
+        self.account.lamports
+    }
+
+    /// Accept `account` as the new starting point, e.g. once a nested invocation was verified
+    pub fn update(&mut self, account: &Account) {
+        self.account.clone_from(account);
+    }
+
+    /// Check that `program_id` was allowed to turn the account into `post`
+    pub fn verify(&self, program_id: &Pubkey, post: &Account) -> InstructionResult {
+        let pre = &self.account;
+        let is_owner = program_id.0 == pre.owner;
+
+        // Only the owner may assign the account, and only while it's writable, not executable
+        // and holds no data
+        if pre.owner != post.owner
+            && (!self.is_writable || pre.executable || !is_owner || !is_zeroed(&post.data))
+        {
+            return Err(InstructionError::ModifiedProgramId);
+        }
+
+        // Only the owner may debit the account, and read-only or executable balances are fixed
+        if pre.lamports > post.lamports && !is_owner {
+            return Err(InstructionError::ExternalAccountLamportSpend);
+        }
+        if pre.lamports != post.lamports {
+            if !self.is_writable {
+                return Err(InstructionError::ReadonlyLamportChange);
+            }
+            if pre.executable {
+                return Err(InstructionError::ExecutableLamportChange);
+            }
+        }
+
+        // The owner may resize writable data, growing it by a bounded amount unless it was empty
+        if pre.data.len() != post.data.len() {
+            if !self.is_writable || pre.executable || !is_owner {
+                return Err(InstructionError::AccountDataSizeChanged);
+            }
+            let limit = if pre.data.is_empty() {
+                MAX_PERMITTED_DATA_LENGTH
+            } else {
+                pre.data.len().saturating_add(MAX_PERMITTED_DATA_INCREASE).min(MAX_PERMITTED_DATA_LENGTH)
+            };
+            if post.data.len() > limit {
+                return Err(InstructionError::InvalidRealloc);
+            }
+        }
+
+        // Only the owner may change the data of a writable, non-executable account
+        if pre.data != post.data && !(is_owner && self.is_writable && !pre.executable) {
+            return Err(if pre.executable {
+                InstructionError::ExecutableDataModified
+            } else if self.is_writable {
+                InstructionError::ExternalAccountDataModified
+            } else {
+                InstructionError::ReadonlyDataModified
+            });
+        }
+
+        // Accounts can only be made executable, by their owner
+        if pre.executable != post.executable
+            && (!self.is_writable || pre.executable || program_id.0 != post.owner)
+        {
+            return Err(InstructionError::ExecutableModified);
+        }
+
+        if pre.rent_epoch != post.rent_epoch {
+            return Err(InstructionError::RentEpochModified);
+        }
+        Ok(())
+    }
+}
+
+/// Verify every account of the transaction and that `program_id` neither created nor destroyed
+/// lamports
+pub fn verify_accounts(program_id: &Pubkey, pre_accounts: &[PreAccount], accounts: &[Account]) -> InstructionResult {
+    let mut pre_sum = 0u128;
+    let mut post_sum = 0u128;
+    for pre in pre_accounts {
+        let post = &accounts[pre.index];
+        pre.verify(program_id, post)?;
+        pre_sum += pre.lamports() as u128;
+        post_sum += post.lamports as u128;
+    }
+    if pre_sum != post_sum {
+        return Err(InstructionError::UnbalancedInstruction);
+    }
+    Ok(())
+}
+
+fn is_zeroed(data: &[u8]) -> bool {
+    data.iter().all(|byte| *byte == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_rules() {
+        let program_id = Pubkey::new([1u8; 32]);
+        let other = Pubkey::new([2u8; 32]);
+        let owned = Account::new(100, vec![1, 2, 3], program_id.0);
+        let external = Account::new(100, vec![1, 2, 3], other.0);
+        let verify = |pre: &Account, is_writable: bool, change: &dyn Fn(&mut Account)| {
+            let mut post = pre.clone();
+            change(&mut post);
+            PreAccount::new(0, is_writable, pre).verify(&program_id, &post)
+        };
+
+        assert_eq!(verify(&owned, true, &|post| post.lamports -= 1), Ok(()));
+        assert_eq!(verify(&owned, false, &|post| post.lamports -= 1), Err(InstructionError::ReadonlyLamportChange));
+        assert_eq!(verify(&external, true, &|post| post.lamports += 1), Ok(()));
+        assert_eq!(verify(&external, true, &|post| post.lamports -= 1), Err(InstructionError::ExternalAccountLamportSpend));
+
+        assert_eq!(verify(&owned, true, &|post| post.data[0] = 9), Ok(()));
+        assert_eq!(verify(&owned, false, &|post| post.data[0] = 9), Err(InstructionError::ReadonlyDataModified));
+        assert_eq!(verify(&external, true, &|post| post.data[0] = 9), Err(InstructionError::ExternalAccountDataModified));
+        assert_eq!(verify(&external, true, &|post| post.data.push(0)), Err(InstructionError::AccountDataSizeChanged));
+
+        // Growth is bounded per instruction unless the account is being allocated
+        let grow = |by: usize| move |post: &mut Account| post.data.resize(3 + by, 0);
+        assert_eq!(verify(&owned, true, &grow(MAX_PERMITTED_DATA_INCREASE)), Ok(()));
+        assert_eq!(verify(&owned, true, &grow(MAX_PERMITTED_DATA_INCREASE + 1)), Err(InstructionError::InvalidRealloc));
+        let empty = Account::new(100, vec![], program_id.0);
+        assert_eq!(verify(&empty, true, &|post| post.data = vec![0; 1 << 20]), Ok(()));
+
+        // Owners may only assign accounts whose data is zeroed
+        assert_eq!(verify(&owned, true, &|post| post.owner = other.0), Err(InstructionError::ModifiedProgramId));
+        assert_eq!(verify(&owned, true, &|post| { post.data = vec![0; 3]; post.owner = other.0 }), Ok(()));
+        assert_eq!(verify(&external, true, &|post| post.owner = program_id.0), Err(InstructionError::ModifiedProgramId));
+
+        let executable = Account::new_executable(100, vec![1], program_id.0);
+        assert_eq!(verify(&executable, true, &|post| post.data[0] = 2), Err(InstructionError::ExecutableDataModified));
+        assert_eq!(verify(&executable, true, &|post| post.lamports += 1), Err(InstructionError::ExecutableLamportChange));
+        assert_eq!(verify(&executable, true, &|post| post.executable = false), Err(InstructionError::ExecutableModified));
+        assert_eq!(verify(&owned, true, &|post| post.executable = true), Ok(()));
+        assert_eq!(verify(&owned, true, &|post| post.rent_epoch = 1), Err(InstructionError::RentEpochModified));
+    }
+
+    #[test]
+    fn test_lamports_must_balance() {
+        let program_id = Pubkey::new([1u8; 32]);
+        let accounts = vec![Account::new(100, vec![], program_id.0), Account::new(0, vec![], [0u8; 32])];
+        let pre_accounts: Vec<_> = accounts
+            .iter()
+            .enumerate()
+            .map(|(index, account)| PreAccount::new(index, true, account))
+            .collect();
+
+        let mut post = accounts.clone();
+        post[0].lamports -= 40;
+        post[1].lamports += 40;
+        assert_eq!(verify_accounts(&program_id, &pre_accounts, &post), Ok(()));
+
+        post[1].lamports += 1;
+        assert_eq!(
+            verify_accounts(&program_id, &pre_accounts, &post),
+            Err(InstructionError::UnbalancedInstruction)
+        );
+    }
+}
@@ -0,0 +1,239 @@
+//! Verified executables shared across transactions, keyed by program id and deployment slot.
+
+use crate::sbpf::Executable;
+use crate::types::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Bytes accounted for each cached executable on top of its program region
+const ENTRY_OVERHEAD: usize = 256;
+
+/// A loaded and verified program
+#[derive(Debug)]
+pub struct ProgramCacheEntry {
+    pub executable: Arc<Executable>,
+    pub deployment_slot: u64,
+    /// First slot in which the program may be invoked
+    pub effective_slot: u64,
+    last_used: AtomicU64,
+}
+
+impl ProgramCacheEntry {
+    pub fn new(executable: Executable, deployment_slot: u64, effective_slot: u64) -> Self {
+        Self {
+            executable: Arc::new(executable),
+            deployment_slot,
+            effective_slot,
+            last_used: AtomicU64::new(0),
+        }
+    }
+
+    /// Bytes the entry counts against the cache capacity
+    pub fn size(&self) -> usize {
+        self.executable.program().len() + ENTRY_OVERHEAD
+    }
+
+    pub fn is_visible(&self, slot: u64) -> bool {
+        slot >= self.effective_slot
+    }
+}
+
+/// Counters describing how well the cache is doing
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProgramCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub insertions: u64,
+    pub evictions: u64,
+}
+
+#[derive(Debug, Default)]
+struct AtomicStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Executables of deployed programs, evicted least recently used first once over capacity
+#[derive(Debug)]
+pub struct ProgramCache {
+    entries: HashMap<Pubkey, Vec<Arc<ProgramCacheEntry>>>,
+    capacity_bytes: usize,
+    size_bytes: usize,
+    clock: AtomicU64,
+    lookups: AtomicStats,
+    insertions: u64,
+    evictions: u64,
+}
+
+impl ProgramCache {
+    pub fn new(capacity_bytes: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            capacity_bytes,
+            size_bytes: 0,
+            clock: AtomicU64::new(0),
+            lookups: AtomicStats::default(),
+            insertions: 0,
+            evictions: 0,
+        }
+    }
+
+    /// Executable of `program_id` as deployed in `deployment_slot`
+    pub fn get(&self, program_id: &Pubkey, deployment_slot: u64) -> Option<Arc<ProgramCacheEntry>> {
+        let entry = self
+            .entries
+            .get(program_id)
+            .and_then(|entries| entries.iter().find(|entry| entry.deployment_slot == deployment_slot));
+        match entry {
+            Some(entry) => {
+                self.lookups.hits.fetch_add(1, Ordering::Relaxed);
+                entry.last_used.store(self.tick(), Ordering::Relaxed);
+                Some(entry.clone())
+            }
+            None => {
+                self.lookups.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Cache a freshly loaded program, evicting others if the cache is over capacity
+    pub fn insert(&mut self, program_id: Pubkey, entry: ProgramCacheEntry) -> Arc<ProgramCacheEntry> {
+        entry.last_used.store(self.tick(), Ordering::Relaxed);
+        let entry = Arc::new(entry);
+        let entries = self.entries.entry(program_id).or_default();
+        if let Some(position) = entries.iter().position(|cached| cached.deployment_slot == entry.deployment_slot) {
+            self.size_bytes -= entries.remove(position).size();
+        }
+        entries.push(entry.clone());
+        self.size_bytes += entry.size();
+        self.insertions += 1;
+
+        self.evict(&entry);
+        entry
+    }
+
+    /// Drop every cached version of `program_id`, e.g. when its account is replaced outright
+    pub fn remove(&mut self, program_id: &Pubkey) {
+        if let Some(entries) = self.entries.remove(program_id) {
+            self.size_bytes -= entries.iter().map(|entry| entry.size()).sum::<usize>();
+        }
+    }
+
+    /// Drop every cached executable, e.g. when switching to another fork
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.size_bytes = 0;
+    }
+
+    /// Evict least recently used entries other than `keep` until within capacity
+    fn evict(&mut self, keep: &Arc<ProgramCacheEntry>) {
+        while self.size_bytes > self.capacity_bytes {
+            let oldest = self
+                .entries
+                .iter()
+                .flat_map(|(program_id, entries)| entries.iter().map(move |entry| (program_id, entry)))
+                .filter(|(_, entry)| !Arc::ptr_eq(entry, keep))
+                .min_by_key(|(_, entry)| entry.last_used.load(Ordering::Relaxed))
+                .map(|(program_id, entry)| (*program_id, entry.deployment_slot));
+            let Some((program_id, deployment_slot)) = oldest else {
+                break;
+            };
+
+            let entries = self.entries.get_mut(&program_id).expect("entry was just found");
+            let position = entries
+                .iter()
+                .position(|entry| entry.deployment_slot == deployment_slot)
+                .expect("entry was just found");
+            self.size_bytes -= entries.remove(position).size();
+            if entries.is_empty() {
+                self.entries.remove(&program_id);
+            }
+            self.evictions += 1;
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Number of cached executables
+    pub fn len(&self) -> usize {
+        self.entries.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.size_bytes
+    }
+
+    pub fn stats(&self) -> ProgramCacheStats {
+        ProgramCacheStats {
+            hits: self.lookups.hits.load(Ordering::Relaxed),
+            misses: self.lookups.misses.load(Ordering::Relaxed),
+            insertions: self.insertions,
+            evictions: self.evictions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(program_len: usize, deployment_slot: u64) -> ProgramCacheEntry {
+        ProgramCacheEntry::new(
+            Executable::from_text_bytes(&vec![0u8; program_len]),
+            deployment_slot,
+            deployment_slot + 1,
+        )
+    }
+
+    #[test]
+    fn test_lookup_by_deployment_slot() {
+        let program_id = Pubkey::new([1u8; 32]);
+        let mut cache = ProgramCache::new(1 << 20);
+
+        assert!(cache.get(&program_id, 5).is_none());
+        cache.insert(program_id, entry(8, 5));
+        let cached = cache.get(&program_id, 5).unwrap();
+        assert!(!cached.is_visible(5));
+        assert!(cached.is_visible(6));
+
+        // An upgrade is a separate entry, the old one is still served to older lookups
+        cache.insert(program_id, entry(16, 9));
+        assert_eq!(cache.get(&program_id, 9).unwrap().executable.program().len(), 16);
+        assert_eq!(cache.get(&program_id, 5).unwrap().executable.program().len(), 8);
+        assert_eq!(cache.len(), 2);
+
+        cache.remove(&program_id);
+        assert!(cache.is_empty());
+        assert_eq!(cache.size_bytes(), 0);
+        assert_eq!(
+            cache.stats(),
+            ProgramCacheStats { hits: 3, misses: 1, insertions: 2, evictions: 0 }
+        );
+    }
+
+    #[test]
+    fn test_least_recently_used_eviction() {
+        let (a, b, c) = (Pubkey::new([1u8; 32]), Pubkey::new([2u8; 32]), Pubkey::new([3u8; 32]));
+        let mut cache = ProgramCache::new(3 * (1024 + ENTRY_OVERHEAD) - 1);
+
+        cache.insert(a, entry(1024, 0));
+        cache.insert(b, entry(1024, 0));
+        cache.get(&a, 0).unwrap();
+        cache.insert(c, entry(1024, 0));
+
+        assert!(cache.get(&b, 0).is_none());
+        assert!(cache.get(&a, 0).is_some());
+        assert!(cache.get(&c, 0).is_some());
+        assert_eq!(cache.stats().evictions, 1);
+        assert_eq!(cache.size_bytes(), 2 * (1024 + ENTRY_OVERHEAD));
+    }
+}
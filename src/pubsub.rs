@@ -0,0 +1,647 @@
+//! WebSocket server for Solana's PubSub API: account, program, signature, logs and slot
+//! subscriptions, notified from the runtime's commit events at the requested commitment.
+
+use crate::account_dump::{UiAccount, UiAccountEncoding};
+use crate::bank::Bank;
+use crate::rpc::{self, CommitmentConfig, CommitmentLevel, RpcError, INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR};
+use crate::runtime::{CommittedTransaction, RuntimeEvent, SharedRuntime};
+use crate::transaction_error::TransactionError;
+use crate::types::*;
+use crate::{Result, TerminatorError};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, info, warn};
+
+/// Sends notifications to one WebSocket connection
+type NotificationSender = mpsc::UnboundedSender<Value>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum MemcmpEncoding {
+    Base58,
+    Base64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Memcmp {
+    offset: usize,
+    bytes: String,
+    encoding: Option<MemcmpEncoding>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum ProgramFilterConfig {
+    DataSize(usize),
+    Memcmp(Memcmp),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ProgramFilter {
+    DataSize(usize),
+    Memcmp { offset: usize, bytes: Vec<u8> },
+}
+
+impl ProgramFilter {
+    fn matches(&self, account: &Account) -> bool {
+        match self {
+            Self::DataSize(size) => account.data.len() == *size,
+            Self::Memcmp { offset, bytes } => account
+                .data
+                .get(*offset..offset.saturating_add(bytes.len()))
+                .is_some_and(|data| data == bytes.as_slice()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct AccountSubscribeConfig {
+    commitment: CommitmentLevel,
+    encoding: Option<UiAccountEncoding>,
+    filters: Vec<ProgramFilterConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum LogsFilterConfig {
+    Named(String),
+    Mentions { mentions: Vec<String> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SubscriptionKind {
+    Account { pubkey: Pubkey, encoding: UiAccountEncoding },
+    Program { program_id: Pubkey, encoding: UiAccountEncoding, filters: Vec<ProgramFilter> },
+    Signature { signature: [u8; 64] },
+    /// Logs of every transaction, or of those mentioning an account
+    Logs { mentions: Option<Pubkey> },
+    Slot,
+}
+
+impl SubscriptionKind {
+    /// Method of the notifications, which also names the unsubscribe method
+    fn notification_method(&self) -> &'static str {
+        match self {
+            Self::Account { .. } => "accountNotification",
+            Self::Program { .. } => "programNotification",
+            Self::Signature { .. } => "signatureNotification",
+            Self::Logs { .. } => "logsNotification",
+            Self::Slot => "slotNotification",
+        }
+    }
+}
+
+struct Subscription {
+    connection: u64,
+    sender: NotificationSender,
+    kind: SubscriptionKind,
+    commitment: CommitmentLevel,
+}
+
+impl Subscription {
+    fn notify(&self, id: u64, result: Value) {
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": self.kind.notification_method(),
+            "params": { "result": result, "subscription": id },
+        });
+        // The connection may be closing
+        let _ = self.sender.send(notification);
+    }
+}
+
+/// Transactions committed in a slot that hasn't been finalized yet
+#[derive(Debug, Clone, Default)]
+struct SlotActivity {
+    transactions: Vec<Arc<CommittedTransaction>>,
+    written_accounts: BTreeSet<Pubkey>,
+}
+
+#[derive(Default)]
+struct PubSubState {
+    subscriptions: HashMap<u64, Subscription>,
+    /// Activity by slot, kept until the slot is rooted or discarded
+    pending: BTreeMap<u64, SlotActivity>,
+}
+
+impl PubSubState {
+    fn notify_transactions<'a>(
+        &mut self,
+        transactions: impl IntoIterator<Item = &'a Arc<CommittedTransaction>>,
+        commitment: CommitmentLevel,
+    ) {
+        for transaction in transactions {
+            let signature = transaction.signature.map(|signature| bs58::encode(signature).into_string());
+            let mut notified = Vec::new();
+            for (&id, subscription) in self.subscriptions.iter().filter(|(_, s)| s.commitment == commitment) {
+                match &subscription.kind {
+                    SubscriptionKind::Signature { signature: subscribed } if transaction.signature == Some(*subscribed) => {
+                        subscription.notify(id, signature_result(transaction.slot, &transaction.error));
+                        notified.push(id);
+                    }
+                    SubscriptionKind::Logs { mentions }
+                        if mentions.is_none_or(|pubkey| transaction.account_keys.contains(&pubkey)) =>
+                    {
+                        subscription.notify(
+                            id,
+                            json!({
+                                "context": { "slot": transaction.slot },
+                                "value": {
+                                    "signature": signature,
+                                    "err": transaction.error,
+                                    "logs": transaction.logs,
+                                },
+                            }),
+                        );
+                    }
+                    _ => {}
+                }
+            }
+            // Signature subscriptions end with their notification
+            for id in notified {
+                self.subscriptions.remove(&id);
+            }
+        }
+    }
+
+    /// Notify account and program subscribers of `written_accounts` as they are in `bank`
+    fn notify_accounts<'a>(
+        &self,
+        bank: &Bank,
+        context_slot: u64,
+        written_accounts: impl IntoIterator<Item = &'a Pubkey>,
+        commitment: CommitmentLevel,
+    ) {
+        let subscriptions: Vec<_> = self
+            .subscriptions
+            .iter()
+            .filter(|(_, subscription)| {
+                subscription.commitment == commitment
+                    && matches!(subscription.kind, SubscriptionKind::Account { .. } | SubscriptionKind::Program { .. })
+            })
+            .collect();
+        if subscriptions.is_empty() {
+            return;
+        }
+
+        for pubkey in written_accounts {
+            // Closed accounts are reported empty and owned by the system program
+            let account = bank
+                .get_account(pubkey)
+                .filter(|account| account.lamports > 0)
+                .unwrap_or_else(|| Account::new(0, vec![], Pubkey::system_program().0));
+            for (&id, subscription) in &subscriptions {
+                match &subscription.kind {
+                    SubscriptionKind::Account { pubkey: subscribed, encoding } if subscribed == pubkey => {
+                        subscription.notify(
+                            id,
+                            json!({ "context": { "slot": context_slot }, "value": encode_account(&account, *encoding) }),
+                        );
+                    }
+                    SubscriptionKind::Program { program_id, encoding, filters }
+                        if account.owner == program_id.0 && filters.iter().all(|filter| filter.matches(&account)) =>
+                    {
+                        subscription.notify(
+                            id,
+                            json!({
+                                "context": { "slot": context_slot },
+                                "value": { "pubkey": pubkey.to_string(), "account": encode_account(&account, *encoding) },
+                            }),
+                        );
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Serves Solana's PubSub API over WebSocket from a runtime shared with the block producer
+#[derive(Clone)]
+pub struct PubSubService {
+    runtime: SharedRuntime,
+    state: Arc<Mutex<PubSubState>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl PubSubService {
+    pub fn new(runtime: SharedRuntime) -> Self {
+        Self {
+            runtime,
+            state: Arc::new(Mutex::new(PubSubState::default())),
+            next_id: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new().route("/", get(handle_upgrade)).with_state(self.clone())
+    }
+
+    /// Serve WebSocket connections on `listener` and notify them of runtime events until
+    /// `shutdown` completes
+    pub async fn serve(self, listener: TcpListener, shutdown: impl Future<Output = ()> + Send + 'static) -> Result<()> {
+        if let Ok(address) = listener.local_addr() {
+            info!("PubSub server listening on {}", address);
+        }
+        let events = self.runtime.read().unwrap().subscribe_events();
+        let dispatcher = tokio::spawn(self.clone().dispatch_events(events));
+        let served = axum::serve(listener, self.router())
+            .with_graceful_shutdown(shutdown)
+            .await
+            .map_err(|e| TerminatorError::RpcError(e.to_string()));
+        dispatcher.abort();
+        served
+    }
+
+    async fn dispatch_events(self, mut events: broadcast::Receiver<RuntimeEvent>) {
+        loop {
+            match events.recv().await {
+                Ok(event) => self.process_event(&event),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("PubSub fell behind the runtime, {} events were not notified", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Notify the subscriptions that `event` concerns
+    pub fn process_event(&self, event: &RuntimeEvent) {
+        let mut state = self.state.lock().unwrap();
+        let runtime = self.runtime.read().unwrap();
+        match event {
+            RuntimeEvent::TransactionCommitted(transaction) => {
+                let activity = state.pending.entry(transaction.slot).or_default();
+                activity.transactions.push(transaction.clone());
+                activity.written_accounts.extend(transaction.written_accounts.iter().copied());
+                state.notify_transactions([transaction], CommitmentLevel::Processed);
+                state.notify_accounts(runtime.bank(), transaction.slot, &transaction.written_accounts, CommitmentLevel::Processed);
+            }
+            RuntimeEvent::BankCreated { slot, parent } => {
+                // Anything pending for the slot belonged to a bank that was replaced
+                state.pending.remove(slot);
+                let result = json!({ "parent": parent, "root": runtime.bank_forks().root(), "slot": slot });
+                for (&id, subscription) in &state.subscriptions {
+                    if subscription.kind == SubscriptionKind::Slot {
+                        subscription.notify(id, result.clone());
+                    }
+                }
+            }
+            RuntimeEvent::SlotFrozen { slot } => {
+                let Some(activity) = state.pending.get(slot).cloned() else {
+                    return;
+                };
+                let bank = runtime.bank_forks().get(*slot).map_or(runtime.bank(), |bank| bank);
+                state.notify_transactions(&activity.transactions, CommitmentLevel::Confirmed);
+                state.notify_accounts(bank, *slot, &activity.written_accounts, CommitmentLevel::Confirmed);
+            }
+            RuntimeEvent::SlotRooted { slot } => {
+                let rooted: Vec<u64> = state.pending.range(..=slot).map(|(&slot, _)| slot).collect();
+                let mut written_accounts = BTreeSet::new();
+                for rooted_slot in rooted {
+                    let activity = state.pending.remove(&rooted_slot).unwrap_or_default();
+                    state.notify_transactions(&activity.transactions, CommitmentLevel::Finalized);
+                    written_accounts.extend(activity.written_accounts);
+                }
+                let bank = rpc::bank(&runtime, CommitmentLevel::Finalized);
+                state.notify_accounts(bank, *slot, &written_accounts, CommitmentLevel::Finalized);
+            }
+            RuntimeEvent::SlotsDiscarded { slots } => {
+                for slot in slots {
+                    state.pending.remove(slot);
+                }
+            }
+        }
+    }
+
+    /// Handle a request from connection `connection`, whose notifications go to `sender`
+    fn handle_message(&self, connection: u64, sender: &NotificationSender, message: &str) -> Value {
+        let Ok(request) = serde_json::from_str::<Value>(message) else {
+            return rpc::error_response(Value::Null, RpcError::new(PARSE_ERROR, "Parse error"));
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let version = request.get("jsonrpc").and_then(Value::as_str);
+        let Some(method) = request.get("method").and_then(Value::as_str).filter(|_| version == Some("2.0")) else {
+            return rpc::error_response(id, RpcError::new(INVALID_REQUEST, "Invalid request"));
+        };
+        let params = match request.get("params") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(params)) => params.clone(),
+            Some(_) => return rpc::error_response(id, RpcError::invalid_params("params must be an array")),
+        };
+
+        debug!("PubSub request {}", method);
+        let result = match method.strip_suffix("Unsubscribe") {
+            Some(kind) => self.unsubscribe(connection, kind, &params),
+            None => self.subscribe(connection, sender, method, &params),
+        };
+        match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+            Err(e) => rpc::error_response(id, e),
+        }
+    }
+
+    fn subscribe(&self, connection: u64, sender: &NotificationSender, method: &str, params: &[Value]) -> std::result::Result<Value, RpcError> {
+        let (kind, commitment) = match method {
+            "accountSubscribe" => {
+                let pubkey = rpc::parse_pubkey(&rpc::param::<String>(params, 0, "pubkey")?)?;
+                let config: AccountSubscribeConfig = rpc::config(params, 1)?;
+                let encoding = config.encoding.unwrap_or(UiAccountEncoding::Binary);
+                (SubscriptionKind::Account { pubkey, encoding }, config.commitment)
+            }
+            "programSubscribe" => {
+                let program_id = rpc::parse_pubkey(&rpc::param::<String>(params, 0, "program id")?)?;
+                let config: AccountSubscribeConfig = rpc::config(params, 1)?;
+                let filters = config.filters.into_iter().map(program_filter).collect::<std::result::Result<_, _>>()?;
+                let encoding = config.encoding.unwrap_or(UiAccountEncoding::Binary);
+                (SubscriptionKind::Program { program_id, encoding, filters }, config.commitment)
+            }
+            "signatureSubscribe" => {
+                let signature = rpc::parse_signature(&rpc::param::<String>(params, 0, "signature")?)?;
+                let config: CommitmentConfig = rpc::config(params, 1)?;
+                (SubscriptionKind::Signature { signature }, config.commitment)
+            }
+            "logsSubscribe" => {
+                let mentions = match rpc::param(params, 0, "filter")? {
+                    LogsFilterConfig::Named(name) if name == "all" || name == "allWithVotes" => None,
+                    LogsFilterConfig::Mentions { mentions } if mentions.len() == 1 => Some(rpc::parse_pubkey(&mentions[0])?),
+                    LogsFilterConfig::Mentions { .. } => {
+                        return Err(RpcError::invalid_params("only one address is supported in mentions"));
+                    }
+                    LogsFilterConfig::Named(name) => return Err(RpcError::invalid_params(format!("unknown filter {}", name))),
+                };
+                let config: CommitmentConfig = rpc::config(params, 1)?;
+                (SubscriptionKind::Logs { mentions }, config.commitment)
+            }
+            "slotSubscribe" => (SubscriptionKind::Slot, CommitmentLevel::Processed),
+            _ => return Err(RpcError::new(METHOD_NOT_FOUND, "Method not found")),
+        };
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let subscription = Subscription { connection, sender: sender.clone(), kind, commitment };
+        let mut state = self.state.lock().unwrap();
+        // A signature already at the requested commitment is notified right away
+        if let SubscriptionKind::Signature { signature } = &subscription.kind {
+            let runtime = self.runtime.read().unwrap();
+            if let Some(status) = runtime.get_signature_status(signature) {
+                if rpc::slot_commitment(&runtime, status.slot) >= commitment {
+                    subscription.notify(id, signature_result(status.slot, &status.error));
+                    return Ok(json!(id));
+                }
+            }
+        }
+        state.subscriptions.insert(id, subscription);
+        Ok(json!(id))
+    }
+
+    fn unsubscribe(&self, connection: u64, kind: &str, params: &[Value]) -> std::result::Result<Value, RpcError> {
+        let id: u64 = rpc::param(params, 0, "subscription id")?;
+        let method = format!("{}Notification", kind);
+        let mut state = self.state.lock().unwrap();
+        let subscribed = state.subscriptions.get(&id).is_some_and(|subscription| {
+            subscription.connection == connection && subscription.kind.notification_method() == method
+        });
+        if !subscribed {
+            return Err(RpcError::new(INVALID_PARAMS, "Invalid subscription id."));
+        }
+        state.subscriptions.remove(&id);
+        Ok(json!(true))
+    }
+
+    fn remove_connection(&self, connection: u64) {
+        self.state.lock().unwrap().subscriptions.retain(|_, subscription| subscription.connection != connection);
+    }
+
+    /// Number of live subscriptions
+    pub fn subscription_count(&self) -> usize {
+        self.state.lock().unwrap().subscriptions.len()
+    }
+}
+
+async fn handle_upgrade(State(service): State<PubSubService>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| handle_socket(service, socket))
+}
+
+async fn handle_socket(service: PubSubService, mut socket: WebSocket) {
+    static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(0);
+    let connection = NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed);
+    let (sender, mut notifications) = mpsc::unbounded_channel();
+
+    loop {
+        tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    // The response goes out before any notification the request triggered
+                    let response = service.handle_message(connection, &sender, &text);
+                    if socket.send(Message::Text(response.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            Some(notification) = notifications.recv() => {
+                if socket.send(Message::Text(notification.to_string())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    service.remove_connection(connection);
+}
+
+fn program_filter(config: ProgramFilterConfig) -> std::result::Result<ProgramFilter, RpcError> {
+    match config {
+        ProgramFilterConfig::DataSize(size) => Ok(ProgramFilter::DataSize(size)),
+        ProgramFilterConfig::Memcmp(memcmp) => {
+            let bytes = match memcmp.encoding.unwrap_or(MemcmpEncoding::Base58) {
+                MemcmpEncoding::Base58 => bs58::decode(&memcmp.bytes).into_vec().map_err(RpcError::invalid_params)?,
+                MemcmpEncoding::Base64 => base64::engine::general_purpose::STANDARD
+                    .decode(&memcmp.bytes)
+                    .map_err(RpcError::invalid_params)?,
+            };
+            Ok(ProgramFilter::Memcmp { offset: memcmp.offset, bytes })
+        }
+    }
+}
+
+fn signature_result(slot: u64, error: &Option<TransactionError>) -> Value {
+    json!({ "context": { "slot": slot }, "value": { "err": error } })
+}
+
+fn encode_account(account: &Account, encoding: UiAccountEncoding) -> Value {
+    // No account data parsers, so jsonParsed falls back to base64
+    let encoding = match encoding {
+        UiAccountEncoding::JsonParsed => UiAccountEncoding::Base64,
+        encoding => encoding,
+    };
+    let ui_account = UiAccount::encode(account, encoding).expect("only jsonParsed fails to encode");
+    serde_json::to_value(ui_account).expect("accounts serialize to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::TerminatorRuntime;
+    use std::sync::RwLock;
+
+    struct Connection {
+        service: PubSubService,
+        events: broadcast::Receiver<RuntimeEvent>,
+        sender: NotificationSender,
+        notifications: mpsc::UnboundedReceiver<Value>,
+    }
+
+    impl Connection {
+        async fn new() -> Self {
+            let runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+            let events = runtime.subscribe_events();
+            let (sender, notifications) = mpsc::unbounded_channel();
+            Self {
+                service: PubSubService::new(Arc::new(RwLock::new(runtime))),
+                events,
+                sender,
+                notifications,
+            }
+        }
+
+        fn request(&self, method: &str, params: Value) -> Value {
+            let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+            self.service.handle_message(0, &self.sender, &request.to_string())
+        }
+
+        fn subscribe(&self, method: &str, params: Value) -> u64 {
+            self.request(method, params)["result"].as_u64().unwrap()
+        }
+
+        /// Process the runtime's events and collect the notifications they triggered
+        fn notifications(&mut self) -> Vec<Value> {
+            while let Ok(event) = self.events.try_recv() {
+                self.service.process_event(&event);
+            }
+            std::iter::from_fn(|| self.notifications.try_recv().ok()).collect()
+        }
+
+        fn runtime(&self) -> std::sync::RwLockWriteGuard<'_, TerminatorRuntime> {
+            self.service.runtime.write().unwrap()
+        }
+    }
+
+    fn methods(notifications: &[Value]) -> Vec<&str> {
+        let mut methods: Vec<_> = notifications.iter().map(|n| n["method"].as_str().unwrap()).collect();
+        methods.sort_unstable();
+        methods
+    }
+
+    #[tokio::test]
+    async fn test_notifications_by_commitment() {
+        let mut connection = Connection::new().await;
+        let recipient = Pubkey::new([2u8; 32]);
+        let signature = bs58::encode([1u8; 64]).into_string();
+
+        let processed = connection.subscribe(
+            "accountSubscribe",
+            json!([recipient.to_string(), { "commitment": "processed", "encoding": "base64" }]),
+        );
+        let finalized = connection.subscribe("accountSubscribe", json!([recipient.to_string()]));
+        connection.subscribe("signatureSubscribe", json!([signature, { "commitment": "confirmed" }]));
+        connection.subscribe("logsSubscribe", json!([{ "mentions": [recipient.to_string()] }, { "commitment": "processed" }]));
+        connection.subscribe(
+            "programSubscribe",
+            json!([Pubkey::system_program().to_string(), { "commitment": "processed", "filters": [{ "dataSize": 0 }] }]),
+        );
+        connection.subscribe("slotSubscribe", json!([]));
+
+        let blockhash = connection.runtime().latest_blockhash();
+        let transfer = Transaction::transfer_for_test(1, 2, 10, blockhash);
+        assert!(connection.runtime().execute_transaction(&transfer).unwrap().success);
+        let notifications = connection.notifications();
+        // The sender and the recipient both changed and are owned by the system program
+        assert_eq!(
+            methods(&notifications),
+            vec!["accountNotification", "logsNotification", "programNotification", "programNotification"]
+        );
+        let account = notifications.iter().find(|n| n["method"] == "accountNotification").unwrap();
+        assert_eq!(account["params"]["subscription"], processed);
+        assert_eq!(account["params"]["result"]["value"]["lamports"], 10);
+        assert_eq!(account["params"]["result"]["value"]["data"], json!(["", "base64"]));
+        let logs = notifications.iter().find(|n| n["method"] == "logsNotification").unwrap();
+        assert_eq!(logs["params"]["result"]["value"]["signature"], signature);
+
+        connection.runtime().advance_slot();
+        let notifications = connection.notifications();
+        assert_eq!(methods(&notifications), vec!["signatureNotification", "slotNotification"]);
+        let slot = notifications.iter().find(|n| n["method"] == "slotNotification").unwrap();
+        assert_eq!(slot["params"]["result"], json!({ "parent": 0, "root": 0, "slot": 1 }));
+        let status = notifications.iter().find(|n| n["method"] == "signatureNotification").unwrap();
+        assert_eq!(status["params"]["result"], json!({ "context": { "slot": 0 }, "value": { "err": null } }));
+
+        connection.runtime().set_root(0).unwrap();
+        let notifications = connection.notifications();
+        assert_eq!(methods(&notifications), vec!["accountNotification"]);
+        assert_eq!(notifications[0]["params"]["subscription"], finalized);
+        assert_eq!(notifications[0]["params"]["result"]["context"]["slot"], 0);
+
+        // Signature subscriptions end once notified
+        assert_eq!(connection.service.subscription_count(), 5);
+        assert_eq!(connection.request("accountUnsubscribe", json!([processed]))["result"], true);
+        assert_eq!(connection.request("accountUnsubscribe", json!([processed]))["error"]["code"], INVALID_PARAMS);
+        assert_eq!(connection.request("logsUnsubscribe", json!([finalized]))["error"]["code"], INVALID_PARAMS);
+        connection.service.remove_connection(0);
+        assert_eq!(connection.service.subscription_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_signature_already_processed() {
+        let mut connection = Connection::new().await;
+        let blockhash = connection.runtime().latest_blockhash();
+        // Funded accounts aren't topped up, so the transfer fails once the fee is charged
+        let from = Pubkey::new([3u8; 32]);
+        connection.runtime().store_account(from, Account::new(5_005, vec![], Pubkey::system_program().0));
+        let failed = Transaction::transfer_for_test(3, 4, 10, blockhash);
+        assert!(!connection.runtime().execute_transaction(&failed).unwrap().success);
+        connection.notifications();
+
+        let signature = bs58::encode([3u8; 64]).into_string();
+        let id = connection.subscribe("signatureSubscribe", json!([signature, { "commitment": "processed" }]));
+        let notifications = connection.notifications();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0]["params"]["subscription"], id);
+        assert!(notifications[0]["params"]["result"]["value"]["err"].is_object());
+        assert_eq!(connection.service.subscription_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_requests() {
+        let connection = Connection::new().await;
+        assert_eq!(connection.request("blockSubscribe", json!([]))["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(connection.request("logsSubscribe", json!(["votes"]))["error"]["code"], INVALID_PARAMS);
+        let too_many = json!([{ "mentions": [Pubkey::new([1u8; 32]).to_string(), Pubkey::new([2u8; 32]).to_string()] }]);
+        assert_eq!(connection.request("logsSubscribe", too_many)["error"]["code"], INVALID_PARAMS);
+        assert_eq!(connection.request("accountSubscribe", json!(["nope"]))["error"]["code"], INVALID_PARAMS);
+        let response = connection.service.handle_message(0, &connection.sender, "{");
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+
+        let filter = program_filter(ProgramFilterConfig::Memcmp(Memcmp {
+            offset: 1,
+            bytes: bs58::encode([7u8, 8]).into_string(),
+            encoding: None,
+        }))
+        .unwrap();
+        assert!(filter.matches(&Account::new(1, vec![0, 7, 8], [0u8; 32])));
+        assert!(!filter.matches(&Account::new(1, vec![0, 7], [0u8; 32])));
+    }
+}
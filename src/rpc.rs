@@ -0,0 +1,973 @@
+//! JSON-RPC server implementing the core of Solana's RPC API, so wallets, web3.js and the Solana
+//! CLI can talk to the runtime like to a local test validator.
+
+use crate::account_dump::{UiAccount, UiAccountEncoding};
+use crate::bank::Bank;
+use crate::block_producer::TransactionSender;
+use crate::genesis;
+use crate::runtime::{SharedRuntime, TerminatorRuntime};
+use crate::solana_format::{
+    CompiledInstruction, SolanaHash, SolanaPubkey, SolanaSignature, SolanaTransaction, SolanaTransactionParser,
+    PACKET_DATA_SIZE,
+};
+use crate::transaction_error::TransactionError;
+use crate::transaction_status::ConfirmedTransaction;
+use crate::types::*;
+use crate::{Result, TerminatorError};
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use base64::Engine;
+use ed25519_dalek::Signer;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::{Arc, RwLockReadGuard};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::Semaphore;
+use tracing::{debug, info};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// A submitted transaction failed simulation or execution
+pub const SEND_TRANSACTION_PREFLIGHT_FAILURE: i64 = -32002;
+pub const TRANSACTION_SIGNATURE_VERIFICATION_FAILURE: i64 = -32003;
+
+/// Most accounts `getMultipleAccounts` returns
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+/// Most signatures `getSignatureStatuses` looks up
+const MAX_SIGNATURE_STATUSES: usize = 256;
+/// Largest account data returned base58 encoded
+const MAX_BASE58_BYTES: usize = 128;
+
+/// Error object of a JSON-RPC response
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into(), data: None }
+    }
+
+    pub(crate) fn invalid_params(message: impl Display) -> Self {
+        Self::new(INVALID_PARAMS, format!("Invalid params: {}", message))
+    }
+
+    fn internal(message: impl Display) -> Self {
+        Self::new(INTERNAL_ERROR, format!("Internal error: {}", message))
+    }
+
+    /// A transaction that failed before it was submitted, with the logs of its simulation
+    fn transaction_failure(err: &TransactionError, logs: &[String], units_consumed: u64) -> Self {
+        Self {
+            code: SEND_TRANSACTION_PREFLIGHT_FAILURE,
+            message: format!("Transaction simulation failed: {}", err),
+            data: Some(json!({
+                "err": err,
+                "logs": logs,
+                "accounts": null,
+                "unitsConsumed": units_consumed,
+                "returnData": null,
+            })),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let mut error = json!({ "code": self.code, "message": self.message });
+        if let Some(data) = &self.data {
+            error["data"] = data.clone();
+        }
+        error
+    }
+}
+
+impl From<TerminatorError> for RpcError {
+    fn from(e: TerminatorError) -> Self {
+        match e {
+            TerminatorError::Transaction(err) => Self::transaction_failure(&err, &[], 0),
+            e => Self::new(SEND_TRANSACTION_PREFLIGHT_FAILURE, format!("Transaction simulation failed: {}", e)),
+        }
+    }
+}
+
+type RpcResult<T> = std::result::Result<T, RpcError>;
+
+/// How settled the state a request reads must be
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CommitmentLevel {
+    /// The working bank, including transactions of the slot still being produced
+    Processed,
+    /// The last frozen bank
+    Confirmed,
+    /// The root bank
+    #[default]
+    Finalized,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum TransactionEncoding {
+    #[serde(alias = "binary")]
+    Base58,
+    Base64,
+    Json,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct CommitmentConfig {
+    pub commitment: CommitmentLevel,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct DataSlice {
+    offset: usize,
+    length: usize,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct AccountInfoConfig {
+    commitment: CommitmentLevel,
+    encoding: Option<UiAccountEncoding>,
+    data_slice: Option<DataSlice>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct SendTransactionConfig {
+    encoding: Option<TransactionEncoding>,
+    skip_preflight: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct SimulateAccountsConfig {
+    addresses: Vec<String>,
+    encoding: Option<UiAccountEncoding>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct SimulateTransactionConfig {
+    sig_verify: bool,
+    replace_recent_blockhash: bool,
+    encoding: Option<TransactionEncoding>,
+    accounts: Option<SimulateAccountsConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct TransactionConfig {
+    commitment: CommitmentLevel,
+    encoding: Option<TransactionEncoding>,
+    max_supported_transaction_version: Option<u8>,
+}
+
+/// Serves Solana's JSON-RPC API over HTTP from a runtime shared with the block producer
+#[derive(Clone)]
+pub struct JsonRpcService {
+    runtime: SharedRuntime,
+    /// Queues submitted transactions for block production; without it they execute right away
+    sender: Option<TransactionSender>,
+    /// Bounds the requests handled at once to `max_connections`
+    requests: Arc<Semaphore>,
+    request_timeout: Duration,
+}
+
+impl JsonRpcService {
+    pub fn new(runtime: SharedRuntime, settings: &NetworkingSettings) -> Self {
+        Self {
+            runtime,
+            sender: None,
+            requests: Arc::new(Semaphore::new(settings.max_connections.max(1) as usize)),
+            request_timeout: Duration::from_millis(settings.connection_timeout_ms as u64),
+        }
+    }
+
+    /// Submit transactions to the block producer instead of executing them directly
+    pub fn with_transaction_sender(mut self, sender: TransactionSender) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/", post(handle_http_request))
+            .route("/health", get(|| async { "ok" }))
+            .with_state(self.clone())
+    }
+
+    /// Serve requests on `listener` until `shutdown` completes
+    pub async fn serve(self, listener: TcpListener, shutdown: impl Future<Output = ()> + Send + 'static) -> Result<()> {
+        if let Ok(address) = listener.local_addr() {
+            info!("JSON-RPC server listening on {}", address);
+        }
+        axum::serve(listener, self.router())
+            .with_graceful_shutdown(shutdown)
+            .await
+            .map_err(|e| TerminatorError::RpcError(e.to_string()))
+    }
+
+    /// Handle a request object, or a batch of them
+    pub fn handle_request(&self, request: Value) -> Value {
+        match request {
+            Value::Array(requests) if requests.is_empty() => {
+                error_response(Value::Null, RpcError::new(INVALID_REQUEST, "Invalid request"))
+            }
+            Value::Array(requests) => requests.iter().map(|request| self.handle_single_request(request)).collect(),
+            request => self.handle_single_request(&request),
+        }
+    }
+
+    fn handle_single_request(&self, request: &Value) -> Value {
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let version = request.get("jsonrpc").and_then(Value::as_str);
+        let Some(method) = request.get("method").and_then(Value::as_str).filter(|_| version == Some("2.0")) else {
+            return error_response(id, RpcError::new(INVALID_REQUEST, "Invalid request"));
+        };
+        let params = match request.get("params") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(params)) => params.clone(),
+            Some(_) => return error_response(id, RpcError::invalid_params("params must be an array")),
+        };
+
+        debug!("RPC request {}", method);
+        match self.dispatch(method, &params) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+            Err(e) => error_response(id, e),
+        }
+    }
+
+    fn dispatch(&self, method: &str, params: &[Value]) -> RpcResult<Value> {
+        match method {
+            "getAccountInfo" => self.get_account_info(params),
+            "getBalance" => self.get_balance(params),
+            "getBlockHeight" | "getSlot" => self.get_slot(params),
+            "getGenesisHash" => Ok(json!(bs58::encode(self.runtime().genesis_hash()).into_string())),
+            "getHealth" => Ok(json!("ok")),
+            "getLatestBlockhash" => self.get_latest_blockhash(params),
+            "getMinimumBalanceForRentExemption" => self.get_minimum_balance_for_rent_exemption(params),
+            "getMultipleAccounts" => self.get_multiple_accounts(params),
+            "getSignatureStatuses" => self.get_signature_statuses(params),
+            "getTransaction" => self.get_transaction(params),
+            "getVersion" => Ok(json!({ "solana-core": env!("CARGO_PKG_VERSION"), "feature-set": 0 })),
+            "requestAirdrop" => self.request_airdrop(params),
+            "sendTransaction" => self.send_transaction(params),
+            "simulateTransaction" => self.simulate_transaction(params),
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, "Method not found")),
+        }
+    }
+
+    fn runtime(&self) -> RwLockReadGuard<'_, TerminatorRuntime> {
+        self.runtime.read().unwrap()
+    }
+
+    fn get_balance(&self, params: &[Value]) -> RpcResult<Value> {
+        let pubkey = parse_pubkey(&param::<String>(params, 0, "pubkey")?)?;
+        let config: CommitmentConfig = config(params, 1)?;
+        let runtime = self.runtime();
+        let bank = bank(&runtime, config.commitment);
+        let lamports = bank.get_account(&pubkey).map_or(0, |account| account.lamports);
+        Ok(with_context(bank, json!(lamports)))
+    }
+
+    fn get_account_info(&self, params: &[Value]) -> RpcResult<Value> {
+        let pubkey = parse_pubkey(&param::<String>(params, 0, "pubkey")?)?;
+        let config: AccountInfoConfig = config(params, 1)?;
+        let runtime = self.runtime();
+        let bank = bank(&runtime, config.commitment);
+        let value = encode_bank_account(bank, &pubkey, &config)?;
+        Ok(with_context(bank, value))
+    }
+
+    fn get_multiple_accounts(&self, params: &[Value]) -> RpcResult<Value> {
+        let pubkeys: Vec<String> = param(params, 0, "pubkeys")?;
+        if pubkeys.len() > MAX_MULTIPLE_ACCOUNTS {
+            return Err(RpcError::invalid_params(format!(
+                "Too many inputs provided; max {}",
+                MAX_MULTIPLE_ACCOUNTS
+            )));
+        }
+        let config: AccountInfoConfig = config(params, 1)?;
+        let runtime = self.runtime();
+        let bank = bank(&runtime, config.commitment);
+        let accounts = pubkeys
+            .iter()
+            .map(|pubkey| encode_bank_account(bank, &parse_pubkey(pubkey)?, &config))
+            .collect::<RpcResult<Vec<_>>>()?;
+        Ok(with_context(bank, json!(accounts)))
+    }
+
+    fn get_latest_blockhash(&self, params: &[Value]) -> RpcResult<Value> {
+        let config: CommitmentConfig = config(params, 0)?;
+        let runtime = self.runtime();
+        let bank = bank(&runtime, config.commitment);
+        Ok(with_context(bank, blockhash_json(bank, bank.blockhash())))
+    }
+
+    fn get_slot(&self, params: &[Value]) -> RpcResult<Value> {
+        let config: CommitmentConfig = config(params, 0)?;
+        Ok(json!(bank(&self.runtime(), config.commitment).slot()))
+    }
+
+    fn get_minimum_balance_for_rent_exemption(&self, params: &[Value]) -> RpcResult<Value> {
+        let data_len: usize = param(params, 0, "data length")?;
+        Ok(json!(self.runtime().rent().minimum_balance(data_len)))
+    }
+
+    fn send_transaction(&self, params: &[Value]) -> RpcResult<Value> {
+        let config: SendTransactionConfig = config(params, 1)?;
+        let encoded: String = param(params, 0, "transaction")?;
+        let wire = decode_transaction(&encoded, config.encoding.unwrap_or(TransactionEncoding::Base58))?;
+        if !wire.verify_signatures() {
+            return Err(RpcError::new(
+                TRANSACTION_SIGNATURE_VERIFICATION_FAILURE,
+                "Transaction signature verification failure",
+            ));
+        }
+        let txn = wire.to_transaction().map_err(RpcError::invalid_params)?;
+        self.submit_transaction(txn, !config.skip_preflight)
+    }
+
+    /// Queue or execute a signed transaction, simulating it first with `preflight`, and return
+    /// its signature
+    fn submit_transaction(&self, txn: Transaction, preflight: bool) -> RpcResult<Value> {
+        let signature = txn
+            .signatures
+            .first()
+            .map(|signature| bs58::encode(signature).into_string())
+            .ok_or_else(|| RpcError::invalid_params("transaction has no signatures"))?;
+
+        {
+            let runtime = self.runtime();
+            if !runtime.is_blockhash_valid(&txn.recent_blockhash) {
+                return Err(RpcError::transaction_failure(&TransactionError::BlockhashNotFound, &[], 0));
+            }
+            if preflight {
+                let simulation = runtime.simulate_transaction(&txn, &SimulationConfig::default())?;
+                if let Some(err) = &simulation.result.error {
+                    let result = &simulation.result;
+                    return Err(RpcError::transaction_failure(err, &result.logs, result.compute_units_consumed));
+                }
+            }
+        }
+
+        match &self.sender {
+            Some(sender) => sender.try_send(txn).map_err(|e| match e {
+                TrySendError::Full(_) => RpcError::internal("transaction queue is full"),
+                TrySendError::Closed(_) => RpcError::internal("block production has stopped"),
+            })?,
+            None => {
+                self.runtime.write().unwrap().execute_transaction(&txn)?;
+            }
+        }
+        Ok(json!(signature))
+    }
+
+    fn simulate_transaction(&self, params: &[Value]) -> RpcResult<Value> {
+        let config: SimulateTransactionConfig = config(params, 1)?;
+        if config.sig_verify && config.replace_recent_blockhash {
+            return Err(RpcError::invalid_params("sigVerify may not be used with replaceRecentBlockhash"));
+        }
+        let encoded: String = param(params, 0, "transaction")?;
+        let wire = decode_transaction(&encoded, config.encoding.unwrap_or(TransactionEncoding::Base58))?;
+        // Signatures cover the wire message, so they are checked before conversion
+        if config.sig_verify && !wire.verify_signatures() {
+            return Err(RpcError::new(
+                TRANSACTION_SIGNATURE_VERIFICATION_FAILURE,
+                "Transaction signature verification failure",
+            ));
+        }
+        let txn = wire.to_transaction().map_err(RpcError::invalid_params)?;
+        let (addresses, account_encoding) = match &config.accounts {
+            Some(accounts) => (
+                Some(accounts.addresses.iter().map(|address| parse_pubkey(address)).collect::<RpcResult<Vec<_>>>()?),
+                accounts.encoding.unwrap_or(UiAccountEncoding::Base64),
+            ),
+            None => (None, UiAccountEncoding::Base64),
+        };
+
+        let runtime = self.runtime();
+        let bank = runtime.bank();
+        if !config.replace_recent_blockhash && !runtime.is_blockhash_valid(&txn.recent_blockhash) {
+            let value = json!({
+                "err": TransactionError::BlockhashNotFound,
+                "logs": [],
+                "accounts": null,
+                "unitsConsumed": 0,
+                "returnData": null,
+            });
+            return Ok(with_context(bank, value));
+        }
+
+        let simulation = runtime
+            .simulate_transaction(
+                &txn,
+                &SimulationConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: config.replace_recent_blockhash,
+                    accounts: addresses,
+                },
+            )
+            .map_err(RpcError::invalid_params)?;
+        let accounts = simulation
+            .accounts
+            .map(|accounts| {
+                accounts
+                    .iter()
+                    .map(|account| match account {
+                        Some(account) => encode_account(account, account_encoding, None),
+                        None => Ok(Value::Null),
+                    })
+                    .collect::<RpcResult<Vec<_>>>()
+            })
+            .transpose()?;
+
+        let result = &simulation.result;
+        let mut value = json!({
+            "err": result.error,
+            "logs": result.logs,
+            "accounts": accounts,
+            "unitsConsumed": result.compute_units_consumed,
+            "returnData": return_data_json(result),
+        });
+        if let Some(blockhash) = simulation.replacement_blockhash {
+            value["replacementBlockhash"] = blockhash_json(bank, blockhash);
+        }
+        Ok(with_context(bank, value))
+    }
+
+    fn get_signature_statuses(&self, params: &[Value]) -> RpcResult<Value> {
+        let signatures: Vec<String> = param(params, 0, "signatures")?;
+        if signatures.len() > MAX_SIGNATURE_STATUSES {
+            return Err(RpcError::invalid_params(format!(
+                "Too many inputs provided; max {}",
+                MAX_SIGNATURE_STATUSES
+            )));
+        }
+        let signatures = signatures.iter().map(|signature| parse_signature(signature)).collect::<RpcResult<Vec<_>>>()?;
+
+        let runtime = self.runtime();
+        let statuses: Vec<Value> = signatures
+            .iter()
+            .map(|signature| {
+                let Some(status) = runtime.get_signature_status(signature) else {
+                    return Value::Null;
+                };
+                let commitment = slot_commitment(&runtime, status.slot);
+                let confirmations = match commitment {
+                    CommitmentLevel::Finalized => Value::Null,
+                    _ => json!(runtime.slot().saturating_sub(status.slot)),
+                };
+                json!({
+                    "slot": status.slot,
+                    "confirmations": confirmations,
+                    "err": status.error,
+                    "status": status_json(&status.error),
+                    "confirmationStatus": commitment,
+                })
+            })
+            .collect();
+        Ok(with_context(runtime.bank(), json!(statuses)))
+    }
+
+    fn get_transaction(&self, params: &[Value]) -> RpcResult<Value> {
+        let signature = parse_signature(&param::<String>(params, 0, "signature")?)?;
+        let config: TransactionConfig = config(params, 1)?;
+        if config.commitment == CommitmentLevel::Processed {
+            return Err(RpcError::invalid_params("Method does not support commitment below `confirmed`"));
+        }
+
+        let runtime = self.runtime();
+        let Some(confirmed) = runtime
+            .get_transaction(&signature)
+            .filter(|confirmed| slot_commitment(&runtime, confirmed.slot) >= config.commitment)
+        else {
+            return Ok(Value::Null);
+        };
+        let encoding = config.encoding.unwrap_or(TransactionEncoding::Json);
+        encode_confirmed_transaction(confirmed, encoding, config.max_supported_transaction_version.is_some())
+    }
+
+    /// Transfer lamports from the genesis faucet
+    fn request_airdrop(&self, params: &[Value]) -> RpcResult<Value> {
+        let pubkey = parse_pubkey(&param::<String>(params, 0, "pubkey")?)?;
+        let lamports: u64 = param(params, 1, "lamports")?;
+        let faucet = genesis::faucet_keypair();
+        let faucet_pubkey = genesis::faucet_pubkey();
+
+        let txn = {
+            let runtime = self.runtime();
+            let balance = runtime.get_account(&faucet_pubkey).map_or(0, |account| account.lamports);
+            if balance < lamports {
+                return Err(RpcError::internal(format!(
+                    "airdrop request failed: the faucet has {} lamports",
+                    balance
+                )));
+            }
+            let mut wire = SolanaTransactionParser::create_transfer_transaction(
+                SolanaPubkey::new(faucet_pubkey.0),
+                SolanaPubkey::new(pubkey.0),
+                lamports,
+                SolanaHash(runtime.latest_blockhash()),
+            );
+            let message = SolanaTransactionParser::message_data(&wire.message).map_err(RpcError::internal)?;
+            wire.signatures = vec![SolanaSignature(faucet.sign(&message).to_bytes())];
+            wire.to_transaction().map_err(RpcError::internal)?
+        };
+        self.submit_transaction(txn, true)
+    }
+}
+
+async fn handle_http_request(State(service): State<JsonRpcService>, body: Bytes) -> Json<Value> {
+    let _permit = service.requests.clone().acquire_owned().await.expect("the request semaphore is never closed");
+    let request: Value = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(_) => return Json(error_response(Value::Null, RpcError::new(PARSE_ERROR, "Parse error"))),
+    };
+
+    // Handlers lock the runtime, which may block while a slot is being produced
+    let timeout = service.request_timeout;
+    let handler = tokio::task::spawn_blocking(move || service.handle_request(request));
+    match tokio::time::timeout(timeout, handler).await {
+        Ok(Ok(response)) => Json(response),
+        Ok(Err(e)) => Json(error_response(Value::Null, RpcError::internal(e))),
+        Err(_) => Json(error_response(Value::Null, RpcError::internal("request timed out"))),
+    }
+}
+
+pub(crate) fn error_response(id: Value, error: RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "error": error.to_json(), "id": id })
+}
+
+pub(crate) fn param<T: DeserializeOwned>(params: &[Value], index: usize, name: &str) -> RpcResult<T> {
+    let value = params
+        .get(index)
+        .ok_or_else(|| RpcError::invalid_params(format!("missing {}", name)))?;
+    serde_json::from_value(value.clone()).map_err(|e| RpcError::invalid_params(format!("invalid {}: {}", name, e)))
+}
+
+/// The optional configuration object at `index`
+pub(crate) fn config<T: DeserializeOwned + Default>(params: &[Value], index: usize) -> RpcResult<T> {
+    match params.get(index) {
+        None | Some(Value::Null) => Ok(T::default()),
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| RpcError::invalid_params(format!("invalid config: {}", e))),
+    }
+}
+
+pub(crate) fn parse_pubkey(pubkey: &str) -> RpcResult<Pubkey> {
+    pubkey.parse().map_err(|_| RpcError::invalid_params(format!("invalid pubkey {}", pubkey)))
+}
+
+pub(crate) fn parse_signature(signature: &str) -> RpcResult<[u8; 64]> {
+    bs58::decode(signature)
+        .into_vec()
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| RpcError::invalid_params(format!("invalid signature {}", signature)))
+}
+
+fn decode_transaction(encoded: &str, encoding: TransactionEncoding) -> RpcResult<SolanaTransaction> {
+    let bytes = match encoding {
+        TransactionEncoding::Base58 => bs58::decode(encoded).into_vec().map_err(RpcError::invalid_params)?,
+        TransactionEncoding::Base64 => base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(RpcError::invalid_params)?,
+        TransactionEncoding::Json => return Err(RpcError::invalid_params("unsupported encoding: json")),
+    };
+    if bytes.len() > PACKET_DATA_SIZE {
+        return Err(RpcError::invalid_params(format!(
+            "transaction too large: {} bytes (max {})",
+            bytes.len(),
+            PACKET_DATA_SIZE
+        )));
+    }
+    SolanaTransactionParser::parse_transaction(&bytes)
+        .map_err(|e| RpcError::invalid_params(format!("failed to deserialize transaction: {}", e)))
+}
+
+/// The bank a read at `commitment` sees. Without any frozen bank every level reads the working
+/// bank.
+pub(crate) fn bank(runtime: &TerminatorRuntime, commitment: CommitmentLevel) -> &Bank {
+    let working_bank = runtime.bank();
+    match commitment {
+        CommitmentLevel::Processed => working_bank,
+        CommitmentLevel::Confirmed if working_bank.is_frozen() => working_bank,
+        CommitmentLevel::Confirmed => working_bank.parent().map_or(working_bank, |parent| parent),
+        CommitmentLevel::Finalized => runtime.bank_forks().root_bank().map_or(working_bank, |root| root),
+    }
+}
+
+/// Highest commitment level reached by the transactions of `slot`
+pub(crate) fn slot_commitment(runtime: &TerminatorRuntime, slot: u64) -> CommitmentLevel {
+    let bank_forks = runtime.bank_forks();
+    if bank_forks.root_bank().is_some() && slot <= bank_forks.root() {
+        CommitmentLevel::Finalized
+    } else if bank_forks.get(slot).is_some() {
+        CommitmentLevel::Confirmed
+    } else {
+        CommitmentLevel::Processed
+    }
+}
+
+fn with_context(bank: &Bank, value: Value) -> Value {
+    json!({ "context": { "slot": bank.slot() }, "value": value })
+}
+
+/// A blockhash with the last block height transactions referencing it are accepted at
+fn blockhash_json(bank: &Bank, blockhash: [u8; 32]) -> Value {
+    json!({
+        "blockhash": bs58::encode(blockhash).into_string(),
+        "lastValidBlockHeight": bank.slot() + bank.blockhash_queue().max_age() as u64,
+    })
+}
+
+fn encode_bank_account(bank: &Bank, pubkey: &Pubkey, config: &AccountInfoConfig) -> RpcResult<Value> {
+    match bank.get_account(pubkey).filter(|account| account.lamports > 0) {
+        Some(account) => encode_account(&account, config.encoding.unwrap_or(UiAccountEncoding::Binary), config.data_slice),
+        None => Ok(Value::Null),
+    }
+}
+
+fn encode_account(account: &Account, encoding: UiAccountEncoding, data_slice: Option<DataSlice>) -> RpcResult<Value> {
+    // No account data parsers, so jsonParsed falls back to base64 like it does for unknown programs
+    let encoding = match encoding {
+        UiAccountEncoding::JsonParsed => UiAccountEncoding::Base64,
+        encoding => encoding,
+    };
+    let data = match data_slice {
+        Some(slice) => {
+            let start = slice.offset.min(account.data.len());
+            let end = slice.offset.saturating_add(slice.length).min(account.data.len());
+            &account.data[start..end]
+        }
+        None => &account.data[..],
+    };
+    if matches!(encoding, UiAccountEncoding::Binary | UiAccountEncoding::Base58) && data.len() > MAX_BASE58_BYTES {
+        return Err(RpcError::invalid_params(format!(
+            "Encoded binary (base 58) data should be less than {} bytes, please use Base64 encoding.",
+            MAX_BASE58_BYTES
+        )));
+    }
+
+    let mut ui_account = UiAccount::encode(&Account { data: data.to_vec(), ..account.clone() }, encoding)
+        .map_err(RpcError::internal)?;
+    ui_account.space = Some(account.data.len() as u64);
+    serde_json::to_value(ui_account).map_err(RpcError::internal)
+}
+
+fn status_json(error: &Option<TransactionError>) -> Value {
+    match error {
+        Some(err) => json!({ "Err": err }),
+        None => json!({ "Ok": null }),
+    }
+}
+
+fn return_data_json(result: &TransactionResult) -> Value {
+    match &result.return_data {
+        Some(return_data) => json!({
+            "programId": return_data.program_id.to_string(),
+            "data": [base64::engine::general_purpose::STANDARD.encode(&return_data.data), "base64"],
+        }),
+        None => Value::Null,
+    }
+}
+
+fn instruction_json(instruction: &CompiledInstruction, stack_height: Option<u32>) -> Value {
+    json!({
+        "programIdIndex": instruction.program_id_index,
+        "accounts": instruction.accounts,
+        "data": bs58::encode(&instruction.data).into_string(),
+        "stackHeight": stack_height,
+    })
+}
+
+/// A processed transaction as `getTransaction` returns it
+fn encode_confirmed_transaction(
+    confirmed: &ConfirmedTransaction,
+    encoding: TransactionEncoding,
+    versioned: bool,
+) -> RpcResult<Value> {
+    let wire = SolanaTransaction::from_transaction(&confirmed.transaction);
+    let message = &wire.message;
+
+    // Balances are recorded in the runtime's account order, the message puts signers first
+    let runtime_keys = confirmed.transaction.account_keys();
+    let message_index = |runtime_index: u8| {
+        runtime_keys
+            .get(runtime_index as usize)
+            .and_then(|key| message.account_keys.iter().position(|message_key| message_key.0 == key.0))
+            .map_or(runtime_index, |index| index as u8)
+    };
+    let balances = |balances: &[u64]| -> Vec<u64> {
+        message
+            .account_keys
+            .iter()
+            .map(|key| {
+                runtime_keys
+                    .iter()
+                    .position(|runtime_key| runtime_key.0 == key.0)
+                    .and_then(|index| balances.get(index).copied())
+                    .unwrap_or(0)
+            })
+            .collect()
+    };
+    let token_balances = |balances: &[crate::transaction_status::TransactionTokenBalance]| {
+        let balances: Vec<_> = balances
+            .iter()
+            .cloned()
+            .map(|mut balance| {
+                balance.account_index = message_index(balance.account_index);
+                balance
+            })
+            .collect();
+        json!(balances)
+    };
+
+    let result = &confirmed.result;
+    let inner_instructions: Vec<Value> = result
+        .inner_instructions
+        .iter()
+        .map(|inner| {
+            let instructions: Vec<Value> = inner
+                .instructions
+                .iter()
+                .map(|inner_instruction| {
+                    let instruction = CompiledInstruction {
+                        program_id_index: message_index(inner_instruction.instruction.program_id_index),
+                        accounts: inner_instruction.instruction.accounts.iter().map(|&index| message_index(index)).collect(),
+                        data: inner_instruction.instruction.data.clone(),
+                    };
+                    instruction_json(&instruction, inner_instruction.stack_height)
+                })
+                .collect();
+            json!({ "index": inner.index, "instructions": instructions })
+        })
+        .collect();
+
+    let meta = json!({
+        "err": result.error,
+        "status": status_json(&result.error),
+        "fee": result.fee,
+        "preBalances": balances(&result.pre_balances),
+        "postBalances": balances(&result.post_balances),
+        "innerInstructions": inner_instructions,
+        "logMessages": result.logs,
+        "preTokenBalances": token_balances(&result.pre_token_balances),
+        "postTokenBalances": token_balances(&result.post_token_balances),
+        "rewards": [],
+        "loadedAddresses": {
+            "writable": result.loaded_addresses.writable.iter().map(Pubkey::to_string).collect::<Vec<_>>(),
+            "readonly": result.loaded_addresses.readonly.iter().map(Pubkey::to_string).collect::<Vec<_>>(),
+        },
+        "returnData": return_data_json(result),
+        "computeUnitsConsumed": result.compute_units_consumed,
+    });
+
+    let transaction = match encoding {
+        TransactionEncoding::Json => json!({
+            "signatures": wire.signatures.iter().map(|signature| bs58::encode(signature.0).into_string()).collect::<Vec<_>>(),
+            "message": {
+                "header": {
+                    "numRequiredSignatures": message.header.num_required_signatures,
+                    "numReadonlySignedAccounts": message.header.num_readonly_signed_accounts,
+                    "numReadonlyUnsignedAccounts": message.header.num_readonly_unsigned_accounts,
+                },
+                "accountKeys": message.account_keys.iter().map(|key| bs58::encode(key.0).into_string()).collect::<Vec<_>>(),
+                "recentBlockhash": bs58::encode(message.recent_blockhash.0).into_string(),
+                "instructions": message.instructions.iter().map(|instruction| instruction_json(instruction, None)).collect::<Vec<_>>(),
+            },
+        }),
+        TransactionEncoding::Base64 => {
+            let bytes = SolanaTransactionParser::serialize_transaction(&wire).map_err(RpcError::internal)?;
+            json!([base64::engine::general_purpose::STANDARD.encode(bytes), "base64"])
+        }
+        TransactionEncoding::Base58 => {
+            let bytes = SolanaTransactionParser::serialize_transaction(&wire).map_err(RpcError::internal)?;
+            json!([bs58::encode(bytes).into_string(), "base58"])
+        }
+    };
+
+    let mut value = json!({
+        "slot": confirmed.slot,
+        "blockTime": null,
+        "meta": meta,
+        "transaction": transaction,
+    });
+    if versioned {
+        value["version"] = json!("legacy");
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+    use std::sync::RwLock;
+
+    async fn service() -> JsonRpcService {
+        let runtime = TerminatorRuntime::new("nonexistent_config.toml").await.unwrap();
+        JsonRpcService::new(Arc::new(RwLock::new(runtime)), &RuntimeConfig::default().networking)
+    }
+
+    fn call(service: &JsonRpcService, method: &str, params: Value) -> Value {
+        service.handle_request(json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }))
+    }
+
+    fn transfer(from: &SigningKey, to: Pubkey, lamports: u64, blockhash: [u8; 32]) -> String {
+        let wire = SolanaTransactionParser::signed_transfer_for_test(from, to, lamports, blockhash);
+        base64::engine::general_purpose::STANDARD.encode(wire)
+    }
+
+    #[tokio::test]
+    async fn test_send_and_confirm_transfer() {
+        let service = service().await;
+        let payer = SigningKey::from_bytes(&[7u8; 32]);
+        let payer_pubkey = Pubkey::new(payer.verifying_key().to_bytes());
+        let recipient = Pubkey::new([2u8; 32]);
+
+        let airdrop = call(&service, "requestAirdrop", json!([payer_pubkey.to_string(), 1_000_000_000]));
+        assert!(airdrop["result"].is_string(), "{}", airdrop);
+        let balance = call(&service, "getBalance", json!([payer_pubkey.to_string()]));
+        assert_eq!(balance["result"]["value"], 1_000_000_000);
+
+        let latest = call(&service, "getLatestBlockhash", json!([]));
+        let blockhash_str = latest["result"]["value"]["blockhash"].as_str().unwrap();
+        let blockhash: [u8; 32] = bs58::decode(blockhash_str).into_vec().unwrap().try_into().unwrap();
+        let encoded = transfer(&payer, recipient, 1_000, blockhash);
+        let sent = call(&service, "sendTransaction", json!([encoded, { "encoding": "base64" }]));
+        let signature = sent["result"].as_str().unwrap().to_string();
+
+        let statuses = call(&service, "getSignatureStatuses", json!([[signature]]));
+        assert_eq!(statuses["result"]["value"][0]["confirmationStatus"], "processed");
+        assert_eq!(statuses["result"]["value"][0]["status"], json!({ "Ok": null }));
+        assert_eq!(call(&service, "getTransaction", json!([signature]))["result"], Value::Null);
+
+        service.runtime.write().unwrap().advance_slot();
+        service.runtime.write().unwrap().set_root(0).unwrap();
+        let statuses = call(&service, "getSignatureStatuses", json!([[signature]]));
+        assert_eq!(statuses["result"]["value"][0]["confirmationStatus"], "finalized");
+        assert_eq!(statuses["result"]["value"][0]["confirmations"], Value::Null);
+
+        let transaction = &call(&service, "getTransaction", json!([signature]))["result"];
+        assert_eq!(transaction["slot"], 0);
+        assert_eq!(transaction["transaction"]["signatures"][0], signature);
+        assert_eq!(transaction["transaction"]["message"]["accountKeys"][0], payer_pubkey.to_string());
+        assert_eq!(transaction["transaction"]["message"]["recentBlockhash"], blockhash_str);
+        assert_eq!(transaction["meta"]["preBalances"][0], 1_000_000_000);
+        assert_eq!(transaction["meta"]["postBalances"][1], 1_000);
+        let base64 = call(&service, "getTransaction", json!([signature, { "encoding": "base64" }]));
+        assert_eq!(base64["result"]["transaction"][0], encoded);
+
+        // A resubmission is caught once executed, after the preflight passes
+        let resent = call(&service, "sendTransaction", json!([encoded, { "encoding": "base64" }]));
+        assert_eq!(resent["error"]["code"], SEND_TRANSACTION_PREFLIGHT_FAILURE);
+        assert_eq!(resent["error"]["data"]["err"], "AlreadyProcessed");
+
+        let expired = transfer(&payer, recipient, 1_000, [9u8; 32]);
+        let rejected = call(&service, "sendTransaction", json!([expired, { "encoding": "base64" }]));
+        assert_eq!(rejected["error"]["data"]["err"], "BlockhashNotFound");
+
+        let forged = transfer(&SigningKey::from_bytes(&[8u8; 32]), recipient, 5, blockhash);
+        let mut wire = decode_transaction(&forged, TransactionEncoding::Base64).unwrap();
+        wire.signatures[0].0[0] ^= 1;
+        let forged = bs58::encode(SolanaTransactionParser::serialize_transaction(&wire).unwrap()).into_string();
+        let rejected = call(&service, "sendTransaction", json!([forged]));
+        assert_eq!(rejected["error"]["code"], TRANSACTION_SIGNATURE_VERIFICATION_FAILURE);
+    }
+
+    #[tokio::test]
+    async fn test_simulate_transaction() {
+        let service = service().await;
+        let payer = SigningKey::from_bytes(&[7u8; 32]);
+        let recipient = Pubkey::new([2u8; 32]);
+        let encoded = transfer(&payer, recipient, 1_000, [9u8; 32]);
+
+        let unknown_blockhash = call(&service, "simulateTransaction", json!([encoded, { "encoding": "base64" }]));
+        assert_eq!(unknown_blockhash["result"]["value"]["err"], "BlockhashNotFound");
+
+        let config = json!({
+            "encoding": "base64",
+            "replaceRecentBlockhash": true,
+            "accounts": { "addresses": [recipient.to_string()], "encoding": "base64" },
+        });
+        let simulated = &call(&service, "simulateTransaction", json!([encoded, config]))["result"]["value"];
+        assert_eq!(simulated["err"], Value::Null);
+        assert_eq!(simulated["accounts"][0]["lamports"], 1_000);
+        assert_eq!(simulated["unitsConsumed"], 1_150);
+        assert!(simulated["replacementBlockhash"]["blockhash"].is_string());
+        assert_eq!(call(&service, "getBalance", json!([recipient.to_string()]))["result"]["value"], 0);
+
+        let config = json!({ "encoding": "base64", "sigVerify": true, "replaceRecentBlockhash": true });
+        let rejected = call(&service, "simulateTransaction", json!([encoded, config]));
+        assert_eq!(rejected["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn test_account_queries() {
+        let service = service().await;
+        let pubkey = Pubkey::new([5u8; 32]);
+        let data: Vec<u8> = (0..=255).chain(0..44).collect();
+        service.runtime.write().unwrap().store_account(pubkey, Account::new(42, data, [6u8; 32]));
+
+        let missing = call(&service, "getAccountInfo", json!([Pubkey::new([1u8; 32]).to_string()]));
+        assert_eq!(missing["result"]["value"], Value::Null);
+
+        let config = json!({ "encoding": "base64", "dataSlice": { "offset": 1, "length": 2 } });
+        let account = &call(&service, "getAccountInfo", json!([pubkey.to_string(), config]))["result"]["value"];
+        assert_eq!(account["lamports"], 42);
+        assert_eq!(account["data"], json!(["AQI=", "base64"]));
+        assert_eq!(account["space"], 300);
+        assert_eq!(account["owner"], Pubkey::new([6u8; 32]).to_string());
+
+        // Large accounts can't be base58 encoded, which is the default
+        let too_large = call(&service, "getAccountInfo", json!([pubkey.to_string()]));
+        assert_eq!(too_large["error"]["code"], INVALID_PARAMS);
+
+        let config = json!({ "encoding": "jsonParsed", "dataSlice": { "offset": 0, "length": 1 } });
+        let accounts = call(&service, "getMultipleAccounts", json!([[pubkey.to_string(), Pubkey::new([1u8; 32]).to_string()], config]));
+        assert_eq!(accounts["result"]["value"][0]["data"], json!(["AA==", "base64"]));
+        assert_eq!(accounts["result"]["value"][1], Value::Null);
+
+        let rent = call(&service, "getMinimumBalanceForRentExemption", json!([0]));
+        assert_eq!(rent["result"], 890_880);
+        assert_eq!(call(&service, "getSlot", json!([{ "commitment": "processed" }]))["result"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_request_errors_and_batches() {
+        let service = service().await;
+        assert_eq!(call(&service, "getFoo", json!([]))["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(call(&service, "getBalance", json!([]))["error"]["code"], INVALID_PARAMS);
+        assert_eq!(call(&service, "getBalance", json!(["not a pubkey"]))["error"]["code"], INVALID_PARAMS);
+
+        let invalid = service.handle_request(json!({ "id": 3, "method": "getSlot" }));
+        assert_eq!(invalid["error"]["code"], INVALID_REQUEST);
+        assert_eq!(invalid["id"], 3);
+        assert_eq!(service.handle_request(json!([]))["error"]["code"], INVALID_REQUEST);
+
+        let batch = service.handle_request(json!([
+            { "jsonrpc": "2.0", "id": 1, "method": "getHealth" },
+            { "jsonrpc": "2.0", "id": 2, "method": "getGenesisHash" },
+        ]));
+        assert_eq!(batch[0]["result"], "ok");
+        assert_eq!(batch[1]["id"], 2);
+        let genesis_hash = service.runtime().genesis_hash();
+        assert_eq!(batch[1]["result"], bs58::encode(genesis_hash).into_string());
+    }
+}
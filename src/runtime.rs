@@ -1,11 +1,68 @@
 use crate::types::*;
+use crate::bpf_loader;
+use crate::elf_loader;
+use crate::sbpf::Executable;
+use crate::program_cache::{ProgramCache, ProgramCacheEntry, ProgramCacheStats};
+use crate::upgradeable_loader;
+use crate::account_dump;
+use crate::accounts_db::{self, AccountsBackgroundService, AccountsDb, AccountsDbConfig};
+use crate::bank::Bank;
+use crate::bank_forks::BankForks;
+use crate::genesis::GenesisConfig;
+use crate::snapshot::{Snapshot, SnapshotSysvars};
+use crate::builtins::{self, BuiltinProgram, BuiltinRegistry};
+use crate::invoke_context::{InstructionProcessor, InvokeContext};
+use crate::syscalls;
+use crate::executor::TransactionExecutor;
+use crate::sysvar::{Rent, SysvarCache, SYSVAR_OWNER};
+use crate::crypto::SolanaCrypto;
+use crate::status_cache::{StatusCache, TransactionStatus};
+use crate::transaction_context::TransactionContext;
+use crate::transaction_error::{InstructionError, TransactionError};
+use crate::transaction_status::{
+    collect_token_balances, ConfirmedTransaction, LoadedAddresses, TransactionHistory, TransactionTokenBalance,
+};
 use crate::{Result, TerminatorError};
 use std::fs;
+use std::path::Path;
 use tracing::{info, warn, debug};
-use std::sync::Once;
+use std::sync::{Arc, Once, RwLock};
+use tokio::sync::broadcast;
 
 static INIT: Once = Once::new();
 
+type InstructionResult = std::result::Result<(), InstructionError>;
+
+/// A runtime shared between the block producer and the services reading from it
+pub type SharedRuntime = Arc<RwLock<TerminatorRuntime>>;
+
+/// Events buffered for each subscriber before the oldest are dropped
+const RUNTIME_EVENT_CAPACITY: usize = 4096;
+
+/// A transaction committed to the working bank
+#[derive(Debug, Clone)]
+pub struct CommittedTransaction {
+    pub slot: u64,
+    pub signature: Option<[u8; 64]>,
+    pub error: Option<TransactionError>,
+    pub logs: Vec<String>,
+    pub account_keys: Vec<Pubkey>,
+    /// Accounts the transaction changed
+    pub written_accounts: Vec<Pubkey>,
+}
+
+/// Progress of the runtime, published to subscribers such as the pubsub server
+#[derive(Debug, Clone)]
+pub enum RuntimeEvent {
+    TransactionCommitted(Arc<CommittedTransaction>),
+    /// A new working bank started on top of the frozen `parent`
+    BankCreated { slot: u64, parent: u64 },
+    SlotFrozen { slot: u64 },
+    SlotRooted { slot: u64 },
+    /// Slots of discarded forks
+    SlotsDiscarded { slots: Vec<u64> },
+}
+
 fn init_logging() {
     INIT.call_once(|| {
         tracing_subscriber::fmt::init();
@@ -15,7 +72,19 @@ fn init_logging() {
 #[derive(Debug, Clone)]
 pub struct TerminatorRuntime {
     config: RuntimeConfig,
-    bank_state: BankState,
+    /// Bank of the slot being executed
+    bank: Bank,
+    /// Frozen banks of earlier slots
+    bank_forks: BankForks,
+    status_cache: StatusCache,
+    transaction_history: TransactionHistory,
+    sysvar_cache: SysvarCache,
+    program_cache: Arc<RwLock<ProgramCache>>,
+    builtins: BuiltinRegistry,
+    /// Flushes the accounts db in the background while the runtime is alive
+    _accounts_background_service: Option<Arc<AccountsBackgroundService>>,
+    genesis_hash: [u8; 32],
+    events: broadcast::Sender<RuntimeEvent>,
 }
 
 impl TerminatorRuntime {
@@ -23,7 +92,7 @@ impl TerminatorRuntime {
         // Initialize logging
         init_logging();
         
-        let config = if fs::metadata(config_path).is_ok() {
+        let mut config: RuntimeConfig = if fs::metadata(config_path).is_ok() {
             let config_str = fs::read_to_string(config_path)
                 .map_err(|e| TerminatorError::SerializationError(e.to_string()))?;
             toml::from_str(&config_str)
@@ -35,22 +104,77 @@ impl TerminatorRuntime {
         
         info!("Initializing Terminator Runtime with config: {:?}", config);
         
-        let mut bank_state = BankState::new();
-        // Initialize with some lamports from config
-        if config.bank.initial_lamports > 0 {
-                    let system_account = Pubkey::system_program();
-        bank_state.accounts.insert(
-            system_account,
-            Account::new(config.bank.initial_lamports, vec![], system_account.0),
-        );
+        let genesis = match &config.bank.genesis_path {
+            Some(path) => GenesisConfig::load(path)?,
+            None => GenesisConfig::with_faucet(config.bank.initial_lamports),
+        };
+        let genesis_hash = genesis.hash();
+        info!("Genesis hash: {}", bs58::encode(genesis_hash).into_string());
+        // Slots are as long as the cluster's genesis says
+        config.block_production.ticks_per_slot = genesis.ticks_per_slot;
+        if let Some(hashes_per_tick) = genesis.poh_config.hashes_per_tick {
+            config.block_production.hashes_per_tick = hashes_per_tick;
         }
+        let bank_state = genesis.bank_state();
+
+        // With an accounts db, pick up from the root it holds
+        let mut accounts_background_service = None;
+        let (bank, bank_forks) = match &config.bank.accounts_path {
+            Some(path) => {
+                let accounts_db = Arc::new(AccountsDb::open(path, AccountsDbConfig::from(&config.performance))?);
+                accounts_background_service = Some(Arc::new(AccountsBackgroundService::start(
+                    accounts_db.clone(),
+                    accounts_db::DEFAULT_FLUSH_INTERVAL,
+                )));
+                match Bank::from_accounts_db(accounts_db.clone())? {
+                    Some(root) => {
+                        let slot = root.slot();
+                        info!("Reopened root at slot {} from {}", slot, path);
+                        let bank_forks = BankForks::new(root);
+                        (bank_forks.new_bank_from_parent(slot, slot + 1)?, bank_forks)
+                    }
+                    None => (Bank::new_with_accounts_db(bank_state, accounts_db), BankForks::default()),
+                }
+            }
+            None => (Bank::new(bank_state), BankForks::default()),
+        };
         
         Ok(Self {
+            sysvar_cache: SysvarCache::new(bank.slot(), genesis.rent, genesis.epoch_schedule),
+            bank,
+            bank_forks,
+            status_cache: StatusCache::new(),
+            transaction_history: TransactionHistory::default(),
+            program_cache: Arc::new(RwLock::new(ProgramCache::new(
+                config.performance.cache_size_mb as usize * 1024 * 1024,
+            ))),
+            builtins: BuiltinRegistry::default(),
+            _accounts_background_service: accounts_background_service,
+            genesis_hash,
+            events: broadcast::channel(RUNTIME_EVENT_CAPACITY).0,
             config,
-            bank_state,
         })
     }
 
+    pub fn config(&self) -> &RuntimeConfig {
+        &self.config
+    }
+
+    /// Hash of the genesis config the runtime started from
+    pub fn genesis_hash(&self) -> [u8; 32] {
+        self.genesis_hash
+    }
+
+    /// Receive an event for every committed transaction and slot transition from now on
+    pub fn subscribe_events(&self) -> broadcast::Receiver<RuntimeEvent> {
+        self.events.subscribe()
+    }
+
+    fn publish(&self, event: RuntimeEvent) {
+        // Nobody may be listening
+        let _ = self.events.send(event);
+    }
+
     pub async fn start(&self) -> Result<()> {
         info!("Starting Terminator Runtime...");
         info!("Configuration loaded:");
@@ -64,9 +188,163 @@ impl TerminatorRuntime {
     pub fn execute_transaction(&mut self, txn: &Transaction) -> Result<TransactionResult> {
         info!("Executing transaction with {} instructions", txn.instructions.len());
         
-        let mut execution_context = ExecutionContext::new(self.config.runtime.compute_budget);
-        let mut logs = Vec::new();
-        
+        let message_hash = self.check_transaction(txn)?;
+        let (result, transaction_context) = self.process_transaction(txn)?;
+        self.commit_transaction(txn, message_hash, &result, transaction_context);
+
+        Ok(result)
+    }
+
+    /// Execute a batch of transactions, running those that don't contend for accounts in parallel.
+    ///
+    /// Results are in the order of `transactions` and the final state is the same as executing
+    /// them one after another.
+    pub fn execute_batch(&mut self, transactions: &[Transaction]) -> Vec<Result<TransactionResult>> {
+        let executor = TransactionExecutor::new(self.config.performance.max_concurrent_transactions as usize);
+        executor.execute_batch(self, transactions)
+    }
+
+    /// Sanitize a transaction and reject it if its blockhash is unknown or expired or it was
+    /// already processed against the same blockhash, returning its message hash
+    pub(crate) fn check_transaction(&self, txn: &Transaction) -> Result<[u8; 32]> {
+        if self.bank.is_frozen() {
+            return Err(TerminatorError::BankError(format!(
+                "bank {} is frozen", self.bank.slot()
+            )));
+        }
+        self.sanitize_transaction(txn)?;
+
+        // An expired blockhash has also aged out of the status cache, so it can't be checked for
+        // duplicates
+        self.check_blockhash(&txn.recent_blockhash)?;
+
+        // Only a status recorded on this fork makes the transaction a duplicate
+        let message_hash = txn.message_hash();
+        let status = self.status_cache.get_status(&txn.recent_blockhash, &message_hash);
+        if status.is_some_and(|status| self.bank.is_ancestor_or_self(status.slot)) {
+            return Err(TransactionError::AlreadyProcessed.into());
+        }
+        Ok(message_hash)
+    }
+
+    /// Record the outcome of a processed transaction
+    pub(crate) fn commit_transaction(
+        &mut self,
+        txn: &Transaction,
+        message_hash: [u8; 32],
+        result: &TransactionResult,
+        transaction_context: TransactionContext,
+    ) {
+        // Failed transactions only hand back their fee payer
+        let written_accounts = self.commit_accounts(transaction_context);
+        self.record_transaction(txn, message_hash, result, written_accounts);
+    }
+
+    /// Record a transaction whose accounts are already committed in the status cache and history,
+    /// and publish it to event listeners
+    pub(crate) fn record_transaction(
+        &mut self,
+        txn: &Transaction,
+        message_hash: [u8; 32],
+        result: &TransactionResult,
+        written_accounts: Vec<Pubkey>,
+    ) {
+        self.bank.add_signature_count(txn.signatures.len() as u64);
+
+        self.status_cache.insert(
+            txn.recent_blockhash,
+            message_hash,
+            txn.signatures.first().copied(),
+            TransactionStatus {
+                slot: self.bank.slot(),
+                error: result.error.clone(),
+            },
+        );
+        if let Some(signature) = txn.signatures.first() {
+            self.transaction_history.insert(
+                *signature,
+                ConfirmedTransaction {
+                    slot: self.bank.slot(),
+                    transaction: txn.clone(),
+                    result: result.clone(),
+                },
+            );
+        }
+
+        // Collecting the account keys isn't free, so the event is only built for listeners
+        if self.events.receiver_count() > 0 {
+            self.publish(RuntimeEvent::TransactionCommitted(Arc::new(CommittedTransaction {
+                slot: self.bank.slot(),
+                signature: txn.signatures.first().copied(),
+                error: result.error.clone(),
+                logs: result.logs.clone(),
+                account_keys: txn.account_keys(),
+                written_accounts,
+            })));
+        }
+    }
+
+    /// Execute a transaction against the current bank without committing any state
+    pub fn simulate_transaction(&self, txn: &Transaction, config: &SimulationConfig) -> Result<SimulationResult> {
+        info!("Simulating transaction with {} instructions", txn.instructions.len());
+
+        if config.sig_verify && config.replace_recent_blockhash {
+            return Err(TerminatorError::TransactionExecutionFailed(
+                "sig_verify may not be used with replace_recent_blockhash".to_string()
+            ));
+        }
+
+        self.sanitize_transaction(txn)?;
+
+        if config.sig_verify && !txn.verify_signatures() {
+            return Err(TransactionError::SignatureFailure.into());
+        }
+
+        let replaced;
+        let (txn, replacement_blockhash) = if config.replace_recent_blockhash {
+            replaced = Transaction {
+                recent_blockhash: self.bank.blockhash(),
+                ..txn.clone()
+            };
+            (&replaced, Some(self.bank.blockhash()))
+        } else {
+            // Only the duplicate check is skipped, a simulation isn't recorded
+            self.check_blockhash(&txn.recent_blockhash)?;
+            (txn, None)
+        };
+
+        let (result, transaction_context) = self.process_transaction(txn)?;
+
+        let accounts = config.accounts.as_ref().map(|pubkeys| {
+            pubkeys
+                .iter()
+                .map(|pubkey| {
+                    transaction_context
+                        .get(pubkey)
+                        .filter(|_| result.success)
+                        .cloned()
+                        .or_else(|| self.bank.get_account(pubkey))
+                        .filter(|account| account.lamports > 0)
+                })
+                .collect()
+        });
+
+        Ok(SimulationResult {
+            result,
+            accounts,
+            replacement_blockhash,
+        })
+    }
+
+    /// Reject a blockhash that is unknown or has aged out of the blockhash queue
+    fn check_blockhash(&self, blockhash: &[u8; 32]) -> Result<()> {
+        if !self.bank.blockhash_queue().is_hash_valid(blockhash) {
+            return Err(TransactionError::BlockhashNotFound.into());
+        }
+        Ok(())
+    }
+
+    fn sanitize_transaction(&self, txn: &Transaction) -> Result<()> {
         // Validate transaction size
         let tx_size = bincode::serialized_size(txn)
             .map_err(|e| TerminatorError::SerializationError(e.to_string()))?;
@@ -76,217 +354,500 @@ impl TerminatorRuntime {
                 format!("Transaction too large: {} > {}", tx_size, self.config.runtime.max_transaction_size)
             ));
         }
+        Ok(())
+    }
 
-        // Execute each instruction
-        for (i, instruction) in txn.instructions.iter().enumerate() {
-            if !execution_context.consume_compute_units(1000) {
-                return Err(TerminatorError::TransactionExecutionFailed(
-                    "Compute budget exceeded".to_string()
-                ));
-            }
-            
-            debug!("Processing instruction {}: {:?}", i, instruction.program_id);
-            self.process_instruction(instruction, &mut execution_context)?;
-            logs.push(format!("Instruction {} processed successfully", i));
+    /// Charge the fee and run every instruction against freshly loaded accounts, leaving the
+    /// bank untouched.
+    ///
+    /// The fee stays charged if an instruction fails, so a failed transaction hands back only
+    /// its fee payer. A payer that can't cover the fee rejects the transaction.
+    pub(crate) fn process_transaction(&self, txn: &Transaction) -> Result<(TransactionResult, TransactionContext)> {
+        let mut execution_context = ExecutionContext::new(self.config.runtime.compute_budget);
+        let mut logs = Vec::new();
+
+        let fee = self.bank.fee_calculator().lamports_per_signature
+            .checked_mul(txn.signatures.len() as u64)
+            .ok_or(TransactionError::InsufficientFundsForFee)?;
+        let mut transaction_context = self.load_accounts(txn);
+        builtins::fund_demo_accounts(txn, &mut transaction_context, &mut execution_context);
+        let pre_balances = transaction_context.balances();
+        let pre_token_balances = self.collect_token_balances(&transaction_context);
+
+        let payer = Pubkey::new(txn.payer);
+        let payer_index = transaction_context.find_index(&payer).expect("the payer is always loaded");
+        let payer_account = transaction_context.get_mut(&payer).expect("the payer is always loaded");
+        payer_account.lamports = payer_account.lamports
+            .checked_sub(fee)
+            .ok_or(TransactionError::InsufficientFundsForFee)?;
+        let charged_payer = payer_account.clone();
+
+        let execution_result = self.execute_instructions(txn, &mut transaction_context, &mut execution_context, &mut logs);
+
+        let (post_balances, post_token_balances) = if execution_result.is_ok() {
+            (transaction_context.balances(), self.collect_token_balances(&transaction_context))
+        } else {
+            let mut post_balances = pre_balances.clone();
+            post_balances[payer_index] = charged_payer.lamports;
+            (post_balances, pre_token_balances.clone())
+        };
+
+        match &execution_result {
+            Ok(()) => info!("Transaction executed successfully, compute units remaining: {}", 
+                            execution_context.compute_units_remaining),
+            Err(e) => warn!("Transaction failed: {}", e),
         }
         
-        info!("Transaction executed successfully, compute units remaining: {}", 
-              execution_context.compute_units_remaining);
-        
-        Ok(TransactionResult {
-            success: true,
+        let result = TransactionResult {
+            success: execution_result.is_ok(),
             compute_units_consumed: self.config.runtime.compute_budget - execution_context.compute_units_remaining,
             logs: execution_context.log_messages,
-            error: None,
-        })
+            error: execution_result.err(),
+            fee,
+            pre_balances,
+            post_balances,
+            pre_token_balances,
+            post_token_balances,
+            inner_instructions: transaction_context.inner_instructions().to_vec(),
+            return_data: transaction_context.return_data().cloned(),
+            loaded_addresses: LoadedAddresses::default(),
+        };
+        if !result.success {
+            transaction_context = TransactionContext::new(vec![payer], vec![charged_payer]);
+        }
+        Ok((result, transaction_context))
     }
 
-    fn process_instruction(&mut self, instruction: &Instruction, context: &mut ExecutionContext) -> Result<()> {
-        // Route instruction based on program ID
-        match instruction.program_id {
-            p if p == Pubkey::system_program() => {
-                self.handle_system_instruction(instruction, context)
-            }
-            p if p == Pubkey::token_program() => {
-                self.handle_token_instruction(instruction, context)
-            }
-            _ => {
-                // Generic program handling
-                self.handle_generic_instruction(instruction, context)
-            }
+    /// Current state of an account in the bank
+    pub fn get_account(&self, pubkey: &Pubkey) -> Option<Account> {
+        self.bank.get_account(pubkey)
+    }
+
+    /// Store an account directly in the bank, e.g. to deploy a program for testing
+    pub fn store_account(&mut self, pubkey: Pubkey, account: Account) {
+        self.program_cache.write().unwrap().remove(&pubkey);
+        self.bank.store_account(pubkey, account);
+    }
+
+    /// Store every account dumped with `solana account --output json` in `dir`, returning how
+    /// many were loaded
+    pub fn load_account_dumps(&mut self, dir: impl AsRef<Path>) -> Result<usize> {
+        let accounts = account_dump::read_accounts_dir(dir)?;
+        let loaded = accounts.len();
+        for (pubkey, account) in accounts {
+            self.store_account(pubkey, account);
         }
+        info!("Loaded {} accounts from dumps", loaded);
+        Ok(loaded)
     }
 
-    fn handle_system_instruction(&mut self, instruction: &Instruction, context: &mut ExecutionContext) -> Result<()> {
-        context.log("Processing system program instruction".to_string());
-        
-        // Handle based on InstructionData
-        match &instruction.data {
-            InstructionData::Transfer { from, to, lamports } => {
-                self.handle_transfer_instruction(*from, *to, *lamports, context)
-            }
-            InstructionData::CreateAccount { from, to, lamports, space, owner } => {
-                self.handle_create_account_instruction(*from, *to, *lamports, *space, *owner, context)
-            }
-            InstructionData::Assign { account, owner } => {
-                self.handle_assign_instruction(*account, *owner, context)
-            }
-            InstructionData::Generic { data } => {
-                // Legacy handling for generic data
-                if data.is_empty() {
-                    return Ok(());
+    /// Dump the existing accounts among `pubkeys` into `dir` in the CLI's JSON format, returning
+    /// how many were written
+    pub fn export_account_dumps(&self, dir: impl AsRef<Path>, pubkeys: &[Pubkey]) -> Result<usize> {
+        let accounts: Vec<_> = pubkeys
+            .iter()
+            .filter_map(|pubkey| Some((*pubkey, self.get_account(pubkey)?)))
+            .collect();
+        account_dump::write_accounts_dir(dir, accounts.iter().map(|(pubkey, account)| (pubkey, account)))
+    }
+
+    /// Load and verify an ELF program, then store it as an executable account owned by the BPF loader
+    pub fn deploy_program(&mut self, program_id: Pubkey, elf: &[u8]) -> Result<()> {
+        elf_loader::load_program(elf, &syscalls::create_syscall_registry())?;
+        info!("Deploying program {} ({} bytes)", program_id, elf.len());
+        let lamports = self.sysvar_cache.rent.minimum_balance(elf.len());
+        self.store_account(program_id, Account::new_executable(lamports, elf.to_vec(), Pubkey::bpf_loader().0));
+        Ok(())
+    }
+
+    /// Executable of a deployed program, loaded and verified on a cache miss.
+    ///
+    /// Upgradeable programs are found through their program data account and only become
+    /// visible in the slot after they were deployed or upgraded.
+    fn load_program(&self, program_id: &Pubkey, accounts: &TransactionContext) -> Result<Arc<Executable>> {
+        let program = accounts.get(program_id)
+            .ok_or_else(|| TerminatorError::AccountNotFound(program_id.to_string()))?;
+        let stored_programdata;
+        let (elf, deployment_slot, effective_slot) = if program.owner == Pubkey::bpf_loader_upgradeable().0 {
+            let programdata_address = upgradeable_loader::programdata_address_of(program)
+                .map_err(|e| TerminatorError::ProgramError(e.to_string()))?;
+            let programdata = match accounts.get(&programdata_address) {
+                Some(programdata) => programdata,
+                None => {
+                    stored_programdata = self.bank.get_account(&programdata_address)
+                        .ok_or_else(|| TerminatorError::AccountNotFound(programdata_address.to_string()))?;
+                    &stored_programdata
                 }
-                
-                match data[0] {
-                    0 => self.handle_create_account(instruction, context),
-                    1 => self.handle_assign(instruction, context),
-                    2 => self.handle_transfer(instruction, context),
-                    _ => {
-                        context.log(format!("Unknown system instruction: {}", data[0]));
-                        Ok(())
-                    }
+            };
+            let (elf, slot) = upgradeable_loader::programdata_elf(programdata)
+                .map_err(|e| TerminatorError::ProgramError(e.to_string()))?;
+            (elf, slot, slot + 1)
+        } else {
+            // Programs of the other loaders can't change once deployed
+            (program.data.as_slice(), 0, 0)
+        };
+
+        if self.bank.slot() < effective_slot {
+            return Err(TerminatorError::ProgramError(format!(
+                "Program {} is not visible until slot {}", program_id, effective_slot
+            )));
+        }
+
+        let cached = self.program_cache.read().unwrap().get(program_id, deployment_slot);
+        if let Some(entry) = cached {
+            return Ok(entry.executable.clone());
+        }
+        debug!("Program cache miss for {} deployed in slot {}", program_id, deployment_slot);
+        let executable = elf_loader::load_program(elf, &syscalls::create_syscall_registry())?;
+        let entry = ProgramCacheEntry::new(executable, deployment_slot, effective_slot);
+        Ok(self.program_cache.write().unwrap().insert(*program_id, entry).executable.clone())
+    }
+
+    /// Hit, miss and eviction counts of the program cache
+    pub fn program_cache_stats(&self) -> ProgramCacheStats {
+        self.program_cache.read().unwrap().stats()
+    }
+
+    /// Register a native program, replacing any builtin already registered under `program_id`.
+    ///
+    /// `compute_units` are charged every time the program is invoked.
+    pub fn register_builtin(
+        &mut self,
+        program_id: Pubkey,
+        name: &str,
+        compute_units: u64,
+        program: impl BuiltinProgram + 'static,
+    ) {
+        info!("Registering builtin {} ({})", name, program_id);
+        self.builtins.register(program_id, name, compute_units, program);
+    }
+
+    fn load_accounts(&self, txn: &Transaction) -> TransactionContext {
+        let account_keys = txn.account_keys();
+        let accounts = account_keys
+            .iter()
+            .map(|key| {
+                self.sysvar_cache.account(key)
+                    .or_else(|| self.bank.get_account(key))
+                    .unwrap_or_else(|| Account::new(0, vec![], Pubkey::system_program().0))
+            })
+            .collect();
+        TransactionContext::new(account_keys, accounts).with_signers(txn.signers())
+    }
+
+    fn collect_token_balances(&self, transaction_context: &TransactionContext) -> Vec<TransactionTokenBalance> {
+        collect_token_balances(
+            transaction_context.account_keys(),
+            transaction_context.accounts(),
+            |key| self.bank.get_account(key),
+        )
+    }
+
+    /// Store the accounts a transaction changed, returning their keys
+    pub(crate) fn commit_accounts(&mut self, transaction_context: TransactionContext) -> Vec<Pubkey> {
+        let mut written = Vec::new();
+        for (pubkey, account) in transaction_context.into_accounts() {
+            // Sysvars are regenerated every slot rather than stored
+            if account.owner == SYSVAR_OWNER {
+                continue;
+            }
+            // Only changes are written to the bank, accounts left without lamports no longer exist
+            let current = self.bank.get_account(&pubkey);
+            if account.lamports == 0 {
+                if current.is_some() {
+                    self.bank.remove_account(&pubkey);
+                    written.push(pubkey);
                 }
+            } else if current.as_ref() != Some(&account) {
+                self.bank.store_account(pubkey, account);
+                written.push(pubkey);
             }
         }
+        written
     }
 
-    fn handle_transfer_instruction(&mut self, from: [u8; 32], to: [u8; 32], lamports: u64, context: &mut ExecutionContext) -> Result<()> {
-        let from_key = Pubkey::new(from);
-        let to_key = Pubkey::new(to);
-        
-        context.log(format!("Transferring {} lamports from {:?} to {:?}", lamports, from_key, to_key));
-        
-        // Get or create from account - for demo purposes, create with sufficient balance if it doesn't exist
-        if !self.bank_state.accounts.contains_key(&from_key) {
-            context.log(format!("Creating from account with initial balance for demo"));
-            let initial_balance = std::cmp::max(lamports * 2, 10_000_000); // Ensure sufficient balance
-            self.bank_state.accounts.insert(from_key, Account::new(initial_balance, vec![], Pubkey::system_program().0));
-        }
-        
-        let from_account = self.bank_state.accounts.get_mut(&from_key)
-            .ok_or_else(|| TerminatorError::AccountNotFound(format!("{:?}", from_key)))?;
-        
-        if from_account.lamports < lamports {
-            return Err(TerminatorError::InsufficientFunds);
+    fn execute_instructions(
+        &self,
+        txn: &Transaction,
+        accounts: &mut TransactionContext,
+        execution_context: &mut ExecutionContext,
+        logs: &mut Vec<String>,
+    ) -> std::result::Result<(), TransactionError> {
+        for (i, instruction) in txn.instructions.iter().enumerate() {
+            let instruction_result = if execution_context.consume_compute_units(1000) {
+                debug!("Processing instruction {}: {:?}", i, instruction.program_id);
+                accounts.push_instruction(instruction).and_then(|()| {
+                    let result = self
+                        .process_instruction(instruction, accounts, execution_context)
+                        .and_then(|()| accounts.verify_instruction());
+                    accounts.pop_instruction();
+                    result
+                })
+            } else {
+                Err(InstructionError::ComputationalBudgetExceeded)
+            };
+
+            instruction_result.map_err(|e| TransactionError::InstructionError(i as u8, e))?;
+            logs.push(format!("Instruction {} processed successfully", i));
         }
-        
-        from_account.lamports -= lamports;
-        
-        let to_account = self.bank_state.accounts.entry(to_key)
-            .or_insert_with(|| Account::new(0, vec![], Pubkey::system_program().0));
-        to_account.lamports += lamports;
-        
         Ok(())
     }
 
-    fn handle_create_account_instruction(&mut self, _from: [u8; 32], to: [u8; 32], lamports: u64, space: u64, owner: [u8; 32], context: &mut ExecutionContext) -> Result<()> {
-        let to_key = Pubkey::new(to);
-        
-        context.log(format!("Creating account {:?} with {} lamports and {} bytes", to_key, lamports, space));
-        
-        // Create new account
-        let new_account = Account::new(lamports, vec![0u8; space as usize], owner);
-        self.bank_state.accounts.insert(to_key, new_account);
-        
-        Ok(())
+    /// Status of a previously executed transaction, looked up by its first signature
+    pub fn get_signature_status(&self, signature: &[u8; 64]) -> Option<&TransactionStatus> {
+        self.status_cache.get_signature_status(signature)
     }
 
-    fn handle_assign_instruction(&mut self, account: [u8; 32], owner: [u8; 32], context: &mut ExecutionContext) -> Result<()> {
-        let account_key = Pubkey::new(account);
-        
-        context.log(format!("Assigning account {:?} to owner {:?}", account_key, owner));
-        
-        if let Some(acc) = self.bank_state.accounts.get_mut(&account_key) {
-            acc.owner = owner;
+    /// A recently executed transaction with its outcome, looked up by its first signature
+    pub fn get_transaction(&self, signature: &[u8; 64]) -> Option<&ConfirmedTransaction> {
+        self.transaction_history.get(signature)
+    }
+
+    /// Rent parameters of the cluster
+    pub fn rent(&self) -> &Rent {
+        &self.sysvar_cache.rent
+    }
+
+    pub fn slot(&self) -> u64 {
+        self.bank.slot()
+    }
+
+    /// Bank of the slot being executed
+    pub fn bank(&self) -> &Bank {
+        &self.bank
+    }
+
+    /// Frozen banks of earlier slots, across forks
+    pub fn bank_forks(&self) -> &BankForks {
+        &self.bank_forks
+    }
+
+    /// Blockhash of the last frozen slot, for new transactions to reference
+    pub fn latest_blockhash(&self) -> [u8; 32] {
+        self.bank.blockhash()
+    }
+
+    /// Whether transactions referencing `blockhash` are still accepted
+    pub fn is_blockhash_valid(&self, blockhash: &[u8; 32]) -> bool {
+        self.bank.blockhash_queue().is_hash_valid(blockhash)
+    }
+
+    /// Accept transactions referencing `blockhash` in the current slot
+    #[cfg(test)]
+    pub(crate) fn register_recent_blockhash_for_test(&mut self, blockhash: [u8; 32]) {
+        self.bank.register_recent_blockhash_for_test(blockhash);
+    }
+
+    /// Bank hash of the last frozen slot
+    pub fn bank_hash(&self) -> [u8; 32] {
+        self.bank.bank_hash()
+    }
+
+    /// Checksum of the lattice hash over every account, to compare state against a reference run
+    pub fn accounts_hash(&self) -> [u8; 32] {
+        self.bank.accounts_lt_hash().checksum()
+    }
+
+    /// Freeze the current slot with `blockhash`, the last PoH hash of its block, and return its
+    /// bank hash. No more transactions execute in the slot.
+    pub fn freeze_slot(&mut self, blockhash: [u8; 32]) -> [u8; 32] {
+        let bank_hash = self.bank.freeze(blockhash);
+        if self.bank_forks.get(self.bank.slot()).is_none() {
+            self.bank_forks
+                .insert(self.bank.clone())
+                .expect("parent of the working bank is in the forks");
         }
-        
-        Ok(())
+        self.publish(RuntimeEvent::SlotFrozen { slot: self.bank.slot() });
+        bank_hash
     }
 
-    fn handle_create_account(&mut self, instruction: &Instruction, context: &mut ExecutionContext) -> Result<()> {
-        if instruction.accounts.len() < 2 {
-            return Err(TerminatorError::TransactionExecutionFailed(
-                "CreateAccount requires at least 2 accounts".to_string()
-            ));
+    /// Advance to the next slot, freezing the current one if it isn't yet
+    pub fn advance_slot(&mut self) -> u64 {
+        if !self.bank.is_frozen() {
+            self.freeze_slot(self.bank.blockhash());
         }
-        
-        let _from = &instruction.accounts[0];
-        let to = &instruction.accounts[1];
-        
-        context.log(format!("Creating account: {:?}", to.pubkey));
-        
-        // Create new account with minimal lamports
-        let new_account = Account::new(1_000_000, vec![], Pubkey::system_program().0);
-        self.bank_state.accounts.insert(to.pubkey, new_account);
-        
-        Ok(())
+        let slot = self.bank.slot();
+        self.new_bank_from_parent(slot, slot + 1)
+            .expect("the frozen working bank is in the forks")
     }
 
-    fn handle_assign(&mut self, _instruction: &Instruction, context: &mut ExecutionContext) -> Result<()> {
-        context.log("Handling assign instruction".to_string());
-        // Simplified assign implementation
-        Ok(())
+    /// Continue from the frozen bank at `parent_slot` with a new bank for `slot`, e.g. to switch
+    /// to another fork. Unfrozen changes of the current bank are discarded.
+    pub fn new_bank_from_parent(&mut self, parent_slot: u64, slot: u64) -> Result<u64> {
+        let bank = self.bank_forks.new_bank_from_parent(parent_slot, slot)?;
+        // Programs may have been deployed differently on another fork
+        if parent_slot != self.bank.slot() {
+            self.program_cache.write().unwrap().clear();
+        }
+        self.bank = bank;
+        self.publish(RuntimeEvent::BankCreated { slot, parent: parent_slot });
+        self.status_cache.purge(slot);
+        self.sysvar_cache = SysvarCache::new(
+            slot,
+            self.sysvar_cache.rent.clone(),
+            self.sysvar_cache.epoch_schedule.clone(),
+        );
+        Ok(slot)
     }
 
-    fn handle_transfer(&mut self, instruction: &Instruction, context: &mut ExecutionContext) -> Result<()> {
-        if instruction.accounts.len() < 2 {
-            return Err(TerminatorError::TransactionExecutionFailed(
-                "Transfer requires at least 2 accounts".to_string()
-            ));
+    /// Root the frozen bank at `slot`, discarding every fork that doesn't descend from it.
+    /// Returns the discarded slots.
+    pub fn set_root(&mut self, slot: u64) -> Result<Vec<u64>> {
+        if !self.bank.is_ancestor_or_self(slot) {
+            return Err(TerminatorError::BankError(format!(
+                "slot {} is not an ancestor of bank {}", slot, self.bank.slot()
+            )));
         }
-        
-        let from_key = instruction.accounts[0].pubkey;
-        let to_key = instruction.accounts[1].pubkey;
-        
-        // Parse lamports from instruction data (simplified)
-        let lamports = if let InstructionData::Generic { data } = &instruction.data {
-            if data.len() >= 9 {
-                u64::from_le_bytes([
-                    data[1], data[2], data[3], data[4],
-                    data[5], data[6], data[7], data[8],
-                ])
-            } else {
-                1000000 // Default transfer amount
-            }
-        } else {
-            1000000
-        };
-        
-        context.log(format!("Transferring {} lamports from {:?} to {:?}", lamports, from_key, to_key));
-        
-        // Get or create accounts
-        let from_account = self.bank_state.accounts.get_mut(&from_key)
-            .ok_or_else(|| TerminatorError::AccountNotFound(format!("{:?}", from_key)))?;
-        
-        if from_account.lamports < lamports {
-            return Err(TerminatorError::InsufficientFunds);
+        let discarded = self.bank_forks.set_root(slot)?;
+        self.status_cache.remove_slots(&discarded);
+        self.transaction_history.remove_slots(&discarded);
+        if !discarded.is_empty() {
+            self.publish(RuntimeEvent::SlotsDiscarded { slots: discarded.clone() });
+        }
+        self.publish(RuntimeEvent::SlotRooted { slot });
+        Ok(discarded)
+    }
+
+    /// Discard the frozen bank at `slot` and every bank built on it
+    pub fn remove_fork(&mut self, slot: u64) -> Result<Vec<u64>> {
+        if self.bank.is_ancestor_or_self(slot) {
+            return Err(TerminatorError::BankError(format!(
+                "bank {} descends from slot {}", self.bank.slot(), slot
+            )));
+        }
+        let discarded = self.bank_forks.remove(slot)?;
+        self.status_cache.remove_slots(&discarded);
+        self.transaction_history.remove_slots(&discarded);
+        self.publish(RuntimeEvent::SlotsDiscarded { slots: discarded.clone() });
+        Ok(discarded)
+    }
+
+    /// Full snapshot of the current slot, freezing it first if it isn't yet
+    pub fn snapshot(&mut self) -> Snapshot {
+        if !self.bank.is_frozen() {
+            self.freeze_slot(self.bank.blockhash());
+        }
+        let mut accounts: Vec<_> = self.bank.accounts().into_iter().collect();
+        accounts.sort_unstable_by_key(|(pubkey, _)| pubkey.0);
+        Snapshot {
+            slot: self.bank.slot(),
+            blockhash: self.bank.blockhash(),
+            bank_hash: self.bank.bank_hash(),
+            fee_calculator: self.bank.fee_calculator().clone(),
+            blockhash_queue: self.bank.blockhash_queue().clone(),
+            sysvars: SnapshotSysvars::from(&self.sysvar_cache),
+            base: None,
+            accounts,
+            accounts_hash: self.accounts_hash(),
         }
-        
-        from_account.lamports -= lamports;
-        
-        let to_account = self.bank_state.accounts.entry(to_key)
-            .or_insert_with(|| Account::new(0, vec![], Pubkey::system_program().0));
-        to_account.lamports += lamports;
-        
-        Ok(())
     }
 
-    fn handle_token_instruction(&mut self, _instruction: &Instruction, context: &mut ExecutionContext) -> Result<()> {
-        context.log("Processing token program instruction".to_string());
-        // Simplified token instruction handling
+    /// Snapshot of the accounts changed in the current slot since the full snapshot `base`
+    pub fn incremental_snapshot(&mut self, base: &Snapshot) -> Result<Snapshot> {
+        self.snapshot().diff(base)
+    }
+
+    /// Continue after the slot of a full snapshot, discarding every bank and transaction status.
+    /// The restored accounts are held in memory.
+    pub fn restore_snapshot(&mut self, snapshot: &Snapshot) -> Result<()> {
+        snapshot.verify()?;
+        let root = Bank::new_frozen(snapshot.bank_state(), snapshot.blockhash_queue.clone());
+        let slot = root.slot();
+        self.bank_forks = BankForks::new(root);
+        self.bank = self.bank_forks.new_bank_from_parent(slot, slot + 1)?;
+        self.status_cache = StatusCache::new();
+        self.transaction_history = TransactionHistory::default();
+        self.program_cache.write().unwrap().clear();
+        self.sysvar_cache = SysvarCache::new(
+            slot + 1,
+            snapshot.sysvars.rent.clone(),
+            snapshot.sysvars.epoch_schedule.clone(),
+        );
+        self.sysvar_cache.last_restart_slot = snapshot.sysvars.last_restart_slot;
+        info!("Restored snapshot of slot {}", slot);
         Ok(())
     }
 
-    fn handle_generic_instruction(&mut self, _instruction: &Instruction, context: &mut ExecutionContext) -> Result<()> {
+    fn process_instruction(&self, instruction: &Instruction, accounts: &mut TransactionContext, context: &mut ExecutionContext) -> InstructionResult {
+        // Builtins are dispatched by program ID, everything else is a deployed or unknown program
+        let Some(builtin) = self.builtins.get(&instruction.program_id) else {
+            return self.handle_generic_instruction(instruction, accounts, context);
+        };
+        if !context.consume_compute_units(builtin.compute_units) {
+            return Err(InstructionError::ComputationalBudgetExceeded);
+        }
+        let mut invoke_context = InvokeContext::new(accounts, context, &self.sysvar_cache, self, instruction);
+        builtin.program.process_instruction(&mut invoke_context)
+    }
+
+    fn handle_generic_instruction(&self, instruction: &Instruction, accounts: &mut TransactionContext, context: &mut ExecutionContext) -> InstructionResult {
+        // Deployed programs run in the sBPF VM
+        let is_deployed_program = accounts
+            .get(&instruction.program_id)
+            .is_some_and(|program| program.executable && bpf_loader::is_bpf_loader(&Pubkey::new(program.owner)));
+        if is_deployed_program {
+            let executable = self
+                .load_program(&instruction.program_id, accounts)
+                .map_err(|e| debug!("Failed to load program {}: {}", instruction.program_id, e))
+                .ok();
+            return bpf_loader::execute_program(instruction, executable.as_deref(), accounts, context, &self.sysvar_cache, self);
+        }
+
         context.log("Processing generic program instruction".to_string());
         // Simplified generic instruction handling
         Ok(())
     }
 }
 
+/// Instructions invoked by programs are routed like top-level instructions
+impl InstructionProcessor for TerminatorRuntime {
+    fn process_instruction(&self, instruction: &Instruction, transaction_context: &mut TransactionContext, execution_context: &mut ExecutionContext) -> InstructionResult {
+        TerminatorRuntime::process_instruction(self, instruction, transaction_context, execution_context)
+    }
+
+    fn is_builtin(&self, program_id: &Pubkey) -> bool {
+        self.builtins.contains(program_id)
+    }
+}
+
 // Add bincode dependency for serialization
 impl Transaction {
     pub fn serialized_size(&self) -> usize {
         bincode::serialized_size(self).unwrap_or(0) as usize
     }
+
+    /// Bytes covered by the signatures
+    pub fn message_data(&self) -> Vec<u8> {
+        bincode::serialize(&(&self.instructions, &self.payer, &self.recent_blockhash))
+            .unwrap_or_default()
+    }
+
+    /// Hash of everything covered by the signatures, used to detect duplicate submissions
+    pub fn message_hash(&self) -> [u8; 32] {
+        SolanaCrypto::sha256_hash(&self.message_data())
+    }
+
+    /// Required signers in signature order: the payer followed by every other signing account
+    pub fn signers(&self) -> Vec<Pubkey> {
+        let mut signers = vec![Pubkey::new(self.payer)];
+        for meta in self.instructions.iter().flat_map(|ix| &ix.accounts) {
+            if meta.is_signer && !signers.contains(&meta.pubkey) {
+                signers.push(meta.pubkey);
+            }
+        }
+        signers
+    }
+
+    /// Check every required signer produced a valid ed25519 signature over the message
+    pub fn verify_signatures(&self) -> bool {
+        let signers = self.signers();
+        if self.signatures.len() != signers.len() {
+            return false;
+        }
+
+        let message = self.message_data();
+        self.signatures.iter().zip(&signers).all(|(signature, signer)| {
+            SolanaCrypto::verify_ed25519_signature(signature, &message, &signer.0).unwrap_or(false)
+        })
+    }
 }
@@ -0,0 +1,881 @@
+//! Interpreter for Solana's sBPF (v1) bytecode.
+
+use crate::transaction_error::InstructionError;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Virtual address of the read-only program region (text and rodata)
+pub const MM_PROGRAM_START: u64 = 0x1_0000_0000;
+/// Virtual address of the stack region
+pub const MM_STACK_START: u64 = 0x2_0000_0000;
+/// Virtual address of the heap region
+pub const MM_HEAP_START: u64 = 0x3_0000_0000;
+/// Virtual address of the serialized program input
+pub const MM_INPUT_START: u64 = 0x4_0000_0000;
+
+/// Size of one instruction slot in bytes
+pub const INSN_SIZE: usize = 8;
+/// Stack space available to each call frame
+pub const STACK_FRAME_SIZE: usize = 4096;
+/// Maximum nesting of internal function calls
+pub const MAX_CALL_DEPTH: usize = 64;
+/// Default size of the program heap
+pub const HEAP_SIZE: usize = 32 * 1024;
+
+/// Opcode constants for the sBPF instruction set
+pub mod ebpf {
+    // Instruction classes
+    pub const BPF_LD: u8 = 0x00;
+    pub const BPF_LDX: u8 = 0x01;
+    pub const BPF_ST: u8 = 0x02;
+    pub const BPF_STX: u8 = 0x03;
+    pub const BPF_ALU: u8 = 0x04;
+    pub const BPF_JMP: u8 = 0x05;
+    pub const BPF_ALU64: u8 = 0x07;
+    pub const BPF_CLS_MASK: u8 = 0x07;
+
+    // Operand source
+    pub const BPF_K: u8 = 0x00;
+    pub const BPF_X: u8 = 0x08;
+
+    // ALU operations
+    pub const BPF_ADD: u8 = 0x00;
+    pub const BPF_SUB: u8 = 0x10;
+    pub const BPF_MUL: u8 = 0x20;
+    pub const BPF_DIV: u8 = 0x30;
+    pub const BPF_OR: u8 = 0x40;
+    pub const BPF_AND: u8 = 0x50;
+    pub const BPF_LSH: u8 = 0x60;
+    pub const BPF_RSH: u8 = 0x70;
+    pub const BPF_NEG: u8 = 0x80;
+    pub const BPF_MOD: u8 = 0x90;
+    pub const BPF_XOR: u8 = 0xa0;
+    pub const BPF_MOV: u8 = 0xb0;
+    pub const BPF_ARSH: u8 = 0xc0;
+    pub const BPF_END: u8 = 0xd0;
+
+    // Jump operations
+    pub const BPF_JA: u8 = 0x00;
+    pub const BPF_JEQ: u8 = 0x10;
+    pub const BPF_JGT: u8 = 0x20;
+    pub const BPF_JGE: u8 = 0x30;
+    pub const BPF_JSET: u8 = 0x40;
+    pub const BPF_JNE: u8 = 0x50;
+    pub const BPF_JSGT: u8 = 0x60;
+    pub const BPF_JSGE: u8 = 0x70;
+    pub const BPF_CALL: u8 = 0x80;
+    pub const BPF_EXIT: u8 = 0x90;
+    pub const BPF_JLT: u8 = 0xa0;
+    pub const BPF_JLE: u8 = 0xb0;
+    pub const BPF_JSLT: u8 = 0xc0;
+    pub const BPF_JSLE: u8 = 0xd0;
+
+    // Memory access
+    pub const LD_DW_IMM: u8 = 0x18;
+    pub const LD_B_REG: u8 = 0x71;
+    pub const LD_H_REG: u8 = 0x69;
+    pub const LD_W_REG: u8 = 0x61;
+    pub const LD_DW_REG: u8 = 0x79;
+    pub const ST_B_IMM: u8 = 0x72;
+    pub const ST_H_IMM: u8 = 0x6a;
+    pub const ST_W_IMM: u8 = 0x62;
+    pub const ST_DW_IMM: u8 = 0x7a;
+    pub const ST_B_REG: u8 = 0x73;
+    pub const ST_H_REG: u8 = 0x6b;
+    pub const ST_W_REG: u8 = 0x63;
+    pub const ST_DW_REG: u8 = 0x7b;
+
+    // Byte swaps
+    pub const LE: u8 = BPF_ALU | BPF_K | BPF_END;
+    pub const BE: u8 = BPF_ALU | BPF_X | BPF_END;
+
+    // Control flow
+    pub const JA: u8 = BPF_JMP | BPF_JA;
+    pub const CALL_IMM: u8 = BPF_JMP | BPF_K | BPF_CALL;
+    pub const CALL_REG: u8 = BPF_JMP | BPF_X | BPF_CALL;
+    pub const EXIT: u8 = BPF_JMP | BPF_EXIT;
+
+    // Frequently used ALU opcodes
+    pub const ADD64_IMM: u8 = BPF_ALU64 | BPF_K | BPF_ADD;
+    pub const ADD64_REG: u8 = BPF_ALU64 | BPF_X | BPF_ADD;
+    pub const MOV64_IMM: u8 = BPF_ALU64 | BPF_K | BPF_MOV;
+    pub const MOV64_REG: u8 = BPF_ALU64 | BPF_X | BPF_MOV;
+    pub const DIV64_IMM: u8 = BPF_ALU64 | BPF_K | BPF_DIV;
+    pub const MOD64_IMM: u8 = BPF_ALU64 | BPF_K | BPF_MOD;
+    pub const DIV32_IMM: u8 = BPF_ALU | BPF_K | BPF_DIV;
+    pub const MOD32_IMM: u8 = BPF_ALU | BPF_K | BPF_MOD;
+}
+
+/// Hash used by sBPF to identify syscalls and functions (murmur3, 32 bit, seed 0)
+pub fn hash_symbol_name(name: &[u8]) -> u32 {
+    murmur3_32(name, 0)
+}
+
+fn murmur3_32(data: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let scramble = |mut k: u32| {
+        k = k.wrapping_mul(C1);
+        k = k.rotate_left(15);
+        k.wrapping_mul(C2)
+    };
+
+    let mut hash = seed;
+    let chunks = data.chunks_exact(4);
+    let remainder = chunks.remainder();
+    for chunk in chunks {
+        hash ^= scramble(u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+        hash = hash.rotate_left(13);
+        hash = hash.wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+    if !remainder.is_empty() {
+        let k = remainder
+            .iter()
+            .enumerate()
+            .fold(0u32, |k, (i, byte)| k | (*byte as u32) << (8 * i));
+        hash ^= scramble(k);
+    }
+
+    hash ^= data.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^ (hash >> 16)
+}
+
+/// Errors raised while executing sBPF bytecode
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum EbpfError {
+    #[error("unsupported instruction {opc:#04x} at pc {pc}")]
+    UnsupportedInstruction { pc: usize, opc: u8 },
+
+    #[error("division by zero at pc {0}")]
+    DivideByZero(usize),
+
+    #[error("access violation at pc {pc}: {kind} of {len} bytes at {vm_addr:#x}")]
+    AccessViolation { pc: usize, kind: &'static str, vm_addr: u64, len: u64 },
+
+    #[error("call depth exceeded at pc {0}")]
+    CallDepthExceeded(usize),
+
+    #[error("unresolved function or syscall {hash:#x} at pc {pc}")]
+    UnsupportedCall { pc: usize, hash: u32 },
+
+    #[error("call to {target:#x} outside the text segment at pc {pc}")]
+    CallOutsideTextSegment { pc: usize, target: u64 },
+
+    #[error("execution ran past the end of the text segment at pc {0}")]
+    ExecutionOverrun(usize),
+
+    #[error("exceeded the compute budget at pc {0}")]
+    ExceededMaxInstructions(usize),
+
+    #[error("syscall failed: {0}")]
+    SyscallError(String),
+
+    /// A syscall failed with an error that becomes the instruction's error
+    #[error("{0}")]
+    InstructionError(#[from] InstructionError),
+}
+
+/// A decoded sBPF instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Insn {
+    pub opc: u8,
+    pub dst: u8,
+    pub src: u8,
+    pub off: i16,
+    pub imm: i64,
+}
+
+impl Insn {
+    pub fn new(opc: u8, dst: u8, src: u8, off: i16, imm: i32) -> Self {
+        Self { opc, dst, src, off, imm: imm as i64 }
+    }
+
+    /// Decode the instruction in slot `pc`
+    pub fn decode(text: &[u8], pc: usize) -> Option<Self> {
+        let bytes = text.get(pc * INSN_SIZE..(pc + 1) * INSN_SIZE)?;
+        Some(Self {
+            opc: bytes[0],
+            dst: bytes[1] & 0x0f,
+            src: bytes[1] >> 4,
+            off: i16::from_le_bytes([bytes[2], bytes[3]]),
+            imm: i32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as i64,
+        })
+    }
+
+    /// Encode into the 8-byte wire representation
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let mut bytes = [0u8; 8];
+        bytes[0] = self.opc;
+        bytes[1] = (self.src << 4) | (self.dst & 0x0f);
+        bytes[2..4].copy_from_slice(&self.off.to_le_bytes());
+        bytes[4..8].copy_from_slice(&(self.imm as i32).to_le_bytes());
+        bytes
+    }
+
+    /// Encode a 16-byte `lddw` loading a full 64-bit immediate
+    pub fn lddw(dst: u8, imm: u64) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&Self::new(ebpf::LD_DW_IMM, dst, 0, 0, imm as u32 as i32).to_bytes());
+        bytes[8..].copy_from_slice(&Self::new(0, 0, 0, 0, (imm >> 32) as u32 as i32).to_bytes());
+        bytes
+    }
+}
+
+/// Loaded program: the read-only region mapped at `MM_PROGRAM_START` plus its function table
+#[derive(Debug, Clone)]
+pub struct Executable {
+    program: Vec<u8>,
+    text_offset: usize,
+    text_len: usize,
+    entry_pc: usize,
+    functions: HashMap<u32, usize>,
+}
+
+impl Executable {
+    /// Wrap raw bytecode with no relocations; execution starts at the first instruction
+    pub fn from_text_bytes(text: &[u8]) -> Self {
+        let mut executable = Self::new(text.to_vec(), 0, text.len(), 0);
+        executable.register_function(0);
+        executable
+    }
+
+    /// Build from a program region whose text section spans `text_offset..text_offset + text_len`
+    pub fn new(program: Vec<u8>, text_offset: usize, text_len: usize, entry_pc: usize) -> Self {
+        Self {
+            program,
+            text_offset,
+            text_len,
+            entry_pc,
+            functions: HashMap::new(),
+        }
+    }
+
+    /// Register an internal function starting at `pc`, returning the hash `call` uses to reach it
+    pub fn register_function(&mut self, pc: usize) -> u32 {
+        let hash = hash_symbol_name(&(pc as u64).to_le_bytes());
+        self.functions.insert(hash, pc);
+        hash
+    }
+
+    pub fn lookup_function(&self, hash: u32) -> Option<usize> {
+        self.functions.get(&hash).copied()
+    }
+
+    pub fn program(&self) -> &[u8] {
+        &self.program
+    }
+
+    pub fn text(&self) -> &[u8] {
+        &self.program[self.text_offset..self.text_offset + self.text_len]
+    }
+
+    pub fn text_offset(&self) -> usize {
+        self.text_offset
+    }
+
+    pub fn entry_pc(&self) -> usize {
+        self.entry_pc
+    }
+
+    /// Number of instruction slots in the text section
+    pub fn instruction_count(&self) -> usize {
+        self.text_len / INSN_SIZE
+    }
+}
+
+/// Virtual address space of a running program
+#[derive(Debug)]
+pub struct MemoryMapping<'a> {
+    program: &'a [u8],
+    stack: Vec<u8>,
+    heap: Vec<u8>,
+    input: Vec<u8>,
+}
+
+impl<'a> MemoryMapping<'a> {
+    pub fn new(program: &'a [u8], heap_size: usize, input: Vec<u8>) -> Self {
+        Self {
+            program,
+            stack: vec![0u8; STACK_FRAME_SIZE * MAX_CALL_DEPTH],
+            heap: vec![0u8; heap_size],
+            input,
+        }
+    }
+
+    /// Serialized input after execution, including any changes made by the program
+    pub fn input(&self) -> &[u8] {
+        &self.input
+    }
+
+    pub fn into_input(self) -> Vec<u8> {
+        self.input
+    }
+
+    fn region(&self, vm_addr: u64) -> Option<(&[u8], u64)> {
+        match vm_addr >> 32 {
+            1 => Some((self.program, MM_PROGRAM_START)),
+            2 => Some((&self.stack, MM_STACK_START)),
+            3 => Some((&self.heap, MM_HEAP_START)),
+            4 => Some((&self.input, MM_INPUT_START)),
+            _ => None,
+        }
+    }
+
+    fn region_mut(&mut self, vm_addr: u64) -> Option<(&mut [u8], u64)> {
+        match vm_addr >> 32 {
+            2 => Some((&mut self.stack, MM_STACK_START)),
+            3 => Some((&mut self.heap, MM_HEAP_START)),
+            4 => Some((&mut self.input, MM_INPUT_START)),
+            _ => None,
+        }
+    }
+
+    /// Translate `len` bytes at `vm_addr` for reading
+    pub fn translate(&self, vm_addr: u64, len: u64) -> Result<&[u8], EbpfError> {
+        let violation = EbpfError::AccessViolation { pc: 0, kind: "load", vm_addr, len };
+        let (region, start) = self.region(vm_addr).ok_or_else(|| violation.clone())?;
+        let offset = (vm_addr - start) as usize;
+        let end = offset.checked_add(len as usize).ok_or_else(|| violation.clone())?;
+        region.get(offset..end).ok_or(violation)
+    }
+
+    /// Translate `len` bytes at `vm_addr` for writing
+    pub fn translate_mut(&mut self, vm_addr: u64, len: u64) -> Result<&mut [u8], EbpfError> {
+        let violation = EbpfError::AccessViolation { pc: 0, kind: "store", vm_addr, len };
+        let (region, start) = self.region_mut(vm_addr).ok_or_else(|| violation.clone())?;
+        let offset = (vm_addr - start) as usize;
+        let end = offset.checked_add(len as usize).ok_or_else(|| violation.clone())?;
+        region.get_mut(offset..end).ok_or(violation)
+    }
+
+    /// Load a little-endian integer of `size` bytes
+    pub fn load(&self, vm_addr: u64, size: u64) -> Result<u64, EbpfError> {
+        let bytes = self.translate(vm_addr, size)?;
+        let mut value = [0u8; 8];
+        value[..bytes.len()].copy_from_slice(bytes);
+        Ok(u64::from_le_bytes(value))
+    }
+
+    /// Store the low `size` bytes of `value` in little-endian order
+    pub fn store(&mut self, vm_addr: u64, size: u64, value: u64) -> Result<(), EbpfError> {
+        let bytes = self.translate_mut(vm_addr, size)?;
+        bytes.copy_from_slice(&value.to_le_bytes()[..size as usize]);
+        Ok(())
+    }
+}
+
+/// Environment a program runs in: compute metering plus whatever syscalls need
+pub trait ContextObject {
+    /// Charge compute units, returning false once the budget is exhausted
+    fn consume(&mut self, units: u64) -> bool;
+
+    /// Compute units left in the budget
+    fn remaining(&self) -> u64;
+}
+
+/// Host function callable from a program; receives registers r1-r5 and returns r0
+pub type SyscallFunction<C> = fn(&mut C, [u64; 5], &mut MemoryMapping) -> Result<u64, EbpfError>;
+
+/// Syscalls available to programs, keyed by the hash of their symbol name
+pub struct SyscallRegistry<C> {
+    functions: HashMap<u32, (&'static str, SyscallFunction<C>)>,
+}
+
+impl<C> SyscallRegistry<C> {
+    pub fn new() -> Self {
+        Self { functions: HashMap::new() }
+    }
+
+    pub fn register(&mut self, name: &'static str, function: SyscallFunction<C>) {
+        self.functions.insert(hash_symbol_name(name.as_bytes()), (name, function));
+    }
+
+    pub fn lookup(&self, hash: u32) -> Option<SyscallFunction<C>> {
+        self.functions.get(&hash).map(|(_, function)| *function)
+    }
+
+    pub fn lookup_name(&self, name: &str) -> Option<SyscallFunction<C>> {
+        self.lookup(hash_symbol_name(name.as_bytes()))
+    }
+}
+
+impl<C> Default for SyscallRegistry<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct CallFrame {
+    saved_registers: [u64; 4],
+    frame_pointer: u64,
+    return_pc: usize,
+}
+
+/// sBPF interpreter bound to a program, its memory and its context
+pub struct EbpfVm<'a, C: ContextObject> {
+    executable: &'a Executable,
+    syscalls: &'a SyscallRegistry<C>,
+    pub context: &'a mut C,
+    pub memory: MemoryMapping<'a>,
+    registers: [u64; 11],
+    frames: Vec<CallFrame>,
+    instructions_executed: u64,
+}
+
+impl<'a, C: ContextObject> EbpfVm<'a, C> {
+    pub fn new(
+        executable: &'a Executable,
+        syscalls: &'a SyscallRegistry<C>,
+        context: &'a mut C,
+        input: Vec<u8>,
+    ) -> Self {
+        Self {
+            executable,
+            syscalls,
+            context,
+            memory: MemoryMapping::new(executable.program(), HEAP_SIZE, input),
+            registers: [0u64; 11],
+            frames: Vec::new(),
+            instructions_executed: 0,
+        }
+    }
+
+    /// Number of instructions executed by the last call to `execute`
+    pub fn instructions_executed(&self) -> u64 {
+        self.instructions_executed
+    }
+
+    /// Run from the entrypoint with r1 pointing at the input region; returns r0
+    pub fn execute(&mut self) -> Result<u64, EbpfError> {
+        self.registers = [0u64; 11];
+        self.registers[1] = MM_INPUT_START;
+        self.registers[10] = MM_STACK_START + STACK_FRAME_SIZE as u64;
+        self.frames.clear();
+        self.instructions_executed = 0;
+
+        let text = self.executable.text();
+        let insn_count = self.executable.instruction_count();
+        let mut pc = self.executable.entry_pc();
+
+        loop {
+            if pc >= insn_count {
+                return Err(EbpfError::ExecutionOverrun(pc));
+            }
+            if !self.context.consume(1) {
+                return Err(EbpfError::ExceededMaxInstructions(pc));
+            }
+            self.instructions_executed += 1;
+
+            let insn = Insn::decode(text, pc).ok_or(EbpfError::ExecutionOverrun(pc))?;
+            let dst = insn.dst as usize;
+            let src = insn.src as usize;
+            if dst > 10 || src > 10 {
+                return Err(EbpfError::UnsupportedInstruction { pc, opc: insn.opc });
+            }
+            let mut next_pc = pc + 1;
+
+            match insn.opc & ebpf::BPF_CLS_MASK {
+                ebpf::BPF_ALU | ebpf::BPF_ALU64 if insn.opc & 0xf0 == ebpf::BPF_END => {
+                    let value = self.registers[dst];
+                    self.registers[dst] = match (insn.opc, insn.imm) {
+                        (ebpf::LE, 16) => value as u16 as u64,
+                        (ebpf::LE, 32) => value as u32 as u64,
+                        (ebpf::LE, 64) => value,
+                        (ebpf::BE, 16) => (value as u16).swap_bytes() as u64,
+                        (ebpf::BE, 32) => (value as u32).swap_bytes() as u64,
+                        (ebpf::BE, 64) => value.swap_bytes(),
+                        _ => return Err(EbpfError::UnsupportedInstruction { pc, opc: insn.opc }),
+                    };
+                }
+                ebpf::BPF_ALU => {
+                    if dst == 10 {
+                        return Err(EbpfError::UnsupportedInstruction { pc, opc: insn.opc });
+                    }
+                    let operand = if insn.opc & ebpf::BPF_X != 0 {
+                        self.registers[src] as u32
+                    } else {
+                        insn.imm as u32
+                    };
+                    let value = self.registers[dst] as u32;
+                    self.registers[dst] = match insn.opc & 0xf0 {
+                        ebpf::BPF_ADD => (value as i32).wrapping_add(operand as i32) as u64,
+                        ebpf::BPF_SUB => (value as i32).wrapping_sub(operand as i32) as u64,
+                        ebpf::BPF_MUL => (value as i32).wrapping_mul(operand as i32) as u64,
+                        ebpf::BPF_DIV => {
+                            if operand == 0 {
+                                return Err(EbpfError::DivideByZero(pc));
+                            }
+                            (value / operand) as u64
+                        }
+                        ebpf::BPF_MOD => {
+                            if operand == 0 {
+                                return Err(EbpfError::DivideByZero(pc));
+                            }
+                            (value % operand) as u64
+                        }
+                        ebpf::BPF_OR => (value | operand) as u64,
+                        ebpf::BPF_AND => (value & operand) as u64,
+                        ebpf::BPF_XOR => (value ^ operand) as u64,
+                        ebpf::BPF_LSH => value.wrapping_shl(operand) as u64,
+                        ebpf::BPF_RSH => value.wrapping_shr(operand) as u64,
+                        ebpf::BPF_ARSH => (value as i32).wrapping_shr(operand) as u32 as u64,
+                        ebpf::BPF_NEG => (value as i32).wrapping_neg() as u32 as u64,
+                        ebpf::BPF_MOV => operand as u64,
+                        _ => return Err(EbpfError::UnsupportedInstruction { pc, opc: insn.opc }),
+                    };
+                }
+                ebpf::BPF_ALU64 => {
+                    if dst == 10 && insn.opc != ebpf::ADD64_IMM {
+                        return Err(EbpfError::UnsupportedInstruction { pc, opc: insn.opc });
+                    }
+                    let operand = if insn.opc & ebpf::BPF_X != 0 {
+                        self.registers[src]
+                    } else {
+                        insn.imm as u64
+                    };
+                    let value = self.registers[dst];
+                    self.registers[dst] = match insn.opc & 0xf0 {
+                        ebpf::BPF_ADD => value.wrapping_add(operand),
+                        ebpf::BPF_SUB => value.wrapping_sub(operand),
+                        ebpf::BPF_MUL => value.wrapping_mul(operand),
+                        ebpf::BPF_DIV => {
+                            if operand == 0 {
+                                return Err(EbpfError::DivideByZero(pc));
+                            }
+                            value / operand
+                        }
+                        ebpf::BPF_MOD => {
+                            if operand == 0 {
+                                return Err(EbpfError::DivideByZero(pc));
+                            }
+                            value % operand
+                        }
+                        ebpf::BPF_OR => value | operand,
+                        ebpf::BPF_AND => value & operand,
+                        ebpf::BPF_XOR => value ^ operand,
+                        ebpf::BPF_LSH => value.wrapping_shl(operand as u32),
+                        ebpf::BPF_RSH => value.wrapping_shr(operand as u32),
+                        ebpf::BPF_ARSH => (value as i64).wrapping_shr(operand as u32) as u64,
+                        ebpf::BPF_NEG => (value as i64).wrapping_neg() as u64,
+                        ebpf::BPF_MOV => operand,
+                        _ => return Err(EbpfError::UnsupportedInstruction { pc, opc: insn.opc }),
+                    };
+                }
+                ebpf::BPF_LD => {
+                    if insn.opc != ebpf::LD_DW_IMM || dst == 10 {
+                        return Err(EbpfError::UnsupportedInstruction { pc, opc: insn.opc });
+                    }
+                    let high = Insn::decode(text, pc + 1).ok_or(EbpfError::ExecutionOverrun(pc + 1))?;
+                    self.registers[dst] = (insn.imm as u32 as u64) | ((high.imm as u32 as u64) << 32);
+                    next_pc = pc + 2;
+                }
+                ebpf::BPF_LDX => {
+                    if dst == 10 {
+                        return Err(EbpfError::UnsupportedInstruction { pc, opc: insn.opc });
+                    }
+                    let size = Self::access_size(insn.opc).ok_or(EbpfError::UnsupportedInstruction { pc, opc: insn.opc })?;
+                    let vm_addr = self.registers[src].wrapping_add(insn.off as i64 as u64);
+                    self.registers[dst] = self.memory.load(vm_addr, size).map_err(|e| Self::at_pc(e, pc))?;
+                }
+                ebpf::BPF_ST | ebpf::BPF_STX => {
+                    let size = Self::access_size(insn.opc).ok_or(EbpfError::UnsupportedInstruction { pc, opc: insn.opc })?;
+                    let vm_addr = self.registers[dst].wrapping_add(insn.off as i64 as u64);
+                    let value = if insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_STX {
+                        self.registers[src]
+                    } else {
+                        insn.imm as u64
+                    };
+                    self.memory.store(vm_addr, size, value).map_err(|e| Self::at_pc(e, pc))?;
+                }
+                ebpf::BPF_JMP => match insn.opc {
+                    ebpf::CALL_IMM => {
+                        let hash = insn.imm as u32;
+                        if let Some(syscall) = self.syscalls.lookup(hash) {
+                            let args = [
+                                self.registers[1],
+                                self.registers[2],
+                                self.registers[3],
+                                self.registers[4],
+                                self.registers[5],
+                            ];
+                            self.registers[0] = syscall(self.context, args, &mut self.memory)
+                                .map_err(|e| Self::at_pc(e, pc))?;
+                        } else if let Some(target_pc) = self.executable.lookup_function(hash) {
+                            self.push_frame(pc, next_pc)?;
+                            next_pc = target_pc;
+                        } else {
+                            return Err(EbpfError::UnsupportedCall { pc, hash });
+                        }
+                    }
+                    ebpf::CALL_REG => {
+                        // Unverified bytecode may name a register past r10
+                        let target = *self
+                            .registers
+                            .get(insn.imm as usize & 0x0f)
+                            .ok_or(EbpfError::UnsupportedInstruction { pc, opc: insn.opc })?;
+                        let text_start = MM_PROGRAM_START + self.executable.text_offset() as u64;
+                        let target_pc = target
+                            .checked_sub(text_start)
+                            .filter(|offset| offset % INSN_SIZE as u64 == 0)
+                            .map(|offset| (offset / INSN_SIZE as u64) as usize)
+                            .filter(|target_pc| *target_pc < insn_count)
+                            .ok_or(EbpfError::CallOutsideTextSegment { pc, target })?;
+                        self.push_frame(pc, next_pc)?;
+                        next_pc = target_pc;
+                    }
+                    ebpf::EXIT => match self.frames.pop() {
+                        Some(frame) => {
+                            self.registers[6..10].copy_from_slice(&frame.saved_registers);
+                            self.registers[10] = frame.frame_pointer;
+                            next_pc = frame.return_pc;
+                        }
+                        None => return Ok(self.registers[0]),
+                    },
+                    _ => {
+                        let operand = if insn.opc & ebpf::BPF_X != 0 {
+                            self.registers[src]
+                        } else {
+                            insn.imm as u64
+                        };
+                        let value = self.registers[dst];
+                        let taken = match insn.opc & 0xf0 {
+                            ebpf::BPF_JA => true,
+                            ebpf::BPF_JEQ => value == operand,
+                            ebpf::BPF_JNE => value != operand,
+                            ebpf::BPF_JGT => value > operand,
+                            ebpf::BPF_JGE => value >= operand,
+                            ebpf::BPF_JLT => value < operand,
+                            ebpf::BPF_JLE => value <= operand,
+                            ebpf::BPF_JSET => value & operand != 0,
+                            ebpf::BPF_JSGT => (value as i64) > operand as i64,
+                            ebpf::BPF_JSGE => (value as i64) >= operand as i64,
+                            ebpf::BPF_JSLT => (value as i64) < operand as i64,
+                            ebpf::BPF_JSLE => (value as i64) <= operand as i64,
+                            _ => return Err(EbpfError::UnsupportedInstruction { pc, opc: insn.opc }),
+                        };
+                        if taken {
+                            next_pc = (next_pc as i64 + insn.off as i64) as usize;
+                        }
+                    }
+                },
+                _ => return Err(EbpfError::UnsupportedInstruction { pc, opc: insn.opc }),
+            }
+
+            pc = next_pc;
+        }
+    }
+
+    fn push_frame(&mut self, pc: usize, return_pc: usize) -> Result<(), EbpfError> {
+        if self.frames.len() + 1 >= MAX_CALL_DEPTH {
+            return Err(EbpfError::CallDepthExceeded(pc));
+        }
+        let mut saved_registers = [0u64; 4];
+        saved_registers.copy_from_slice(&self.registers[6..10]);
+        self.frames.push(CallFrame {
+            saved_registers,
+            frame_pointer: self.registers[10],
+            return_pc,
+        });
+        self.registers[10] += STACK_FRAME_SIZE as u64;
+        Ok(())
+    }
+
+    fn access_size(opc: u8) -> Option<u64> {
+        if opc & 0xe0 != 0x60 {
+            return None;
+        }
+        match opc & 0x18 {
+            0x00 => Some(4),
+            0x08 => Some(2),
+            0x10 => Some(1),
+            0x18 => Some(8),
+            _ => None,
+        }
+    }
+
+    /// Attach the faulting program counter to errors raised by the mapping or a syscall
+    fn at_pc(error: EbpfError, pc: usize) -> EbpfError {
+        match error {
+            EbpfError::AccessViolation { kind, vm_addr, len, .. } => {
+                EbpfError::AccessViolation { pc, kind, vm_addr, len }
+            }
+            EbpfError::ExceededMaxInstructions(_) => EbpfError::ExceededMaxInstructions(pc),
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ebpf::*;
+
+    struct TestContext {
+        remaining: u64,
+    }
+
+    impl ContextObject for TestContext {
+        fn consume(&mut self, units: u64) -> bool {
+            if self.remaining < units {
+                return false;
+            }
+            self.remaining -= units;
+            true
+        }
+
+        fn remaining(&self) -> u64 {
+            self.remaining
+        }
+    }
+
+    fn assemble(insns: &[Insn]) -> Vec<u8> {
+        insns.iter().flat_map(|insn| insn.to_bytes()).collect()
+    }
+
+    fn run(text: &[u8], budget: u64) -> (Result<u64, EbpfError>, u64) {
+        let executable = Executable::from_text_bytes(text);
+        let syscalls = SyscallRegistry::new();
+        let mut context = TestContext { remaining: budget };
+        let result = EbpfVm::new(&executable, &syscalls, &mut context, vec![0u8; 64]).execute();
+        (result, context.remaining)
+    }
+
+    #[test]
+    fn test_murmur3_syscall_hashes() {
+        assert_eq!(hash_symbol_name(b"sol_log_"), 0x207559bd);
+        assert_eq!(hash_symbol_name(b"abort"), 0xb6fc1a11);
+        assert_eq!(hash_symbol_name(b"sol_panic_"), 0x686093bb);
+    }
+
+    #[test]
+    fn test_arithmetic_and_exit() {
+        let text = assemble(&[
+            Insn::new(MOV64_IMM, 0, 0, 0, 6),
+            Insn::new(BPF_ALU64 | BPF_K | BPF_MUL, 0, 0, 0, 7),
+            Insn::new(MOV64_IMM, 1, 0, 0, -1),
+            Insn::new(BPF_ALU | BPF_K | BPF_ADD, 1, 0, 0, 1),
+            Insn::new(ADD64_REG, 0, 1, 0, 0),
+            Insn::new(EXIT, 0, 0, 0, 0),
+        ]);
+        let (result, remaining) = run(&text, 100);
+        assert_eq!(result, Ok(42));
+        assert_eq!(remaining, 94);
+    }
+
+    #[test]
+    fn test_lddw_and_byte_swap() {
+        let mut text = Insn::lddw(0, 0x1122_3344_5566_7788).to_vec();
+        text.extend(assemble(&[
+            Insn::new(BE, 0, 0, 0, 64),
+            Insn::new(EXIT, 0, 0, 0, 0),
+        ]));
+        assert_eq!(run(&text, 100).0, Ok(0x8877_6655_4433_2211));
+    }
+
+    #[test]
+    fn test_stack_memory_and_loop() {
+        // Sum 1..=10 through a stack slot
+        let text = assemble(&[
+            Insn::new(ST_DW_IMM, 10, 0, -8, 0),
+            Insn::new(MOV64_IMM, 1, 0, 0, 10),
+            Insn::new(LD_DW_REG, 2, 10, -8, 0),
+            Insn::new(ADD64_REG, 2, 1, 0, 0),
+            Insn::new(ST_DW_REG, 10, 2, -8, 0),
+            Insn::new(BPF_ALU64 | BPF_K | BPF_SUB, 1, 0, 0, 1),
+            Insn::new(BPF_JMP | BPF_K | BPF_JNE, 1, 0, -5, 0),
+            Insn::new(LD_DW_REG, 0, 10, -8, 0),
+            Insn::new(EXIT, 0, 0, 0, 0),
+        ]);
+        assert_eq!(run(&text, 1000).0, Ok(55));
+    }
+
+    #[test]
+    fn test_input_region_is_writable() {
+        let text = assemble(&[
+            Insn::new(ST_B_IMM, 1, 0, 3, 0x2a),
+            Insn::new(LD_B_REG, 0, 1, 3, 0),
+            Insn::new(EXIT, 0, 0, 0, 0),
+        ]);
+        let executable = Executable::from_text_bytes(&text);
+        let syscalls = SyscallRegistry::new();
+        let mut context = TestContext { remaining: 100 };
+        let mut vm = EbpfVm::new(&executable, &syscalls, &mut context, vec![0u8; 8]);
+        assert_eq!(vm.execute(), Ok(0x2a));
+        assert_eq!(vm.memory.input()[3], 0x2a);
+    }
+
+    #[test]
+    fn test_internal_call_preserves_callee_saved_registers() {
+        let function_hash = hash_symbol_name(&4u64.to_le_bytes());
+        let text = assemble(&[
+            Insn::new(MOV64_IMM, 6, 0, 0, 5),
+            Insn::new(CALL_IMM, 0, 0, 0, function_hash as i32),
+            Insn::new(ADD64_REG, 0, 6, 0, 0),
+            Insn::new(EXIT, 0, 0, 0, 0),
+            // function: clobbers r6 and returns 10
+            Insn::new(MOV64_IMM, 6, 0, 0, 100),
+            Insn::new(MOV64_IMM, 0, 0, 0, 10),
+            Insn::new(EXIT, 0, 0, 0, 0),
+        ]);
+        let mut program = Executable::from_text_bytes(&text);
+        program.register_function(4);
+        let syscalls = SyscallRegistry::new();
+        let mut context = TestContext { remaining: 100 };
+        assert_eq!(EbpfVm::new(&program, &syscalls, &mut context, vec![]).execute(), Ok(15));
+    }
+
+    #[test]
+    fn test_syscall_dispatch() {
+        fn double(_: &mut TestContext, args: [u64; 5], _: &mut MemoryMapping) -> Result<u64, EbpfError> {
+            Ok(args[0] * 2)
+        }
+        let mut syscalls = SyscallRegistry::new();
+        syscalls.register("double", double);
+        let text = assemble(&[
+            Insn::new(MOV64_IMM, 1, 0, 0, 21),
+            Insn::new(CALL_IMM, 0, 0, 0, hash_symbol_name(b"double") as i32),
+            Insn::new(EXIT, 0, 0, 0, 0),
+        ]);
+        let executable = Executable::from_text_bytes(&text);
+        let mut context = TestContext { remaining: 100 };
+        assert_eq!(EbpfVm::new(&executable, &syscalls, &mut context, vec![]).execute(), Ok(42));
+    }
+
+    #[test]
+    fn test_runtime_faults() {
+        let divide = assemble(&[
+            Insn::new(MOV64_IMM, 1, 0, 0, 0),
+            Insn::new(BPF_ALU64 | BPF_X | BPF_DIV, 0, 1, 0, 0),
+            Insn::new(EXIT, 0, 0, 0, 0),
+        ]);
+        assert_eq!(run(&divide, 100).0, Err(EbpfError::DivideByZero(1)));
+
+        let write_program = assemble(&[
+            Insn::new(LD_DW_IMM, 1, 0, 0, 0),
+            Insn::new(0, 0, 0, 0, 1),
+            Insn::new(ST_B_IMM, 1, 0, 0, 1),
+            Insn::new(EXIT, 0, 0, 0, 0),
+        ]);
+        assert!(matches!(
+            run(&write_program, 100).0,
+            Err(EbpfError::AccessViolation { pc: 2, kind: "store", .. })
+        ));
+
+        let infinite_loop = assemble(&[Insn::new(JA, 0, 0, -1, 0)]);
+        assert_eq!(run(&infinite_loop, 50), (Err(EbpfError::ExceededMaxInstructions(0)), 0));
+
+        let unknown_call = assemble(&[Insn::new(CALL_IMM, 0, 0, 0, 0x1234)]);
+        assert_eq!(run(&unknown_call, 50).0, Err(EbpfError::UnsupportedCall { pc: 0, hash: 0x1234 }));
+
+        let overrun = assemble(&[Insn::new(MOV64_IMM, 0, 0, 0, 0)]);
+        assert_eq!(run(&overrun, 50).0, Err(EbpfError::ExecutionOverrun(1)));
+
+        let call_r11 = assemble(&[Insn::new(CALL_REG, 0, 0, 0, 11), Insn::new(EXIT, 0, 0, 0, 0)]);
+        assert_eq!(run(&call_r11, 50).0, Err(EbpfError::UnsupportedInstruction { pc: 0, opc: CALL_REG }));
+    }
+}
@@ -0,0 +1,310 @@
+//! Snapshots: the state of a frozen slot captured to a zstd-compressed archive, to restore it in
+//! another run. An incremental snapshot holds only the accounts changed since a full snapshot it
+//! is applied on top of.
+
+use crate::accounts_hash;
+use crate::blockhash_queue::BlockhashQueue;
+use crate::sysvar::{EpochSchedule, Rent, SysvarCache};
+use crate::types::*;
+use crate::{Result, TerminatorError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Format version written to and expected in archives
+pub const SNAPSHOT_VERSION: u32 = 1;
+/// Leading bytes of every snapshot archive
+const SNAPSHOT_MAGIC: &[u8; 8] = b"TDSNAPSH";
+const ZSTD_LEVEL: i32 = 3;
+
+/// The full snapshot an incremental snapshot was taken against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotBase {
+    pub slot: u64,
+    pub accounts_hash: [u8; 32],
+}
+
+/// Sysvars that aren't derived from the slot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotSysvars {
+    pub rent: Rent,
+    pub epoch_schedule: EpochSchedule,
+    pub last_restart_slot: u64,
+}
+
+impl From<&SysvarCache> for SnapshotSysvars {
+    fn from(sysvars: &SysvarCache) -> Self {
+        Self {
+            rent: sysvars.rent.clone(),
+            epoch_schedule: sysvars.epoch_schedule.clone(),
+            last_restart_slot: sysvars.last_restart_slot,
+        }
+    }
+}
+
+/// State of a frozen slot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub slot: u64,
+    pub blockhash: [u8; 32],
+    pub bank_hash: [u8; 32],
+    pub fee_calculator: FeeCalculator,
+    pub blockhash_queue: BlockhashQueue,
+    pub sysvars: SnapshotSysvars,
+    /// Set for incremental snapshots
+    pub base: Option<SnapshotBase>,
+    /// Every account of a full snapshot. An incremental snapshot holds the accounts changed since
+    /// its base, with zero lamports for the ones that were closed.
+    pub accounts: Vec<(Pubkey, Account)>,
+    /// Checksum of the accounts lattice hash of the whole state at `slot`
+    pub accounts_hash: [u8; 32],
+}
+
+impl Snapshot {
+    pub fn is_incremental(&self) -> bool {
+        self.base.is_some()
+    }
+
+    /// Incremental snapshot of this full snapshot's state relative to `base`
+    pub fn diff(&self, base: &Snapshot) -> Result<Snapshot> {
+        if self.is_incremental() || base.is_incremental() {
+            return Err(snapshot_error("incremental snapshots are taken between full snapshots"));
+        }
+        if base.slot > self.slot {
+            return Err(snapshot_error(format!("base slot {} is after slot {}", base.slot, self.slot)));
+        }
+        let mut base_accounts: HashMap<&Pubkey, &Account> =
+            base.accounts.iter().map(|(pubkey, account)| (pubkey, account)).collect();
+        let mut accounts = Vec::new();
+        for (pubkey, account) in &self.accounts {
+            if base_accounts.remove(pubkey) != Some(account) {
+                accounts.push((*pubkey, account.clone()));
+            }
+        }
+        accounts.extend(
+            base_accounts
+                .into_iter()
+                .map(|(pubkey, account)| (*pubkey, Account { lamports: 0, ..account.clone() })),
+        );
+        accounts.sort_unstable_by_key(|(pubkey, _)| pubkey.0);
+
+        Ok(Snapshot {
+            base: Some(SnapshotBase { slot: base.slot, accounts_hash: base.accounts_hash }),
+            accounts,
+            ..self.clone_without_accounts()
+        })
+    }
+
+    /// Full snapshot of the state after applying the incremental snapshot `incremental` on top of
+    /// this one
+    pub fn apply(&self, incremental: &Snapshot) -> Result<Snapshot> {
+        let base = incremental
+            .base
+            .ok_or_else(|| snapshot_error("only incremental snapshots can be applied"))?;
+        if self.is_incremental() {
+            return Err(snapshot_error("incremental snapshots apply on top of full snapshots"));
+        }
+        if base.slot != self.slot || base.accounts_hash != self.accounts_hash {
+            return Err(snapshot_error(format!(
+                "incremental snapshot of slot {} was not taken against the snapshot of slot {}",
+                incremental.slot, self.slot
+            )));
+        }
+        let mut accounts: HashMap<Pubkey, Account> = self.accounts.iter().cloned().collect();
+        for (pubkey, account) in &incremental.accounts {
+            if account.lamports > 0 {
+                accounts.insert(*pubkey, account.clone());
+            } else {
+                accounts.remove(pubkey);
+            }
+        }
+        let mut accounts: Vec<_> = accounts.into_iter().collect();
+        accounts.sort_unstable_by_key(|(pubkey, _)| pubkey.0);
+
+        let snapshot = Snapshot {
+            base: None,
+            accounts,
+            ..incremental.clone_without_accounts()
+        };
+        snapshot.verify()?;
+        Ok(snapshot)
+    }
+
+    /// Check the accounts of a full snapshot against its accounts hash
+    pub fn verify(&self) -> Result<()> {
+        if self.is_incremental() {
+            return Err(snapshot_error("apply an incremental snapshot to its base before verifying it"));
+        }
+        let lt_hash = accounts_hash::calculate_accounts_lt_hash(self.accounts.iter().map(|(pubkey, account)| (pubkey, account)));
+        if lt_hash.checksum() != self.accounts_hash {
+            return Err(snapshot_error(format!("accounts hash mismatch in snapshot of slot {}", self.slot)));
+        }
+        Ok(())
+    }
+
+    /// The snapshot's bank state, with only the existing accounts
+    pub fn bank_state(&self) -> BankState {
+        BankState {
+            accounts: self
+                .accounts
+                .iter()
+                .filter(|(_, account)| account.lamports > 0)
+                .cloned()
+                .collect(),
+            slot: self.slot,
+            blockhash: self.blockhash,
+            bank_hash: self.bank_hash,
+            fee_calculator: self.fee_calculator.clone(),
+        }
+    }
+
+    /// Conventional archive file name: `snapshot-<slot>-<hash>.bin.zst` or
+    /// `incremental-snapshot-<base slot>-<slot>-<hash>.bin.zst`, with the bs58 accounts hash
+    pub fn archive_file_name(&self) -> String {
+        let hash = bs58::encode(self.accounts_hash).into_string();
+        match self.base {
+            Some(base) => format!("incremental-snapshot-{}-{}-{}.bin.zst", base.slot, self.slot, hash),
+            None => format!("snapshot-{}-{}.bin.zst", self.slot, hash),
+        }
+    }
+
+    /// Write the snapshot to a compressed archive at `path`
+    pub fn write_archive(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path).map_err(snapshot_error)?);
+        writer.write_all(SNAPSHOT_MAGIC).map_err(snapshot_error)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_le_bytes()).map_err(snapshot_error)?;
+
+        let mut encoder = zstd::Encoder::new(writer, ZSTD_LEVEL).map_err(snapshot_error)?;
+        bincode::serialize_into(&mut encoder, self)
+            .map_err(|e| TerminatorError::SerializationError(e.to_string()))?;
+        encoder
+            .finish()
+            .and_then(|mut writer| writer.flush())
+            .map_err(snapshot_error)?;
+        fs::rename(&tmp_path, path).map_err(snapshot_error)
+    }
+
+    /// Read a snapshot archive written by [`Snapshot::write_archive`]. Full snapshots are verified
+    /// against their accounts hash.
+    pub fn read_archive(path: impl AsRef<Path>) -> Result<Snapshot> {
+        let mut reader = BufReader::new(File::open(path.as_ref()).map_err(snapshot_error)?);
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header).map_err(snapshot_error)?;
+        if &header[..8] != SNAPSHOT_MAGIC {
+            return Err(snapshot_error(format!("{} is not a snapshot archive", path.as_ref().display())));
+        }
+        let version = u32::from_le_bytes(header[8..].try_into().unwrap());
+        if version != SNAPSHOT_VERSION {
+            return Err(snapshot_error(format!("unsupported snapshot version {}", version)));
+        }
+
+        let decoder = zstd::Decoder::with_buffer(reader).map_err(snapshot_error)?;
+        let snapshot: Snapshot = bincode::deserialize_from(decoder)
+            .map_err(|e| TerminatorError::SerializationError(e.to_string()))?;
+        if !snapshot.is_incremental() {
+            snapshot.verify()?;
+        }
+        Ok(snapshot)
+    }
+
+    fn clone_without_accounts(&self) -> Snapshot {
+        Snapshot {
+            slot: self.slot,
+            blockhash: self.blockhash,
+            bank_hash: self.bank_hash,
+            fee_calculator: self.fee_calculator.clone(),
+            blockhash_queue: self.blockhash_queue.clone(),
+            sysvars: self.sysvars.clone(),
+            base: self.base,
+            accounts: Vec::new(),
+            accounts_hash: self.accounts_hash,
+        }
+    }
+}
+
+/// Read a full snapshot archive and, if given, an incremental one on top of it
+pub fn load_snapshot_archives(full: impl AsRef<Path>, incremental: Option<&Path>) -> Result<Snapshot> {
+    let snapshot = Snapshot::read_archive(full)?;
+    match incremental {
+        Some(path) => snapshot.apply(&Snapshot::read_archive(path)?),
+        None => Ok(snapshot),
+    }
+}
+
+fn snapshot_error(e: impl ToString) -> TerminatorError {
+    TerminatorError::SnapshotError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(slot: u64, accounts: &[(u8, u64)]) -> Snapshot {
+        let accounts: Vec<_> = accounts
+            .iter()
+            .map(|&(key, lamports)| (Pubkey::new([key; 32]), Account::new(lamports, vec![key], [0u8; 32])))
+            .collect();
+        let lt_hash = accounts_hash::calculate_accounts_lt_hash(accounts.iter().map(|(pubkey, account)| (pubkey, account)));
+        Snapshot {
+            slot,
+            blockhash: [slot as u8; 32],
+            bank_hash: [slot as u8 + 1; 32],
+            fee_calculator: FeeCalculator::default(),
+            blockhash_queue: BlockhashQueue::default(),
+            sysvars: SnapshotSysvars::from(&SysvarCache::default()),
+            base: None,
+            accounts,
+            accounts_hash: lt_hash.checksum(),
+        }
+    }
+
+    #[test]
+    fn test_archive_roundtrip_and_integrity() {
+        let dir = tempfile::tempdir().unwrap();
+        let full = snapshot(4, &[(1, 10), (2, 20)]);
+        let path = dir.path().join(full.archive_file_name());
+        full.write_archive(&path).unwrap();
+        let read = Snapshot::read_archive(&path).unwrap();
+        assert_eq!(read.slot, 4);
+        assert_eq!(read.accounts, full.accounts);
+        assert_eq!(read.accounts_hash, full.accounts_hash);
+
+        let mut tampered = full.clone();
+        tampered.accounts[0].1.lamports += 1;
+        tampered.write_archive(&path).unwrap();
+        assert!(matches!(Snapshot::read_archive(&path), Err(TerminatorError::SnapshotError(_))));
+
+        fs::write(&path, b"not a snapshot").unwrap();
+        assert!(Snapshot::read_archive(&path).is_err());
+    }
+
+    #[test]
+    fn test_incremental_snapshot() {
+        let base = snapshot(4, &[(1, 10), (2, 20), (3, 30)]);
+        let full = snapshot(9, &[(1, 10), (2, 25), (4, 40)]);
+        let incremental = full.diff(&base).unwrap();
+        assert!(incremental.is_incremental());
+        assert!(incremental.archive_file_name().starts_with("incremental-snapshot-4-9-"));
+        // Account 2 changed, 3 was closed and 4 created
+        let changed: Vec<_> = incremental.accounts.iter().map(|(pubkey, account)| (pubkey.0[0], account.lamports)).collect();
+        assert_eq!(changed, vec![(2, 25), (3, 0), (4, 40)]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let (base_path, incremental_path) = (dir.path().join("base"), dir.path().join("incremental"));
+        base.write_archive(&base_path).unwrap();
+        incremental.write_archive(&incremental_path).unwrap();
+        let restored = load_snapshot_archives(&base_path, Some(&incremental_path)).unwrap();
+        assert_eq!(restored.slot, 9);
+        assert_eq!(restored.accounts, full.accounts);
+        assert!(!restored.is_incremental());
+
+        // Applying on top of another base is refused
+        let other = snapshot(4, &[(1, 10)]);
+        assert!(other.apply(&incremental).is_err());
+        assert!(base.apply(&full).is_err());
+    }
+}
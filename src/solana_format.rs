@@ -1,6 +1,6 @@
+use crate::types::{AccountMeta, Instruction, InstructionData, Pubkey, Transaction};
 use crate::{Result, TerminatorError};
 use serde::{Deserialize, Serialize};
-use serde_with::{serde_as, Bytes};
 
 /// Real Solana transaction format compatible with Solana's wire format
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,7 +48,7 @@ impl SolanaPubkey {
     pub fn new_unique() -> Self {
         use sha2::{Sha256, Digest};
         let mut hasher = Sha256::new();
-        hasher.update(&std::time::SystemTime::now()
+        hasher.update(std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_nanos()
@@ -71,6 +71,7 @@ impl SolanaPubkey {
     }
 
     /// Parse from base58 string (like Solana CLI)
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self> {
         let bytes = bs58::decode(s)
             .into_vec()
@@ -86,6 +87,7 @@ impl SolanaPubkey {
     }
 
     /// Convert to base58 string (like Solana CLI)
+    #[allow(clippy::inherent_to_string_shadow_display)]
     pub fn to_string(&self) -> String {
         bs58::encode(&self.0).into_string()
     }
@@ -101,16 +103,30 @@ impl std::fmt::Display for SolanaPubkey {
 pub struct SolanaTransactionParser;
 
 impl SolanaTransactionParser {
-    /// Parse a transaction from Solana's wire format (bincode)
+    /// Parse a legacy transaction from Solana's wire format, with compact-u16 length prefixes
     pub fn parse_transaction(data: &[u8]) -> Result<SolanaTransaction> {
-        bincode::deserialize(data)
-            .map_err(|e| TerminatorError::SerializationError(format!("Failed to parse transaction: {}", e)))
+        let mut reader = WireReader::new(data);
+        let signature_count = reader.read_compact_u16()?;
+        let mut signatures = Vec::with_capacity(signature_count);
+        for _ in 0..signature_count {
+            signatures.push(SolanaSignature(reader.read_array()?));
+        }
+        let message = reader.read_message()?;
+        if reader.remaining() > 0 {
+            return Err(wire_error(format!("{} trailing bytes", reader.remaining())));
+        }
+        Ok(SolanaTransaction { signatures, message })
     }
 
     /// Serialize transaction to Solana's wire format
     pub fn serialize_transaction(tx: &SolanaTransaction) -> Result<Vec<u8>> {
-        bincode::serialize(tx)
-            .map_err(|e| TerminatorError::SerializationError(format!("Failed to serialize transaction: {}", e)))
+        let mut data = Vec::new();
+        write_compact_u16(&mut data, tx.signatures.len())?;
+        for signature in &tx.signatures {
+            data.extend_from_slice(&signature.0);
+        }
+        data.extend_from_slice(&Self::message_data(&tx.message)?);
+        Ok(data)
     }
 
     /// Parse transaction from JSON (like Solana RPC)
@@ -132,8 +148,8 @@ impl SolanaTransactionParser {
         lamports: u64,
         recent_blockhash: SolanaHash,
     ) -> SolanaTransaction {
-        // System program transfer instruction data
-        let mut instruction_data = vec![2u8]; // Transfer instruction
+        // System program transfer instruction data: a u32 discriminant and the lamports
+        let mut instruction_data = SYSTEM_TRANSFER_DISCRIMINANT.to_le_bytes().to_vec();
         instruction_data.extend_from_slice(&lamports.to_le_bytes());
 
         let instruction = CompiledInstruction {
@@ -161,8 +177,25 @@ impl SolanaTransactionParser {
 
     /// Extract message for signing (without signatures)
     pub fn message_data(message: &SolanaMessage) -> Result<Vec<u8>> {
-        bincode::serialize(message)
-            .map_err(|e| TerminatorError::SerializationError(format!("Failed to serialize message: {}", e)))
+        let mut data = vec![
+            message.header.num_required_signatures,
+            message.header.num_readonly_signed_accounts,
+            message.header.num_readonly_unsigned_accounts,
+        ];
+        write_compact_u16(&mut data, message.account_keys.len())?;
+        for key in &message.account_keys {
+            data.extend_from_slice(&key.0);
+        }
+        data.extend_from_slice(&message.recent_blockhash.0);
+        write_compact_u16(&mut data, message.instructions.len())?;
+        for instruction in &message.instructions {
+            data.push(instruction.program_id_index);
+            write_compact_u16(&mut data, instruction.accounts.len())?;
+            data.extend_from_slice(&instruction.accounts);
+            write_compact_u16(&mut data, instruction.data.len())?;
+            data.extend_from_slice(&instruction.data);
+        }
+        Ok(data)
     }
 
     /// Validate transaction format
@@ -196,15 +229,301 @@ impl SolanaTransactionParser {
     }
 }
 
+/// Largest serialized transaction, the payload of one network packet
+pub const PACKET_DATA_SIZE: usize = 1232;
+
+const SYSTEM_CREATE_ACCOUNT_DISCRIMINANT: u32 = 0;
+const SYSTEM_ASSIGN_DISCRIMINANT: u32 = 1;
+const SYSTEM_TRANSFER_DISCRIMINANT: u32 = 2;
+
+fn wire_error(message: impl std::fmt::Display) -> TerminatorError {
+    TerminatorError::SerializationError(format!("Invalid wire transaction: {}", message))
+}
+
+/// Append `len` as a compact-u16: 7 bits per byte, low bits first
+fn write_compact_u16(data: &mut Vec<u8>, len: usize) -> Result<()> {
+    let mut value = u16::try_from(len).map_err(|_| wire_error(format!("length {} exceeds u16", len)))?;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            data.push(byte);
+            return Ok(());
+        }
+        data.push(byte | 0x80);
+    }
+}
+
+struct WireReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> WireReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.offset
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.remaining() < len {
+            return Err(wire_error("unexpected end of data"));
+        }
+        let bytes = &self.data[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.read_bytes(N)?.try_into().unwrap())
+    }
+
+    fn read_compact_u16(&mut self) -> Result<usize> {
+        let mut value = 0usize;
+        for shift in [0, 7, 14] {
+            let byte = self.read_u8()?;
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return u16::try_from(value)
+                    .map(usize::from)
+                    .map_err(|_| wire_error("compact-u16 overflow"));
+            }
+        }
+        Err(wire_error("compact-u16 longer than 3 bytes"))
+    }
+
+    fn read_message(&mut self) -> Result<SolanaMessage> {
+        let num_required_signatures = self.read_u8()?;
+        if num_required_signatures & 0x80 != 0 {
+            return Err(wire_error(format!(
+                "versioned message v{} is not supported",
+                num_required_signatures & 0x7f
+            )));
+        }
+        let header = MessageHeader {
+            num_required_signatures,
+            num_readonly_signed_accounts: self.read_u8()?,
+            num_readonly_unsigned_accounts: self.read_u8()?,
+        };
+        let key_count = self.read_compact_u16()?;
+        let mut account_keys = Vec::with_capacity(key_count);
+        for _ in 0..key_count {
+            account_keys.push(SolanaPubkey(self.read_array()?));
+        }
+        let recent_blockhash = SolanaHash(self.read_array()?);
+        let instruction_count = self.read_compact_u16()?;
+        let mut instructions = Vec::with_capacity(instruction_count);
+        for _ in 0..instruction_count {
+            let program_id_index = self.read_u8()?;
+            let account_count = self.read_compact_u16()?;
+            let accounts = self.read_bytes(account_count)?.to_vec();
+            let data_len = self.read_compact_u16()?;
+            let data = self.read_bytes(data_len)?.to_vec();
+            instructions.push(CompiledInstruction { program_id_index, accounts, data });
+        }
+        Ok(SolanaMessage { header, account_keys, recent_blockhash, instructions })
+    }
+}
+
+impl SolanaMessage {
+    pub fn is_signer(&self, index: usize) -> bool {
+        index < self.header.num_required_signatures as usize
+    }
+
+    pub fn is_writable(&self, index: usize) -> bool {
+        let num_signers = self.header.num_required_signatures as usize;
+        if index < num_signers {
+            index < num_signers.saturating_sub(self.header.num_readonly_signed_accounts as usize)
+        } else {
+            index < self.account_keys.len().saturating_sub(self.header.num_readonly_unsigned_accounts as usize)
+        }
+    }
+}
+
+impl SolanaTransaction {
+    /// The first signature, which identifies the transaction
+    pub fn signature(&self) -> Option<[u8; 64]> {
+        self.signatures.first().map(|signature| signature.0)
+    }
+
+    /// Check every signature against the signer at the same position in the account keys
+    pub fn verify_signatures(&self) -> bool {
+        let Ok(message) = SolanaTransactionParser::message_data(&self.message) else {
+            return false;
+        };
+        self.signatures.len() == self.message.header.num_required_signatures as usize
+            && self
+                .signatures
+                .iter()
+                .zip(&self.message.account_keys)
+                .all(|(signature, key)| {
+                    crate::crypto::SolanaCrypto::verify_ed25519_signature(&signature.0, &message, &key.0).unwrap_or(false)
+                })
+    }
+
+    /// The transaction in the runtime's representation. System program instructions are decoded
+    /// into their typed instruction data.
+    pub fn to_transaction(&self) -> Result<Transaction> {
+        SolanaTransactionParser::validate_transaction_format(self)?;
+        let message = &self.message;
+        let payer = message
+            .account_keys
+            .first()
+            .ok_or_else(|| wire_error("transaction has no fee payer"))?;
+        let instructions = message
+            .instructions
+            .iter()
+            .map(|instruction| {
+                let program_id = Pubkey::new(message.account_keys[instruction.program_id_index as usize].0);
+                let accounts: Vec<AccountMeta> = instruction
+                    .accounts
+                    .iter()
+                    .map(|&index| AccountMeta {
+                        pubkey: Pubkey::new(message.account_keys[index as usize].0),
+                        is_signer: message.is_signer(index as usize),
+                        is_writable: message.is_writable(index as usize),
+                    })
+                    .collect();
+                let data = decode_instruction_data(&program_id, &accounts, &instruction.data);
+                Instruction { program_id, accounts, data }
+            })
+            .collect();
+        Ok(Transaction {
+            instructions,
+            signatures: self.signatures.iter().map(|signature| signature.0).collect(),
+            payer: payer.0,
+            recent_blockhash: message.recent_blockhash.0,
+        })
+    }
+
+    /// Compile a runtime transaction into a legacy message: signers first, then writable
+    /// accounts before read-only ones, with the payer leading
+    pub fn from_transaction(txn: &Transaction) -> SolanaTransaction {
+        // (key, signer, writable) in order of first use
+        let mut keys: Vec<(Pubkey, bool, bool)> = vec![(Pubkey::new(txn.payer), true, true)];
+        let mut add = |pubkey: Pubkey, signer: bool, writable: bool| match keys.iter_mut().find(|(key, _, _)| *key == pubkey) {
+            Some((_, is_signer, is_writable)) => {
+                *is_signer |= signer;
+                *is_writable |= writable;
+            }
+            None => keys.push((pubkey, signer, writable)),
+        };
+        for instruction in &txn.instructions {
+            for meta in &instruction.accounts {
+                add(meta.pubkey, meta.is_signer, meta.is_writable);
+            }
+            for pubkey in instruction.data.accounts() {
+                add(pubkey, false, true);
+            }
+            add(instruction.program_id, false, false);
+        }
+        keys.sort_by_key(|(_, signer, writable)| (!signer, !writable));
+
+        let index_of = |pubkey: &Pubkey| keys.iter().position(|(key, _, _)| key == pubkey).unwrap() as u8;
+        let instructions = txn
+            .instructions
+            .iter()
+            .map(|instruction| {
+                let accounts = if instruction.accounts.is_empty() {
+                    instruction.data.accounts().iter().map(index_of).collect()
+                } else {
+                    instruction.accounts.iter().map(|meta| index_of(&meta.pubkey)).collect()
+                };
+                CompiledInstruction {
+                    program_id_index: index_of(&instruction.program_id),
+                    accounts,
+                    data: encode_instruction_data(&instruction.data),
+                }
+            })
+            .collect();
+
+        let count = |signer: bool, writable: bool| {
+            keys.iter().filter(|(_, is_signer, is_writable)| *is_signer == signer && *is_writable == writable).count() as u8
+        };
+        let header = MessageHeader {
+            num_required_signatures: count(true, true) + count(true, false),
+            num_readonly_signed_accounts: count(true, false),
+            num_readonly_unsigned_accounts: count(false, false),
+        };
+        SolanaTransaction {
+            signatures: txn.signatures.iter().map(|signature| SolanaSignature(*signature)).collect(),
+            message: SolanaMessage {
+                header,
+                account_keys: keys.iter().map(|(key, _, _)| SolanaPubkey(key.0)).collect(),
+                recent_blockhash: SolanaHash(txn.recent_blockhash),
+                instructions,
+            },
+        }
+    }
+}
+
+/// Typed instruction data for the system instructions the runtime models, raw data otherwise
+fn decode_instruction_data(program_id: &Pubkey, accounts: &[AccountMeta], data: &[u8]) -> InstructionData {
+    let generic = || InstructionData::Generic { data: data.to_vec() };
+    if *program_id != Pubkey::system_program() || data.len() < 4 {
+        return generic();
+    }
+    let u64_at = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+    let key = |index: usize| accounts[index].pubkey.0;
+    match u32::from_le_bytes(data[..4].try_into().unwrap()) {
+        SYSTEM_CREATE_ACCOUNT_DISCRIMINANT if data.len() == 52 && accounts.len() >= 2 => InstructionData::CreateAccount {
+            from: key(0),
+            to: key(1),
+            lamports: u64_at(4),
+            space: u64_at(12),
+            owner: data[20..52].try_into().unwrap(),
+        },
+        SYSTEM_ASSIGN_DISCRIMINANT if data.len() == 36 && !accounts.is_empty() => InstructionData::Assign {
+            account: key(0),
+            owner: data[4..36].try_into().unwrap(),
+        },
+        SYSTEM_TRANSFER_DISCRIMINANT if data.len() == 12 && accounts.len() >= 2 => InstructionData::Transfer {
+            from: key(0),
+            to: key(1),
+            lamports: u64_at(4),
+        },
+        _ => generic(),
+    }
+}
+
+fn encode_instruction_data(data: &InstructionData) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    match data {
+        InstructionData::CreateAccount { lamports, space, owner, .. } => {
+            encoded.extend_from_slice(&SYSTEM_CREATE_ACCOUNT_DISCRIMINANT.to_le_bytes());
+            encoded.extend_from_slice(&lamports.to_le_bytes());
+            encoded.extend_from_slice(&space.to_le_bytes());
+            encoded.extend_from_slice(owner);
+        }
+        InstructionData::Assign { owner, .. } => {
+            encoded.extend_from_slice(&SYSTEM_ASSIGN_DISCRIMINANT.to_le_bytes());
+            encoded.extend_from_slice(owner);
+        }
+        InstructionData::Transfer { lamports, .. } => {
+            encoded.extend_from_slice(&SYSTEM_TRANSFER_DISCRIMINANT.to_le_bytes());
+            encoded.extend_from_slice(&lamports.to_le_bytes());
+        }
+        InstructionData::Generic { data } => encoded.extend_from_slice(data),
+    }
+    encoded
+}
+
 /// Advanced Solana features
 pub struct SolanaFeatures;
 
 impl SolanaFeatures {
     /// Create a Program Derived Address instruction
     pub fn create_pda_instruction(
-        program_id: SolanaPubkey,
+        _program_id: SolanaPubkey,
         seeds: &[&[u8]],
-        payer: SolanaPubkey,
+        _payer: SolanaPubkey,
     ) -> Result<CompiledInstruction> {
         // Simplified PDA creation instruction
         let mut instruction_data = vec![0u8]; // CreateAccount instruction
@@ -243,6 +562,29 @@ impl SolanaFeatures {
     }
 }
 
+#[cfg(test)]
+impl SolanaTransactionParser {
+    /// A wire-format transfer signed by `from`
+    pub(crate) fn signed_transfer_for_test(
+        from: &ed25519_dalek::SigningKey,
+        to: Pubkey,
+        lamports: u64,
+        recent_blockhash: [u8; 32],
+    ) -> Vec<u8> {
+        use ed25519_dalek::Signer;
+
+        let mut wire = Self::create_transfer_transaction(
+            SolanaPubkey::new(from.verifying_key().to_bytes()),
+            SolanaPubkey::new(to.0),
+            lamports,
+            SolanaHash(recent_blockhash),
+        );
+        let message = Self::message_data(&wire.message).unwrap();
+        wire.signatures = vec![SolanaSignature(from.sign(&message).to_bytes())];
+        Self::serialize_transaction(&wire).unwrap()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -303,6 +645,52 @@ mod tests {
         assert_eq!(tx.message.account_keys.len(), parsed.message.account_keys.len());
     }
 
+    #[test]
+    fn test_wire_format() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let signing_key = SigningKey::from_bytes(&[1u8; 32]);
+        let from = SolanaPubkey::new(signing_key.verifying_key().to_bytes());
+        let to = SolanaPubkey::new([2u8; 32]);
+        let mut tx = SolanaTransactionParser::create_transfer_transaction(from, to, 1_000_000, SolanaHash([3u8; 32]));
+        let message = SolanaTransactionParser::message_data(&tx.message).unwrap();
+        assert_eq!(&message[..4], &[1, 0, 1, 3]);
+        assert_eq!(message.len(), 3 + 1 + 3 * 32 + 32 + 1 + 1 + 1 + 2 + 1 + 12);
+        tx.signatures = vec![SolanaSignature(signing_key.sign(&message).to_bytes())];
+        assert!(tx.verify_signatures());
+
+        let wire = SolanaTransactionParser::serialize_transaction(&tx).unwrap();
+        assert_eq!(wire[0], 1);
+        assert_eq!(&wire[65..], message.as_slice());
+        let parsed = SolanaTransactionParser::parse_transaction(&wire).unwrap();
+        assert!(parsed.verify_signatures());
+        assert!(SolanaTransactionParser::parse_transaction(&wire[..wire.len() - 1]).is_err());
+        let mut trailing = wire.clone();
+        trailing.push(0);
+        assert!(SolanaTransactionParser::parse_transaction(&trailing).is_err());
+        let mut versioned = wire.clone();
+        versioned[65] = 0x80;
+        assert!(SolanaTransactionParser::parse_transaction(&versioned).is_err());
+
+        // Lengths past 127 take two bytes
+        let mut data = Vec::new();
+        write_compact_u16(&mut data, 300).unwrap();
+        assert_eq!(data, vec![0xac, 0x02]);
+        assert_eq!(WireReader::new(&data).read_compact_u16().unwrap(), 300);
+
+        let txn = parsed.to_transaction().unwrap();
+        assert_eq!(txn.payer, from.0);
+        assert!(matches!(
+            txn.instructions[0].data,
+            InstructionData::Transfer { lamports: 1_000_000, to, .. } if to == [2u8; 32]
+        ));
+        assert!(txn.instructions[0].accounts[0].is_signer && txn.instructions[0].accounts[1].is_writable);
+
+        // Compiling the runtime transaction gives back the signed message
+        let compiled = SolanaTransaction::from_transaction(&txn);
+        assert_eq!(SolanaTransactionParser::message_data(&compiled.message).unwrap(), message);
+    }
+
     #[test]
     fn test_system_program_ids() {
         let system = SolanaPubkey::system_program();
@@ -0,0 +1,464 @@
+//! Loader for Solana's own full snapshot archives (`snapshot-<slot>-<hash>.tar.zst`), to run
+//! against real cluster state.
+//!
+//! An archive holds the bank fields of the snapshot slot in `snapshots/<slot>/<slot>` and the
+//! account storages in `accounts/<slot>.<id>`. Only the leading bank fields are decoded, up to the
+//! stakes; storages are scanned up to their first empty entry rather than the lengths recorded
+//! after the stakes.
+
+use crate::accounts_hash;
+use crate::blockhash_queue::BlockhashQueue;
+use crate::genesis::{FeeRateGovernor, Inflation};
+use crate::snapshot::{Snapshot, SnapshotSysvars};
+use crate::sysvar::{EpochSchedule, Rent};
+use crate::types::*;
+use crate::{Result, TerminatorError};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Component, Path};
+use tracing::{debug, info};
+
+/// `StoredMeta`: write version, data length and pubkey
+const STORED_META_SIZE: usize = 48;
+/// `AccountMeta`: lamports, rent epoch, owner and the executable flag padded to 8 bytes
+const ACCOUNT_META_SIZE: usize = 56;
+/// Obsolete per-account hash following the metadata
+const ACCOUNT_HASH_SIZE: usize = 32;
+const STORED_ACCOUNT_HEADER_SIZE: usize = STORED_META_SIZE + ACCOUNT_META_SIZE + ACCOUNT_HASH_SIZE;
+/// Largest account Solana allows
+const MAX_PERMITTED_DATA_LENGTH: u64 = 10 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SolanaHashInfo {
+    pub lamports_per_signature: u64,
+    pub hash_index: u64,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SolanaBlockhashQueue {
+    pub last_hash_index: u64,
+    pub last_hash: Option<[u8; 32]>,
+    pub ages: HashMap<[u8; 32], SolanaHashInfo>,
+    pub max_age: usize,
+}
+
+impl From<&SolanaBlockhashQueue> for BlockhashQueue {
+    fn from(queue: &SolanaBlockhashQueue) -> Self {
+        let mut ages: Vec<_> = queue.ages.iter().collect();
+        ages.sort_unstable_by_key(|(_, info)| info.hash_index);
+        let mut blockhash_queue = BlockhashQueue::new(queue.max_age);
+        for (hash, info) in ages {
+            blockhash_queue.register_hash(*hash, info.lamports_per_signature);
+        }
+        blockhash_queue
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RentCollector {
+    pub epoch: u64,
+    pub epoch_schedule: EpochSchedule,
+    pub slots_per_year: f64,
+    pub rent: Rent,
+}
+
+/// Leading fields of a snapshot's bank, in their serialized order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SolanaBankFields {
+    pub blockhash_queue: SolanaBlockhashQueue,
+    pub ancestors: HashMap<u64, usize>,
+    pub hash: [u8; 32],
+    pub parent_hash: [u8; 32],
+    pub parent_slot: u64,
+    pub hard_forks: Vec<(u64, usize)>,
+    pub transaction_count: u64,
+    pub tick_height: u64,
+    pub signature_count: u64,
+    pub capitalization: u64,
+    pub max_tick_height: u64,
+    pub hashes_per_tick: Option<u64>,
+    pub ticks_per_slot: u64,
+    pub ns_per_slot: u128,
+    pub genesis_creation_time: i64,
+    pub slots_per_year: f64,
+    pub accounts_data_len: u64,
+    pub slot: u64,
+    pub epoch: u64,
+    pub block_height: u64,
+    pub collector_id: [u8; 32],
+    pub collector_fees: u64,
+    pub lamports_per_signature: u64,
+    pub fee_rate_governor: FeeRateGovernor,
+    pub collected_rent: u64,
+    pub rent_collector: RentCollector,
+    pub epoch_schedule: EpochSchedule,
+    pub inflation: Inflation,
+}
+
+/// Which accounts to keep when loading a snapshot. An empty filter keeps every account, otherwise
+/// an account is kept if its owner or its address is listed.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotFilter {
+    pub owners: HashSet<Pubkey>,
+    pub pubkeys: HashSet<Pubkey>,
+}
+
+impl SnapshotFilter {
+    pub fn with_owner(mut self, owner: Pubkey) -> Self {
+        self.owners.insert(owner);
+        self
+    }
+
+    pub fn with_pubkey(mut self, pubkey: Pubkey) -> Self {
+        self.pubkeys.insert(pubkey);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.owners.is_empty() && self.pubkeys.is_empty()
+    }
+
+    pub fn matches(&self, pubkey: &Pubkey, account: &Account) -> bool {
+        self.is_empty() || self.pubkeys.contains(pubkey) || self.owners.contains(&Pubkey::new(account.owner))
+    }
+
+    /// Whether any version of `pubkey` can match, before looking at the account
+    fn may_match(&self, pubkey: &Pubkey) -> bool {
+        !self.owners.is_empty() || self.pubkeys.is_empty() || self.pubkeys.contains(pubkey)
+    }
+}
+
+/// An account as stored in an append-vec
+#[derive(Debug, Clone)]
+pub struct StoredAccount {
+    pub pubkey: Pubkey,
+    pub write_version: u64,
+    pub account: Account,
+}
+
+/// Accounts stored in the append-vec `bytes`, up to the first empty or truncated entry
+pub fn read_append_vec(bytes: &[u8]) -> Vec<StoredAccount> {
+    let u64_at = |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+    let mut accounts = Vec::new();
+    let mut offset = 0;
+    while offset + STORED_ACCOUNT_HEADER_SIZE <= bytes.len() {
+        let write_version = u64_at(offset);
+        let data_len = u64_at(offset + 8);
+        let pubkey: [u8; 32] = bytes[offset + 16..offset + 48].try_into().unwrap();
+        let meta = offset + STORED_META_SIZE;
+        let lamports = u64_at(meta);
+        let data_start = offset + STORED_ACCOUNT_HEADER_SIZE;
+        // Storages are zero-filled past their last entry
+        if pubkey == [0u8; 32] && lamports == 0 && data_len == 0 {
+            break;
+        }
+        if data_len > MAX_PERMITTED_DATA_LENGTH || data_start + data_len as usize > bytes.len() {
+            break;
+        }
+        let data_end = data_start + data_len as usize;
+        accounts.push(StoredAccount {
+            pubkey: Pubkey::new(pubkey),
+            write_version,
+            account: Account {
+                lamports,
+                rent_epoch: u64_at(meta + 8),
+                owner: bytes[meta + 16..meta + 48].try_into().unwrap(),
+                executable: bytes[meta + 48] != 0,
+                data: bytes[data_start..data_end].to_vec(),
+            },
+        });
+        offset = (data_end + 7) & !7;
+    }
+    accounts
+}
+
+/// Accounts and bank fields of a Solana snapshot
+#[derive(Debug, Clone)]
+pub struct SolanaSnapshot {
+    pub bank_fields: SolanaBankFields,
+    /// Latest version of every existing account that passed the filter
+    pub accounts: HashMap<Pubkey, Account>,
+}
+
+impl SolanaSnapshot {
+    pub fn slot(&self) -> u64 {
+        self.bank_fields.slot
+    }
+
+    pub fn bank_state(&self) -> BankState {
+        BankState {
+            accounts: self.accounts.clone(),
+            slot: self.bank_fields.slot,
+            blockhash: self.bank_fields.blockhash_queue.last_hash.unwrap_or_default(),
+            bank_hash: self.bank_fields.hash,
+            fee_calculator: FeeCalculator {
+                lamports_per_signature: self.bank_fields.lamports_per_signature,
+            },
+        }
+    }
+
+    /// The snapshot in the runtime's own format, to restore with
+    /// [`TerminatorRuntime::restore_snapshot`](crate::TerminatorRuntime::restore_snapshot). The
+    /// accounts hash covers the loaded accounts only.
+    pub fn into_snapshot(self) -> Snapshot {
+        let state = self.bank_state();
+        let mut accounts: Vec<_> = self.accounts.into_iter().collect();
+        accounts.sort_unstable_by_key(|(pubkey, _)| pubkey.0);
+        let lt_hash = accounts_hash::calculate_accounts_lt_hash(accounts.iter().map(|(pubkey, account)| (pubkey, account)));
+        Snapshot {
+            slot: state.slot,
+            blockhash: state.blockhash,
+            bank_hash: state.bank_hash,
+            fee_calculator: state.fee_calculator,
+            blockhash_queue: BlockhashQueue::from(&self.bank_fields.blockhash_queue),
+            sysvars: SnapshotSysvars {
+                rent: self.bank_fields.rent_collector.rent,
+                epoch_schedule: self.bank_fields.epoch_schedule,
+                last_restart_slot: 0,
+            },
+            base: None,
+            accounts,
+            accounts_hash: lt_hash.checksum(),
+        }
+    }
+}
+
+/// Where an archive entry belongs
+enum ArchiveEntry {
+    BankFields,
+    Storage { slot: u64 },
+    Other,
+}
+
+fn classify_entry(path: &Path) -> ArchiveEntry {
+    let components: Vec<_> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect();
+    match components.as_slice() {
+        ["snapshots", slot, file] if slot == file && slot.parse::<u64>().is_ok() => ArchiveEntry::BankFields,
+        ["accounts", file] => match file.split_once('.').and_then(|(slot, _)| slot.parse().ok()) {
+            Some(slot) => ArchiveEntry::Storage { slot },
+            None => ArchiveEntry::Other,
+        },
+        _ => ArchiveEntry::Other,
+    }
+}
+
+/// Load the zstd-compressed snapshot archive at `path`, keeping the accounts `filter` matches
+pub fn load_solana_snapshot(path: impl AsRef<Path>, filter: &SnapshotFilter) -> Result<SolanaSnapshot> {
+    let file = File::open(path.as_ref()).map_err(snapshot_error)?;
+    let decoder = zstd::Decoder::new(BufReader::new(file)).map_err(snapshot_error)?;
+    let mut archive = tar::Archive::new(decoder);
+
+    let mut bank_fields = None;
+    // Newest version of every account seen, by (slot, write version); `None` if it was closed or
+    // filtered out, so that it still hides older versions
+    let mut latest: HashMap<Pubkey, (u64, u64, Option<Account>)> = HashMap::new();
+    let mut storages = 0usize;
+
+    for entry in archive.entries().map_err(snapshot_error)? {
+        let mut entry = entry.map_err(snapshot_error)?;
+        let entry_path = entry.path().map_err(snapshot_error)?.into_owned();
+        match classify_entry(&entry_path) {
+            ArchiveEntry::BankFields => {
+                let mut bytes = Vec::new();
+                entry.read_to_end(&mut bytes).map_err(snapshot_error)?;
+                // The fields past the ones decoded are left unread
+                let fields: SolanaBankFields = bincode::deserialize(&bytes)
+                    .map_err(|e| TerminatorError::SerializationError(e.to_string()))?;
+                bank_fields = Some(fields);
+            }
+            ArchiveEntry::Storage { slot } => {
+                let mut bytes = Vec::new();
+                entry.read_to_end(&mut bytes).map_err(snapshot_error)?;
+                storages += 1;
+                for stored in read_append_vec(&bytes) {
+                    if !filter.may_match(&stored.pubkey) {
+                        continue;
+                    }
+                    let version = (slot, stored.write_version);
+                    if let Some((newest_slot, newest_write_version, _)) = latest.get(&stored.pubkey) {
+                        if (*newest_slot, *newest_write_version) > version {
+                            continue;
+                        }
+                    }
+                    let keep = stored.account.lamports > 0 && filter.matches(&stored.pubkey, &stored.account);
+                    latest.insert(stored.pubkey, (slot, stored.write_version, keep.then_some(stored.account)));
+                }
+            }
+            ArchiveEntry::Other => debug!("Skipping snapshot entry {}", entry_path.display()),
+        }
+    }
+
+    let bank_fields = bank_fields.ok_or_else(|| snapshot_error("archive has no bank fields"))?;
+    let accounts: HashMap<_, _> = latest
+        .into_iter()
+        .filter_map(|(pubkey, (_, _, account))| Some((pubkey, account?)))
+        .collect();
+    info!(
+        "Loaded {} accounts from {} storages of the snapshot of slot {}",
+        accounts.len(),
+        storages,
+        bank_fields.slot
+    );
+    Ok(SolanaSnapshot { bank_fields, accounts })
+}
+
+fn snapshot_error(e: impl ToString) -> TerminatorError {
+    TerminatorError::SnapshotError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWNER_A: [u8; 32] = [0xA0; 32];
+    const OWNER_B: [u8; 32] = [0xB0; 32];
+
+    fn append_vec(accounts: &[(u8, u64, [u8; 32], &[u8])]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (write_version, &(key, lamports, owner, data)) in accounts.iter().enumerate() {
+            bytes.extend_from_slice(&(write_version as u64).to_le_bytes());
+            bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(&[key; 32]);
+            bytes.extend_from_slice(&lamports.to_le_bytes());
+            bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+            bytes.extend_from_slice(&owner);
+            bytes.extend_from_slice(&[0u8; 8]);
+            bytes.extend_from_slice(&[0u8; ACCOUNT_HASH_SIZE]);
+            bytes.extend_from_slice(data);
+            bytes.resize((bytes.len() + 7) & !7, 0);
+        }
+        // Unused capacity
+        bytes.resize(bytes.len() + 512, 0);
+        bytes
+    }
+
+    fn bank_fields(slot: u64) -> SolanaBankFields {
+        SolanaBankFields {
+            blockhash_queue: SolanaBlockhashQueue {
+                last_hash_index: 2,
+                last_hash: Some([2u8; 32]),
+                ages: HashMap::from([
+                    ([1u8; 32], SolanaHashInfo { lamports_per_signature: 5000, hash_index: 1, timestamp: 0 }),
+                    ([2u8; 32], SolanaHashInfo { lamports_per_signature: 5000, hash_index: 2, timestamp: 0 }),
+                ]),
+                max_age: 300,
+            },
+            ancestors: HashMap::from([(slot, 0)]),
+            hash: [7u8; 32],
+            parent_hash: [6u8; 32],
+            parent_slot: slot - 1,
+            hard_forks: vec![],
+            transaction_count: 42,
+            tick_height: slot * 64,
+            signature_count: 3,
+            capitalization: 1_000,
+            max_tick_height: (slot + 1) * 64,
+            hashes_per_tick: Some(12_500),
+            ticks_per_slot: 64,
+            ns_per_slot: 400_000_000,
+            genesis_creation_time: 0,
+            slots_per_year: 78_892_314.0,
+            accounts_data_len: 0,
+            slot,
+            epoch: 0,
+            block_height: slot,
+            collector_id: [9u8; 32],
+            collector_fees: 0,
+            lamports_per_signature: 5000,
+            fee_rate_governor: FeeRateGovernor {
+                target_lamports_per_signature: 10_000,
+                target_signatures_per_slot: 20_000,
+                min_lamports_per_signature: 5_000,
+                max_lamports_per_signature: 100_000,
+                burn_percent: 50,
+            },
+            collected_rent: 0,
+            rent_collector: RentCollector {
+                epoch: 0,
+                epoch_schedule: EpochSchedule::default(),
+                slots_per_year: 78_892_314.0,
+                rent: Rent::default(),
+            },
+            epoch_schedule: EpochSchedule::default(),
+            inflation: Inflation { initial: 0.08, terminal: 0.015, taper: 0.15, foundation: 0.05, foundation_term: 7.0, unused: 0.0 },
+        }
+    }
+
+    fn write_archive(path: &Path) {
+        let mut manifest = bincode::serialize(&bank_fields(10)).unwrap();
+        // Stakes and the rest of the manifest follow
+        manifest.extend_from_slice(&[0xAB; 64]);
+        let files: Vec<(&str, Vec<u8>)> = vec![
+            ("version", b"1.2.0".to_vec()),
+            ("snapshots/10/10", manifest),
+            ("accounts/5.1", append_vec(&[(1, 100, OWNER_A, b"a"), (2, 200, OWNER_B, b"bb"), (3, 300, OWNER_A, &[])])),
+            // Account 1 updated and account 3 closed in a later slot
+            ("accounts/10.2", append_vec(&[(1, 150, OWNER_A, b"aaa"), (3, 0, [0u8; 32], &[])])),
+        ];
+
+        let encoder = zstd::Encoder::new(File::create(path).unwrap(), 3).unwrap();
+        let mut builder = tar::Builder::new(encoder);
+        for (name, bytes) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(bytes.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, bytes.as_slice()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_load_solana_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("snapshot-10-7.tar.zst");
+        write_archive(&path);
+
+        let snapshot = load_solana_snapshot(&path, &SnapshotFilter::default()).unwrap();
+        assert_eq!(snapshot.slot(), 10);
+        assert_eq!(snapshot.bank_fields.hash, [7u8; 32]);
+        assert_eq!(snapshot.accounts.len(), 2);
+        let updated = &snapshot.accounts[&Pubkey::new([1u8; 32])];
+        assert_eq!((updated.lamports, updated.data.as_slice(), updated.rent_epoch), (150, &b"aaa"[..], u64::MAX));
+        assert_eq!(snapshot.accounts[&Pubkey::new([2u8; 32])].lamports, 200);
+        assert!(!snapshot.accounts.contains_key(&Pubkey::new([3u8; 32])));
+
+        let state = snapshot.bank_state();
+        assert_eq!(state.blockhash, [2u8; 32]);
+        assert_eq!(state.fee_calculator.lamports_per_signature, 5000);
+        let converted = snapshot.into_snapshot();
+        converted.verify().unwrap();
+        assert!(converted.blockhash_queue.is_hash_valid(&[1u8; 32]));
+    }
+
+    #[test]
+    fn test_filtered_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("snapshot-10-7.tar.zst");
+        write_archive(&path);
+
+        // The closed account no longer belongs to the owner, its old version must not resurface
+        let by_owner = load_solana_snapshot(&path, &SnapshotFilter::default().with_owner(Pubkey::new(OWNER_A))).unwrap();
+        assert_eq!(by_owner.accounts.keys().collect::<Vec<_>>(), vec![&Pubkey::new([1u8; 32])]);
+
+        let by_pubkey = load_solana_snapshot(&path, &SnapshotFilter::default().with_pubkey(Pubkey::new([2u8; 32]))).unwrap();
+        assert_eq!(by_pubkey.accounts.keys().collect::<Vec<_>>(), vec![&Pubkey::new([2u8; 32])]);
+
+        let missing = dir.path().join("empty.tar.zst");
+        let encoder = zstd::Encoder::new(File::create(&missing).unwrap(), 3).unwrap();
+        tar::Builder::new(encoder).into_inner().unwrap().finish().unwrap();
+        assert!(matches!(
+            load_solana_snapshot(&missing, &SnapshotFilter::default()),
+            Err(TerminatorError::SnapshotError(_))
+        ));
+    }
+}
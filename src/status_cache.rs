@@ -0,0 +1,144 @@
+use crate::transaction_error::TransactionError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Number of slots a blockhash stays in the cache, matching Solana's `MAX_CACHE_ENTRIES`
+pub const MAX_CACHE_ENTRIES: u64 = 300;
+
+/// Outcome of a processed transaction as recorded in the status cache
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionStatus {
+    pub slot: u64,
+    pub error: Option<TransactionError>,
+}
+
+impl TransactionStatus {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct BlockhashStatuses {
+    /// Highest slot a transaction referencing this blockhash was recorded in
+    max_slot: u64,
+    statuses: HashMap<[u8; 32], TransactionStatus>,
+}
+
+/// Recently processed transactions, grouped by the blockhash they reference
+#[derive(Debug, Clone, Default)]
+pub struct StatusCache {
+    blockhashes: HashMap<[u8; 32], BlockhashStatuses>,
+    signatures: HashMap<[u8; 64], ([u8; 32], [u8; 32])>,
+}
+
+impl StatusCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Look up the status of a transaction by its blockhash and message hash
+    pub fn get_status(&self, blockhash: &[u8; 32], message_hash: &[u8; 32]) -> Option<&TransactionStatus> {
+        self.blockhashes
+            .get(blockhash)
+            .and_then(|entry| entry.statuses.get(message_hash))
+    }
+
+    /// Look up the status of a past transaction by its first signature
+    pub fn get_signature_status(&self, signature: &[u8; 64]) -> Option<&TransactionStatus> {
+        let (blockhash, message_hash) = self.signatures.get(signature)?;
+        self.get_status(blockhash, message_hash)
+    }
+
+    /// Record a processed transaction
+    pub fn insert(
+        &mut self,
+        blockhash: [u8; 32],
+        message_hash: [u8; 32],
+        signature: Option<[u8; 64]>,
+        status: TransactionStatus,
+    ) {
+        let entry = self.blockhashes.entry(blockhash).or_default();
+        entry.max_slot = entry.max_slot.max(status.slot);
+        entry.statuses.insert(message_hash, status);
+
+        if let Some(signature) = signature {
+            self.signatures.insert(signature, (blockhash, message_hash));
+        }
+    }
+
+    /// Drop every blockhash that has not been referenced within `MAX_CACHE_ENTRIES` slots
+    pub fn purge(&mut self, current_slot: u64) {
+        self.blockhashes
+            .retain(|_, entry| entry.max_slot + MAX_CACHE_ENTRIES >= current_slot);
+
+        let blockhashes = &self.blockhashes;
+        self.signatures
+            .retain(|_, (blockhash, _)| blockhashes.contains_key(blockhash));
+    }
+
+    /// Drop the statuses recorded in `slots`, e.g. when their forks are discarded
+    pub fn remove_slots(&mut self, slots: &[u64]) {
+        for entry in self.blockhashes.values_mut() {
+            entry.statuses.retain(|_, status| !slots.contains(&status.slot));
+        }
+        self.blockhashes.retain(|_, entry| !entry.statuses.is_empty());
+
+        let blockhashes = &self.blockhashes;
+        self.signatures.retain(|_, (blockhash, message_hash)| {
+            blockhashes
+                .get(blockhash)
+                .is_some_and(|entry| entry.statuses.contains_key(message_hash))
+        });
+    }
+
+    /// Number of cached transaction statuses
+    pub fn len(&self) -> usize {
+        self.blockhashes.values().map(|entry| entry.statuses.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blockhashes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ok_status(slot: u64) -> TransactionStatus {
+        TransactionStatus { slot, error: None }
+    }
+
+    #[test]
+    fn test_insert_and_lookup() {
+        let mut cache = StatusCache::new();
+        let blockhash = [1u8; 32];
+        let message_hash = [2u8; 32];
+        let signature = [3u8; 64];
+
+        cache.insert(blockhash, message_hash, Some(signature), ok_status(5));
+
+        assert_eq!(cache.get_status(&blockhash, &message_hash), Some(&ok_status(5)));
+        assert_eq!(cache.get_status(&[9u8; 32], &message_hash), None);
+        assert_eq!(cache.get_signature_status(&signature), Some(&ok_status(5)));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_purge_expired_blockhashes() {
+        let mut cache = StatusCache::new();
+        let old_signature = [3u8; 64];
+        cache.insert([1u8; 32], [2u8; 32], Some(old_signature), ok_status(0));
+        cache.insert([4u8; 32], [5u8; 32], None, ok_status(100));
+
+        cache.purge(MAX_CACHE_ENTRIES);
+        assert_eq!(cache.len(), 2);
+
+        cache.purge(MAX_CACHE_ENTRIES + 1);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get_status(&[1u8; 32], &[2u8; 32]).is_none());
+        assert!(cache.get_signature_status(&old_signature).is_none());
+        assert!(cache.get_status(&[4u8; 32], &[5u8; 32]).is_some());
+    }
+}
@@ -0,0 +1,591 @@
+//! Host functions available to on-chain programs, with Solana's compute unit costs.
+
+use crate::cpi;
+use crate::crypto::{AddressDerivation, SolanaCrypto, MAX_SEEDS};
+use crate::invoke_context::InvokeContext;
+use crate::sbpf::{ContextObject, EbpfError, MemoryMapping, SyscallRegistry};
+use crate::sysvar::{Clock, EpochSchedule, Rent};
+use crate::TerminatorError;
+use base64::Engine;
+
+pub(crate) type SyscallResult = Result<u64, EbpfError>;
+
+/// Default per-syscall compute unit costs
+pub mod costs {
+    pub const SYSCALL_BASE_COST: u64 = 100;
+    pub const LOG_64_UNITS: u64 = 100;
+    pub const LOG_PUBKEY_UNITS: u64 = 100;
+    pub const SHA256_BASE_COST: u64 = 85;
+    pub const SHA256_BYTE_COST: u64 = 1;
+    pub const SHA256_MAX_SLICES: u64 = 20_000;
+    pub const CREATE_PROGRAM_ADDRESS_UNITS: u64 = 1500;
+    pub const MEM_OP_BASE_COST: u64 = 10;
+    pub const CPI_BYTES_PER_UNIT: u64 = 250;
+    pub const SECP256K1_RECOVER_COST: u64 = 25_000;
+    pub const SYSVAR_BASE_COST: u64 = 100;
+    pub const INVOKE_UNITS: u64 = 1000;
+}
+
+/// Largest return data a program may set
+pub const MAX_RETURN_DATA: usize = 1024;
+
+/// Status codes returned by `sol_secp256k1_recover`
+const SECP256K1_RECOVER_INVALID_RECOVERY_ID: u64 = 2;
+const SECP256K1_RECOVER_INVALID_SIGNATURE: u64 = 3;
+
+/// Syscalls every program can call
+pub fn create_syscall_registry<'a>() -> SyscallRegistry<InvokeContext<'a>> {
+    let mut syscalls = SyscallRegistry::new();
+    syscalls.register("abort", syscall_abort);
+    syscalls.register("sol_panic_", syscall_panic);
+
+    syscalls.register("sol_log_", syscall_log);
+    syscalls.register("sol_log_64_", syscall_log_u64);
+    syscalls.register("sol_log_pubkey", syscall_log_pubkey);
+    syscalls.register("sol_log_compute_units_", syscall_log_compute_units);
+    syscalls.register("sol_log_data", syscall_log_data);
+
+    syscalls.register("sol_sha256", syscall_sha256);
+    syscalls.register("sol_keccak256", syscall_keccak256);
+    syscalls.register("sol_blake3", syscall_blake3);
+    syscalls.register("sol_secp256k1_recover", syscall_secp256k1_recover);
+
+    syscalls.register("sol_create_program_address", syscall_create_program_address);
+    syscalls.register("sol_try_find_program_address", syscall_try_find_program_address);
+
+    syscalls.register("sol_get_clock_sysvar", syscall_get_clock_sysvar);
+    syscalls.register("sol_get_rent_sysvar", syscall_get_rent_sysvar);
+    syscalls.register("sol_get_epoch_schedule_sysvar", syscall_get_epoch_schedule_sysvar);
+    syscalls.register("sol_get_last_restart_slot", syscall_get_last_restart_slot);
+
+    syscalls.register("sol_set_return_data", syscall_set_return_data);
+    syscalls.register("sol_get_return_data", syscall_get_return_data);
+
+    syscalls.register("sol_memcpy_", syscall_memcpy);
+    syscalls.register("sol_memmove_", syscall_memmove);
+    syscalls.register("sol_memset_", syscall_memset);
+    syscalls.register("sol_memcmp_", syscall_memcmp);
+
+    syscalls.register("sol_invoke_signed_c", cpi::syscall_invoke_signed_c);
+    syscalls.register("sol_invoke_signed_rust", cpi::syscall_invoke_signed_rust);
+    syscalls
+}
+
+pub(crate) fn consume(invoke_context: &mut InvokeContext, units: u64) -> Result<(), EbpfError> {
+    if invoke_context.consume(units) {
+        Ok(())
+    } else {
+        Err(EbpfError::ExceededMaxInstructions(0))
+    }
+}
+
+pub(crate) fn syscall_error(message: impl Into<String>) -> EbpfError {
+    EbpfError::SyscallError(message.into())
+}
+
+fn translate_string(memory: &MemoryMapping, addr: u64, len: u64) -> Result<String, EbpfError> {
+    std::str::from_utf8(memory.translate(addr, len)?)
+        .map(str::to_string)
+        .map_err(|_| syscall_error("invalid string"))
+}
+
+pub(crate) fn translate_array<const N: usize>(memory: &MemoryMapping, addr: u64) -> Result<[u8; N], EbpfError> {
+    Ok(memory.translate(addr, N as u64)?.try_into().expect("translated exactly N bytes"))
+}
+
+/// Translate an array of `&[u8]` (pointer and length pairs) into owned byte vectors
+pub(crate) fn translate_slices(memory: &MemoryMapping, addr: u64, len: u64) -> Result<Vec<Vec<u8>>, EbpfError> {
+    let descriptors = memory.translate(addr, len.saturating_mul(16))?.to_vec();
+    descriptors
+        .chunks_exact(16)
+        .map(|descriptor| {
+            let ptr = u64::from_le_bytes(descriptor[0..8].try_into().unwrap());
+            let len = u64::from_le_bytes(descriptor[8..16].try_into().unwrap());
+            Ok(memory.translate(ptr, len)?.to_vec())
+        })
+        .collect()
+}
+
+pub(crate) fn write_bytes(memory: &mut MemoryMapping, addr: u64, bytes: &[u8]) -> Result<(), EbpfError> {
+    memory.translate_mut(addr, bytes.len() as u64)?.copy_from_slice(bytes);
+    Ok(())
+}
+
+fn syscall_abort(_: &mut InvokeContext, _: [u64; 5], _: &mut MemoryMapping) -> SyscallResult {
+    Err(syscall_error("program aborted"))
+}
+
+fn syscall_panic(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping) -> SyscallResult {
+    consume(invoke_context, args[1])?;
+    let file = String::from_utf8_lossy(memory.translate(args[0], args[1])?).into_owned();
+    let message = format!("panicked at {}:{}:{}", file, args[2], args[3]);
+    invoke_context.log(format!("Program log: {}", message));
+    Err(syscall_error(message))
+}
+
+fn syscall_log(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping) -> SyscallResult {
+    consume(invoke_context, costs::SYSCALL_BASE_COST.max(args[1]))?;
+    let message = translate_string(memory, args[0], args[1])?;
+    invoke_context.log(format!("Program log: {}", message));
+    Ok(0)
+}
+
+fn syscall_log_u64(invoke_context: &mut InvokeContext, args: [u64; 5], _: &mut MemoryMapping) -> SyscallResult {
+    consume(invoke_context, costs::LOG_64_UNITS)?;
+    invoke_context.log(format!(
+        "Program log: {:#x}, {:#x}, {:#x}, {:#x}, {:#x}",
+        args[0], args[1], args[2], args[3], args[4]
+    ));
+    Ok(0)
+}
+
+fn syscall_log_pubkey(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping) -> SyscallResult {
+    consume(invoke_context, costs::LOG_PUBKEY_UNITS)?;
+    let pubkey = crate::types::Pubkey::new(translate_array(memory, args[0])?);
+    invoke_context.log(format!("Program log: {}", pubkey));
+    Ok(0)
+}
+
+fn syscall_log_compute_units(invoke_context: &mut InvokeContext, _: [u64; 5], _: &mut MemoryMapping) -> SyscallResult {
+    consume(invoke_context, costs::SYSCALL_BASE_COST)?;
+    let remaining = invoke_context.remaining();
+    invoke_context.log(format!("Program consumption: {} units remaining", remaining));
+    Ok(0)
+}
+
+fn syscall_log_data(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping) -> SyscallResult {
+    consume(invoke_context, costs::SYSCALL_BASE_COST)?;
+    let fields = translate_slices(memory, args[0], args[1])?;
+    consume(invoke_context, costs::SYSCALL_BASE_COST.saturating_mul(fields.len() as u64))?;
+    consume(invoke_context, fields.iter().map(|field| field.len() as u64).sum())?;
+
+    let encoded: Vec<String> = fields
+        .iter()
+        .map(|field| base64::engine::general_purpose::STANDARD.encode(field))
+        .collect();
+    invoke_context.log(format!("Program data: {}", encoded.join(" ")));
+    Ok(0)
+}
+
+/// Shared implementation of the hashing syscalls: `args` are (slices, slice count, result)
+fn hash_slices(
+    invoke_context: &mut InvokeContext,
+    args: [u64; 5],
+    memory: &mut MemoryMapping,
+    hash: fn(&[u8]) -> [u8; 32],
+) -> SyscallResult {
+    if args[1] > costs::SHA256_MAX_SLICES {
+        return Err(syscall_error(format!("too many slices ({} > {})", args[1], costs::SHA256_MAX_SLICES)));
+    }
+    consume(invoke_context, costs::SHA256_BASE_COST)?;
+
+    let slices = translate_slices(memory, args[0], args[1])?;
+    let mut data = Vec::new();
+    for slice in &slices {
+        let cost = costs::SHA256_BYTE_COST.saturating_mul(slice.len() as u64 / 2);
+        consume(invoke_context, costs::MEM_OP_BASE_COST.max(cost))?;
+        data.extend_from_slice(slice);
+    }
+    write_bytes(memory, args[2], &hash(&data))?;
+    Ok(0)
+}
+
+fn syscall_sha256(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping) -> SyscallResult {
+    hash_slices(invoke_context, args, memory, SolanaCrypto::sha256_hash)
+}
+
+fn syscall_keccak256(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping) -> SyscallResult {
+    hash_slices(invoke_context, args, memory, SolanaCrypto::keccak256_hash)
+}
+
+fn syscall_blake3(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping) -> SyscallResult {
+    hash_slices(invoke_context, args, memory, SolanaCrypto::blake3_hash)
+}
+
+fn syscall_secp256k1_recover(
+    invoke_context: &mut InvokeContext,
+    args: [u64; 5],
+    memory: &mut MemoryMapping,
+) -> SyscallResult {
+    consume(invoke_context, costs::SECP256K1_RECOVER_COST)?;
+    let hash: [u8; 32] = translate_array(memory, args[0])?;
+    let signature: [u8; 64] = translate_array(memory, args[2])?;
+    memory.translate_mut(args[3], 64)?;
+
+    let recovery_id = match u8::try_from(args[1]) {
+        Ok(recovery_id) if recovery_id < 4 => recovery_id,
+        _ => return Ok(SECP256K1_RECOVER_INVALID_RECOVERY_ID),
+    };
+    match SolanaCrypto::secp256k1_recover(&hash, recovery_id, &signature) {
+        Ok(public_key) => {
+            write_bytes(memory, args[3], &public_key)?;
+            Ok(0)
+        }
+        Err(_) => Ok(SECP256K1_RECOVER_INVALID_SIGNATURE),
+    }
+}
+
+/// Translate the seeds and program id shared by the program address syscalls
+fn translate_program_address_inputs(
+    memory: &MemoryMapping,
+    seeds_addr: u64,
+    seeds_len: u64,
+    program_id_addr: u64,
+) -> Result<(Vec<Vec<u8>>, [u8; 32]), EbpfError> {
+    if seeds_len > MAX_SEEDS as u64 {
+        return Err(syscall_error(format!("too many seeds ({} > {})", seeds_len, MAX_SEEDS)));
+    }
+    let seeds = translate_slices(memory, seeds_addr, seeds_len)?;
+    let program_id = translate_array(memory, program_id_addr)?;
+    Ok((seeds, program_id))
+}
+
+/// Map address derivation failures: bad seeds abort the program, an on-curve address is reported
+pub(crate) fn create_program_address(seeds: &[&[u8]], program_id: &[u8; 32]) -> Result<Option<[u8; 32]>, EbpfError> {
+    match AddressDerivation::create_program_address(seeds, program_id) {
+        Ok(address) => Ok(Some(address)),
+        Err(TerminatorError::InvalidSeeds(message)) => Err(syscall_error(message)),
+        Err(_) => Ok(None),
+    }
+}
+
+fn syscall_create_program_address(
+    invoke_context: &mut InvokeContext,
+    args: [u64; 5],
+    memory: &mut MemoryMapping,
+) -> SyscallResult {
+    consume(invoke_context, costs::CREATE_PROGRAM_ADDRESS_UNITS)?;
+    let (seeds, program_id) = translate_program_address_inputs(memory, args[0], args[1], args[2])?;
+    let seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+
+    match create_program_address(&seeds, &program_id)? {
+        Some(address) => {
+            write_bytes(memory, args[3], &address)?;
+            Ok(0)
+        }
+        None => Ok(1),
+    }
+}
+
+fn syscall_try_find_program_address(
+    invoke_context: &mut InvokeContext,
+    args: [u64; 5],
+    memory: &mut MemoryMapping,
+) -> SyscallResult {
+    consume(invoke_context, costs::CREATE_PROGRAM_ADDRESS_UNITS)?;
+    let (seeds, program_id) = translate_program_address_inputs(memory, args[0], args[1], args[2])?;
+
+    for bump in (0..=u8::MAX).rev() {
+        let bump_seed = [bump];
+        let mut seeds_with_bump: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+        seeds_with_bump.push(&bump_seed);
+
+        if let Some(address) = create_program_address(&seeds_with_bump, &program_id)? {
+            write_bytes(memory, args[4], &bump_seed)?;
+            write_bytes(memory, args[3], &address)?;
+            return Ok(0);
+        }
+        consume(invoke_context, costs::CREATE_PROGRAM_ADDRESS_UNITS)?;
+    }
+    Ok(1)
+}
+
+/// Sysvars are copied into program memory using their in-memory (`repr(C)`) layout
+fn clock_bytes(clock: &Clock) -> Vec<u8> {
+    bincode::serialize(clock).expect("clock serializes")
+}
+
+fn rent_bytes(rent: &Rent) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(24);
+    bytes.extend_from_slice(&rent.lamports_per_byte_year.to_le_bytes());
+    bytes.extend_from_slice(&rent.exemption_threshold.to_le_bytes());
+    bytes.push(rent.burn_percent);
+    bytes.resize(24, 0);
+    bytes
+}
+
+fn epoch_schedule_bytes(schedule: &EpochSchedule) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(40);
+    bytes.extend_from_slice(&schedule.slots_per_epoch.to_le_bytes());
+    bytes.extend_from_slice(&schedule.leader_schedule_slot_offset.to_le_bytes());
+    bytes.extend_from_slice(&[schedule.warmup as u8, 0, 0, 0, 0, 0, 0, 0]);
+    bytes.extend_from_slice(&schedule.first_normal_epoch.to_le_bytes());
+    bytes.extend_from_slice(&schedule.first_normal_slot.to_le_bytes());
+    bytes
+}
+
+fn copy_sysvar(invoke_context: &mut InvokeContext, memory: &mut MemoryMapping, addr: u64, bytes: &[u8]) -> SyscallResult {
+    consume(invoke_context, costs::SYSVAR_BASE_COST + bytes.len() as u64)?;
+    write_bytes(memory, addr, bytes)?;
+    Ok(0)
+}
+
+fn syscall_get_clock_sysvar(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping) -> SyscallResult {
+    let bytes = clock_bytes(&invoke_context.sysvars().clock);
+    copy_sysvar(invoke_context, memory, args[0], &bytes)
+}
+
+fn syscall_get_rent_sysvar(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping) -> SyscallResult {
+    let bytes = rent_bytes(&invoke_context.sysvars().rent);
+    copy_sysvar(invoke_context, memory, args[0], &bytes)
+}
+
+fn syscall_get_epoch_schedule_sysvar(
+    invoke_context: &mut InvokeContext,
+    args: [u64; 5],
+    memory: &mut MemoryMapping,
+) -> SyscallResult {
+    let bytes = epoch_schedule_bytes(&invoke_context.sysvars().epoch_schedule);
+    copy_sysvar(invoke_context, memory, args[0], &bytes)
+}
+
+fn syscall_get_last_restart_slot(
+    invoke_context: &mut InvokeContext,
+    args: [u64; 5],
+    memory: &mut MemoryMapping,
+) -> SyscallResult {
+    let bytes = invoke_context.sysvars().last_restart_slot.to_le_bytes();
+    copy_sysvar(invoke_context, memory, args[0], &bytes)
+}
+
+fn syscall_set_return_data(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping) -> SyscallResult {
+    consume(invoke_context, args[1] / costs::CPI_BYTES_PER_UNIT + costs::SYSCALL_BASE_COST)?;
+    if args[1] > MAX_RETURN_DATA as u64 {
+        return Err(syscall_error(format!("return data too large ({} > {})", args[1], MAX_RETURN_DATA)));
+    }
+    let data = memory.translate(args[0], args[1])?.to_vec();
+    let program_id = invoke_context.program_id();
+    invoke_context.transaction_context.set_return_data(program_id, data);
+    Ok(0)
+}
+
+fn syscall_get_return_data(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping) -> SyscallResult {
+    consume(invoke_context, costs::SYSCALL_BASE_COST)?;
+    let Some(return_data) = invoke_context.transaction_context.return_data().cloned() else {
+        return Ok(0);
+    };
+
+    let length = args[1].min(return_data.data.len() as u64);
+    if length != 0 {
+        consume(invoke_context, (length + 32) / costs::CPI_BYTES_PER_UNIT)?;
+        write_bytes(memory, args[0], &return_data.data[..length as usize])?;
+        write_bytes(memory, args[2], &return_data.program_id.0)?;
+    }
+    Ok(return_data.data.len() as u64)
+}
+
+fn mem_op_cost(len: u64) -> u64 {
+    costs::MEM_OP_BASE_COST.max(len / costs::CPI_BYTES_PER_UNIT)
+}
+
+fn syscall_memcpy(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping) -> SyscallResult {
+    consume(invoke_context, mem_op_cost(args[2]))?;
+    let (dst, src, len) = (args[0], args[1], args[2]);
+    if src.max(dst) - src.min(dst) < len {
+        return Err(syscall_error("overlapping copy"));
+    }
+    let bytes = memory.translate(src, len)?.to_vec();
+    write_bytes(memory, dst, &bytes)?;
+    Ok(0)
+}
+
+fn syscall_memmove(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping) -> SyscallResult {
+    consume(invoke_context, mem_op_cost(args[2]))?;
+    let bytes = memory.translate(args[1], args[2])?.to_vec();
+    write_bytes(memory, args[0], &bytes)?;
+    Ok(0)
+}
+
+fn syscall_memset(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping) -> SyscallResult {
+    consume(invoke_context, mem_op_cost(args[2]))?;
+    memory.translate_mut(args[0], args[2])?.fill(args[1] as u8);
+    Ok(0)
+}
+
+fn syscall_memcmp(invoke_context: &mut InvokeContext, args: [u64; 5], memory: &mut MemoryMapping) -> SyscallResult {
+    consume(invoke_context, mem_op_cost(args[2]))?;
+    let left = memory.translate(args[0], args[2])?;
+    let right = memory.translate(args[1], args[2])?;
+    let result = left
+        .iter()
+        .zip(right)
+        .find(|(a, b)| a != b)
+        .map(|(a, b)| *a as i32 - *b as i32)
+        .unwrap_or(0);
+    write_bytes(memory, args[3], &result.to_le_bytes())?;
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sbpf::{hash_symbol_name, MM_HEAP_START, MM_INPUT_START};
+    use crate::sysvar::SysvarCache;
+    use crate::transaction_context::TransactionContext;
+    use crate::types::*;
+
+    /// Call a syscall by name with a fresh context; `input` is mapped at `MM_INPUT_START`
+    fn call(name: &str, args: [u64; 5], input: Vec<u8>, budget: u64) -> (SyscallResult, Vec<u8>, Vec<String>, u64) {
+        let instruction = Instruction {
+            program_id: Pubkey::new([1u8; 32]),
+            accounts: vec![],
+            data: InstructionData::Generic { data: vec![] },
+        };
+        let processor = |_: &Instruction, _: &mut TransactionContext, _: &mut ExecutionContext| Ok(());
+        let mut transaction_context = TransactionContext::new(vec![], vec![]);
+        let mut execution_context = ExecutionContext::new(budget);
+        let sysvars = SysvarCache::new(100, Rent::default(), EpochSchedule::default());
+        let syscalls = create_syscall_registry();
+        let syscall = syscalls
+            .lookup(hash_symbol_name(name.as_bytes()))
+            .expect("syscall is registered");
+        let mut memory = MemoryMapping::new(&[], 1024, input);
+        let (result, remaining) = {
+            let mut invoke_context =
+                InvokeContext::new(&mut transaction_context, &mut execution_context, &sysvars, &processor, &instruction);
+            let result = syscall(&mut invoke_context, args, &mut memory);
+            (result, invoke_context.remaining())
+        };
+        (result, memory.into_input(), execution_context.log_messages, remaining)
+    }
+
+    /// Input region holding one slice descriptor per entry of `data`, followed by the data itself
+    fn slices_input(data: &[&[u8]]) -> Vec<u8> {
+        let mut descriptors = Vec::new();
+        let mut payload = Vec::new();
+        let payload_start = (data.len() * 16) as u64;
+        for item in data {
+            descriptors.extend_from_slice(&(MM_INPUT_START + payload_start + payload.len() as u64).to_le_bytes());
+            descriptors.extend_from_slice(&(item.len() as u64).to_le_bytes());
+            payload.extend_from_slice(item);
+        }
+        descriptors.extend(payload);
+        descriptors.resize(descriptors.len() + 64, 0);
+        descriptors
+    }
+
+    #[test]
+    fn test_log_syscalls() {
+        let (result, _, logs, remaining) = call("sol_log_", [MM_INPUT_START, 5, 0, 0, 0], b"hello".to_vec(), 1000);
+        assert_eq!(result, Ok(0));
+        assert_eq!(logs, vec!["Program log: hello"]);
+        assert_eq!(remaining, 900);
+
+        let (_, _, logs, _) = call("sol_log_64_", [1, 2, 3, 4, 255], vec![], 1000);
+        assert_eq!(logs, vec!["Program log: 0x1, 0x2, 0x3, 0x4, 0xff"]);
+
+        let (_, _, logs, _) = call("sol_log_pubkey", [MM_INPUT_START, 0, 0, 0, 0], vec![0u8; 32], 1000);
+        assert_eq!(logs, vec!["Program log: 11111111111111111111111111111111"]);
+
+        let (_, _, logs, _) = call("sol_log_compute_units_", [0; 5], vec![], 1000);
+        assert_eq!(logs, vec!["Program consumption: 900 units remaining"]);
+
+        let input = slices_input(&[b"ab", b"c"]);
+        let (_, _, logs, _) = call("sol_log_data", [MM_INPUT_START, 2, 0, 0, 0], input, 1000);
+        assert_eq!(logs, vec!["Program data: YWI= Yw=="]);
+
+        let (result, _, _, remaining) = call("sol_log_", [MM_INPUT_START, 5, 0, 0, 0], b"hello".to_vec(), 50);
+        assert_eq!(result, Err(EbpfError::ExceededMaxInstructions(0)));
+        assert_eq!(remaining, 0);
+    }
+
+    #[test]
+    fn test_hash_syscalls() {
+        let input = slices_input(&[b"hel", b"lo"]);
+        let result_addr = MM_INPUT_START + input.len() as u64 - 32;
+
+        let (result, output, _, remaining) = call("sol_sha256", [MM_INPUT_START, 2, result_addr, 0, 0], input.clone(), 1000);
+        assert_eq!(result, Ok(0));
+        assert_eq!(output[output.len() - 32..], SolanaCrypto::sha256_hash(b"hello"));
+        assert_eq!(remaining, 1000 - 85 - 10 - 10);
+
+        let (_, output, _, _) = call("sol_keccak256", [MM_INPUT_START, 2, result_addr, 0, 0], input.clone(), 1000);
+        assert_eq!(output[output.len() - 32..], SolanaCrypto::keccak256_hash(b"hello"));
+
+        let (_, output, _, _) = call("sol_blake3", [MM_INPUT_START, 2, result_addr, 0, 0], input, 1000);
+        assert_eq!(output[output.len() - 32..], SolanaCrypto::blake3_hash(b"hello"));
+    }
+
+    #[test]
+    fn test_program_address_syscalls() {
+        let program_id = [5u8; 32];
+        let (expected, bump) = AddressDerivation::derive_program_address(&[b"vault"], &program_id).unwrap();
+
+        let mut input = slices_input(&[b"vault"]);
+        let program_id_addr = MM_INPUT_START + input.len() as u64;
+        input.extend_from_slice(&program_id);
+        let address_addr = MM_INPUT_START + input.len() as u64;
+        input.extend_from_slice(&[0u8; 33]);
+
+        let args = [MM_INPUT_START, 1, program_id_addr, address_addr, address_addr + 32];
+        let (result, output, _, _) = call("sol_try_find_program_address", args, input.clone(), 1_000_000);
+        assert_eq!(result, Ok(0));
+        let address_at = (address_addr - MM_INPUT_START) as usize;
+        assert_eq!(output[address_at..address_at + 32], expected);
+        assert_eq!(output[address_at + 32], bump);
+
+        let too_many = [MM_INPUT_START, 17, program_id_addr, address_addr, 0];
+        assert!(matches!(
+            call("sol_create_program_address", too_many, input, 1_000_000).0,
+            Err(EbpfError::SyscallError(_))
+        ));
+    }
+
+    #[test]
+    fn test_sysvar_and_return_data_syscalls() {
+        let (result, output, _, remaining) = call("sol_get_clock_sysvar", [MM_INPUT_START, 0, 0, 0, 0], vec![0u8; 40], 1000);
+        assert_eq!(result, Ok(0));
+        assert_eq!(u64::from_le_bytes(output[0..8].try_into().unwrap()), 100);
+        assert_eq!(remaining, 1000 - 100 - 40);
+
+        let (_, output, _, _) = call("sol_get_rent_sysvar", [MM_INPUT_START, 0, 0, 0, 0], vec![0u8; 24], 1000);
+        assert_eq!(u64::from_le_bytes(output[0..8].try_into().unwrap()), 3480);
+        assert_eq!(f64::from_le_bytes(output[8..16].try_into().unwrap()), 2.0);
+
+        let (result, _, _, _) = call("sol_set_return_data", [MM_INPUT_START, 2000, 0, 0, 0], vec![0u8; 2000], 1000);
+        assert!(matches!(result, Err(EbpfError::SyscallError(_))));
+
+        let (result, _, _, _) = call("sol_get_return_data", [MM_HEAP_START, 8, MM_HEAP_START + 8, 0, 0], vec![], 1000);
+        assert_eq!(result, Ok(0));
+    }
+
+    #[test]
+    fn test_memory_syscalls() {
+        let base = MM_INPUT_START;
+        let (result, output, _, _) = call("sol_memcpy_", [base + 4, base, 4, 0, 0], vec![1, 2, 3, 4, 0, 0, 0, 0], 100);
+        assert_eq!(result, Ok(0));
+        assert_eq!(output, vec![1, 2, 3, 4, 1, 2, 3, 4]);
+
+        let (result, _, _, _) = call("sol_memcpy_", [base + 2, base, 4, 0, 0], vec![0u8; 8], 100);
+        assert!(matches!(result, Err(EbpfError::SyscallError(_))));
+
+        let (_, output, _, _) = call("sol_memmove_", [base + 2, base, 4, 0, 0], vec![1, 2, 3, 4, 0, 0, 0, 0], 100);
+        assert_eq!(output, vec![1, 2, 1, 2, 3, 4, 0, 0]);
+
+        let (_, output, _, _) = call("sol_memset_", [base, 7, 3, 0, 0], vec![0u8; 4], 100);
+        assert_eq!(output, vec![7, 7, 7, 0]);
+
+        let (_, output, _, _) = call("sol_memcmp_", [base, base + 2, 2, base + 4, 0], vec![1, 5, 1, 2, 0, 0, 0, 0], 100);
+        assert_eq!(i32::from_le_bytes(output[4..8].try_into().unwrap()), 3);
+    }
+
+    #[test]
+    fn test_secp256k1_recover_syscall() {
+        let secret_key = libsecp256k1::SecretKey::parse(&[9u8; 32]).unwrap();
+        let hash = SolanaCrypto::keccak256_hash(b"message");
+        let (signature, recovery_id) = libsecp256k1::sign(&libsecp256k1::Message::parse(&hash), &secret_key);
+
+        let mut input = hash.to_vec();
+        input.extend_from_slice(&signature.serialize());
+        input.extend_from_slice(&[0u8; 64]);
+        let args = [MM_INPUT_START, recovery_id.serialize() as u64, MM_INPUT_START + 32, MM_INPUT_START + 96, 0];
+
+        let (result, output, _, _) = call("sol_secp256k1_recover", args, input.clone(), 30_000);
+        assert_eq!(result, Ok(0));
+        let public_key = libsecp256k1::PublicKey::from_secret_key(&secret_key).serialize();
+        assert_eq!(output[96..160], public_key[1..]);
+
+        let invalid_id = [MM_INPUT_START, 9, MM_INPUT_START + 32, MM_INPUT_START + 96, 0];
+        assert_eq!(call("sol_secp256k1_recover", invalid_id, input, 30_000).0, Ok(2));
+    }
+}
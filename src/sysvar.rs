@@ -0,0 +1,254 @@
+use crate::types::*;
+use serde::{Deserialize, Serialize};
+
+/// Owner of every sysvar account (Sysvar1111111111111111111111111111111111111)
+pub const SYSVAR_OWNER: [u8; 32] = [
+    6, 167, 213, 23, 24, 117, 247, 41, 199, 61, 147, 64, 143, 33, 97, 32,
+    6, 126, 216, 140, 118, 224, 140, 40, 127, 193, 148, 96, 0, 0, 0, 0,
+];
+
+/// Target slot duration used to estimate the cluster clock
+pub const DEFAULT_MS_PER_SLOT: u64 = 400;
+/// Slots per epoch on mainnet
+pub const DEFAULT_SLOTS_PER_EPOCH: u64 = 432_000;
+/// Shortest epoch during warmup
+pub const MINIMUM_SLOTS_PER_EPOCH: u64 = 32;
+
+impl Pubkey {
+    /// SysvarC1ock11111111111111111111111111111111
+    pub fn sysvar_clock() -> Self {
+        Self([
+            6, 167, 213, 23, 24, 199, 116, 201, 40, 86, 99, 152, 105, 29, 94, 182,
+            139, 94, 184, 163, 155, 75, 109, 92, 115, 85, 91, 33, 0, 0, 0, 0,
+        ])
+    }
+
+    /// SysvarRent111111111111111111111111111111111
+    pub fn sysvar_rent() -> Self {
+        Self([
+            6, 167, 213, 23, 25, 44, 92, 81, 33, 140, 201, 76, 61, 74, 241, 127,
+            88, 218, 238, 8, 155, 161, 253, 68, 227, 219, 217, 138, 0, 0, 0, 0,
+        ])
+    }
+
+    /// SysvarEpochSchedu1e111111111111111111111111
+    pub fn sysvar_epoch_schedule() -> Self {
+        Self([
+            6, 167, 213, 23, 24, 220, 63, 238, 2, 211, 228, 127, 1, 0, 248, 176,
+            84, 247, 148, 46, 96, 89, 30, 63, 80, 135, 25, 168, 5, 0, 0, 0,
+        ])
+    }
+
+    /// SysvarLastRestartS1ot1111111111111111111111
+    pub fn sysvar_last_restart_slot() -> Self {
+        Self([
+            6, 167, 213, 23, 25, 6, 221, 225, 205, 63, 148, 125, 202, 180, 200, 244,
+            244, 245, 27, 173, 15, 152, 19, 184, 0, 210, 137, 71, 31, 192, 0, 0,
+        ])
+    }
+}
+
+/// Cluster time as seen by programs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Clock {
+    pub slot: u64,
+    pub epoch_start_timestamp: i64,
+    pub epoch: u64,
+    pub leader_schedule_epoch: u64,
+    pub unix_timestamp: i64,
+}
+
+/// Rent configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rent {
+    pub lamports_per_byte_year: u64,
+    pub exemption_threshold: f64,
+    pub burn_percent: u8,
+}
+
+impl Rent {
+    /// Bytes charged for every account on top of its data
+    pub const ACCOUNT_STORAGE_OVERHEAD: u64 = 128;
+
+    /// Minimum balance for an account with `data_len` bytes to be rent exempt
+    pub fn minimum_balance(&self, data_len: usize) -> u64 {
+        let bytes = Self::ACCOUNT_STORAGE_OVERHEAD + data_len as u64;
+        ((bytes * self.lamports_per_byte_year) as f64 * self.exemption_threshold) as u64
+    }
+
+    pub fn is_exempt(&self, lamports: u64, data_len: usize) -> bool {
+        lamports >= self.minimum_balance(data_len)
+    }
+}
+
+impl Default for Rent {
+    fn default() -> Self {
+        Self {
+            lamports_per_byte_year: 3480,
+            exemption_threshold: 2.0,
+            burn_percent: 50,
+        }
+    }
+}
+
+/// Epoch lengths, including the warmup period of short epochs after genesis
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpochSchedule {
+    pub slots_per_epoch: u64,
+    pub leader_schedule_slot_offset: u64,
+    pub warmup: bool,
+    pub first_normal_epoch: u64,
+    pub first_normal_slot: u64,
+}
+
+impl EpochSchedule {
+    pub fn new(slots_per_epoch: u64, warmup: bool) -> Self {
+        let (first_normal_epoch, first_normal_slot) = if warmup {
+            let next_power_of_two = slots_per_epoch.next_power_of_two();
+            let epoch = next_power_of_two.trailing_zeros() - MINIMUM_SLOTS_PER_EPOCH.trailing_zeros();
+            (epoch as u64, next_power_of_two - MINIMUM_SLOTS_PER_EPOCH)
+        } else {
+            (0, 0)
+        };
+        Self {
+            slots_per_epoch,
+            leader_schedule_slot_offset: slots_per_epoch,
+            warmup,
+            first_normal_epoch,
+            first_normal_slot,
+        }
+    }
+
+    /// Epoch containing `slot`
+    pub fn get_epoch(&self, slot: u64) -> u64 {
+        self.get_epoch_and_slot_index(slot).0
+    }
+
+    /// Epoch containing `slot` and the slot's offset within it
+    pub fn get_epoch_and_slot_index(&self, slot: u64) -> (u64, u64) {
+        if slot < self.first_normal_slot {
+            let epoch = (slot + MINIMUM_SLOTS_PER_EPOCH + 1).next_power_of_two().trailing_zeros()
+                - MINIMUM_SLOTS_PER_EPOCH.trailing_zeros()
+                - 1;
+            let epoch_len = 2u64.pow(epoch + MINIMUM_SLOTS_PER_EPOCH.trailing_zeros());
+            (epoch as u64, slot - (epoch_len - MINIMUM_SLOTS_PER_EPOCH))
+        } else {
+            let normal_slot_index = slot - self.first_normal_slot;
+            (
+                self.first_normal_epoch + normal_slot_index / self.slots_per_epoch,
+                normal_slot_index % self.slots_per_epoch,
+            )
+        }
+    }
+
+    /// Epoch whose leader schedule is known at `slot`
+    pub fn get_leader_schedule_epoch(&self, slot: u64) -> u64 {
+        if slot < self.first_normal_slot {
+            self.get_epoch(slot) + 1
+        } else {
+            let offset = slot - self.first_normal_slot + self.leader_schedule_slot_offset;
+            self.first_normal_epoch + offset / self.slots_per_epoch
+        }
+    }
+
+    /// First slot of `epoch`
+    pub fn get_first_slot_in_epoch(&self, epoch: u64) -> u64 {
+        if epoch <= self.first_normal_epoch {
+            (2u64.pow(epoch as u32) - 1) * MINIMUM_SLOTS_PER_EPOCH
+        } else {
+            (epoch - self.first_normal_epoch) * self.slots_per_epoch + self.first_normal_slot
+        }
+    }
+}
+
+impl Default for EpochSchedule {
+    fn default() -> Self {
+        Self::new(DEFAULT_SLOTS_PER_EPOCH, true)
+    }
+}
+
+/// Sysvar values for the slot a transaction executes in
+#[derive(Debug, Clone, Default)]
+pub struct SysvarCache {
+    pub clock: Clock,
+    pub rent: Rent,
+    pub epoch_schedule: EpochSchedule,
+    pub last_restart_slot: u64,
+}
+
+impl SysvarCache {
+    /// Sysvars for `slot`, with the clock advancing `DEFAULT_MS_PER_SLOT` per slot from genesis
+    pub fn new(slot: u64, rent: Rent, epoch_schedule: EpochSchedule) -> Self {
+        let epoch = epoch_schedule.get_epoch(slot);
+        let timestamp_at = |slot: u64| (slot * DEFAULT_MS_PER_SLOT / 1000) as i64;
+        let clock = Clock {
+            slot,
+            epoch_start_timestamp: timestamp_at(epoch_schedule.get_first_slot_in_epoch(epoch)),
+            epoch,
+            leader_schedule_epoch: epoch_schedule.get_leader_schedule_epoch(slot),
+            unix_timestamp: timestamp_at(slot),
+        };
+        Self {
+            clock,
+            rent,
+            epoch_schedule,
+            last_restart_slot: 0,
+        }
+    }
+
+    /// Account holding the bincode-serialized sysvar, if `pubkey` is a supported sysvar
+    pub fn account(&self, pubkey: &Pubkey) -> Option<Account> {
+        let data = match *pubkey {
+            p if p == Pubkey::sysvar_clock() => bincode::serialize(&self.clock),
+            p if p == Pubkey::sysvar_rent() => bincode::serialize(&self.rent),
+            p if p == Pubkey::sysvar_epoch_schedule() => bincode::serialize(&self.epoch_schedule),
+            p if p == Pubkey::sysvar_last_restart_slot() => bincode::serialize(&self.last_restart_slot),
+            _ => return None,
+        }
+        .ok()?;
+        let lamports = self.rent.minimum_balance(data.len()).max(1);
+        Some(Account::new(lamports, data, SYSVAR_OWNER))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_epoch_schedule_warmup() {
+        let schedule = EpochSchedule::default();
+        assert_eq!(schedule.first_normal_epoch, 14);
+        assert_eq!(schedule.first_normal_slot, 524_256);
+
+        assert_eq!(schedule.get_epoch_and_slot_index(0), (0, 0));
+        assert_eq!(schedule.get_epoch_and_slot_index(31), (0, 31));
+        assert_eq!(schedule.get_epoch_and_slot_index(32), (1, 0));
+        assert_eq!(schedule.get_epoch_and_slot_index(96), (2, 0));
+        assert_eq!(schedule.get_epoch_and_slot_index(524_256), (14, 0));
+        assert_eq!(schedule.get_epoch_and_slot_index(524_256 + 432_001), (15, 1));
+        assert_eq!(schedule.get_first_slot_in_epoch(2), 96);
+        assert_eq!(schedule.get_first_slot_in_epoch(15), 524_256 + 432_000);
+    }
+
+    #[test]
+    fn test_rent_exemption() {
+        let rent = Rent::default();
+        assert_eq!(rent.minimum_balance(0), 890_880);
+        assert_eq!(rent.minimum_balance(165), 2_039_280);
+        assert!(rent.is_exempt(2_039_280, 165));
+    }
+
+    #[test]
+    fn test_sysvar_accounts() {
+        let sysvars = SysvarCache::new(100, Rent::default(), EpochSchedule::default());
+        assert_eq!(sysvars.clock.epoch, 2);
+        assert_eq!(sysvars.clock.unix_timestamp, 40);
+
+        let clock_account = sysvars.account(&Pubkey::sysvar_clock()).unwrap();
+        assert_eq!(clock_account.owner, SYSVAR_OWNER);
+        assert_eq!(bincode::deserialize::<Clock>(&clock_account.data).unwrap(), sysvars.clock);
+        assert_eq!(sysvars.account(&Pubkey::sysvar_rent()).unwrap().data.len(), 17);
+        assert!(sysvars.account(&Pubkey::system_program()).is_none());
+    }
+}
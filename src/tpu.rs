@@ -0,0 +1,335 @@
+//! UDP ingest of raw wire transactions, like a validator's TPU: packets are deduplicated,
+//! deserialized and signature verified in parallel, then queued for block production.
+
+use crate::block_producer::TransactionSender;
+use crate::solana_format::SolanaTransactionParser;
+use crate::types::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::future::Future;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::net::UdpSocket;
+use tracing::{debug, info, warn};
+
+/// Most packets read off the socket and verified together
+pub const MAX_PACKET_BATCH: usize = 128;
+/// Packets remembered for deduplication before the oldest are forgotten
+pub const MAX_DEDUP_PACKETS: usize = 100_000;
+
+/// Counters of the packets ingested and why any were dropped
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TpuStats {
+    pub packets_received: u64,
+    /// Packets larger than the maximum transaction size
+    pub oversized: u64,
+    pub duplicates: u64,
+    /// Packets that aren't a well-formed legacy transaction
+    pub malformed: u64,
+    pub sigverify_failed: u64,
+    /// Verified transactions the block producer's queue was full or no longer there to take
+    pub unscheduled: u64,
+    pub forwarded: u64,
+}
+
+impl TpuStats {
+    pub fn dropped(&self) -> u64 {
+        self.oversized + self.duplicates + self.malformed + self.sigverify_failed + self.unscheduled
+    }
+}
+
+#[derive(Debug, Default)]
+struct AtomicTpuStats {
+    packets_received: AtomicU64,
+    oversized: AtomicU64,
+    duplicates: AtomicU64,
+    malformed: AtomicU64,
+    sigverify_failed: AtomicU64,
+    unscheduled: AtomicU64,
+    forwarded: AtomicU64,
+}
+
+/// Why a packet didn't make it to the scheduler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PacketError {
+    Malformed,
+    SignatureFailure,
+}
+
+/// Recently seen packets, by a hash of their bytes
+#[derive(Debug)]
+struct PacketDeduper {
+    capacity: usize,
+    seen: HashSet<u64>,
+    order: VecDeque<u64>,
+}
+
+impl PacketDeduper {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Remember `packet`, returning whether it is new
+    fn insert(&mut self, packet: &[u8]) -> bool {
+        let mut hasher = DefaultHasher::new();
+        packet.hash(&mut hasher);
+        let hash = hasher.finish();
+        if !self.seen.insert(hash) {
+            return false;
+        }
+        self.order.push_back(hash);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// Receives transaction packets over UDP and feeds the verified ones to the block producer
+#[derive(Debug)]
+pub struct TpuService {
+    socket: UdpSocket,
+    sender: TransactionSender,
+    max_packet_size: usize,
+    deduper: Mutex<PacketDeduper>,
+    stats: AtomicTpuStats,
+}
+
+impl TpuService {
+    /// Ingest packets of up to `max_packet_size` bytes, usually the runtime's
+    /// `max_transaction_size`, from `socket`
+    pub fn new(socket: UdpSocket, sender: TransactionSender, max_packet_size: usize) -> Self {
+        Self {
+            socket,
+            sender,
+            max_packet_size,
+            deduper: Mutex::new(PacketDeduper::new(MAX_DEDUP_PACKETS)),
+            stats: AtomicTpuStats::default(),
+        }
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn stats(&self) -> TpuStats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        TpuStats {
+            packets_received: load(&self.stats.packets_received),
+            oversized: load(&self.stats.oversized),
+            duplicates: load(&self.stats.duplicates),
+            malformed: load(&self.stats.malformed),
+            sigverify_failed: load(&self.stats.sigverify_failed),
+            unscheduled: load(&self.stats.unscheduled),
+            forwarded: load(&self.stats.forwarded),
+        }
+    }
+
+    /// Ingest packets until `shutdown` completes
+    pub async fn run(&self, shutdown: impl Future<Output = ()>) {
+        if let Ok(address) = self.socket.local_addr() {
+            info!("TPU listening for transaction packets on {}", address);
+        }
+        // One spare byte tells oversized datagrams from ones that fit exactly
+        let mut buffer = vec![0u8; self.max_packet_size + 1];
+        tokio::pin!(shutdown);
+
+        loop {
+            let mut packets = Vec::new();
+            tokio::select! {
+                _ = &mut shutdown => break,
+                received = self.socket.recv_from(&mut buffer) => match received {
+                    Ok((len, _)) => packets.push(buffer[..len].to_vec()),
+                    Err(e) => {
+                        warn!("Failed to receive a packet: {}", e);
+                        continue;
+                    }
+                },
+            }
+            // Whatever else already arrived is verified in the same batch
+            while packets.len() < MAX_PACKET_BATCH {
+                match self.socket.try_recv_from(&mut buffer) {
+                    Ok((len, _)) => packets.push(buffer[..len].to_vec()),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        warn!("Failed to receive a packet: {}", e);
+                        break;
+                    }
+                }
+            }
+            self.process_packets(packets);
+        }
+        info!("TPU stopped: {:?}", self.stats());
+    }
+
+    /// Drop oversized and duplicate packets, verify the rest in parallel and queue the valid
+    /// transactions
+    fn process_packets(&self, packets: Vec<Vec<u8>>) {
+        self.stats.packets_received.fetch_add(packets.len() as u64, Ordering::Relaxed);
+        let packets: Vec<Vec<u8>> = {
+            let mut deduper = self.deduper.lock().unwrap();
+            packets
+                .into_iter()
+                .filter(|packet| {
+                    if packet.len() > self.max_packet_size {
+                        self.stats.oversized.fetch_add(1, Ordering::Relaxed);
+                        false
+                    } else if !deduper.insert(packet) {
+                        self.stats.duplicates.fetch_add(1, Ordering::Relaxed);
+                        false
+                    } else {
+                        true
+                    }
+                })
+                .collect()
+        };
+
+        for result in verify_packets(&packets) {
+            match result {
+                Ok(txn) => {
+                    if self.sender.try_send(txn).is_ok() {
+                        self.stats.forwarded.fetch_add(1, Ordering::Relaxed);
+                    } else {
+                        self.stats.unscheduled.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Err(PacketError::Malformed) => {
+                    self.stats.malformed.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(PacketError::SignatureFailure) => {
+                    self.stats.sigverify_failed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
+
+/// Deserialize and signature verify every packet, in parallel
+fn verify_packets(packets: &[Vec<u8>]) -> Vec<std::result::Result<Transaction, PacketError>> {
+    packets
+        .par_iter()
+        .map(|packet| {
+            let wire = SolanaTransactionParser::parse_transaction(packet).map_err(|e| {
+                debug!("Dropping malformed packet: {}", e);
+                PacketError::Malformed
+            })?;
+            if !wire.verify_signatures() {
+                return Err(PacketError::SignatureFailure);
+            }
+            wire.to_transaction().map_err(|_| PacketError::Malformed)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solana_format::PACKET_DATA_SIZE;
+    use ed25519_dalek::SigningKey;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    fn transfer_packet(seed: u8, lamports: u64) -> Vec<u8> {
+        let from = SigningKey::from_bytes(&[seed; 32]);
+        SolanaTransactionParser::signed_transfer_for_test(&from, Pubkey::new([2u8; 32]), lamports, [3u8; 32])
+    }
+
+    async fn service() -> (TpuService, mpsc::Receiver<Transaction>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let (sender, receiver) = mpsc::channel(2);
+        (TpuService::new(socket, sender, PACKET_DATA_SIZE), receiver)
+    }
+
+    #[tokio::test]
+    async fn test_drop_counters() {
+        let (service, mut receiver) = service().await;
+        let valid = transfer_packet(1, 10);
+        let mut forged = transfer_packet(4, 10);
+        forged[1] ^= 1;
+
+        service.process_packets(vec![
+            valid.clone(),
+            valid.clone(),
+            transfer_packet(5, 20),
+            vec![0u8; PACKET_DATA_SIZE + 1],
+            vec![1, 2, 3],
+            forged,
+        ]);
+        service.process_packets(vec![valid]);
+
+        assert_eq!(
+            service.stats(),
+            TpuStats {
+                packets_received: 7,
+                oversized: 1,
+                duplicates: 2,
+                malformed: 1,
+                sigverify_failed: 1,
+                unscheduled: 0,
+                forwarded: 2,
+            }
+        );
+        assert_eq!(service.stats().dropped(), 5);
+
+        // A full queue turns transactions away rather than buffering them
+        service.process_packets(vec![transfer_packet(8, 40)]);
+        assert_eq!(service.stats().unscheduled, 1);
+        assert_eq!(service.stats().forwarded, 2);
+
+        let txn = receiver.try_recv().unwrap();
+        assert!(matches!(txn.instructions[0].data, InstructionData::Transfer { lamports: 10, .. }));
+        assert!(receiver.try_recv().is_ok());
+
+        drop(receiver);
+        service.process_packets(vec![transfer_packet(6, 30)]);
+        assert_eq!(service.stats().unscheduled, 2);
+    }
+
+    #[tokio::test]
+    async fn test_receive_over_udp() {
+        let (service, mut receiver) = service().await;
+        let service = Arc::new(service);
+        let address = service.local_addr().unwrap();
+        let (shutdown, shutdown_receiver) = tokio::sync::oneshot::channel::<()>();
+        let running = tokio::spawn({
+            let service = service.clone();
+            async move {
+                service.run(async { let _ = shutdown_receiver.await; }).await;
+            }
+        });
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(&vec![0u8; PACKET_DATA_SIZE + 100], address).await.unwrap();
+        client.send_to(&transfer_packet(7, 10), address).await.unwrap();
+        let txn = tokio::time::timeout(Duration::from_secs(5), receiver.recv()).await.unwrap().unwrap();
+        assert_eq!(txn.signatures.len(), 1);
+
+        shutdown.send(()).unwrap();
+        running.await.unwrap();
+        let stats = service.stats();
+        assert_eq!(stats.forwarded, 1);
+        assert_eq!(stats.oversized, 1);
+    }
+
+    #[test]
+    fn test_deduper_forgets_oldest() {
+        let mut deduper = PacketDeduper::new(2);
+        assert!(deduper.insert(&[1]));
+        assert!(!deduper.insert(&[1]));
+        assert!(deduper.insert(&[2]));
+        assert!(deduper.insert(&[3]));
+        assert!(deduper.insert(&[1]));
+        assert!(!deduper.insert(&[3]));
+    }
+}
@@ -0,0 +1,323 @@
+use crate::pre_account::{verify_accounts, PreAccount};
+use crate::transaction_error::InstructionError;
+use crate::transaction_status::{InnerInstruction, InnerInstructions, TransactionReturnData};
+use crate::types::*;
+
+/// Deepest instruction stack: the top-level instruction plus four nested invocations
+pub const MAX_INSTRUCTION_STACK_DEPTH: usize = 5;
+
+/// Accounts loaded for a single transaction.
+///
+/// Instructions operate on these copies; they are only written back to the
+/// bank once every instruction in the transaction has succeeded.
+#[derive(Debug, Clone)]
+pub struct TransactionContext {
+    account_keys: Vec<Pubkey>,
+    accounts: Vec<Account>,
+    /// Accounts that signed the transaction
+    signers: Vec<Pubkey>,
+    return_data: Option<TransactionReturnData>,
+    inner_instructions: Vec<InnerInstructions>,
+    instruction_stack: Vec<InstructionFrame>,
+    top_level_instructions: usize,
+}
+
+/// An executing instruction and the transaction's accounts as of the last verification
+#[derive(Debug, Clone)]
+struct InstructionFrame {
+    program_id: Pubkey,
+    signers: Vec<Pubkey>,
+    pre_accounts: Vec<PreAccount>,
+}
+
+impl TransactionContext {
+    pub fn new(account_keys: Vec<Pubkey>, accounts: Vec<Account>) -> Self {
+        debug_assert_eq!(account_keys.len(), accounts.len());
+        Self {
+            account_keys,
+            accounts,
+            signers: Vec::new(),
+            return_data: None,
+            inner_instructions: Vec::new(),
+            instruction_stack: Vec::new(),
+            top_level_instructions: 0,
+        }
+    }
+
+    /// Let top-level instructions use the signatures of `signers`
+    pub fn with_signers(mut self, signers: Vec<Pubkey>) -> Self {
+        self.signers = signers;
+        self
+    }
+
+    pub fn account_keys(&self) -> &[Pubkey] {
+        &self.account_keys
+    }
+
+    pub fn accounts(&self) -> &[Account] {
+        &self.accounts
+    }
+
+    pub fn find_index(&self, pubkey: &Pubkey) -> Option<usize> {
+        self.account_keys.iter().position(|key| key == pubkey)
+    }
+
+    pub fn get(&self, pubkey: &Pubkey) -> Option<&Account> {
+        self.find_index(pubkey).map(|index| &self.accounts[index])
+    }
+
+    pub fn get_mut(&mut self, pubkey: &Pubkey) -> Option<&mut Account> {
+        self.find_index(pubkey).map(move |index| &mut self.accounts[index])
+    }
+
+    /// Lamports of every loaded account, in account key order
+    pub fn balances(&self) -> Vec<u64> {
+        self.accounts.iter().map(|account| account.lamports).collect()
+    }
+
+    /// Return data set by the most recent program; empty data clears it
+    pub fn set_return_data(&mut self, program_id: Pubkey, data: Vec<u8>) {
+        self.return_data = if data.is_empty() {
+            None
+        } else {
+            Some(TransactionReturnData { program_id, data })
+        };
+    }
+
+    pub fn return_data(&self) -> Option<&TransactionReturnData> {
+        self.return_data.as_ref()
+    }
+
+    /// Enter `instruction`, enforcing the depth limit and reentrancy rules.
+    ///
+    /// A program may invoke itself directly, but not while another program is between
+    /// its frames on the stack. Every account of the transaction is recorded so the changes the
+    /// program makes can be verified; those the instruction doesn't list are read-only to it.
+    ///
+    /// An instruction is signed by the accounts its metas mark as signers. Typed system
+    /// instructions are also signed by the accounts they debit or reassign: at the top level if
+    /// the transaction carries their signature, when invoked because the caller's privileges were
+    /// checked to cover them.
+    pub fn push_instruction(&mut self, instruction: &Instruction) -> Result<(), InstructionError> {
+        let program_id = instruction.program_id;
+        let is_reentrant = self.instruction_stack.iter().any(|frame| frame.program_id == program_id)
+            && self.instruction_stack.last().map(|frame| frame.program_id) != Some(program_id);
+        if is_reentrant {
+            return Err(InstructionError::ReentrancyNotAllowed);
+        }
+        if self.instruction_stack.len() >= MAX_INSTRUCTION_STACK_DEPTH {
+            return Err(InstructionError::CallDepth);
+        }
+        if self.instruction_stack.is_empty() {
+            self.top_level_instructions += 1;
+        }
+
+        let mut signers: Vec<Pubkey> = instruction
+            .accounts
+            .iter()
+            .filter(|meta| meta.is_signer)
+            .map(|meta| meta.pubkey)
+            .collect();
+        let required = instruction.data.signer_accounts().into_iter();
+        if self.instruction_stack.is_empty() {
+            signers.extend(required.filter(|pubkey| self.signers.contains(pubkey)));
+        } else {
+            signers.extend(required);
+        }
+
+        let mut pre_accounts: Vec<PreAccount> = Vec::new();
+        let implied = instruction.data.accounts();
+        let metas = instruction.accounts.iter().map(|meta| (meta.pubkey, meta.is_writable));
+        for (pubkey, is_writable) in metas.chain(implied.into_iter().map(|pubkey| (pubkey, true))) {
+            let Some(index) = self.find_index(&pubkey) else {
+                continue;
+            };
+            match pre_accounts.iter().position(|pre| pre.index() == index) {
+                Some(position) if is_writable && !pre_accounts[position].is_writable() => {
+                    pre_accounts[position] = PreAccount::new(index, true, &self.accounts[index]);
+                }
+                Some(_) => {}
+                None => pre_accounts.push(PreAccount::new(index, is_writable, &self.accounts[index])),
+            }
+        }
+        for (index, account) in self.accounts.iter().enumerate() {
+            if !pre_accounts.iter().any(|pre| pre.index() == index) {
+                pre_accounts.push(PreAccount::new(index, false, account));
+            }
+        }
+        self.instruction_stack.push(InstructionFrame { program_id, signers, pre_accounts });
+        Ok(())
+    }
+
+    /// Whether the current instruction is signed by `pubkey`
+    pub fn is_signer(&self, pubkey: &Pubkey) -> bool {
+        self.instruction_stack.last().is_some_and(|frame| frame.signers.contains(pubkey))
+    }
+
+    /// Check the changes the current instruction made to its accounts since it was entered
+    /// or last verified, then accept them
+    pub fn verify_instruction(&mut self) -> Result<(), InstructionError> {
+        let Some(frame) = self.instruction_stack.last_mut() else {
+            return Ok(());
+        };
+        verify_accounts(&frame.program_id, &frame.pre_accounts, &self.accounts)?;
+        for pre in &mut frame.pre_accounts {
+            pre.update(&self.accounts[pre.index()]);
+        }
+        Ok(())
+    }
+
+    /// Leave the current instruction, handing its verified changes back to the caller
+    pub fn pop_instruction(&mut self) {
+        self.instruction_stack.pop();
+        if let Some(caller) = self.instruction_stack.last_mut() {
+            for pre in &mut caller.pre_accounts {
+                pre.update(&self.accounts[pre.index()]);
+            }
+        }
+    }
+
+    /// Number of instructions currently executing; 1 while running a top-level instruction
+    pub fn instruction_stack_height(&self) -> usize {
+        self.instruction_stack.len()
+    }
+
+    /// Index of the top-level instruction being executed
+    pub fn top_level_instruction_index(&self) -> usize {
+        self.top_level_instructions.saturating_sub(1)
+    }
+
+    /// Record an instruction invoked while executing top-level instruction `index`
+    pub fn record_inner_instruction(&mut self, index: u8, instruction: InnerInstruction) {
+        match self.inner_instructions.last_mut() {
+            Some(last) if last.index == index => last.instructions.push(instruction),
+            _ => self.inner_instructions.push(InnerInstructions {
+                index,
+                instructions: vec![instruction],
+            }),
+        }
+    }
+
+    pub fn inner_instructions(&self) -> &[InnerInstructions] {
+        &self.inner_instructions
+    }
+
+    /// Consume the context, yielding every loaded account with its final state
+    pub fn into_accounts(self) -> impl Iterator<Item = (Pubkey, Account)> {
+        self.account_keys.into_iter().zip(self.accounts)
+    }
+}
+
+impl Transaction {
+    /// Every account referenced by the transaction, payer first, without duplicates
+    pub fn account_keys(&self) -> Vec<Pubkey> {
+        let mut keys = vec![Pubkey::new(self.payer)];
+        let mut push = |key: Pubkey| {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        };
+
+        for instruction in &self.instructions {
+            for meta in &instruction.accounts {
+                push(meta.pubkey);
+            }
+            for pubkey in instruction.data.accounts() {
+                push(pubkey);
+            }
+            push(instruction.program_id);
+        }
+
+        keys
+    }
+}
+
+impl InstructionData {
+    /// Accounts named by typed system instruction data, all of which the instruction writes
+    pub fn accounts(&self) -> Vec<Pubkey> {
+        match self {
+            InstructionData::Transfer { from, to, .. } | InstructionData::CreateAccount { from, to, .. } => {
+                vec![Pubkey::new(*from), Pubkey::new(*to)]
+            }
+            InstructionData::Assign { account, .. } => vec![Pubkey::new(*account)],
+            InstructionData::Generic { .. } => vec![],
+        }
+    }
+
+    /// Accounts typed system instruction data takes lamports from or reassigns, which must sign it
+    pub fn signer_accounts(&self) -> Vec<Pubkey> {
+        match self {
+            InstructionData::Transfer { from, .. } | InstructionData::CreateAccount { from, .. } => {
+                vec![Pubkey::new(*from)]
+            }
+            InstructionData::Assign { account, .. } => vec![Pubkey::new(*account)],
+            InstructionData::Generic { .. } => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instruction(program_id: Pubkey, accounts: Vec<AccountMeta>) -> Instruction {
+        Instruction {
+            program_id,
+            accounts,
+            data: InstructionData::Generic { data: vec![] },
+        }
+    }
+
+    #[test]
+    fn test_instruction_stack_rules() {
+        let a = instruction(Pubkey::new([1u8; 32]), vec![]);
+        let b = instruction(Pubkey::new([2u8; 32]), vec![]);
+        let mut transaction_context = TransactionContext::new(vec![], vec![]);
+
+        transaction_context.push_instruction(&a).unwrap();
+        // Direct recursion is allowed
+        transaction_context.push_instruction(&a).unwrap();
+        transaction_context.push_instruction(&b).unwrap();
+        assert_eq!(transaction_context.push_instruction(&a), Err(InstructionError::ReentrancyNotAllowed));
+        transaction_context.push_instruction(&b).unwrap();
+        transaction_context.push_instruction(&b).unwrap();
+        assert_eq!(transaction_context.instruction_stack_height(), MAX_INSTRUCTION_STACK_DEPTH);
+        assert_eq!(transaction_context.push_instruction(&b), Err(InstructionError::CallDepth));
+
+        for _ in 0..MAX_INSTRUCTION_STACK_DEPTH {
+            transaction_context.pop_instruction();
+        }
+        transaction_context.push_instruction(&b).unwrap();
+        assert_eq!(transaction_context.top_level_instruction_index(), 1);
+    }
+
+    #[test]
+    fn test_nested_instruction_verification() {
+        let (caller, callee) = (Pubkey::new([1u8; 32]), Pubkey::new([2u8; 32]));
+        let (a, b) = (Pubkey::new([3u8; 32]), Pubkey::new([4u8; 32]));
+        let meta = |pubkey: Pubkey, is_writable: bool| AccountMeta { pubkey, is_signer: false, is_writable };
+        let mut transaction_context = TransactionContext::new(
+            vec![a, b],
+            vec![Account::new(100, vec![], caller.0), Account::new(100, vec![], callee.0)],
+        );
+
+        transaction_context.push_instruction(&instruction(caller, vec![meta(a, true), meta(b, true)])).unwrap();
+        transaction_context.get_mut(&a).unwrap().lamports -= 10;
+        transaction_context.get_mut(&b).unwrap().lamports += 10;
+        transaction_context.verify_instruction().unwrap();
+
+        // The callee may only debit its own accounts, but the caller accepts the result
+        transaction_context.push_instruction(&instruction(callee, vec![meta(a, true), meta(b, true)])).unwrap();
+        transaction_context.get_mut(&b).unwrap().lamports -= 50;
+        transaction_context.get_mut(&a).unwrap().lamports += 50;
+        transaction_context.verify_instruction().unwrap();
+        transaction_context.pop_instruction();
+        transaction_context.verify_instruction().unwrap();
+
+        // Read-only accounts must be left untouched
+        transaction_context.push_instruction(&instruction(callee, vec![meta(a, false), meta(b, true)])).unwrap();
+        transaction_context.get_mut(&b).unwrap().lamports -= 1;
+        transaction_context.get_mut(&a).unwrap().lamports += 1;
+        assert_eq!(transaction_context.verify_instruction(), Err(InstructionError::ReadonlyLamportChange));
+    }
+}
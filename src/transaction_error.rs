@@ -0,0 +1,382 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Reasons a transaction failed, mirroring Solana's `TransactionError`.
+///
+/// Variant order and the default serde representation match Solana, so the
+/// JSON form is identical to the `err` field returned by RPC.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Error)]
+pub enum TransactionError {
+    #[error("Account in use")]
+    AccountInUse,
+
+    #[error("Account loaded twice")]
+    AccountLoadedTwice,
+
+    #[error("Attempt to debit an account but found no record of a prior credit.")]
+    AccountNotFound,
+
+    #[error("Attempt to load a program that does not exist")]
+    ProgramAccountNotFound,
+
+    #[error("Insufficient funds for fee")]
+    InsufficientFundsForFee,
+
+    #[error("This account may not be used to pay transaction fees")]
+    InvalidAccountForFee,
+
+    #[error("This transaction has already been processed")]
+    AlreadyProcessed,
+
+    #[error("Blockhash not found")]
+    BlockhashNotFound,
+
+    #[error("Error processing Instruction {0}: {1}")]
+    InstructionError(u8, InstructionError),
+
+    #[error("Loader call chain is too deep")]
+    CallChainTooDeep,
+
+    #[error("Transaction requires a fee but has no signature present")]
+    MissingSignatureForFee,
+
+    #[error("Transaction contains an invalid account reference")]
+    InvalidAccountIndex,
+
+    #[error("Transaction did not pass signature verification")]
+    SignatureFailure,
+
+    #[error("This program may not be used for executing instructions")]
+    InvalidProgramForExecution,
+
+    #[error("Transaction failed to sanitize accounts offsets correctly")]
+    SanitizeFailure,
+
+    #[error("Transactions are currently disabled due to cluster maintenance")]
+    ClusterMaintenance,
+
+    #[error("Transaction processing left an account with an outstanding borrowed reference")]
+    AccountBorrowOutstanding,
+
+    #[error("Transaction would exceed max Block Cost Limit")]
+    WouldExceedMaxBlockCostLimit,
+
+    #[error("Transaction version is unsupported")]
+    UnsupportedVersion,
+
+    #[error("Transaction loads a writable account that cannot be written")]
+    InvalidWritableAccount,
+
+    #[error("Transaction would exceed max account limit within the block")]
+    WouldExceedMaxAccountCostLimit,
+
+    #[error("Transaction would exceed account data limit within the block")]
+    WouldExceedAccountDataBlockLimit,
+
+    #[error("Transaction locked too many accounts")]
+    TooManyAccountLocks,
+
+    #[error("Transaction loads an address table account that doesn't exist")]
+    AddressLookupTableNotFound,
+
+    #[error("Transaction loads an address table account with an invalid owner")]
+    InvalidAddressLookupTableOwner,
+
+    #[error("Transaction loads an address table account with invalid data")]
+    InvalidAddressLookupTableData,
+
+    #[error("Transaction address table lookup uses an invalid index")]
+    InvalidAddressLookupTableIndex,
+
+    #[error("Transaction leaves an account with a lower balance than rent-exempt minimum")]
+    InvalidRentPayingAccount,
+
+    #[error("Transaction would exceed max Vote Cost Limit")]
+    WouldExceedMaxVoteCostLimit,
+
+    #[error("Transaction would exceed total account data limit")]
+    WouldExceedAccountDataTotalLimit,
+
+    #[error("Transaction contains a duplicate instruction ({0}) that is not allowed")]
+    DuplicateInstruction(u8),
+
+    #[error("Transaction results in an account ({account_index}) with insufficient funds for rent")]
+    InsufficientFundsForRent { account_index: u8 },
+
+    #[error("Transaction exceeded max loaded accounts data size cap")]
+    MaxLoadedAccountsDataSizeExceeded,
+
+    #[error("LoadedAccountsDataSizeLimit set for transaction must be greater than 0.")]
+    InvalidLoadedAccountsDataSizeLimit,
+
+    #[error("ResanitizationNeeded")]
+    ResanitizationNeeded,
+
+    #[error("Execution of the program referenced by account at index {account_index} is temporarily restricted.")]
+    ProgramExecutionTemporarilyRestricted { account_index: u8 },
+
+    #[error("Sum of account balances before and after transaction do not match")]
+    UnbalancedTransaction,
+
+    #[error("Program cache hit max limit")]
+    ProgramCacheHitMaxLimit,
+
+    #[error("CommitCancelled")]
+    CommitCancelled,
+}
+
+/// Reasons an instruction failed, mirroring Solana's `InstructionError`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Error)]
+pub enum InstructionError {
+    #[error("generic instruction error")]
+    GenericError,
+
+    #[error("invalid program argument")]
+    InvalidArgument,
+
+    #[error("invalid instruction data")]
+    InvalidInstructionData,
+
+    #[error("invalid account data for instruction")]
+    InvalidAccountData,
+
+    #[error("account data too small for instruction")]
+    AccountDataTooSmall,
+
+    #[error("insufficient funds for instruction")]
+    InsufficientFunds,
+
+    #[error("incorrect program id for instruction")]
+    IncorrectProgramId,
+
+    #[error("missing required signature for instruction")]
+    MissingRequiredSignature,
+
+    #[error("instruction requires an uninitialized account")]
+    AccountAlreadyInitialized,
+
+    #[error("instruction requires an initialized account")]
+    UninitializedAccount,
+
+    #[error("sum of account balances before and after instruction do not match")]
+    UnbalancedInstruction,
+
+    #[error("instruction illegally modified the program id of an account")]
+    ModifiedProgramId,
+
+    #[error("instruction spent from the balance of an account it does not own")]
+    ExternalAccountLamportSpend,
+
+    #[error("instruction modified data of an account it does not own")]
+    ExternalAccountDataModified,
+
+    #[error("instruction changed the balance of a read-only account")]
+    ReadonlyLamportChange,
+
+    #[error("instruction modified data of a read-only account")]
+    ReadonlyDataModified,
+
+    #[error("instruction contains duplicate accounts")]
+    DuplicateAccountIndex,
+
+    #[error("instruction changed executable bit of an account")]
+    ExecutableModified,
+
+    #[error("instruction modified rent epoch of an account")]
+    RentEpochModified,
+
+    #[error("insufficient account keys for instruction")]
+    NotEnoughAccountKeys,
+
+    #[error("program other than the account's owner changed the size of the account data")]
+    AccountDataSizeChanged,
+
+    #[error("instruction expected an executable account")]
+    AccountNotExecutable,
+
+    #[error("instruction tries to borrow reference for an account which is already borrowed")]
+    AccountBorrowFailed,
+
+    #[error("instruction left account with an outstanding borrowed reference")]
+    AccountBorrowOutstanding,
+
+    #[error("instruction modifications of multiply-passed account differ")]
+    DuplicateAccountOutOfSync,
+
+    #[error("custom program error: {0:#x}")]
+    Custom(u32),
+
+    #[error("program returned invalid error code")]
+    InvalidError,
+
+    #[error("instruction changed executable accounts data")]
+    ExecutableDataModified,
+
+    #[error("instruction changed the balance of an executable account")]
+    ExecutableLamportChange,
+
+    #[error("executable accounts must be rent exempt")]
+    ExecutableAccountNotRentExempt,
+
+    #[error("Unsupported program id")]
+    UnsupportedProgramId,
+
+    #[error("Cross-program invocation call depth too deep")]
+    CallDepth,
+
+    #[error("An account required by the instruction is missing")]
+    MissingAccount,
+
+    #[error("Cross-program invocation reentrancy not allowed for this instruction")]
+    ReentrancyNotAllowed,
+
+    #[error("Length of the seed is too long for address generation")]
+    MaxSeedLengthExceeded,
+
+    #[error("Provided seeds do not result in a valid address")]
+    InvalidSeeds,
+
+    #[error("Failed to reallocate account data")]
+    InvalidRealloc,
+
+    #[error("Computational budget exceeded")]
+    ComputationalBudgetExceeded,
+
+    #[error("Cross-program invocation with unauthorized signer or writable account")]
+    PrivilegeEscalation,
+
+    #[error("Failed to create program execution environment")]
+    ProgramEnvironmentSetupFailure,
+
+    #[error("Program failed to complete")]
+    ProgramFailedToComplete,
+
+    #[error("Program failed to compile")]
+    ProgramFailedToCompile,
+
+    #[error("Account is immutable")]
+    Immutable,
+
+    #[error("Incorrect authority provided")]
+    IncorrectAuthority,
+
+    #[error("Failed to serialize or deserialize account data: {0}")]
+    BorshIoError(String),
+
+    #[error("An account does not have enough lamports to be rent-exempt")]
+    AccountNotRentExempt,
+
+    #[error("Invalid account owner")]
+    InvalidAccountOwner,
+
+    #[error("Program arithmetic overflowed")]
+    ArithmeticOverflow,
+
+    #[error("Unsupported sysvar")]
+    UnsupportedSysvar,
+
+    #[error("Provided owner is not allowed")]
+    IllegalOwner,
+
+    #[error("Accounts data allocations exceeded the maximum allowed per transaction")]
+    MaxAccountsDataAllocationsExceeded,
+
+    #[error("Max accounts exceeded")]
+    MaxAccountsExceeded,
+
+    #[error("Max instruction trace length exceeded")]
+    MaxInstructionTraceLengthExceeded,
+
+    #[error("Builtin programs must consume compute units")]
+    BuiltinProgramsMustConsumeComputeUnits,
+}
+
+/// Builtin program error codes live in the upper 32 bits of a program's return value
+const BUILTIN_BIT_SHIFT: u64 = 32;
+
+/// Decode the non-zero value a program returned, following Solana's `ProgramError` encoding
+impl From<u64> for InstructionError {
+    fn from(error: u64) -> Self {
+        if error >> BUILTIN_BIT_SHIFT == 0 {
+            return Self::Custom(error as u32);
+        }
+        if error as u32 != 0 {
+            return Self::InvalidError;
+        }
+
+        match error >> BUILTIN_BIT_SHIFT {
+            1 => Self::Custom(0),
+            2 => Self::InvalidArgument,
+            3 => Self::InvalidInstructionData,
+            4 => Self::InvalidAccountData,
+            5 => Self::AccountDataTooSmall,
+            6 => Self::InsufficientFunds,
+            7 => Self::IncorrectProgramId,
+            8 => Self::MissingRequiredSignature,
+            9 => Self::AccountAlreadyInitialized,
+            10 => Self::UninitializedAccount,
+            11 => Self::NotEnoughAccountKeys,
+            12 => Self::AccountBorrowFailed,
+            13 => Self::MaxSeedLengthExceeded,
+            14 => Self::InvalidSeeds,
+            15 => Self::BorshIoError("Unknown".to_string()),
+            16 => Self::AccountNotRentExempt,
+            17 => Self::UnsupportedSysvar,
+            18 => Self::IllegalOwner,
+            19 => Self::MaxAccountsDataAllocationsExceeded,
+            20 => Self::InvalidRealloc,
+            21 => Self::MaxInstructionTraceLengthExceeded,
+            22 => Self::BuiltinProgramsMustConsumeComputeUnits,
+            23 => Self::InvalidAccountOwner,
+            24 => Self::ArithmeticOverflow,
+            25 => Self::Immutable,
+            26 => Self::IncorrectAuthority,
+            _ => Self::InvalidError,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_rpc_json_shape() {
+        let err = TransactionError::InstructionError(0, InstructionError::Custom(1));
+        assert_eq!(serde_json::to_value(&err).unwrap(), json!({"InstructionError": [0, {"Custom": 1}]}));
+
+        let err = TransactionError::InstructionError(2, InstructionError::InvalidAccountData);
+        assert_eq!(serde_json::to_value(&err).unwrap(), json!({"InstructionError": [2, "InvalidAccountData"]}));
+
+        assert_eq!(serde_json::to_value(TransactionError::BlockhashNotFound).unwrap(), json!("BlockhashNotFound"));
+        assert_eq!(
+            serde_json::to_value(TransactionError::InsufficientFundsForRent { account_index: 3 }).unwrap(),
+            json!({"InsufficientFundsForRent": {"account_index": 3}})
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let err = TransactionError::InstructionError(1, InstructionError::BorshIoError("eof".to_string()));
+        let json = serde_json::to_string(&err).unwrap();
+        let parsed: TransactionError = serde_json::from_str(&json).unwrap();
+        assert_eq!(err, parsed);
+    }
+
+    #[test]
+    fn test_program_return_codes() {
+        assert_eq!(InstructionError::from(7), InstructionError::Custom(7));
+        assert_eq!(InstructionError::from(1 << 32), InstructionError::Custom(0));
+        assert_eq!(InstructionError::from(3 << 32), InstructionError::InvalidInstructionData);
+        assert_eq!(InstructionError::from(11 << 32), InstructionError::NotEnoughAccountKeys);
+        assert_eq!(InstructionError::from((3 << 32) | 1), InstructionError::InvalidError);
+        assert_eq!(InstructionError::from(1000 << 32), InstructionError::InvalidError);
+    }
+
+    #[test]
+    fn test_display_matches_solana() {
+        let err = TransactionError::InstructionError(0, InstructionError::Custom(1));
+        assert_eq!(err.to_string(), "Error processing Instruction 0: custom program error: 0x1");
+    }
+}
@@ -0,0 +1,265 @@
+use crate::solana_format::CompiledInstruction;
+use crate::types::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Size of an SPL token account
+const TOKEN_ACCOUNT_LEN: usize = 165;
+/// Size of an SPL token mint
+const TOKEN_MINT_LEN: usize = 82;
+/// Offset of `decimals` within a mint
+const TOKEN_MINT_DECIMALS_OFFSET: usize = 44;
+
+/// Token amount formatted like Solana's RPC `uiTokenAmount`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UiTokenAmount {
+    pub ui_amount: Option<f64>,
+    pub decimals: u8,
+    pub amount: String,
+    pub ui_amount_string: String,
+}
+
+impl UiTokenAmount {
+    pub fn new(amount: u64, decimals: u8) -> Self {
+        let ui_amount_string = if decimals == 0 {
+            amount.to_string()
+        } else {
+            let digits = format!("{:0>width$}", amount, width = decimals as usize + 1);
+            let (whole, fraction) = digits.split_at(digits.len() - decimals as usize);
+            let fraction = fraction.trim_end_matches('0');
+            if fraction.is_empty() {
+                whole.to_string()
+            } else {
+                format!("{}.{}", whole, fraction)
+            }
+        };
+
+        Self {
+            ui_amount: Some(amount as f64 / 10f64.powi(decimals as i32)),
+            decimals,
+            amount: amount.to_string(),
+            ui_amount_string,
+        }
+    }
+}
+
+/// Balance of a token account referenced by a transaction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionTokenBalance {
+    pub account_index: u8,
+    pub mint: String,
+    pub ui_token_amount: UiTokenAmount,
+    pub owner: String,
+    pub program_id: String,
+}
+
+/// Instruction invoked by a program, recorded in the order it executed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InnerInstruction {
+    pub instruction: CompiledInstruction,
+    pub stack_height: Option<u32>,
+}
+
+/// Inner instructions issued while executing one top-level instruction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InnerInstructions {
+    pub index: u8,
+    pub instructions: Vec<InnerInstruction>,
+}
+
+/// Data returned by the last program that set return data
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionReturnData {
+    pub program_id: Pubkey,
+    pub data: Vec<u8>,
+}
+
+/// Accounts loaded from address lookup tables
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoadedAddresses {
+    pub writable: Vec<Pubkey>,
+    pub readonly: Vec<Pubkey>,
+}
+
+/// Most transactions kept in the history before the oldest are evicted
+pub const MAX_TRANSACTION_HISTORY: usize = 10_000;
+
+/// A processed transaction with the slot it executed in and its outcome
+#[derive(Debug, Clone)]
+pub struct ConfirmedTransaction {
+    pub slot: u64,
+    pub transaction: Transaction,
+    pub result: TransactionResult,
+}
+
+/// Recently processed transactions by first signature, to look up their full outcome
+#[derive(Debug, Clone)]
+pub struct TransactionHistory {
+    capacity: usize,
+    transactions: HashMap<[u8; 64], ConfirmedTransaction>,
+    /// Signatures in the order they were recorded, oldest first
+    order: VecDeque<[u8; 64]>,
+}
+
+impl Default for TransactionHistory {
+    fn default() -> Self {
+        Self::new(MAX_TRANSACTION_HISTORY)
+    }
+}
+
+impl TransactionHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            transactions: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Record a processed transaction, evicting the oldest once full
+    pub fn insert(&mut self, signature: [u8; 64], transaction: ConfirmedTransaction) {
+        if self.transactions.insert(signature, transaction).is_none() {
+            self.order.push_back(signature);
+        }
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.transactions.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn get(&self, signature: &[u8; 64]) -> Option<&ConfirmedTransaction> {
+        self.transactions.get(signature)
+    }
+
+    /// Drop the transactions executed in `slots`, e.g. when their forks are discarded
+    pub fn remove_slots(&mut self, slots: &[u64]) {
+        self.transactions.retain(|_, transaction| !slots.contains(&transaction.slot));
+        let transactions = &self.transactions;
+        self.order.retain(|signature| transactions.contains_key(signature));
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+}
+
+/// Collect balances of every SPL token account among `accounts`.
+///
+/// `find_account` is used to resolve the mint of each token account for its decimals.
+pub fn collect_token_balances(
+    account_keys: &[Pubkey],
+    accounts: &[Account],
+    find_account: impl Fn(&Pubkey) -> Option<Account>,
+) -> Vec<TransactionTokenBalance> {
+    let token_program = Pubkey::token_program();
+
+    account_keys
+        .iter()
+        .zip(accounts)
+        .enumerate()
+        .filter(|(_, (_, account))| {
+            account.owner == token_program.0 && account.data.len() == TOKEN_ACCOUNT_LEN
+        })
+        .filter_map(|(index, (_, account))| {
+            let mint = Pubkey::new(account.data[0..32].try_into().ok()?);
+            let owner = Pubkey::new(account.data[32..64].try_into().ok()?);
+            let amount = u64::from_le_bytes(account.data[64..72].try_into().ok()?);
+
+            let mint_account = account_keys
+                .iter()
+                .position(|key| *key == mint)
+                .map(|mint_index| accounts[mint_index].clone())
+                .or_else(|| find_account(&mint))?;
+            if mint_account.data.len() != TOKEN_MINT_LEN {
+                return None;
+            }
+            let decimals = mint_account.data[TOKEN_MINT_DECIMALS_OFFSET];
+
+            Some(TransactionTokenBalance {
+                account_index: index as u8,
+                mint: mint.to_string(),
+                ui_token_amount: UiTokenAmount::new(amount, decimals),
+                owner: owner.to_string(),
+                program_id: token_program.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ui_token_amount_formatting() {
+        assert_eq!(UiTokenAmount::new(1_500_000, 6).ui_amount_string, "1.5");
+        assert_eq!(UiTokenAmount::new(42, 0).ui_amount_string, "42");
+        assert_eq!(UiTokenAmount::new(5, 3).ui_amount_string, "0.005");
+        assert_eq!(UiTokenAmount::new(2_000, 3).ui_amount_string, "2");
+        assert_eq!(UiTokenAmount::new(1_500_000, 6).ui_amount, Some(1.5));
+    }
+
+    #[test]
+    fn test_transaction_history_eviction() {
+        let confirmed = |slot| ConfirmedTransaction {
+            slot,
+            transaction: Transaction {
+                instructions: vec![],
+                signatures: vec![],
+                payer: [0u8; 32],
+                recent_blockhash: [0u8; 32],
+            },
+            result: TransactionResult::default(),
+        };
+        let mut history = TransactionHistory::new(2);
+        history.insert([1u8; 64], confirmed(1));
+        history.insert([2u8; 64], confirmed(2));
+        history.insert([3u8; 64], confirmed(2));
+        assert_eq!(history.len(), 2);
+        assert!(history.get(&[1u8; 64]).is_none());
+        assert_eq!(history.get(&[3u8; 64]).unwrap().slot, 2);
+
+        history.remove_slots(&[2]);
+        assert!(history.is_empty());
+        history.insert([4u8; 64], confirmed(3));
+        assert_eq!(history.len(), 1);
+    }
+
+    #[test]
+    fn test_collect_token_balances() {
+        let mint = Pubkey::new([1u8; 32]);
+        let owner = Pubkey::new([2u8; 32]);
+        let token_account_key = Pubkey::new([3u8; 32]);
+
+        let mut mint_data = vec![0u8; TOKEN_MINT_LEN];
+        mint_data[TOKEN_MINT_DECIMALS_OFFSET] = 2;
+        let mint_account = Account::new(1, mint_data, Pubkey::token_program().0);
+
+        let mut token_data = vec![0u8; TOKEN_ACCOUNT_LEN];
+        token_data[0..32].copy_from_slice(&mint.0);
+        token_data[32..64].copy_from_slice(&owner.0);
+        token_data[64..72].copy_from_slice(&1234u64.to_le_bytes());
+        let token_account = Account::new(1, token_data, Pubkey::token_program().0);
+
+        let keys = vec![Pubkey::new([9u8; 32]), token_account_key];
+        let accounts = vec![Account::new(1, vec![], [0u8; 32]), token_account];
+        let balances = collect_token_balances(&keys, &accounts, |key| {
+            (*key == mint).then(|| mint_account.clone())
+        });
+
+        assert_eq!(balances.len(), 1);
+        assert_eq!(balances[0].account_index, 1);
+        assert_eq!(balances[0].mint, mint.to_string());
+        assert_eq!(balances[0].owner, owner.to_string());
+        assert_eq!(balances[0].ui_token_amount.ui_amount_string, "12.34");
+    }
+}
@@ -1,8 +1,10 @@
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, Bytes};
+use crate::transaction_error::TransactionError;
+use crate::transaction_status::{InnerInstructions, LoadedAddresses, TransactionReturnData, TransactionTokenBalance};
 use std::collections::HashMap;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Pubkey(pub [u8; 32]);
 
 impl Pubkey {
@@ -32,9 +34,63 @@ impl Pubkey {
             28, 180, 133, 237, 95, 91, 55, 145, 58, 140, 245, 133, 126, 255, 0, 169,
         ])
     }
+
+    /// BPFLoader2111111111111111111111111111111111
+    pub fn bpf_loader() -> Self {
+        Self([
+            2, 168, 246, 145, 78, 136, 161, 110, 57, 90, 225, 40, 148, 143, 250, 105,
+            86, 147, 55, 104, 24, 221, 71, 67, 82, 33, 243, 198, 0, 0, 0, 0,
+        ])
+    }
+
+    /// BPFLoader1111111111111111111111111111111111
+    pub fn bpf_loader_deprecated() -> Self {
+        Self([
+            2, 168, 246, 145, 78, 136, 161, 107, 189, 35, 149, 133, 95, 100, 4, 217,
+            180, 244, 86, 183, 130, 27, 176, 20, 87, 73, 66, 140, 0, 0, 0, 0,
+        ])
+    }
+
+    /// BPFLoaderUpgradeab1e11111111111111111111111
+    pub fn bpf_loader_upgradeable() -> Self {
+        Self([
+            2, 168, 246, 145, 78, 136, 161, 176, 226, 16, 21, 62, 247, 99, 174, 43,
+            0, 194, 185, 61, 22, 193, 36, 210, 192, 83, 122, 16, 4, 128, 0, 0,
+        ])
+    }
+
+    /// NativeLoader1111111111111111111111111111111, owner of builtin program accounts
+    pub fn native_loader() -> Self {
+        Self([
+            5, 135, 132, 191, 20, 139, 164, 40, 47, 176, 18, 87, 72, 136, 169, 241,
+            83, 160, 125, 173, 247, 101, 192, 69, 92, 154, 151, 3, 128, 0, 0, 0,
+        ])
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Parse a base58 address
+impl std::str::FromStr for Pubkey {
+    type Err = crate::TerminatorError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let bytes = bs58::decode(s)
+            .into_vec()
+            .map_err(|_| crate::TerminatorError::SerializationError(format!("Invalid base58 pubkey: {}", s)))?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| crate::TerminatorError::SerializationError(format!("Invalid pubkey length: {}", s)))?;
+        Ok(Self(bytes))
+    }
+}
+
+/// Base58 representation, as used by the Solana CLI and RPC
+impl std::fmt::Display for Pubkey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", bs58::encode(&self.0).into_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Account {
     pub lamports: u64,
     pub data: Vec<u8>,
@@ -91,6 +147,23 @@ pub struct Transaction {
     pub recent_blockhash: [u8; 32],
 }
 
+#[cfg(test)]
+impl Transaction {
+    /// A system transfer between the accounts `[from; 32]` and `[to; 32]`, signed as `[from; 64]`
+    pub(crate) fn transfer_for_test(from: u8, to: u8, lamports: u64, recent_blockhash: [u8; 32]) -> Self {
+        Transaction {
+            instructions: vec![Instruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![],
+                data: InstructionData::Transfer { from: [from; 32], to: [to; 32], lamports },
+            }],
+            signatures: vec![[from; 64]],
+            payer: [from; 32],
+            recent_blockhash,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum InstructionData {
     Transfer {
@@ -121,12 +194,42 @@ pub enum InstructionData {
     },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TransactionResult {
     pub success: bool,
     pub compute_units_consumed: u64,
     pub logs: Vec<String>,
-    pub error: Option<String>,
+    pub error: Option<TransactionError>,
+    /// Fee for the transaction at the bank's lamports-per-signature rate
+    pub fee: u64,
+    /// Lamports of every account key (see `Transaction::account_keys`) before and after execution
+    pub pre_balances: Vec<u64>,
+    pub post_balances: Vec<u64>,
+    pub pre_token_balances: Vec<TransactionTokenBalance>,
+    pub post_token_balances: Vec<TransactionTokenBalance>,
+    /// Instructions invoked by programs, grouped by top-level instruction index
+    pub inner_instructions: Vec<InnerInstructions>,
+    pub return_data: Option<TransactionReturnData>,
+    pub loaded_addresses: LoadedAddresses,
+}
+
+/// Options for `TerminatorRuntime::simulate_transaction`, mirroring `simulateTransaction`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SimulationConfig {
+    /// Verify signatures before executing
+    pub sig_verify: bool,
+    /// Execute against the bank's latest blockhash instead of the transaction's own
+    pub replace_recent_blockhash: bool,
+    /// Accounts whose post-simulation state should be returned
+    pub accounts: Option<Vec<Pubkey>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulationResult {
+    pub result: TransactionResult,
+    /// Post-simulation state of the requested accounts, `None` for accounts that don't exist
+    pub accounts: Option<Vec<Option<Account>>>,
+    pub replacement_blockhash: Option<[u8; 32]>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -137,6 +240,8 @@ pub struct RuntimeConfig {
     pub performance: PerformanceSettings,
     pub networking: NetworkingSettings,
     pub testing: TestingSettings,
+    #[serde(default)]
+    pub block_production: BlockProductionSettings,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -152,6 +257,12 @@ pub struct BankSettings {
     pub initial_lamports: u64,
     pub rent_collection_enabled: bool,
     pub fee_rate_governor_enabled: bool,
+    /// Directory of the accounts db persisting rooted state, kept in memory if unset
+    #[serde(default)]
+    pub accounts_path: Option<String>,
+    /// Genesis file to start from; without one a faucet is funded with `initial_lamports`
+    #[serde(default)]
+    pub genesis_path: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -172,6 +283,27 @@ pub struct PerformanceSettings {
 pub struct NetworkingSettings {
     pub max_connections: u32,
     pub connection_timeout_ms: u32,
+    /// Address the JSON-RPC server listens on
+    #[serde(default = "default_rpc_bind_address")]
+    pub rpc_bind_address: String,
+    /// Address the WebSocket PubSub server listens on
+    #[serde(default = "default_pubsub_bind_address")]
+    pub pubsub_bind_address: String,
+    /// UDP address transaction packets are ingested on
+    #[serde(default = "default_tpu_bind_address")]
+    pub tpu_bind_address: String,
+}
+
+fn default_rpc_bind_address() -> String {
+    "127.0.0.1:8899".to_string()
+}
+
+fn default_pubsub_bind_address() -> String {
+    "127.0.0.1:8900".to_string()
+}
+
+fn default_tpu_bind_address() -> String {
+    "127.0.0.1:8003".to_string()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -181,6 +313,28 @@ pub struct TestingSettings {
     pub property_testing: bool,
 }
 
+/// Slot clock and PoH parameters for the local block producer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlockProductionSettings {
+    pub slot_duration_ms: u64,
+    pub ticks_per_slot: u64,
+    pub hashes_per_tick: u64,
+    /// Most queued transactions executed in one batch
+    pub max_batch_size: usize,
+}
+
+impl Default for BlockProductionSettings {
+    fn default() -> Self {
+        Self {
+            slot_duration_ms: crate::sysvar::DEFAULT_MS_PER_SLOT,
+            ticks_per_slot: crate::poh::DEFAULT_TICKS_PER_SLOT,
+            hashes_per_tick: crate::poh::DEFAULT_HASHES_PER_TICK,
+            max_batch_size: 64,
+        }
+    }
+}
+
 impl Default for RuntimeConfig {
     fn default() -> Self {
         Self {
@@ -194,6 +348,8 @@ impl Default for RuntimeConfig {
                 initial_lamports: 1_000_000_000_000,
                 rent_collection_enabled: true,
                 fee_rate_governor_enabled: true,
+                accounts_path: None,
+                genesis_path: None,
             },
             logging: LoggingSettings {
                 level: "info".to_string(),
@@ -208,12 +364,16 @@ impl Default for RuntimeConfig {
             networking: NetworkingSettings {
                 max_connections: 1000,
                 connection_timeout_ms: 5000,
+                rpc_bind_address: default_rpc_bind_address(),
+                pubsub_bind_address: default_pubsub_bind_address(),
+                tpu_bind_address: default_tpu_bind_address(),
             },
             testing: TestingSettings {
                 fuzz_iterations: 1000,
                 differential_testing: true,
                 property_testing: true,
             },
+            block_production: BlockProductionSettings::default(),
         }
     }
 }
@@ -223,6 +383,8 @@ pub struct BankState {
     pub accounts: HashMap<Pubkey, Account>,
     pub slot: u64,
     pub blockhash: [u8; 32],
+    /// Hash of the last frozen slot, committing to its accounts and blockhash
+    pub bank_hash: [u8; 32],
     pub fee_calculator: FeeCalculator,
 }
 
@@ -232,12 +394,19 @@ impl BankState {
             accounts: HashMap::new(),
             slot: 0,
             blockhash: [0u8; 32],
+            bank_hash: [0u8; 32],
             fee_calculator: FeeCalculator::default(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+impl Default for BankState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FeeCalculator {
     pub lamports_per_signature: u64,
 }
@@ -0,0 +1,791 @@
+//! The BPF Loader Upgradeable program: programs are written into a buffer account,
+//! then deployed into a program account that points at a separate program data account.
+
+use crate::bpf_loader::MAX_PERMITTED_DATA_INCREASE;
+use crate::crypto::AddressDerivation;
+use crate::elf_loader;
+use crate::invoke_context::InvokeContext;
+use crate::syscalls::create_syscall_registry;
+use crate::transaction_context::TransactionContext;
+use crate::transaction_error::InstructionError;
+use crate::types::*;
+use serde::{Deserialize, Serialize};
+
+/// Size of the `Buffer` state preceding a buffer's program bytes
+pub const BUFFER_METADATA_SIZE: usize = 37;
+/// Size of the `Program` state, the whole of a program account's data
+pub const PROGRAM_SIZE: usize = 36;
+/// Size of the `ProgramData` state preceding a program's ELF
+pub const PROGRAMDATA_METADATA_SIZE: usize = 45;
+/// Largest account the loader may create or extend to
+pub const MAX_PERMITTED_DATA_LENGTH: usize = 10 * 1024 * 1024;
+
+type InstructionResult = std::result::Result<(), InstructionError>;
+
+/// State stored at the start of every account owned by the loader
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpgradeableLoaderState {
+    Uninitialized,
+    Buffer {
+        authority_address: Option<Pubkey>,
+    },
+    Program {
+        programdata_address: Pubkey,
+    },
+    ProgramData {
+        slot: u64,
+        upgrade_authority_address: Option<Pubkey>,
+    },
+}
+
+impl UpgradeableLoaderState {
+    pub fn deserialize(data: &[u8]) -> Result<Self, InstructionError> {
+        bincode::deserialize(data).map_err(|_| InstructionError::InvalidAccountData)
+    }
+
+    /// Write the state over the start of `data`
+    pub fn serialize_into(&self, data: &mut [u8]) -> InstructionResult {
+        bincode::serialize_into(data, self).map_err(|_| InstructionError::AccountDataTooSmall)
+    }
+}
+
+/// Instructions of the upgradeable loader, bincode-encoded like Solana's
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpgradeableLoaderInstruction {
+    /// Accounts: buffer (writable), buffer authority
+    InitializeBuffer,
+    /// Accounts: buffer (writable), buffer authority (signer)
+    Write { offset: u32, bytes: Vec<u8> },
+    /// Accounts: payer (writable, signer), program data (writable), program (writable),
+    /// buffer (writable), rent sysvar, clock sysvar, system program, upgrade authority (signer)
+    DeployWithMaxDataLen { max_data_len: usize },
+    /// Accounts: program data (writable), program (writable), buffer (writable),
+    /// spill (writable), rent sysvar, clock sysvar, upgrade authority (signer)
+    Upgrade,
+    /// Accounts: buffer or program data (writable), current authority (signer), new authority (optional)
+    SetAuthority,
+    /// Accounts: account to close (writable), recipient (writable), authority (signer, optional),
+    /// program (writable, when closing program data)
+    Close,
+    /// Accounts: program data (writable), program (writable), system program, payer (writable, signer)
+    ExtendProgram { additional_bytes: u32 },
+}
+
+impl Pubkey {
+    /// Program data account of an upgradeable program
+    pub fn programdata_address(program_id: &Pubkey) -> Self {
+        let (address, _) = AddressDerivation::derive_program_address(&[&program_id.0], &Self::bpf_loader_upgradeable().0)
+            .expect("a program data address exists for every program id");
+        Self::new(address)
+    }
+}
+
+/// Program data account referenced by an upgradeable program account
+pub fn programdata_address_of(program: &Account) -> Result<Pubkey, InstructionError> {
+    match UpgradeableLoaderState::deserialize(&program.data)? {
+        UpgradeableLoaderState::Program { programdata_address } => Ok(programdata_address),
+        _ => Err(InstructionError::InvalidAccountData),
+    }
+}
+
+/// The deployed ELF and its deployment slot, if `programdata` holds a program
+pub fn programdata_elf(programdata: &Account) -> Result<(&[u8], u64), InstructionError> {
+    match UpgradeableLoaderState::deserialize(&programdata.data)? {
+        UpgradeableLoaderState::ProgramData { slot, .. } => Ok((&programdata.data[PROGRAMDATA_METADATA_SIZE..], slot)),
+        _ => Err(InstructionError::InvalidAccountData),
+    }
+}
+
+/// Accounts of the instruction being processed, by position
+struct InstructionAccounts<'a> {
+    instruction: &'a Instruction,
+}
+
+impl InstructionAccounts<'_> {
+    fn meta(&self, index: usize) -> Result<&AccountMeta, InstructionError> {
+        self.instruction.accounts.get(index).ok_or(InstructionError::NotEnoughAccountKeys)
+    }
+
+    fn key(&self, index: usize) -> Result<Pubkey, InstructionError> {
+        self.meta(index).map(|meta| meta.pubkey)
+    }
+
+    fn writable_key(&self, index: usize) -> Result<Pubkey, InstructionError> {
+        let meta = self.meta(index)?;
+        if !meta.is_writable {
+            return Err(InstructionError::InvalidArgument);
+        }
+        Ok(meta.pubkey)
+    }
+
+    /// Key of an account that must have signed, typically an authority
+    fn signer_key(&self, index: usize) -> Result<Pubkey, InstructionError> {
+        let meta = self.meta(index)?;
+        if !meta.is_signer {
+            return Err(InstructionError::MissingRequiredSignature);
+        }
+        Ok(meta.pubkey)
+    }
+}
+
+fn get<'a>(transaction_context: &'a TransactionContext, pubkey: &Pubkey) -> Result<&'a Account, InstructionError> {
+    transaction_context.get(pubkey).ok_or(InstructionError::MissingAccount)
+}
+
+fn get_mut<'a>(transaction_context: &'a mut TransactionContext, pubkey: &Pubkey) -> Result<&'a mut Account, InstructionError> {
+    transaction_context.get_mut(pubkey).ok_or(InstructionError::MissingAccount)
+}
+
+fn check_owner(account: &Account) -> InstructionResult {
+    if account.owner != Pubkey::bpf_loader_upgradeable().0 {
+        return Err(InstructionError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+fn check_authority(
+    authority: Option<Pubkey>,
+    accounts: &InstructionAccounts,
+    index: usize,
+    execution_context: &mut ExecutionContext,
+) -> InstructionResult {
+    let Some(authority) = authority else {
+        execution_context.log("Account is immutable".to_string());
+        return Err(InstructionError::Immutable);
+    };
+    if accounts.key(index)? != authority {
+        execution_context.log("Incorrect authority provided".to_string());
+        return Err(InstructionError::IncorrectAuthority);
+    }
+    if accounts.signer_key(index).is_err() {
+        execution_context.log("Authority did not sign".to_string());
+        return Err(InstructionError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+
+/// Move every lamport of `from` into `to`
+fn drain(transaction_context: &mut TransactionContext, from: &Pubkey, to: &Pubkey) -> InstructionResult {
+    let lamports = std::mem::take(&mut get_mut(transaction_context, from)?.lamports);
+    let recipient = get_mut(transaction_context, to)?;
+    recipient.lamports = recipient
+        .lamports
+        .checked_add(lamports)
+        .ok_or(InstructionError::ArithmeticOverflow)?;
+    Ok(())
+}
+
+/// Program bytes of a buffer whose authority is the signer at `authority_index`
+fn buffer_program_bytes(
+    transaction_context: &TransactionContext,
+    buffer_key: &Pubkey,
+    accounts: &InstructionAccounts,
+    authority_index: usize,
+    execution_context: &mut ExecutionContext,
+) -> Result<Vec<u8>, InstructionError> {
+    let buffer = get(transaction_context, buffer_key)?;
+    check_owner(buffer)?;
+    let UpgradeableLoaderState::Buffer { authority_address } = UpgradeableLoaderState::deserialize(&buffer.data)? else {
+        execution_context.log("Invalid Buffer account".to_string());
+        return Err(InstructionError::InvalidArgument);
+    };
+    let bytes = buffer.data[BUFFER_METADATA_SIZE..].to_vec();
+    check_authority(authority_address, accounts, authority_index, execution_context)?;
+    if bytes.is_empty() {
+        execution_context.log("Buffer account too small".to_string());
+        return Err(InstructionError::InvalidAccountData);
+    }
+    Ok(bytes)
+}
+
+/// Load and verify `elf` the same way it will be loaded when invoked
+fn verify_elf(elf: &[u8], execution_context: &mut ExecutionContext) -> InstructionResult {
+    elf_loader::load_program(elf, &create_syscall_registry()).map_err(|error| {
+        execution_context.log(format!("{}", error));
+        InstructionError::InvalidAccountData
+    })?;
+    Ok(())
+}
+
+/// Execute an instruction of the upgradeable loader.
+///
+/// Accounts owned by the system program are created and debited by invoking it.
+pub fn process_instruction(invoke_context: &mut InvokeContext) -> InstructionResult {
+    let instruction = invoke_context.instruction();
+    let sysvars = invoke_context.sysvars();
+    let InstructionData::Generic { data } = &instruction.data else {
+        return Err(InstructionError::InvalidInstructionData);
+    };
+    let loader_instruction: UpgradeableLoaderInstruction =
+        bincode::deserialize(data).map_err(|_| InstructionError::InvalidInstructionData)?;
+    let accounts = InstructionAccounts { instruction };
+    let transaction_context = &mut *invoke_context.transaction_context;
+    let execution_context = &mut *invoke_context.execution_context;
+
+    match loader_instruction {
+        UpgradeableLoaderInstruction::InitializeBuffer => {
+            let buffer_key = accounts.writable_key(0)?;
+            let authority_address = Some(accounts.key(1)?);
+            let buffer = get_mut(transaction_context, &buffer_key)?;
+            check_owner(buffer)?;
+            if UpgradeableLoaderState::deserialize(&buffer.data)? != UpgradeableLoaderState::Uninitialized {
+                execution_context.log("Buffer account already initialized".to_string());
+                return Err(InstructionError::AccountAlreadyInitialized);
+            }
+            UpgradeableLoaderState::Buffer { authority_address }.serialize_into(&mut buffer.data)
+        }
+
+        UpgradeableLoaderInstruction::Write { offset, bytes } => {
+            let buffer_key = accounts.writable_key(0)?;
+            let buffer = get(transaction_context, &buffer_key)?;
+            check_owner(buffer)?;
+            let UpgradeableLoaderState::Buffer { authority_address } = UpgradeableLoaderState::deserialize(&buffer.data)? else {
+                execution_context.log("Invalid Buffer account".to_string());
+                return Err(InstructionError::InvalidAccountData);
+            };
+            check_authority(authority_address, &accounts, 1, execution_context)?;
+
+            let start = BUFFER_METADATA_SIZE + offset as usize;
+            let buffer = get_mut(transaction_context, &buffer_key)?;
+            let Some(target) = buffer.data.get_mut(start..start + bytes.len()) else {
+                execution_context.log(format!("Write overflow: {} < {}", buffer.data.len(), start + bytes.len()));
+                return Err(InstructionError::AccountDataTooSmall);
+            };
+            target.copy_from_slice(&bytes);
+            Ok(())
+        }
+
+        UpgradeableLoaderInstruction::DeployWithMaxDataLen { max_data_len } => {
+            let payer_key = accounts.writable_key(0)?;
+            accounts.signer_key(0)?;
+            let programdata_key = accounts.writable_key(1)?;
+            let program_key = accounts.writable_key(2)?;
+            let buffer_key = accounts.writable_key(3)?;
+
+            let program = get(transaction_context, &program_key)?;
+            check_owner(program)?;
+            if program.data.len() < PROGRAM_SIZE {
+                execution_context.log("Program account too small".to_string());
+                return Err(InstructionError::AccountDataTooSmall);
+            }
+            if program.lamports < sysvars.rent.minimum_balance(program.data.len()) {
+                execution_context.log("Program account not rent-exempt".to_string());
+                return Err(InstructionError::ExecutableAccountNotRentExempt);
+            }
+            if UpgradeableLoaderState::deserialize(&program.data)? != UpgradeableLoaderState::Uninitialized {
+                execution_context.log("Program account already initialized".to_string());
+                return Err(InstructionError::AccountAlreadyInitialized);
+            }
+
+            let elf = buffer_program_bytes(transaction_context, &buffer_key, &accounts, 7, execution_context)?;
+            let authority = accounts.key(7)?;
+            if max_data_len < elf.len() {
+                execution_context.log("Max data length is too small to hold Buffer data".to_string());
+                return Err(InstructionError::AccountDataTooSmall);
+            }
+            let programdata_len = PROGRAMDATA_METADATA_SIZE.saturating_add(max_data_len);
+            if programdata_len > MAX_PERMITTED_DATA_LENGTH {
+                execution_context.log("Max data length is too large".to_string());
+                return Err(InstructionError::InvalidArgument);
+            }
+            if programdata_key != Pubkey::programdata_address(&program_key) {
+                execution_context.log("ProgramData address is not derived".to_string());
+                return Err(InstructionError::InvalidArgument);
+            }
+            let existing = get(transaction_context, &programdata_key)?;
+            if existing.lamports != 0 || !existing.data.is_empty() {
+                execution_context.log("ProgramData account already in use".to_string());
+                return Err(InstructionError::AccountAlreadyInitialized);
+            }
+            verify_elf(&elf, execution_context)?;
+
+            // The payer receives the buffer's lamports, then funds the program data account
+            drain(transaction_context, &buffer_key, &payer_key)?;
+            let create_programdata = Instruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![],
+                data: InstructionData::CreateAccount {
+                    from: payer_key.0,
+                    to: programdata_key.0,
+                    lamports: sysvars.rent.minimum_balance(programdata_len),
+                    space: programdata_len as u64,
+                    owner: Pubkey::bpf_loader_upgradeable().0,
+                },
+            };
+            invoke_context.invoke(&create_programdata, &[])?;
+            let transaction_context = &mut *invoke_context.transaction_context;
+            let execution_context = &mut *invoke_context.execution_context;
+
+            let programdata = get_mut(transaction_context, &programdata_key)?;
+            UpgradeableLoaderState::ProgramData {
+                slot: sysvars.clock.slot,
+                upgrade_authority_address: Some(authority),
+            }
+            .serialize_into(&mut programdata.data)?;
+            programdata.data[PROGRAMDATA_METADATA_SIZE..PROGRAMDATA_METADATA_SIZE + elf.len()].copy_from_slice(&elf);
+
+            let program = get_mut(transaction_context, &program_key)?;
+            UpgradeableLoaderState::Program { programdata_address: programdata_key }.serialize_into(&mut program.data)?;
+            program.executable = true;
+            get_mut(transaction_context, &buffer_key)?.data.truncate(BUFFER_METADATA_SIZE);
+
+            execution_context.log(format!("Deployed program {}", program_key));
+            Ok(())
+        }
+
+        UpgradeableLoaderInstruction::Upgrade => {
+            let programdata_key = accounts.writable_key(0)?;
+            let program_key = accounts.writable_key(1)?;
+            let buffer_key = accounts.writable_key(2)?;
+            let spill_key = accounts.writable_key(3)?;
+
+            let program = get(transaction_context, &program_key)?;
+            check_owner(program)?;
+            if !program.executable {
+                execution_context.log("Program account not executable".to_string());
+                return Err(InstructionError::AccountNotExecutable);
+            }
+            if programdata_address_of(program)? != programdata_key {
+                execution_context.log("Program and ProgramData account mismatch".to_string());
+                return Err(InstructionError::InvalidArgument);
+            }
+
+            let programdata = get(transaction_context, &programdata_key)?;
+            check_owner(programdata)?;
+            let UpgradeableLoaderState::ProgramData { slot, upgrade_authority_address } =
+                UpgradeableLoaderState::deserialize(&programdata.data)?
+            else {
+                execution_context.log("Invalid ProgramData account".to_string());
+                return Err(InstructionError::InvalidAccountData);
+            };
+            let capacity = programdata.data.len() - PROGRAMDATA_METADATA_SIZE;
+            if slot == sysvars.clock.slot {
+                execution_context.log("Program was deployed in this block already".to_string());
+                return Err(InstructionError::InvalidArgument);
+            }
+            check_authority(upgrade_authority_address, &accounts, 6, execution_context)?;
+
+            let elf = buffer_program_bytes(transaction_context, &buffer_key, &accounts, 6, execution_context)?;
+            if elf.len() > capacity {
+                execution_context.log("ProgramData account not large enough".to_string());
+                return Err(InstructionError::AccountDataTooSmall);
+            }
+            verify_elf(&elf, execution_context)?;
+
+            let programdata = get_mut(transaction_context, &programdata_key)?;
+            UpgradeableLoaderState::ProgramData {
+                slot: sysvars.clock.slot,
+                upgrade_authority_address,
+            }
+            .serialize_into(&mut programdata.data)?;
+            let program_bytes = &mut programdata.data[PROGRAMDATA_METADATA_SIZE..];
+            program_bytes[..elf.len()].copy_from_slice(&elf);
+            program_bytes[elf.len()..].fill(0);
+
+            // Excess program data lamports and the whole buffer go to the spill account
+            let required = sysvars.rent.minimum_balance(programdata.data.len());
+            let excess = programdata.lamports.saturating_sub(required);
+            programdata.lamports -= excess;
+            drain(transaction_context, &buffer_key, &spill_key)?;
+            let spill = get_mut(transaction_context, &spill_key)?;
+            spill.lamports = spill.lamports.checked_add(excess).ok_or(InstructionError::ArithmeticOverflow)?;
+            get_mut(transaction_context, &buffer_key)?.data.truncate(BUFFER_METADATA_SIZE);
+
+            execution_context.log(format!("Upgraded program {}", program_key));
+            Ok(())
+        }
+
+        UpgradeableLoaderInstruction::SetAuthority => {
+            let account_key = accounts.writable_key(0)?;
+            let new_authority = accounts.key(2).ok();
+            let account = get(transaction_context, &account_key)?;
+            check_owner(account)?;
+
+            let state = match UpgradeableLoaderState::deserialize(&account.data)? {
+                UpgradeableLoaderState::Buffer { authority_address } => {
+                    if new_authority.is_none() {
+                        execution_context.log("Buffer authority is not optional".to_string());
+                        return Err(InstructionError::IncorrectAuthority);
+                    }
+                    check_authority(authority_address, &accounts, 1, execution_context)?;
+                    UpgradeableLoaderState::Buffer { authority_address: new_authority }
+                }
+                UpgradeableLoaderState::ProgramData { slot, upgrade_authority_address } => {
+                    check_authority(upgrade_authority_address, &accounts, 1, execution_context)?;
+                    UpgradeableLoaderState::ProgramData {
+                        slot,
+                        upgrade_authority_address: new_authority,
+                    }
+                }
+                _ => {
+                    execution_context.log("Account does not support authorities".to_string());
+                    return Err(InstructionError::InvalidArgument);
+                }
+            };
+            state.serialize_into(&mut get_mut(transaction_context, &account_key)?.data)?;
+            execution_context.log(format!("New authority {:?}", new_authority));
+            Ok(())
+        }
+
+        UpgradeableLoaderInstruction::Close => {
+            let account_key = accounts.writable_key(0)?;
+            let recipient_key = accounts.writable_key(1)?;
+            if account_key == recipient_key {
+                execution_context.log("Recipient is the same as the account being closed".to_string());
+                return Err(InstructionError::InvalidArgument);
+            }
+            let account = get(transaction_context, &account_key)?;
+            check_owner(account)?;
+
+            match UpgradeableLoaderState::deserialize(&account.data)? {
+                UpgradeableLoaderState::Uninitialized => {}
+                UpgradeableLoaderState::Buffer { authority_address } => {
+                    check_authority(authority_address, &accounts, 2, execution_context)?;
+                }
+                UpgradeableLoaderState::ProgramData { slot, upgrade_authority_address } => {
+                    let program_key = accounts.writable_key(3)?;
+                    let program = get(transaction_context, &program_key)?;
+                    if program.owner != Pubkey::bpf_loader_upgradeable().0 || programdata_address_of(program)? != account_key {
+                        execution_context.log("ProgramData account does not match ProgramData account".to_string());
+                        return Err(InstructionError::InvalidArgument);
+                    }
+                    if slot == sysvars.clock.slot {
+                        execution_context.log("Program was deployed in this block already".to_string());
+                        return Err(InstructionError::InvalidArgument);
+                    }
+                    check_authority(upgrade_authority_address, &accounts, 2, execution_context)?;
+                }
+                UpgradeableLoaderState::Program { .. } => {
+                    execution_context.log("Program account cannot be closed".to_string());
+                    return Err(InstructionError::InvalidArgument);
+                }
+            }
+
+            drain(transaction_context, &account_key, &recipient_key)?;
+            let account = get_mut(transaction_context, &account_key)?;
+            account.data = bincode::serialize(&UpgradeableLoaderState::Uninitialized).unwrap_or_default();
+            execution_context.log(format!("Closed {}", account_key));
+            Ok(())
+        }
+
+        UpgradeableLoaderInstruction::ExtendProgram { additional_bytes } => {
+            if additional_bytes == 0 {
+                execution_context.log("Additional bytes must be greater than 0".to_string());
+                return Err(InstructionError::InvalidInstructionData);
+            }
+            let programdata_key = accounts.writable_key(0)?;
+            let program_key = accounts.writable_key(1)?;
+
+            let program = get(transaction_context, &program_key)?;
+            check_owner(program)?;
+            if programdata_address_of(program)? != programdata_key {
+                execution_context.log("ProgramData account does not match ProgramData account".to_string());
+                return Err(InstructionError::InvalidArgument);
+            }
+            let programdata = get(transaction_context, &programdata_key)?;
+            check_owner(programdata)?;
+            let UpgradeableLoaderState::ProgramData { slot, upgrade_authority_address } =
+                UpgradeableLoaderState::deserialize(&programdata.data)?
+            else {
+                execution_context.log("ProgramData state is invalid".to_string());
+                return Err(InstructionError::InvalidAccountData);
+            };
+            if slot == sysvars.clock.slot {
+                execution_context.log("Program was extended in this block already".to_string());
+                return Err(InstructionError::InvalidArgument);
+            }
+            if upgrade_authority_address.is_none() {
+                execution_context.log("Cannot extend ProgramData accounts that are not upgradeable".to_string());
+                return Err(InstructionError::Immutable);
+            }
+
+            if additional_bytes as usize > MAX_PERMITTED_DATA_INCREASE {
+                execution_context.log(format!("Cannot extend ProgramData by more than {} bytes", MAX_PERMITTED_DATA_INCREASE));
+                return Err(InstructionError::InvalidRealloc);
+            }
+            let new_len = programdata.data.len().saturating_add(additional_bytes as usize);
+            if new_len > MAX_PERMITTED_DATA_LENGTH {
+                execution_context.log("Extended ProgramData length exceeds the maximum".to_string());
+                return Err(InstructionError::InvalidRealloc);
+            }
+            let required = sysvars.rent.minimum_balance(new_len).saturating_sub(programdata.lamports);
+            if required > 0 {
+                let payer_key = accounts.writable_key(3)?;
+                accounts.signer_key(3)?;
+                let transfer = Instruction {
+                    program_id: Pubkey::system_program(),
+                    accounts: vec![],
+                    data: InstructionData::Transfer {
+                        from: payer_key.0,
+                        to: programdata_key.0,
+                        lamports: required,
+                    },
+                };
+                invoke_context.invoke(&transfer, &[])?;
+            }
+            let transaction_context = &mut *invoke_context.transaction_context;
+            let execution_context = &mut *invoke_context.execution_context;
+
+            let programdata = get_mut(transaction_context, &programdata_key)?;
+            programdata.data.resize(new_len, 0);
+            // Like an upgrade, the extended program isn't visible until the next slot
+            UpgradeableLoaderState::ProgramData {
+                slot: sysvars.clock.slot,
+                upgrade_authority_address,
+            }
+            .serialize_into(&mut programdata.data)?;
+            execution_context.log(format!("Extended ProgramData account by {} bytes", additional_bytes));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf_loader::test_elf::{assemble, build};
+    use crate::sbpf::{ebpf, Insn};
+    use crate::builtins::{BuiltinProgram, SystemProgram};
+    use crate::invoke_context::InstructionProcessor;
+    use crate::sysvar::{EpochSchedule, Rent, SysvarCache};
+
+    /// Routes the loader's invocations to the system program, as the runtime does
+    struct SystemProcessor<'a> {
+        sysvars: &'a SysvarCache,
+    }
+
+    impl InstructionProcessor for SystemProcessor<'_> {
+        fn process_instruction(
+            &self,
+            instruction: &Instruction,
+            transaction_context: &mut TransactionContext,
+            execution_context: &mut ExecutionContext,
+        ) -> InstructionResult {
+            assert_eq!(instruction.program_id, Pubkey::system_program());
+            let mut invoke_context =
+                InvokeContext::new(transaction_context, execution_context, self.sysvars, self, instruction);
+            SystemProgram.process_instruction(&mut invoke_context)
+        }
+
+        fn is_builtin(&self, program_id: &Pubkey) -> bool {
+            *program_id == Pubkey::system_program()
+        }
+    }
+
+    /// Process and verify `instruction` as a top-level instruction
+    fn process(
+        instruction: &Instruction,
+        transaction_context: &mut TransactionContext,
+        execution_context: &mut ExecutionContext,
+        sysvars: &SysvarCache,
+    ) -> InstructionResult {
+        let processor = SystemProcessor { sysvars };
+        transaction_context.push_instruction(instruction)?;
+        let mut invoke_context = InvokeContext::new(transaction_context, execution_context, sysvars, &processor, instruction);
+        let result = process_instruction(&mut invoke_context).and_then(|()| transaction_context.verify_instruction());
+        transaction_context.pop_instruction();
+        result
+    }
+
+    fn meta(pubkey: Pubkey, is_signer: bool, is_writable: bool) -> AccountMeta {
+        AccountMeta { pubkey, is_signer, is_writable }
+    }
+
+    fn loader_instruction(loader_instruction: UpgradeableLoaderInstruction, accounts: Vec<AccountMeta>) -> Instruction {
+        Instruction {
+            program_id: Pubkey::bpf_loader_upgradeable(),
+            accounts,
+            data: InstructionData::Generic { data: bincode::serialize(&loader_instruction).unwrap() },
+        }
+    }
+
+    #[test]
+    fn test_state_layout() {
+        let authority = Some(Pubkey::new([1u8; 32]));
+        let sizes = [
+            (UpgradeableLoaderState::Buffer { authority_address: authority }, BUFFER_METADATA_SIZE),
+            (UpgradeableLoaderState::Program { programdata_address: Pubkey::new([2u8; 32]) }, PROGRAM_SIZE),
+            (UpgradeableLoaderState::ProgramData { slot: 3, upgrade_authority_address: authority }, PROGRAMDATA_METADATA_SIZE),
+        ];
+        for (state, size) in sizes {
+            assert_eq!(bincode::serialize(&state).unwrap().len(), size);
+        }
+
+        let write = UpgradeableLoaderInstruction::Write { offset: 5, bytes: vec![9] };
+        assert_eq!(bincode::serialize(&write).unwrap(), [1, 0, 0, 0, 5, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 9]);
+    }
+
+    #[test]
+    fn test_buffer_authority_checks() {
+        let buffer = Pubkey::new([1u8; 32]);
+        let authority = Pubkey::new([2u8; 32]);
+        let other = Pubkey::new([3u8; 32]);
+        let sysvars = SysvarCache::new(1, Rent::default(), EpochSchedule::default());
+        let mut execution_context = ExecutionContext::new(200_000);
+        let mut transaction_context = TransactionContext::new(
+            vec![buffer, authority, other],
+            vec![
+                Account::new(1, vec![0u8; BUFFER_METADATA_SIZE + 4], Pubkey::bpf_loader_upgradeable().0),
+                Account::new(1, vec![], [0u8; 32]),
+                Account::new(1, vec![], [0u8; 32]),
+            ],
+        );
+        let mut process = |instruction: UpgradeableLoaderInstruction, accounts: Vec<AccountMeta>| {
+            process(
+                &loader_instruction(instruction, accounts),
+                &mut transaction_context,
+                &mut execution_context,
+                &sysvars,
+            )
+        };
+
+        let initialize = || vec![meta(buffer, false, true), meta(authority, false, false)];
+        assert_eq!(process(UpgradeableLoaderInstruction::InitializeBuffer, initialize()), Ok(()));
+        assert_eq!(
+            process(UpgradeableLoaderInstruction::InitializeBuffer, initialize()),
+            Err(InstructionError::AccountAlreadyInitialized)
+        );
+
+        let write = |bytes: Vec<u8>| UpgradeableLoaderInstruction::Write { offset: 2, bytes };
+        assert_eq!(
+            process(write(vec![7, 7]), vec![meta(buffer, false, true), meta(other, true, false)]),
+            Err(InstructionError::IncorrectAuthority)
+        );
+        assert_eq!(
+            process(write(vec![7, 7]), vec![meta(buffer, false, true), meta(authority, false, false)]),
+            Err(InstructionError::MissingRequiredSignature)
+        );
+        assert_eq!(
+            process(write(vec![7, 7, 7]), vec![meta(buffer, false, true), meta(authority, true, false)]),
+            Err(InstructionError::AccountDataTooSmall)
+        );
+        assert_eq!(process(write(vec![7, 7]), vec![meta(buffer, false, true), meta(authority, true, false)]), Ok(()));
+
+        // Buffers can't be made immutable
+        let set_authority = |new_authority: Option<Pubkey>| {
+            let mut accounts = vec![meta(buffer, false, true), meta(authority, true, false)];
+            accounts.extend(new_authority.map(|key| meta(key, false, false)));
+            accounts
+        };
+        assert_eq!(
+            process(UpgradeableLoaderInstruction::SetAuthority, set_authority(None)),
+            Err(InstructionError::IncorrectAuthority)
+        );
+        assert_eq!(process(UpgradeableLoaderInstruction::SetAuthority, set_authority(Some(other))), Ok(()));
+
+        assert_eq!(
+            process(
+                UpgradeableLoaderInstruction::Close,
+                vec![meta(buffer, false, true), meta(other, false, true), meta(other, true, false)]
+            ),
+            Ok(())
+        );
+        assert_eq!(transaction_context.get(&buffer).unwrap().lamports, 0);
+        assert_eq!(transaction_context.get(&other).unwrap().lamports, 2);
+    }
+
+    #[test]
+    fn test_deploy_upgrade_and_extend() {
+        let program_id = Pubkey::new([1u8; 32]);
+        let programdata_key = Pubkey::programdata_address(&program_id);
+        let buffer = Pubkey::new([2u8; 32]);
+        let authority = Pubkey::new([3u8; 32]);
+        let payer = Pubkey::new([4u8; 32]);
+        let elf = build(&assemble(&[Insn::new(ebpf::MOV64_IMM, 0, 0, 0, 0), Insn::new(ebpf::EXIT, 0, 0, 0, 0)]), &[], &[], &[]);
+        let rent = Rent::default();
+
+        let mut buffer_data = vec![0u8; BUFFER_METADATA_SIZE];
+        UpgradeableLoaderState::Buffer { authority_address: Some(authority) }
+            .serialize_into(&mut buffer_data)
+            .unwrap();
+        buffer_data.extend_from_slice(&elf);
+        let buffer_account = Account::new(rent.minimum_balance(buffer_data.len()), buffer_data, Pubkey::bpf_loader_upgradeable().0);
+
+        let mut transaction_context = TransactionContext::new(
+            vec![payer, programdata_key, program_id, buffer, authority],
+            vec![
+                Account::new(10_000_000_000, vec![], [0u8; 32]),
+                Account::new(0, vec![], [0u8; 32]),
+                Account::new(rent.minimum_balance(PROGRAM_SIZE), vec![0u8; PROGRAM_SIZE], Pubkey::bpf_loader_upgradeable().0),
+                buffer_account.clone(),
+                Account::new(0, vec![], [0u8; 32]),
+            ],
+        );
+        let mut execution_context = ExecutionContext::new(200_000);
+        let mut sysvars = SysvarCache::new(5, rent.clone(), EpochSchedule::default());
+
+        let deploy = loader_instruction(
+            UpgradeableLoaderInstruction::DeployWithMaxDataLen { max_data_len: elf.len() * 2 },
+            vec![
+                meta(payer, true, true),
+                meta(programdata_key, false, true),
+                meta(program_id, false, true),
+                meta(buffer, false, true),
+                meta(Pubkey::sysvar_rent(), false, false),
+                meta(Pubkey::sysvar_clock(), false, false),
+                meta(Pubkey::system_program(), false, false),
+                meta(authority, true, false),
+            ],
+        );
+        process(&deploy, &mut transaction_context, &mut execution_context, &sysvars).unwrap();
+
+        let program = transaction_context.get(&program_id).unwrap();
+        assert!(program.executable);
+        assert_eq!(programdata_address_of(program), Ok(programdata_key));
+        let programdata = transaction_context.get(&programdata_key).unwrap();
+        assert_eq!(programdata.data.len(), PROGRAMDATA_METADATA_SIZE + elf.len() * 2);
+        let (deployed, slot) = programdata_elf(programdata).unwrap();
+        assert_eq!((&deployed[..elf.len()], slot), (elf.as_slice(), 5));
+        assert_eq!(transaction_context.get(&buffer).unwrap().lamports, 0);
+
+        // Upgrades must wait for the next slot
+        *transaction_context.get_mut(&buffer).unwrap() = buffer_account;
+        let upgrade = loader_instruction(
+            UpgradeableLoaderInstruction::Upgrade,
+            vec![
+                meta(programdata_key, false, true),
+                meta(program_id, false, true),
+                meta(buffer, false, true),
+                meta(payer, false, true),
+                meta(Pubkey::sysvar_rent(), false, false),
+                meta(Pubkey::sysvar_clock(), false, false),
+                meta(authority, true, false),
+            ],
+        );
+        assert_eq!(
+            process(&upgrade, &mut transaction_context, &mut execution_context, &sysvars),
+            Err(InstructionError::InvalidArgument)
+        );
+        sysvars = SysvarCache::new(6, rent.clone(), EpochSchedule::default());
+        process(&upgrade, &mut transaction_context, &mut execution_context, &sysvars).unwrap();
+        let programdata = transaction_context.get(&programdata_key).unwrap();
+        assert_eq!(programdata_elf(programdata).unwrap().1, 6);
+
+        sysvars = SysvarCache::new(7, rent.clone(), EpochSchedule::default());
+        let extend = loader_instruction(
+            UpgradeableLoaderInstruction::ExtendProgram { additional_bytes: 100 },
+            vec![
+                meta(programdata_key, false, true),
+                meta(program_id, false, true),
+                meta(Pubkey::system_program(), false, false),
+                meta(payer, true, true),
+            ],
+        );
+        process(&extend, &mut transaction_context, &mut execution_context, &sysvars).unwrap();
+        let programdata = transaction_context.get(&programdata_key).unwrap();
+        assert_eq!(programdata.data.len(), PROGRAMDATA_METADATA_SIZE + elf.len() * 2 + 100);
+        assert!(rent.is_exempt(programdata.lamports, programdata.data.len()));
+
+        // Without an upgrade authority the program is final
+        sysvars = SysvarCache::new(8, rent, EpochSchedule::default());
+        let set_authority = loader_instruction(
+            UpgradeableLoaderInstruction::SetAuthority,
+            vec![meta(programdata_key, false, true), meta(authority, true, false)],
+        );
+        process(&set_authority, &mut transaction_context, &mut execution_context, &sysvars).unwrap();
+        assert_eq!(
+            process(&upgrade, &mut transaction_context, &mut execution_context, &sysvars),
+            Err(InstructionError::Immutable)
+        );
+    }
+}